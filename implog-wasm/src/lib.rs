@@ -0,0 +1,2 @@
+// Re-exporting the bindings links them into this crate's cdylib.
+pub use implog::wasm::*;
@@ -0,0 +1,130 @@
+use crate::representation::{Assumption, GroundTuple, Table};
+
+// Relational operators over tables, for post-processing derived relations (or building facts)
+// without going through rules. Operators read the full values of their input tables, and produce
+// tables holding only full values (every delta is zero). Tuples with a zero value are absent from
+// the results. Assumption values combine like they do in rules: operators merging tuples add their
+// values, and joins multiply them.
+
+fn insert<A: Assumption>(table: &mut Table<A>, tuple: GroundTuple, value: A) {
+    if value.is_zero() {
+        return;
+    }
+    let (old, _) = table.entry(tuple).or_insert_with(|| (A::zero(), A::zero()));
+    *old = old.plus(&value);
+}
+
+fn rows<A: Assumption>(table: &Table<A>) -> impl Iterator<Item = (&GroundTuple, &A)> {
+    table
+        .iter()
+        .map(|(tuple, (value, _))| (tuple, value))
+        .filter(|(_, value)| !value.is_zero())
+}
+
+// The tuples satisfying a predicate.
+pub fn select<A: Assumption>(
+    table: &Table<A>,
+    predicate: impl Fn(&GroundTuple) -> bool,
+) -> Table<A> {
+    let mut result = Table::new();
+    for (tuple, value) in rows(table) {
+        if predicate(tuple) {
+            insert(&mut result, tuple.clone(), value.clone());
+        }
+    }
+    result
+}
+
+// The given columns of each tuple, in the given order. Tuples agreeing on those columns are merged.
+pub fn project<A: Assumption>(table: &Table<A>, columns: &[usize]) -> Table<A> {
+    let mut result = Table::new();
+    for (tuple, value) in rows(table) {
+        let projected = columns.iter().map(|column| tuple[*column]).collect();
+        insert(&mut result, projected, value.clone());
+    }
+    result
+}
+
+// Pairs of tuples agreeing on each pair of (left, right) columns, concatenated.
+pub fn join<A: Assumption>(left: &Table<A>, right: &Table<A>, on: &[(usize, usize)]) -> Table<A> {
+    let mut result = Table::new();
+    for (left_tuple, left_value) in rows(left) {
+        for (right_tuple, right_value) in rows(right) {
+            if on.iter().all(|(l, r)| left_tuple[*l] == right_tuple[*r]) {
+                let tuple = left_tuple.iter().chain(right_tuple).copied().collect();
+                insert(&mut result, tuple, left_value.times(right_value));
+            }
+        }
+    }
+    result
+}
+
+pub fn union<A: Assumption>(left: &Table<A>, right: &Table<A>) -> Table<A> {
+    let mut result = Table::new();
+    for (tuple, value) in rows(left).chain(rows(right)) {
+        insert(&mut result, tuple.clone(), value.clone());
+    }
+    result
+}
+
+// The tuples of the left table absent from the right table. Tuples present in both are removed
+// whatever their assumption values.
+pub fn difference<A: Assumption>(left: &Table<A>, right: &Table<A>) -> Table<A> {
+    select(left, |tuple| {
+        right.get(tuple).is_none_or(|(value, _)| value.is_zero())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::representation::DNFAssumption;
+
+    use super::*;
+
+    fn table(tuples: &[&[i64]]) -> Table<DNFAssumption> {
+        tuples
+            .iter()
+            .map(|tuple| {
+                (
+                    tuple.to_vec(),
+                    (DNFAssumption::one(), DNFAssumption::zero()),
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn algebra_operators() {
+        let edges = table(&[&[1, 2], &[2, 3], &[3, 3]]);
+        let loops = select(&edges, |tuple| tuple[0] == tuple[1]);
+        assert_eq!(loops, table(&[&[3, 3]]));
+        assert_eq!(project(&edges, &[1]), table(&[&[2], &[3]]));
+        assert_eq!(
+            join(&edges, &edges, &[(1, 0)]),
+            table(&[&[1, 2, 2, 3], &[2, 3, 3, 3], &[3, 3, 3, 3]])
+        );
+        assert_eq!(
+            union(&loops, &table(&[&[1, 1]])),
+            table(&[&[1, 1], &[3, 3]])
+        );
+        assert_eq!(difference(&edges, &loops), table(&[&[1, 2], &[2, 3]]));
+    }
+
+    #[test]
+    fn algebra_assumptions() {
+        let leaf = ("A".to_string(), vec![]);
+        let assumed: Table<DNFAssumption> = Table::from([(
+            vec![1],
+            (
+                DNFAssumption::singleton(leaf.clone()),
+                DNFAssumption::zero(),
+            ),
+        )]);
+        let joined = join(&assumed, &table(&[&[1], &[2]]), &[(0, 0)]);
+        assert_eq!(joined[&vec![1, 1]].0, DNFAssumption::singleton(leaf));
+        assert_eq!(
+            union(&assumed, &table(&[&[1]]))[&vec![1]].0,
+            DNFAssumption::one()
+        );
+    }
+}
@@ -1,16 +1,119 @@
-use std::collections::BTreeSet;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+use std::str::FromStr;
 
 use either::Either;
+use lalrpop_util::ParseError;
 
+use crate::builtin::{CallBuiltin, GraphBuiltin};
+use crate::grammar::{LocatedProgramParser, RecoveringProgramParser};
+use crate::lint::{Level, Lint};
 use crate::representation::Symbol;
+use crate::value::{CONS, NIL, Value};
 
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum StatementAST {
     Rule(AtomAST, Vec<AtomAST>),
-    Question(Vec<AtomAST>),
+    // `?naive`, `?magic`, or `?td` instead of `?` evaluates a question with the strategy set by the
+    // corresponding pragma (`bottom_up`, `magic`, or `top_down`), for that question only. `? x, y :
+    // body.` selects the variables answers bind: answers that agree on them are merged, and their
+    // assumption values summed. `? [A] |- body.` asks a hypothetical question (see `Modifiers`).
+    // Modifiers like `where definite order by x limit 10` follow the body.
+    Question(Vec<AtomAST>, Option<Pragma>, Option<Vec<String>>, Modifiers),
+    // `?abduce body.` finds, for each answer, the minimal sets of leaf assumptions of abducible
+    // relations (every relation, if none are declared `.abducible`) it's derivable under.
+    Abduce(Vec<AtomAST>),
+    // `?diff body1; body2.` compares the answers to two question bodies over the same variables.
+    Diff(Vec<AtomAST>, Vec<AtomAST>),
+    // `!expect body.` and `!expect_count body = n.` are evaluated like questions, but fail
+    // interpretation if the body has no answers, or doesn't have exactly n answers.
+    Expect(Vec<AtomAST>, Option<usize>),
+    Builtin(BuiltinAST),
+    // `.input Rel(arity).` declares an extensional relation whose facts are loaded from a file.
+    Input(String, usize),
+    // `.output Rel.` dumps a relation to a file at the end of interpretation.
+    Output(String),
+    // `:- body.` is an integrity constraint: its body must never be derivable. Assumption values
+    // under which the body holds are inconsistent.
+    Constraint(Vec<AtomAST>),
+    // `.abducible Rel.` declares that tuples of a relation may be assumed with brackets.
+    Abducible(String),
+    // `.allow lint.`, `.warn lint.`, or `.deny lint.` configures the level of a lint.
+    LintLevel(Level, Lint),
+    // `.pragma name.` changes how the following questions are evaluated.
+    Pragma(Pragma),
+    // `.decl Rel(name: type, ...).` declares the columns of a relation. Every atom of a declared
+    // relation must have one term per column, and answers are printed with the column names. A
+    // merge function like `@min` before the last column makes it a lattice column (see `Merge`).
+    Decl(String, Vec<(String, Type)>, Option<Merge>),
+    // `.weight A(x) = n.` sets the weight of the leaf assumptions a literal matches (variables match
+    // anything) to n, for ranking answers by the cheapest assumptions they hold under. The first
+    // matching weight applies, and leaf assumptions no weight matches weigh 1.
+    Weight(LiteralAST, usize),
+    // `.probability A(x) = p.` sets the probability of the leaf assumptions a literal matches to p,
+    // for computing the probability of answers (see `Assumption::probability`). The first matching
+    // probability applies, and leaf assumptions no probability matches hold with probability 1.
+    Probability(LiteralAST, f64),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Pragma {
+    // Evaluate questions bottom-up, over the whole model of the rules.
+    BottomUp,
+    // Evaluate questions with the magic-set transformation.
+    Magic,
+    // Evaluate questions top-down, with tabling.
+    TopDown,
+}
+
+// Modifiers of a question's answers. Hypotheses `[A(1)], [B] |- ` before the body are assumed
+// while answering the question only, as if by rules `[A(1)] :- .`, and answers are reported with
+// the hypotheses discharged from their assumption values, so what-if questions don't need edits to
+// the program. `where ...` keeps the answers whose assumption values pass a filter. `order by x, y`
+// sorts answers by the values bound to some variables, by the first variable, then the second,
+// and so on. `limit n` keeps the first n answers.
+#[derive(Debug, Clone, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Modifiers {
+    pub hypotheses: Vec<LiteralAST>,
+    pub filter: Option<Filter>,
+    pub order_by: Vec<String>,
+    pub limit: Option<usize>,
+}
+
+// Filters on the assumption values of answers, separating definite conclusions from hypothetical
+// ones.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Filter {
+    // `where definite` keeps answers that hold unconditionally (with assumption value one).
+    Definite,
+    // `where conditional` keeps answers that only hold under some assumptions.
+    Conditional,
+    // `where assuming A(1)` keeps answers whose assumption values mention a leaf assumption.
+    Assuming(LiteralAST),
+    // `where excluding A(1)` keeps answers whose assumption values don't mention a leaf assumption.
+    Excluding(LiteralAST),
+}
+
+impl Filter {
+    pub fn new(kind: &str, lit: Option<LiteralAST>) -> Result<Self, &'static str> {
+        match (kind, lit) {
+            ("definite", None) => Ok(Filter::Definite),
+            ("conditional", None) => Ok(Filter::Conditional),
+            ("assuming", Some(lit)) => Ok(Filter::Assuming(lit)),
+            ("excluding", Some(lit)) => Ok(Filter::Excluding(lit)),
+            ("definite" | "conditional", Some(_)) => Err("this filter doesn't take an atom"),
+            ("assuming" | "excluding", None) => Err("this filter takes an atom"),
+            _ => Err("unknown filter"),
+        }
+    }
 }
 
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum AtomAST {
     Literal(LiteralAST),
     Brackets(LiteralAST),
@@ -18,15 +121,294 @@ pub enum AtomAST {
 }
 
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct LiteralAST {
     pub relation: String,
     pub terms: Vec<TermAST>,
+    // Where the literal was parsed from, or `None` if it was generated by a transformation.
+    pub span: Option<Span>,
+    // Where each term was parsed from, or empty if the literal was generated.
+    pub term_spans: Vec<Span>,
+}
+
+// `x = y` is parsed as a literal of this relation, which is a built-in (see `CallBuiltin`).
+pub const EQUAL: &str = "=";
+
+// Explicit invocation of a built-in graph algorithm: `.builtin Reach = transitive_closure(Edge).`
+// computes `relation` from the tuples of `source`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct BuiltinAST {
+    pub relation: String,
+    pub kind: GraphBuiltin,
+    pub source: String,
 }
 
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum TermAST {
     Variable(String),
     Constant(Symbol),
+    // `$name` is a placeholder for a constant supplied from outside the program. Parameters are
+    // substituted by `substitute_params` before a program is checked and interpreted.
+    Parameter(String),
+    // `functor(term, ...)` is a compound term, like `pair(x, y)` or `succ(n)`. Ground compound
+    // terms are interned to symbols (see `value`), and compound terms in atoms unify with the
+    // symbols of the terms they match.
+    Compound(String, Vec<TermAST>),
+}
+
+// Column types. `int` columns hold integers and `symbol` columns hold strings, which are interned
+// to symbols (see `value`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Type {
+    Int,
+    Symbol,
+}
+
+impl FromStr for Type {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "int" => Ok(Type::Int),
+            "symbol" => Ok(Type::Symbol),
+            _ => Err("unknown type"),
+        }
+    }
+}
+
+impl Type {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Type::Int => "int",
+            Type::Symbol => "symbol",
+        }
+    }
+}
+
+// Merge functions of lattice columns, as in `.decl Dist(node: int, @min cost: int).`. A derived
+// tuple agreeing with a tuple already in the table on every other column is merged with it: the
+// merged tuple holds the merge of their last columns, under the assumptions of both. Tuples a
+// merged tuple holds whenever they do are superseded, so relations derived without assumptions
+// keep one tuple per value of their other columns. `@union` merges lists as sets, and `@name`
+// merges with the lattice registered as `name` with `Environment::register_lattice`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Merge {
+    Min,
+    Max,
+    Union,
+    Lattice(String),
+}
+
+impl Merge {
+    pub fn new(name: &str) -> Self {
+        match name {
+            "min" => Merge::Min,
+            "max" => Merge::Max,
+            "union" => Merge::Union,
+            _ => Merge::Lattice(name.to_string()),
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        match self {
+            Merge::Min => "min",
+            Merge::Max => "max",
+            Merge::Union => "union",
+            Merge::Lattice(name) => name,
+        }
+    }
+}
+
+impl FromStr for Pragma {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "bottom_up" => Ok(Pragma::BottomUp),
+            "magic" => Ok(Pragma::Magic),
+            "top_down" => Ok(Pragma::TopDown),
+            _ => Err("unknown pragma"),
+        }
+    }
+}
+
+impl Pragma {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Pragma::BottomUp => "bottom_up",
+            Pragma::Magic => "magic",
+            Pragma::TopDown => "top_down",
+        }
+    }
+}
+
+// Statements are displayed in surface syntax, and parse back to the same statement.
+impl fmt::Display for StatementAST {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        use StatementAST::*;
+        match self {
+            Rule(head, body) => write!(f, "{} :- {}.", head, List(body, ", ")),
+            Question(body, strategy, select, modifiers) => {
+                let marker = match strategy {
+                    None => "?",
+                    Some(self::Pragma::BottomUp) => "?naive",
+                    Some(self::Pragma::Magic) => "?magic",
+                    Some(self::Pragma::TopDown) => "?td",
+                };
+                write!(f, "{} ", marker)?;
+                if let Some(select) = select {
+                    write!(f, "{} : ", List(select, ", "))?;
+                }
+                for (idx, hypothesis) in modifiers.hypotheses.iter().enumerate() {
+                    let separator = if idx + 1 == modifiers.hypotheses.len() {
+                        " |-"
+                    } else {
+                        ","
+                    };
+                    write!(f, "[{}]{} ", hypothesis, separator)?;
+                }
+                write!(f, "{}{}.", List(body, ", "), modifiers)
+            }
+            Abduce(body) => write!(f, "?abduce {}.", List(body, ", ")),
+            Diff(left, right) => {
+                write!(f, "?diff {}; {}.", List(left, ", "), List(right, ", "))
+            }
+            Expect(body, None) => write!(f, "!expect {}.", List(body, ", ")),
+            Expect(body, Some(count)) => {
+                write!(f, "!expect_count {} = {}.", List(body, ", "), count)
+            }
+            Builtin(builtin) => write!(
+                f,
+                ".builtin {} = {}({}).",
+                builtin.relation,
+                builtin.kind.name(),
+                builtin.source
+            ),
+            Input(relation, arity) => write!(f, ".input {}({}).", relation, arity),
+            Output(relation) => write!(f, ".output {}.", relation),
+            Constraint(body) => write!(f, ":- {}.", List(body, ", ")),
+            Abducible(relation) => write!(f, ".abducible {}.", relation),
+            LintLevel(level, lint) => {
+                let level = match level {
+                    Level::Allow => "allow",
+                    Level::Warn => "warn",
+                    Level::Deny => "deny",
+                };
+                write!(f, ".{} {}.", level, lint.name())
+            }
+            Pragma(pragma) => write!(f, ".pragma {}.", pragma.name()),
+            Decl(relation, columns, merge) => {
+                let mut columns: Vec<String> = columns
+                    .iter()
+                    .map(|(name, ty)| format!("{}: {}", name, ty.name()))
+                    .collect();
+                if let (Some(merge), Some(last)) = (merge, columns.last_mut()) {
+                    *last = format!("@{} {}", merge.name(), last);
+                }
+                write!(f, ".decl {}({}).", relation, columns.join(", "))
+            }
+            Weight(lit, weight) => write!(f, ".weight {} = {}.", lit, weight),
+            Probability(lit, p) => write!(f, ".probability {} = {}.", lit, p),
+        }
+    }
+}
+
+// Modifiers are displayed with a leading space, so they can follow a question's body. Hypotheses
+// precede the body, so they aren't displayed here.
+impl fmt::Display for Modifiers {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.filter {
+            None => {}
+            Some(Filter::Definite) => write!(f, " where definite")?,
+            Some(Filter::Conditional) => write!(f, " where conditional")?,
+            Some(Filter::Assuming(lit)) => write!(f, " where assuming {}", lit)?,
+            Some(Filter::Excluding(lit)) => write!(f, " where excluding {}", lit)?,
+        }
+        if !self.order_by.is_empty() {
+            write!(f, " order by {}", List(&self.order_by, ", "))?;
+        }
+        if let Some(limit) = self.limit {
+            write!(f, " limit {}", limit)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for AtomAST {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AtomAST::Literal(lit) => write!(f, "{}", lit),
+            AtomAST::Brackets(lit) => write!(f, "[{}]", lit),
+            AtomAST::Arrow(lhs, rhs) => write!(f, "{} -> {}", lhs, rhs),
+        }
+    }
+}
+
+impl fmt::Display for LiteralAST {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.terms.is_empty() {
+            write!(f, "{}", self.relation)
+        } else if self.relation == EQUAL {
+            write!(f, "{} = {}", self.terms[0], self.terms[1])
+        } else {
+            write!(f, "{}({})", self.relation, List(&self.terms, ", "))
+        }
+    }
+}
+
+impl fmt::Display for TermAST {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TermAST::Variable(var) => write!(f, "{}", var),
+            TermAST::Constant(cons) => write!(f, "{}", Value(*cons)),
+            TermAST::Parameter(param) => write!(f, "${}", param),
+            TermAST::Compound(functor, _) if functor == CONS || functor == NIL => {
+                let mut elems = vec![];
+                let mut tail = self;
+                while let TermAST::Compound(functor, args) = tail
+                    && functor == CONS
+                {
+                    elems.push(&args[0]);
+                    tail = &args[1];
+                }
+                write!(f, "[{}", List(&elems, ", "))?;
+                match tail {
+                    TermAST::Compound(functor, _) if functor == NIL => write!(f, "]"),
+                    _ => write!(f, " | {}]", tail),
+                }
+            }
+            TermAST::Compound(functor, args) => write!(f, "{}({})", functor, List(args, ", ")),
+        }
+    }
+}
+
+// The list term `[elems | tail]`, or `[elems]` if there's no tail.
+pub fn list_term(elems: Vec<TermAST>, tail: Option<TermAST>) -> TermAST {
+    let nil = TermAST::Compound(NIL.to_string(), vec![]);
+    elems
+        .into_iter()
+        .rev()
+        .fold(tail.unwrap_or(nil), |tail, elem| {
+            TermAST::Compound(CONS.to_string(), vec![elem, tail])
+        })
+}
+
+struct List<'a, T>(&'a [T], &'a str);
+
+impl<T: fmt::Display> fmt::Display for List<'_, T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (idx, item) in self.0.iter().enumerate() {
+            if idx > 0 {
+                write!(f, "{}", self.1)?;
+            }
+            write!(f, "{}", item)?;
+        }
+        Ok(())
+    }
 }
 
 impl StatementAST {
@@ -34,16 +416,135 @@ impl StatementAST {
         use StatementAST::*;
         match self {
             Rule(head, _) => Some(head),
-            Question(_) => None,
+            Question(_, _, _, _)
+            | Abduce(_)
+            | Diff(_, _)
+            | Expect(_, _)
+            | Builtin(_)
+            | Input(_, _)
+            | Output(_)
+            | Constraint(_)
+            | Abducible(_)
+            | LintLevel(_, _)
+            | Pragma(_)
+            | Decl(_, _, _)
+            | Weight(_, _)
+            | Probability(_, _) => None,
         }
     }
 
+    // The body of a statement with a single body. Diffs have two bodies, see `bodies`.
     pub fn body(&self) -> &Vec<AtomAST> {
         use StatementAST::*;
+        const EMPTY: &Vec<AtomAST> = &Vec::new();
         match self {
-            Rule(_, body) | Question(body) => body,
+            Rule(_, body)
+            | Question(body, _, _, _)
+            | Abduce(body)
+            | Expect(body, _)
+            | Constraint(body) => body,
+            Diff(_, _)
+            | Builtin(_)
+            | Input(_, _)
+            | Output(_)
+            | Abducible(_)
+            | LintLevel(_, _)
+            | Pragma(_)
+            | Decl(_, _, _)
+            | Weight(_, _)
+            | Probability(_, _) => EMPTY,
         }
     }
+
+    pub fn bodies(&self) -> Vec<&Vec<AtomAST>> {
+        match self {
+            StatementAST::Diff(left, right) => vec![left, right],
+            _ => vec![self.body()],
+        }
+    }
+
+    pub(crate) fn atoms_mut(&mut self) -> Vec<&mut AtomAST> {
+        use StatementAST::*;
+        match self {
+            Rule(head, body) => std::iter::once(head).chain(body).collect(),
+            Question(body, _, _, _) | Abduce(body) | Expect(body, _) | Constraint(body) => {
+                body.iter_mut().collect()
+            }
+            Diff(left, right) => left.iter_mut().chain(right).collect(),
+            Builtin(_)
+            | Input(_, _)
+            | Output(_)
+            | Abducible(_)
+            | LintLevel(_, _)
+            | Pragma(_)
+            | Decl(_, _, _)
+            | Weight(_, _)
+            | Probability(_, _) => vec![],
+        }
+    }
+
+    // Every constant in a statement, including those of hypotheses, filters, weights, and
+    // probabilities, and those nested in compound terms.
+    pub(crate) fn constants_mut(&mut self) -> Vec<&mut Symbol> {
+        use StatementAST::*;
+        let lits: Vec<&mut LiteralAST> = match self {
+            Question(body, _, _, modifiers) => {
+                let mut lits: Vec<_> = body.iter_mut().flat_map(AtomAST::literals_mut).collect();
+                lits.extend(&mut modifiers.hypotheses);
+                if let Some(Filter::Assuming(lit) | Filter::Excluding(lit)) = &mut modifiers.filter
+                {
+                    lits.push(lit);
+                }
+                lits
+            }
+            Weight(lit, _) | Probability(lit, _) => vec![lit],
+            _ => self
+                .atoms_mut()
+                .into_iter()
+                .flat_map(AtomAST::literals_mut)
+                .collect(),
+        };
+        lits.into_iter()
+            .flat_map(|lit| lit.terms.iter_mut().flat_map(TermAST::leaves_mut))
+            .filter_map(|term| match term {
+                TermAST::Constant(symbol) => Some(symbol),
+                _ => None,
+            })
+            .collect()
+    }
+
+    // Every relation name in a statement, including those of declarations, hypotheses, and filters.
+    fn relations_mut(&mut self) -> Vec<&mut String> {
+        use StatementAST::*;
+        let mut relations: Vec<&mut String> = vec![];
+        match self {
+            Question(body, _, _, modifiers) => {
+                relations.extend(body.iter_mut().flat_map(AtomAST::relations_mut));
+                relations.extend(modifiers.hypotheses.iter_mut().map(|lit| &mut lit.relation));
+                if let Some(Filter::Assuming(lit) | Filter::Excluding(lit)) = &mut modifiers.filter
+                {
+                    relations.push(&mut lit.relation);
+                }
+            }
+            Builtin(builtin) => relations.extend([&mut builtin.relation, &mut builtin.source]),
+            Input(relation, _) | Output(relation) | Abducible(relation) | Decl(relation, _, _) => {
+                relations.push(relation)
+            }
+            Weight(lit, _) | Probability(lit, _) => relations.push(&mut lit.relation),
+            Rule(_, _)
+            | Abduce(_)
+            | Diff(_, _)
+            | Expect(_, _)
+            | Constraint(_)
+            | LintLevel(_, _)
+            | Pragma(_) => relations.extend(
+                self.atoms_mut()
+                    .into_iter()
+                    .flat_map(AtomAST::relations_mut),
+            ),
+        }
+        relations
+    }
 }
 
 impl AtomAST {
@@ -56,11 +557,34 @@ impl AtomAST {
             Arrow(lit1, lit2) => Either::Right(lit1.vars().chain(lit2.vars())),
         }
     }
+
+    pub fn literals(&self) -> Vec<&LiteralAST> {
+        use AtomAST::*;
+        match self {
+            Literal(lit) | Brackets(lit) => vec![lit],
+            Arrow(lit1, lit2) => vec![lit1, lit2],
+        }
+    }
+
+    pub(crate) fn literals_mut(&mut self) -> Vec<&mut LiteralAST> {
+        use AtomAST::*;
+        match self {
+            Literal(lit) | Brackets(lit) => vec![lit],
+            Arrow(lit1, lit2) => vec![lit1, lit2],
+        }
+    }
+
+    fn relations_mut(&mut self) -> Vec<&mut String> {
+        self.literals_mut()
+            .into_iter()
+            .map(|lit| &mut lit.relation)
+            .collect()
+    }
 }
 
 impl LiteralAST {
     pub fn vars(&self) -> impl Iterator<Item = &str> + '_ {
-        self.terms.iter().filter_map(TermAST::try_var)
+        self.terms.iter().flat_map(TermAST::vars)
     }
 }
 
@@ -69,71 +593,586 @@ impl TermAST {
         use TermAST::*;
         match self {
             Variable(s) => Some(s),
-            Constant(_) => None,
+            Constant(_) | Parameter(_) | Compound(_, _) => None,
         }
     }
 
     pub fn try_cons(&self) -> Option<Symbol> {
         use TermAST::*;
         match self {
-            Variable(_) => None,
+            Variable(_) | Parameter(_) | Compound(_, _) => None,
             Constant(s) => Some(*s),
         }
     }
+
+    // The variables of a term, including those nested in compound terms.
+    pub fn vars(&self) -> Vec<&str> {
+        match self {
+            TermAST::Compound(_, args) => args.iter().flat_map(TermAST::vars).collect(),
+            _ => self.try_var().into_iter().collect(),
+        }
+    }
+
+    // The terms of a term that aren't compound, including those nested in compound terms.
+    pub(crate) fn leaves_mut(&mut self) -> Vec<&mut TermAST> {
+        match self {
+            TermAST::Compound(_, args) => args.iter_mut().flat_map(TermAST::leaves_mut).collect(),
+            _ => vec![self],
+        }
+    }
+}
+
+// The variables selected by a question, which are parsed as atoms. Only nullary literals can be
+// read as variables.
+pub fn selected_vars(atoms: Vec<AtomAST>) -> Result<Vec<String>, &'static str> {
+    atoms
+        .into_iter()
+        .map(|atom| match atom {
+            AtomAST::Literal(lit) if lit.terms.is_empty() && !lit.relation.contains("::") => {
+                Ok(lit.relation)
+            }
+            _ => Err("only variables can be selected"),
+        })
+        .collect()
+}
+
+// A column of a declaration as parsed: its merge function, if it's a lattice column, its name, and
+// its type.
+pub type DeclColumn = (Option<Merge>, String, Type);
+
+// The declaration of a relation's columns. Only the last column can be a lattice column.
+pub fn decl(relation: String, columns: Vec<DeclColumn>) -> Result<StatementAST, &'static str> {
+    let mut merge = None;
+    for (idx, (column_merge, _, _)) in columns.iter().enumerate() {
+        if column_merge.is_some() {
+            if idx + 1 != columns.len() {
+                return Err("only the last column can have a merge function");
+            }
+            merge = column_merge.clone();
+        }
+    }
+    let columns = columns
+        .into_iter()
+        .map(|(_, name, ty)| (name, ty))
+        .collect();
+    Ok(StatementAST::Decl(relation, columns, merge))
+}
+
+// Qualify the relations a module defines. In `module graph { ... }`, the relations defined by the
+// statements of the module (those in rule heads, or declared by `.decl`, `.input`, `.abducible`, or
+// `.builtin`) are named `graph::Name`, both in the module and outside of it. Other names in the
+// module refer to relations outside of it, so a module can read relations defined elsewhere.
+// Nested modules are qualified from the inside out, so `module a { module b { P :- . } }` defines
+// `a::b::P`.
+pub fn qualify_module(module: &str, mut stmts: Vec<StatementAST>) -> Vec<StatementAST> {
+    let mut defined = BTreeSet::new();
+    for stmt in &stmts {
+        match stmt {
+            StatementAST::Rule(head, _) => {
+                defined.extend(head.literals().into_iter().map(|lit| lit.relation.clone()))
+            }
+            StatementAST::Builtin(BuiltinAST { relation, .. })
+            | StatementAST::Input(relation, _)
+            | StatementAST::Abducible(relation)
+            | StatementAST::Decl(relation, _, _) => {
+                defined.insert(relation.clone());
+            }
+            _ => {}
+        }
+    }
+    for stmt in &mut stmts {
+        for relation in stmt.relations_mut() {
+            if defined.contains(relation) {
+                *relation = format!("{}::{}", module, relation);
+            }
+        }
+    }
+    stmts
+}
+
+// The module a relation is in, if its name is qualified, like `graph` for `graph::Edge`.
+pub fn module_of(relation: &str) -> Option<&str> {
+    relation.rsplit_once("::").map(|(module, _)| module)
+}
+
+pub fn hypotheses(atoms: Vec<AtomAST>) -> Result<Vec<LiteralAST>, &'static str> {
+    atoms
+        .into_iter()
+        .map(|atom| match atom {
+            AtomAST::Brackets(lit) => Ok(lit),
+            _ => Err("only bracketed atoms can be hypotheses"),
+        })
+        .collect()
+}
+
+// Replace every parameter in a program with the constant bound to it. Fails on the first parameter
+// that isn't bound.
+pub fn substitute_params(
+    stmts: &[StatementAST],
+    params: &BTreeMap<String, Symbol>,
+) -> Result<Vec<StatementAST>, String> {
+    let mut stmts = stmts.to_vec();
+    for stmt in &mut stmts {
+        for atom in stmt.atoms_mut() {
+            for lit in atom.literals_mut() {
+                for term in lit.terms.iter_mut().flat_map(TermAST::leaves_mut) {
+                    if let TermAST::Parameter(param) = term {
+                        let Some(symbol) = params.get(param) else {
+                            return Err(format!("parameter ${} is not bound", param));
+                        };
+                        *term = TermAST::Constant(*symbol);
+                    }
+                }
+            }
+        }
+    }
+    Ok(stmts)
+}
+
+// Rewrite rules with an arrow in the head, which derive an implication, into rules deriving its
+// conclusion under its premise: `A(x) -> B(x) :- body.` becomes `B(x) :- body, [A(x)].`. By the
+// deduction theorem, reading the implication back with an arrow in a body discharges the premise
+// again.
+pub fn lower_head_arrows(stmts: &[StatementAST]) -> Vec<StatementAST> {
+    stmts
+        .iter()
+        .map(|stmt| match stmt {
+            StatementAST::Rule(AtomAST::Arrow(lhs, rhs), body) => {
+                let mut body = body.clone();
+                body.push(AtomAST::Brackets(lhs.clone()));
+                StatementAST::Rule(AtomAST::Literal(rhs.clone()), body)
+            }
+            _ => stmt.clone(),
+        })
+        .collect()
+}
+
+// Whether a program makes or reads assumptions: with brackets, arrows, hypothetical questions,
+// filters on assumption values, abduction, or weights and probabilities of leaf assumptions.
+// Programs that don't are plain Datalog, whose tuples all hold with assumption value one, so every
+// semiring answers them alike, and the boolean semiring does so fastest.
+pub fn uses_assumptions(stmts: &[StatementAST]) -> bool {
+    use StatementAST::*;
+    stmts.iter().any(|stmt| {
+        let atoms: Vec<_> = match stmt {
+            Rule(head, body) => std::iter::once(head).chain(body).collect(),
+            Diff(left, right) => left.iter().chain(right).collect(),
+            _ => stmt.body().iter().collect(),
+        };
+        atoms
+            .iter()
+            .any(|atom| !matches!(atom, AtomAST::Literal(_)))
+            || match stmt {
+                Question(_, _, _, modifiers) => {
+                    !modifiers.hypotheses.is_empty() || modifiers.filter.is_some()
+                }
+                Abduce(_) | Abducible(_) | Weight(_, _) | Probability(_, _) => true,
+                _ => false,
+            }
+    })
 }
 
 pub fn check(stmt: &StatementAST) -> bool {
+    why_ill_formed(stmt).is_none()
+}
+
+// The variables bound by the atoms of a body.
+fn range(body: &[AtomAST]) -> BTreeSet<&str> {
+    body.iter()
+        .flat_map(|atom| match atom {
+            AtomAST::Literal(lit) | AtomAST::Arrow(_, lit) => lit.vars().collect(),
+            AtomAST::Brackets(_) => vec![],
+        })
+        .collect()
+}
+
+// The variables of a rule's head that its body doesn't bind. Each binding of the body derives a
+// skolem term for each of them, as if they were existentially quantified: a compound term whose
+// functor names the head's relation and the variable, applied to the body's binding. The same
+// binding always derives the same skolem terms, so rules with existential variables still reach a
+// fixpoint, unless they recursively derive ever deeper skolem terms; those stop at `MAX_TERM_DEPTH`
+// (see `value`), so their models are truncated rather than infinite.
+pub fn existential_vars<'a>(head: &'a AtomAST, body: &'a [AtomAST]) -> BTreeSet<&'a str> {
+    let range = range(body);
+    head.vars().filter(|var| !range.contains(var)).collect()
+}
+
+// Check that a parsed statement is well formed, returning why it isn't if it isn't.
+pub fn why_ill_formed(stmt: &StatementAST) -> Option<&'static str> {
     use AtomAST::*;
     use StatementAST::*;
-    // Check that a parsed statement is well formed. Just return true/false for now.
 
     // 1. Statements must be properly range restricted. The range of a statement is the set of
     //    variables appearing in the body as (just) literals or in the RHS literal of arrow atoms.
-    //    The set of variables in the head, in the LHS literal of arrow atoms, or in the literal of
-    //    bracket atoms must be a subset of the range.
-    let mut range = BTreeSet::new();
-    for atom in stmt.body() {
-        match atom {
-            Literal(lit) | Arrow(_, lit) => range.extend(lit.vars()),
-            Brackets(_) => {}
+    //    The set of variables in the LHS literal of arrow atoms (including an arrow in the head),
+    //    or in the literal of bracket atoms must be a subset of the range. Other variables of the
+    //    head are existential (see `existential_vars`).
+    for body in stmt.bodies() {
+        let range = range(body);
+        if let Some(Arrow(lit, _)) = stmt.head()
+            && lit.vars().any(|var| !range.contains(var))
+        {
+            return Some("a variable of the head's arrow isn't bound by the body");
+        }
+        for atom in body {
+            match atom {
+                Brackets(lit) | Arrow(lit, _) if lit.vars().any(|var| !range.contains(var)) => {
+                    return Some("a variable of a bracket or arrow isn't bound by the body");
+                }
+                _ => {}
+            }
         }
     }
 
-    if let Some(head) = stmt.head()
-        && head.vars().any(|var| !range.contains(var))
-    {
-        return false;
+    // 2. Arrows are allowed in the head, since `lower_head_arrows` rewrites them away before
+    //    interpretation.
+
+    // 3. (Maybe TEMPORARY?) no brackets or arrows in the body of questions for now. We just print
+    //    ground atoms and their assumption values matching a question query, and it's not obvious
+    //    what a bracket or arrow in a question means.
+    if let Question(_, _, _, _) | Abduce(_) | Expect(_, _) | Diff(_, _) = stmt {
+        for atom in stmt.bodies().into_iter().flatten() {
+            match atom {
+                Literal(_) => {}
+                Brackets(_) | Arrow(_, _) => {
+                    return Some("brackets and arrows are only allowed in rules and constraints");
+                }
+            }
+        }
     }
-    for atom in stmt.body() {
-        match atom {
-            Brackets(lit) | Arrow(lit, _) if lit.vars().any(|var| !range.contains(var)) => {
-                return false;
+
+    // 4. Parameters must have been substituted.
+    let head = stmt.head().into_iter();
+    for atom in head.chain(stmt.bodies().into_iter().flatten()) {
+        let (Literal(lit) | Brackets(lit) | Arrow(lit, _)) = atom;
+        let rhs = if let Arrow(_, rhs) = atom {
+            Some(rhs)
+        } else {
+            None
+        };
+        for lit in std::iter::once(lit).chain(rhs) {
+            if lit
+                .terms
+                .iter()
+                .any(|term| matches!(term, TermAST::Parameter(_)))
+            {
+                return Some("a parameter isn't substituted");
             }
-            _ => {}
         }
     }
 
-    // 2. (TEMPORARY) no arrows in the head for now. If we have bandwidth we can implement a
-    //    transform to get rid of arrows in the head.
-    if let Some(head) = stmt.head()
-        && let Arrow(_, _) = head
+    // 5. The bodies of a diff must have the same variables, so their answers can be compared.
+    if let Diff(left, right) = stmt {
+        let left: BTreeSet<_> = left.iter().flat_map(AtomAST::vars).collect();
+        let right: BTreeSet<_> = right.iter().flat_map(AtomAST::vars).collect();
+        if left != right {
+            return Some("the two sides of a diff have different variables");
+        }
+    }
+
+    // 6. Built-ins can't compute a relation from itself.
+    if let Builtin(builtin) = stmt
+        && builtin.relation == builtin.source
     {
-        return false;
+        return Some("a built-in can't compute a relation from itself");
     }
 
-    // 3. (Maybe TEMPORARY?) no brackets or arrows in the body of questions for now. We just print
-    //    ground atoms and their assumption values matching a question query, and it's not obvious
-    //    what a bracket or arrow in a question means.
-    if let Question(body) = stmt {
+    // 7. The variables a question selects must appear in its body, and the variables it's ordered
+    //    by must be bound by its answers. Its hypotheses and the leaf assumption it's filtered by
+    //    must be ground.
+    if let Question(body, _, Some(select), _) = stmt {
+        let vars: BTreeSet<_> = body.iter().flat_map(AtomAST::vars).collect();
+        if select.iter().any(|var| !vars.contains(var.as_str())) {
+            return Some("a selected variable doesn't appear in the question");
+        }
+    }
+
+    if let Question(body, _, select, modifiers) = stmt {
+        let vars: BTreeSet<&str> = match select {
+            Some(select) => select.iter().map(String::as_str).collect(),
+            None => body.iter().flat_map(AtomAST::vars).collect(),
+        };
+        if modifiers
+            .order_by
+            .iter()
+            .any(|var| !vars.contains(var.as_str()))
+        {
+            return Some("a question is ordered by a variable its answers don't bind");
+        }
+        if let Some(Filter::Assuming(lit) | Filter::Excluding(lit)) = &modifiers.filter
+            && lit.vars().next().is_some()
+        {
+            return Some("a question is filtered by an assumption with variables");
+        }
+        if modifiers
+            .hypotheses
+            .iter()
+            .any(|lit| lit.vars().next().is_some())
+        {
+            return Some("a hypothesis has variables");
+        }
+    }
+
+    // 8. The columns of a declaration must have distinct names, and `@min` and `@max` columns must
+    //    hold integers. Probabilities must be between 0 and 1.
+    if let Decl(_, columns, merge) = stmt {
+        let names: BTreeSet<_> = columns.iter().map(|(name, _)| name).collect();
+        if names.len() != columns.len() {
+            return Some("two columns of a declaration have the same name");
+        }
+        if let Some(Merge::Min | Merge::Max) = merge
+            && columns.last().is_some_and(|(_, ty)| *ty != Type::Int)
+        {
+            return Some("a min or max column doesn't have type int");
+        }
+    }
+    if let Probability(_, p) = stmt
+        && !(0.0..=1.0).contains(p)
+    {
+        return Some("a probability isn't between 0 and 1");
+    }
+
+    // 9. Built-in calls (list built-ins and equalities) are only plain literals in bodies, with the
+    //    right number of arguments, and with their inputs bound by the atoms before them.
+    if let Some(head) = stmt.head()
+        && head
+            .literals()
+            .into_iter()
+            .any(|lit| CallBuiltin::of(lit).is_some())
+    {
+        return Some("a built-in call can't be derived");
+    }
+    for body in stmt.bodies() {
+        let mut bound = BTreeSet::new();
         for atom in body {
             match atom {
-                Literal(_) => {}
-                Brackets(_) | Arrow(_, _) => return false,
+                Literal(lit) => {
+                    if let Some(builtin) = CallBuiltin::of(lit) {
+                        if lit.terms.len() != builtin.arity() {
+                            return Some("a built-in call has the wrong number of arguments");
+                        }
+                        let is_bound =
+                            |term: &TermAST| term.vars().iter().all(|var| bound.contains(var));
+                        if !builtin.callable(&lit.terms, is_bound) {
+                            return Some("a built-in call's inputs aren't bound by earlier atoms");
+                        }
+                    }
+                    bound.extend(lit.vars());
+                }
+                Brackets(_) | Arrow(_, _)
+                    if atom
+                        .literals()
+                        .into_iter()
+                        .any(|lit| CallBuiltin::of(lit).is_some()) =>
+                {
+                    return Some("a built-in call is in brackets or an arrow");
+                }
+                Arrow(_, lit) => bound.extend(lit.vars()),
+                Brackets(_) => {}
+            }
+        }
+    }
+
+    None
+}
+
+// A byte range of the source a statement, literal, or term was parsed from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+}
+
+impl Span {
+    // The line and column (counted from 1, in characters) the span starts at.
+    pub fn line_column(&self, source: &str) -> (usize, usize) {
+        let before = &source[..self.start];
+        let line = before.matches('\n').count() + 1;
+        let line_start = before.rfind('\n').map_or(0, |idx| idx + 1);
+        (line, before[line_start..].chars().count() + 1)
+    }
+
+    // The whole lines of the source the span covers.
+    pub fn excerpt<'a>(&self, source: &'a str) -> &'a str {
+        let start = source[..self.start].rfind('\n').map_or(0, |idx| idx + 1);
+        let end = source[self.end..]
+            .find('\n')
+            .map_or(source.len(), |idx| self.end + idx);
+        &source[start..end]
+    }
+
+    // A message about what was parsed at this span, located like `file:line:column: message`, and
+    // followed by an excerpt of the source.
+    pub fn locate(&self, file: &str, source: &str, message: &str) -> String {
+        let (line, column) = self.line_column(source);
+        let mut located = format!("{}:{}:{}: {}", file, line, column, message);
+        for line in self.excerpt(source).lines() {
+            located.push_str(&format!("\n  | {}", line));
+        }
+        located
+    }
+}
+
+// Where an error was found in a program: the statement (counted from 1), and the span of the
+// literal or term in it the error is about, when the literal was parsed rather than generated.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Location {
+    pub statement: Option<usize>,
+    pub span: Option<Span>,
+}
+
+impl Location {
+    // The location of a literal used with some arity: its first term past the arity, if it has
+    // more terms, or else the literal.
+    pub fn past_arity(lit: &LiteralAST, arity: usize) -> Self {
+        Self {
+            statement: None,
+            span: lit.term_spans.get(arity).copied().or(lit.span),
+        }
+    }
+}
+
+// Parse a program, checking that every statement is well formed. Errors are located in the source.
+pub fn parse_checked(file: &str, source: &str) -> Result<(Vec<Span>, Vec<StatementAST>), String> {
+    let located = LocatedProgramParser::new()
+        .parse(source)
+        .map_err(|err| locate_parse_error(file, source, err))?;
+    let (spans, stmts): (Vec<_>, Vec<_>) = located.into_iter().unzip();
+    for (span, stmt) in spans.iter().zip(&stmts) {
+        if let Some(reason) = why_ill_formed(stmt) {
+            return Err(span.locate(file, source, reason));
+        }
+    }
+    Ok((spans, stmts))
+}
+
+// An error raised by an action of the grammar, like an unknown type, located at the text it was
+// raised for when the action knows it, like a number too large for a symbol.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct GrammarError {
+    pub message: &'static str,
+    pub span: Option<Span>,
+}
+
+impl GrammarError {
+    pub fn at(message: &'static str, span: Span) -> Self {
+        Self {
+            message,
+            span: Some(span),
+        }
+    }
+}
+
+impl From<&'static str> for GrammarError {
+    fn from(message: &'static str) -> Self {
+        Self {
+            message,
+            span: None,
+        }
+    }
+}
+
+impl fmt::Display for GrammarError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+// A syntax error, with the text of the token it was found at.
+pub type SyntaxError = ParseError<usize, String, GrammarError>;
+
+// A statement parsed by `RecoveringProgramParser`, or the syntax error found in its place.
+pub type RecoveredStatement = Result<(Span, StatementAST), SyntaxError>;
+
+// Parse a program, recovering from syntax errors by skipping to the end of the statement they
+// were found in, or to the end of the program. Returns the statements that parsed, and every syntax
+// error located in the source. An error the parser can't recover from (like one in a module left
+// open) ends the program.
+pub fn parse_recovering(file: &str, source: &str) -> (Vec<(Span, StatementAST)>, Vec<String>) {
+    let mut recovered = vec![];
+    // Text already parsed is blanked out, so the rest is parsed at the same offsets.
+    let mut text = source.to_string();
+    let mut skip_error = false;
+    loop {
+        let (mut more, resume) = match RecoveringProgramParser::new().parse(&text) {
+            Ok(more) => (more, None),
+            // Errors raised by grammar actions end the parse, so the statements before one are
+            // parsed again from the text before it, and parsing resumes after it. The rest of its
+            // statement is then a syntax error of its own, which isn't reported.
+            Err(ParseError::User { error }) if let Some(span) = error.span => {
+                let mut before = RecoveringProgramParser::new()
+                    .parse(&text[..span.start])
+                    .unwrap_or_default();
+                // The statement cut off by the error.
+                if matches!(before.last(), Some(Err(_))) {
+                    before.pop();
+                }
+                skip_first_error(&mut before, &mut skip_error);
+                before.push(Err(ParseError::User { error }));
+                (before, Some(span.end))
             }
+            Err(err) => (vec![Err(err.map_token(|token| token.to_string()))], None),
+        };
+        skip_first_error(&mut more, &mut skip_error);
+        recovered.append(&mut more);
+        let Some(end) = resume else {
+            break;
+        };
+        text.replace_range(..end, &" ".repeat(end));
+        skip_error = true;
+    }
+
+    let mut located = vec![];
+    let mut errors = vec![];
+    for stmt in recovered {
+        match stmt {
+            Ok(stmt) => located.push(stmt),
+            Err(err) => errors.push(locate_parse_error(file, source, err)),
         }
     }
+    (located, errors)
+}
+
+fn skip_first_error(recovered: &mut Vec<RecoveredStatement>, skip: &mut bool) {
+    if *skip && let Some(idx) = recovered.iter().position(Result::is_err) {
+        let _ = recovered.remove(idx);
+        *skip = false;
+    }
+}
 
-    true
+// Parse errors are located at the token they were found at, when there is one.
+pub fn locate_parse_error<T: fmt::Display>(
+    file: &str,
+    source: &str,
+    err: ParseError<usize, T, GrammarError>,
+) -> String {
+    match parse_error_span(&err) {
+        Some(span) => span.locate(file, source, &err.to_string()),
+        None => format!("{}: {}", file, err),
+    }
+}
+
+// The empty span at the token a parse error was found at, or the span an error raised by a grammar
+// action was raised for, if it's known.
+pub fn parse_error_span<T>(err: &ParseError<usize, T, GrammarError>) -> Option<Span> {
+    let location = match err {
+        ParseError::InvalidToken { location } | ParseError::UnrecognizedEof { location, .. } => {
+            *location
+        }
+        ParseError::UnrecognizedToken {
+            token: (start, _, _),
+            ..
+        }
+        | ParseError::ExtraToken {
+            token: (start, _, _),
+        } => *start,
+        ParseError::User { error } => return error.span,
+    };
+    Some(Span {
+        start: location,
+        end: location,
+    })
 }
 
 #[cfg(test)]
@@ -149,6 +1188,89 @@ mod tests {
         }
     }
 
+    #[test]
+    fn locate_statements() {
+        let program = "E(1, 2) :- .\n\n  P(x) :-\n    E(x, z), [A(y)].\n";
+        let parsed = LocatedProgramParser::new().parse(program).unwrap();
+        let (span, stmt) = &parsed[1];
+        assert_eq!(span.line_column(program), (3, 3));
+        assert_eq!(
+            why_ill_formed(stmt),
+            Some("a variable of a bracket or arrow isn't bound by the body")
+        );
+        assert_eq!(
+            span.locate("path.dl", program, "ill formed"),
+            "path.dl:3:3: ill formed\n  |   P(x) :-\n  |     E(x, z), [A(y)]."
+        );
+    }
+
+    #[test]
+    fn recover_from_syntax_errors() {
+        let program = "E(1, 2) :- .\nP(x :- E(x, y).\n? E(x, y).\n? P(x) :- .\nQ :- .\n";
+        let (located, errors) = parse_recovering("path.dl", program);
+        let stmts: Vec<_> = located.iter().map(|(_, stmt)| stmt.to_string()).collect();
+        assert_eq!(stmts, vec!["E(1, 2) :- .", "? E(x, y).", "Q :- ."]);
+        assert_eq!(errors.len(), 2);
+        assert!(errors[0].starts_with("path.dl:2:5: "));
+        assert!(errors[1].starts_with("path.dl:4:8: "));
+
+        let (located, errors) = parse_recovering("path.dl", "E(1, 2) :- .\nP(x");
+        assert_eq!(located.len(), 1);
+        assert_eq!(errors.len(), 1);
+
+        let (located, errors) = parse_recovering(
+            "path.dl",
+            "E(1, 2) :- .\nE(99999999999999999999) :- .\nE(2, 3) :- .\n",
+        );
+        assert_eq!(located.len(), 2);
+        assert_eq!(
+            errors,
+            vec!["path.dl:2:3: number too large\n  | E(99999999999999999999) :- ."]
+        );
+    }
+
+    #[test]
+    fn detect_assumptions() {
+        let uses = |program: &str| uses_assumptions(&ProgramParser::new().parse(program).unwrap());
+        assert!(!uses(
+            "E(1, 2) :- .\nP(x, y) :- E(x, y), x = 1.\n:- P(2, 2).\n? P(x, y)."
+        ));
+        assert!(uses("P(x) :- [A(x)]."));
+        assert!(uses("A(1) -> P(1) :- ."));
+        assert!(uses("Q :- P, A -> B."));
+        assert!(uses("?diff P(x); A(x) -> P(x)."));
+        assert!(uses("? [A] |- P."));
+        assert!(uses("? P(x) where definite."));
+        assert!(uses("?abduce P(x)."));
+        assert!(uses(".probability A(x) = 0.5."));
+    }
+
+    #[test]
+    fn qualify_modules() {
+        let program = "module graph {\n  .decl Edge(from: int, to: int).\n  Edge(1, 2) :- .\n  \
+                       Path(x, y) :- Edge(x, y), [Trusted(x)].\n  ? Path(1, y) where assuming Trusted(1).\n}\n\
+                       ? graph::Path(x, y), graph::Edge(y, z).\n";
+        let parsed = ProgramParser::new().parse(program).unwrap();
+        let stmts: Vec<_> = parsed.iter().map(ToString::to_string).collect();
+        assert_eq!(
+            stmts,
+            vec![
+                ".decl graph::Edge(from: int, to: int).",
+                "graph::Edge(1, 2) :- .",
+                "graph::Path(x, y) :- graph::Edge(x, y), [Trusted(x)].",
+                "? graph::Path(1, y) where assuming Trusted(1).",
+                "? graph::Path(x, y), graph::Edge(y, z).",
+            ]
+        );
+        assert!(parsed.iter().all(check));
+
+        let (located, errors) =
+            parse_recovering("path.dl", "module m {\n  P(1 :- .\n  Q(1) :- .\n}\n");
+        assert_eq!(errors.len(), 1);
+        assert_eq!(located[0].1.to_string(), "m::Q(1) :- .");
+        assert!(ProgramParser::new().parse("? m::P : m::P.").is_err());
+    }
+
     fn parse_and_fail_check(program: &str) {
         let parsed = ProgramParser::new().parse(program).unwrap();
         for stmt in parsed {
@@ -177,7 +1299,7 @@ P(x, z) :- E(x, y), P(y, z).
 ? P(1, 4).
 ? P(4, 1).
 "#;
-        parse_and_check(&program);
+        parse_and_check(program);
     }
 
     #[test]
@@ -199,7 +1321,7 @@ X :- .
 ? P.
 ? X.
 "#;
-        parse_and_check(&program);
+        parse_and_check(program);
     }
 
     #[test]
@@ -223,15 +1345,21 @@ Y(a, b) :- Y(b, a), Y(b, a).
 ? X(a, b).
 ? Y(a, b).
 "#;
-        parse_and_check(&program);
+        parse_and_check(program);
     }
 
     #[test]
-    fn parse_and_fail_check_range_head() {
+    fn parse_and_check_existential_head() {
         let program = r#"
 A(a, b) :- B(b).
 "#;
-        parse_and_fail_check(&program);
+        parse_and_check(program);
+        let parsed = ProgramParser::new().parse(program).unwrap();
+        let StatementAST::Rule(head, body) = &parsed[0] else {
+            panic!()
+        };
+        assert_eq!(existential_vars(head, body), BTreeSet::from(["a"]));
+        parse_and_fail_check("C(a) -> A(b) :- B(b).");
     }
 
     #[test]
@@ -239,7 +1367,7 @@ A(a, b) :- B(b).
         let program = r#"
 A(b) :- B(b), [C(b, a)].
 "#;
-        parse_and_fail_check(&program);
+        parse_and_fail_check(program);
     }
 
     #[test]
@@ -247,16 +1375,49 @@ A(b) :- B(b), [C(b, a)].
         let program = r#"
 A(b) :- C(a, b) -> B(b).
 "#;
-        parse_and_fail_check(&program);
+        parse_and_fail_check(program);
     }
 
-    // Temporary (see check()).
     #[test]
-    fn parse_and_fail_check_arrow_in_head() {
+    fn parse_and_check_arrow_in_head() {
         let program = r#"
 A(1) -> B(2) :- .
 "#;
-        parse_and_fail_check(&program);
+        parse_and_check(program);
+        let parsed = ProgramParser::new().parse(program).unwrap();
+        assert_eq!(lower_head_arrows(&parsed)[0].to_string(), "B(2) :- [A(1)].");
+    }
+
+    #[test]
+    fn parse_and_check_lists() {
+        let program = r#"
+P(x) :- L(xs), member(x, [0 | xs]).
+"#;
+        parse_and_check(program);
+        let parsed = ProgramParser::new().parse(program).unwrap();
+        assert_eq!(parsed[0].to_string(), "P(x) :- L(xs), member(x, [0 | xs]).");
+        let parsed = ProgramParser::new()
+            .parse("L([], [1, \"a\"]) :- .")
+            .unwrap();
+        assert_eq!(parsed[0].to_string(), "L([], [1, \"a\"]) :- .");
+        parse_and_fail_check("P(x) :- member(x, xs), L(xs).");
+        parse_and_fail_check("P(n) :- L(xs), length(xs).");
+        parse_and_fail_check("member(x, xs) :- L(x, xs).");
+    }
+
+    #[test]
+    fn parse_and_check_equality() {
+        let program = r#"
+P(x, y) :- E(x, z), z = pair(y, 3), x = y.
+!expect_count P(x, y) = 1.
+"#;
+        parse_and_check(program);
+        let parsed = ProgramParser::new().parse(program).unwrap();
+        assert_eq!(
+            parsed[0].to_string(),
+            "P(x, y) :- E(x, z), z = pair(y, 3), x = y."
+        );
+        parse_and_fail_check("P(x) :- x = y, E(x, y).");
     }
 
     // (Maybe) temporary (see check()).
@@ -265,7 +1426,73 @@ A(1) -> B(2) :- .
         let program = r#"
 ? [A].
 "#;
-        parse_and_fail_check(&program);
+        parse_and_fail_check(program);
+    }
+
+    #[test]
+    fn parse_and_check_builtin() {
+        let program = r#"
+.builtin Reach = transitive_closure(Edge).
+.builtin Same = scc(Edge).
+.builtin Dist = shortest_path(Weighted).
+"#;
+        parse_and_check(program);
+    }
+
+    #[test]
+    fn parse_and_check_input_output() {
+        let program = r#"
+.input E(2).
+.output P.
+P(x, y) :- E(x, y).
+"#;
+        parse_and_check(program);
+    }
+
+    #[test]
+    fn parse_and_check_constraint() {
+        let program = r#"
+:- A, [B].
+:- E(x, y), E(y, x).
+"#;
+        parse_and_check(program);
+    }
+
+    #[test]
+    fn parse_and_fail_check_range_constraint() {
+        let program = r#"
+:- E(x, y), [F(z)].
+"#;
+        parse_and_fail_check(program);
+    }
+
+    #[test]
+    fn parse_and_fail_check_builtin_self() {
+        let program = r#"
+.builtin Edge = transitive_closure(Edge).
+"#;
+        parse_and_fail_check(program);
+    }
+
+    #[test]
+    fn substitute_and_check_params() {
+        let program = r#"
+P(x, $dst) :- E($src, x).
+? P(x, $dst), Q($src).
+"#;
+        let parsed = ProgramParser::new().parse(program).unwrap();
+        assert!(!check(&parsed[0]));
+
+        let params = BTreeMap::from([("src".to_string(), 3), ("dst".to_string(), 4)]);
+        let substituted = substitute_params(&parsed, &params).unwrap();
+        let StatementAST::Rule(AtomAST::Literal(head), _) = &substituted[0] else {
+            panic!()
+        };
+        assert_eq!(head.terms[1].try_cons(), Some(4));
+        assert!(check(&substituted[0]));
+
+        let params = BTreeMap::from([("src".to_string(), 3)]);
+        assert!(substitute_params(&parsed, &params).is_err());
     }
 
     // (Maybe) temporary (see check()).
@@ -274,6 +1501,141 @@ A(1) -> B(2) :- .
         let program = r#"
 ? A -> B.
 "#;
-        parse_and_fail_check(&program);
+        parse_and_fail_check(program);
+    }
+
+    #[test]
+    fn display_round_trip() {
+        let program = r#"
+.pragma top_down.
+.deny unused_relation.
+.input E(2).
+.builtin R = transitive_closure(E).
+[A(x)] :- E(x, 1), B -> C($p).
+:- A(1).
+? R(x, y), E(y, 3).
+?diff R(x, y); E(x, y).
+!expect_count E(x, y) = 2.
+?td E(x, y).
+.decl E(from: int, to: int).
+?magic x : E(x, y), R(y, z).
+? x : E(x, y) order by x limit 5.
+? E(x, y) where excluding A(1) order by y.
+? x : [A(1)], [B] |- E(x, y).
+.weight A(x, 2) = 3.
+.probability A(1, y) = 0.25.
+?abduce E(x, 1).
+"#;
+        let parsed = ProgramParser::new().parse(program).unwrap();
+        let displayed: Vec<_> = parsed.iter().map(ToString::to_string).collect();
+        let reparsed = ProgramParser::new().parse(&displayed.join("\n")).unwrap();
+        let redisplayed: Vec<_> = reparsed.iter().map(ToString::to_string).collect();
+        assert_eq!(displayed, redisplayed);
+        assert_eq!(displayed[4], "[A(x)] :- E(x, 1), B -> C($p).");
+        assert_eq!(displayed[10], ".decl E(from: int, to: int).");
+        assert_eq!(displayed[11], "?magic x : E(x, y), R(y, z).");
+        assert_eq!(displayed[12], "? x : E(x, y) order by x limit 5.");
+        assert_eq!(displayed[13], "? E(x, y) where excluding A(1) order by y.");
+        assert_eq!(displayed[14], "? x : [A(1)], [B] |- E(x, y).");
+        assert_eq!(displayed[15], ".weight A(x, 2) = 3.");
+        assert_eq!(displayed[16], ".probability A(1, y) = 0.25.");
+        assert_eq!(displayed[17], "?abduce E(x, 1).");
+        assert!(ProgramParser::new().parse("? E(x) : E(x).").is_err());
+    }
+
+    #[test]
+    fn parse_and_check_decl() {
+        let parsed = ProgramParser::new()
+            .parse(".decl Edge(from: int, to: int).")
+            .unwrap();
+        assert!(check(&parsed[0]));
+        assert!(ProgramParser::new().parse(".decl E(x: str).").is_err());
+        let parsed = ProgramParser::new()
+            .parse(".decl E(x: int, x: int).")
+            .unwrap();
+        assert!(!check(&parsed[0]));
+    }
+
+    #[test]
+    fn parse_and_check_lattice_decl() {
+        let parsed = ProgramParser::new()
+            .parse(".decl Dist(node: int, @min cost: int).")
+            .unwrap();
+        assert!(check(&parsed[0]));
+        assert!(matches!(
+            &parsed[0],
+            StatementAST::Decl(_, _, Some(Merge::Min))
+        ));
+        assert_eq!(
+            parsed[0].to_string(),
+            ".decl Dist(node: int, @min cost: int)."
+        );
+        let parsed = ProgramParser::new()
+            .parse(".decl Best(x: int, @join y: symbol).")
+            .unwrap();
+        assert!(
+            matches!(&parsed[0], StatementAST::Decl(_, _, Some(Merge::Lattice(name))) if name == "join")
+        );
+        assert!(
+            ProgramParser::new()
+                .parse(".decl Dist(@min cost: int, node: int).")
+                .is_err()
+        );
+        let parsed = ProgramParser::new()
+            .parse(".decl Dist(node: int, @max name: symbol).")
+            .unwrap();
+        assert!(!check(&parsed[0]));
+    }
+
+    #[test]
+    fn parse_and_check_diff() {
+        parse_and_check("?diff P(x), Q(x, y); R(y, x).");
+        parse_and_fail_check("?diff P(x), Q(x, y); R(x).");
+    }
+
+    #[test]
+    fn parse_and_check_order_by() {
+        parse_and_check("? E(x, y) order by y, x limit 10.");
+        parse_and_check("? E(x, y) limit 0.");
+        parse_and_fail_check("? E(x, y) order by z.");
+        parse_and_fail_check("? x : E(x, y) order by y.");
+        assert!(ProgramParser::new().parse("? E(x, y) order by.").is_err());
+    }
+
+    #[test]
+    fn parse_and_check_filters() {
+        parse_and_check("? E(x, y) where definite.");
+        parse_and_check("? E(x, y) where assuming A(1, \"a\") limit 1.");
+        parse_and_fail_check("? E(x, y) where excluding A(x).");
+        assert!(
+            ProgramParser::new()
+                .parse("? E(x, y) where maybe.")
+                .is_err()
+        );
+        assert!(
+            ProgramParser::new()
+                .parse("? E(x, y) where definite A.")
+                .is_err()
+        );
+        assert!(
+            ProgramParser::new()
+                .parse("? E(x, y) where assuming.")
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn parse_and_check_probability() {
+        parse_and_check(".probability A(1) = 0.5.");
+        parse_and_check(".probability A(x) = 1.");
+        parse_and_fail_check(".probability A(x) = 1.5.");
+    }
+
+    #[test]
+    fn parse_and_check_hypotheses() {
+        parse_and_check("? [A()], [B(1)] |- P(x).");
+        parse_and_check("? [A] |- E(x), P(x).");
+        parse_and_fail_check("? [A(x)] |- P(x).");
+        assert!(ProgramParser::new().parse("? A |- P(x).").is_err());
     }
 }
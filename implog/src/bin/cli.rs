@@ -1,19 +1,914 @@
-use std::io::{Read, Result, stdin};
+use std::collections::{BTreeMap, BTreeSet};
+use std::env::args;
+use std::fs::{metadata, read_to_string, write};
+use std::io::{BufRead, Error, Read, Result, Write, stdin, stdout};
+use std::path::{Path, PathBuf};
+use std::process::exit;
+use std::sync::{Mutex, Once};
+use std::thread::sleep;
+use std::time::{Duration, Instant, SystemTime};
 
-use implog::ast::check;
-use implog::grammar::ProgramParser;
-//use implog::interpret::Environment;
+use implog::ast::{
+    AtomAST, Location, Span, StatementAST, locate_parse_error, lower_head_arrows,
+    substitute_params, uses_assumptions, why_ill_formed,
+};
+use implog::codegen::compile;
+use implog::fingerprint::fingerprint;
+use implog::grammar::LocatedProgramParser;
+use implog::include::{Program, expand};
+use implog::interpret::{
+    CancellationToken, Consistency, Diff, Environment, InterpretError, Progress, Strategy,
+    Verbosity,
+};
+use implog::json::{ToJson, answers_to_json, relation_diff_to_json};
+use implog::lint::{Level, lint};
+use implog::pretty::pretty;
+use implog::representation::{
+    Assumption, BooleanAssumption, DNFAssumption, GroundTuple, Symbol, TopKAssumption,
+    TropicalAssumption,
+};
+use implog::souffle::lower;
+use implog::souffle_grammar::ProgramParser as SouffleParser;
+use implog::value::{Value, parse_value};
+
+enum Format {
+    Text,
+    Json,
+}
+
+enum Mode {
+    Interpret,
+    Lint,
+    Fingerprint,
+    Desugar,
+    Compile,
+    Fmt,
+    Check,
+    // The files of the old and new programs to compare.
+    Diff(PathBuf, PathBuf),
+}
+
+enum Semiring {
+    Dnf,
+    Boolean,
+    Tropical,
+    TopK,
+    // The boolean semiring for programs that don't use assumptions (see `uses_assumptions`), and
+    // DNFs otherwise. Statements streamed or watched may start using assumptions later, and
+    // programs diffed may differ in whether they do, so those always use DNFs.
+    Auto,
+}
+
+// How many conjunctions values of the top-k semiring keep.
+const TOP_K: usize = 3;
+
+struct Options {
+    format: Format,
+    strategy: Strategy,
+    simplify: bool,
+    rank: bool,
+    sort: bool,
+    probabilities: bool,
+    consistency: Consistency,
+    facts_dir: Option<PathBuf>,
+    output_dir: Option<PathBuf>,
+    index_advice: bool,
+    verbosity: Verbosity,
+    threads: usize,
+    max_iterations: Option<usize>,
+    assumption_budget: Option<usize>,
+    timeout: Option<Duration>,
+    progress: bool,
+    why_not: bool,
+    stats: bool,
+    trace: bool,
+    time: bool,
+    explain: Option<(String, GroundTuple)>,
+    all_proofs: bool,
+    depgraph: Option<PathBuf>,
+    provenance: Option<PathBuf>,
+    keep_going: bool,
+    stream: bool,
+    watch: Option<PathBuf>,
+}
+
+enum Syntax {
+    Implog,
+    Souffle,
+}
 
 pub fn main() -> Result<()> {
+    let mut options = Options {
+        format: Format::Text,
+        strategy: Strategy::BottomUp,
+        simplify: false,
+        rank: false,
+        sort: false,
+        probabilities: false,
+        consistency: Consistency::Exclude,
+        facts_dir: None,
+        output_dir: None,
+        index_advice: false,
+        verbosity: Verbosity::Count,
+        threads: 1,
+        max_iterations: None,
+        assumption_budget: None,
+        timeout: None,
+        progress: false,
+        why_not: false,
+        stats: false,
+        trace: false,
+        time: false,
+        explain: None,
+        all_proofs: false,
+        depgraph: None,
+        provenance: None,
+        keep_going: false,
+        stream: false,
+        watch: None,
+    };
+    let mut syntax = Syntax::Implog;
+    let mut semiring = Semiring::Dnf;
+    let mut mode = Mode::Interpret;
+    let mut params = BTreeMap::new();
+    let mut args = args().skip(1).peekable();
+    // `implog-cli lint` only runs the lint pass, `implog-cli fingerprint` only prints the
+    // program's fingerprint, and `implog-cli desugar` only prints the program as evaluated (with
+    // parameters substituted, lowered from Soufflé syntax, and with arrows in heads lowered by
+    // `lower_head_arrows`), without interpreting the program.
+    // `implog-cli compile` prints a Rust module evaluating the program's rules (see `codegen`).
+    // `implog-cli fmt` prints the program formatted canonically (see `pretty`). `implog-cli diff
+    // old.dl new.dl` interprets two programs, and compares the tuples of the relations they output
+    // or ask questions about.
+    if args.next_if(|arg| arg == "lint").is_some() {
+        mode = Mode::Lint;
+    } else if args.next_if(|arg| arg == "fingerprint").is_some() {
+        mode = Mode::Fingerprint;
+    } else if args.next_if(|arg| arg == "desugar").is_some() {
+        mode = Mode::Desugar;
+    } else if args.next_if(|arg| arg == "compile").is_some() {
+        mode = Mode::Compile;
+    } else if args.next_if(|arg| arg == "fmt").is_some() {
+        mode = Mode::Fmt;
+    } else if args.next_if(|arg| arg == "diff").is_some() {
+        let (Some(old), Some(new)) = (args.next(), args.next()) else {
+            return Err(Error::other("diff expects an old and a new program file"));
+        };
+        mode = Mode::Diff(PathBuf::from(old), PathBuf::from(new));
+    }
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--format" => match args.next().as_deref() {
+                Some("text") => options.format = Format::Text,
+                Some("json") => options.format = Format::Json,
+                _ => return Err(Error::other("--format expects `text` or `json`")),
+            },
+            "--syntax" => match args.next().as_deref() {
+                Some("implog") => syntax = Syntax::Implog,
+                Some("souffle") => syntax = Syntax::Souffle,
+                _ => return Err(Error::other("--syntax expects `implog` or `souffle`")),
+            },
+            "--semiring" => match args.next().as_deref() {
+                Some("dnf") => semiring = Semiring::Dnf,
+                Some("boolean") => semiring = Semiring::Boolean,
+                Some("tropical") => semiring = Semiring::Tropical,
+                Some("top-k") => semiring = Semiring::TopK,
+                Some("auto") => semiring = Semiring::Auto,
+                // Probabilities are computed from DNF values (see `.probability`).
+                Some("probability") => {
+                    semiring = Semiring::Dnf;
+                    options.probabilities = true;
+                }
+                _ => {
+                    return Err(Error::other(
+                        "--semiring expects `dnf`, `boolean`, `tropical`, `top-k`, `auto`, or \
+                         `probability`",
+                    ));
+                }
+            },
+            "--magic" => options.strategy = Strategy::Magic,
+            "--top-down" => options.strategy = Strategy::TopDown,
+            "--simplify" => options.simplify = true,
+            // Ranks answers by the total weight of their cheapest assumptions (see `.weight`).
+            "--rank" => options.rank = true,
+            // Sorts answers by the values they bind, so runs can be diffed.
+            "--sort" => options.sort = true,
+            // Keeps answers entailing a violated constraint, marking them instead of excluding them.
+            "--flag-inconsistent" => options.consistency = Consistency::Flag,
+            "--index-advice" => options.index_advice = true,
+            "--why-not" => options.why_not = true,
+            "--stats" => options.stats = true,
+            "--trace" => options.trace = true,
+            // Reports how long parsing and evaluation took, and the iterations of each question.
+            "--time" => options.time = true,
+            "--progress" => options.progress = true,
+            // Reports every syntax error and ill-formed statement, instead of only the first, and
+            // interprets the other statements.
+            "--keep-going" => options.keep_going = true,
+            // Checks the program without interpreting it: reports every syntax error, ill-formed
+            // statement, and lint diagnostic, and fails if any of them is an error.
+            "--check" => {
+                mode = Mode::Check;
+                options.keep_going = true;
+            }
+            // Interprets statements as they arrive on stdin, answering each question once it's read,
+            // so implog can be driven as a coprocess.
+            "--stream" => options.stream = true,
+            // Interprets a program file, then interprets it again whenever it (or a file it
+            // includes) changes, printing the fresh answers.
+            "--watch" => match args.next() {
+                Some(file) => options.watch = Some(PathBuf::from(file)),
+                None => return Err(Error::other("--watch expects a file")),
+            },
+            // Timeouts are in seconds, and cover interpreting the whole program.
+            "--timeout" => match args.next().and_then(|secs| secs.parse().ok()) {
+                Some(secs) if secs >= 0.0 => options.timeout = Some(Duration::from_secs_f64(secs)),
+                _ => return Err(Error::other("--timeout expects a number of seconds")),
+            },
+            // Splits joins over large tables across threads.
+            "--threads" => match args.next().and_then(|threads| threads.parse().ok()) {
+                Some(threads) if threads > 0 => options.threads = threads,
+                _ => return Err(Error::other("--threads expects a positive number")),
+            },
+            "--max-iterations" => match args.next().and_then(|limit| limit.parse().ok()) {
+                Some(limit) => options.max_iterations = Some(limit),
+                None => return Err(Error::other("--max-iterations expects a number")),
+            },
+            // Widens assumption values of derived tuples that grow beyond a size, making answers
+            // approximate.
+            "--assumption-budget" => match args.next().and_then(|size| size.parse().ok()) {
+                Some(size) => options.assumption_budget = Some(size),
+                None => return Err(Error::other("--assumption-budget expects a number")),
+            },
+            // `-v` prints the bindings of each answer, and `-vv` also prints their assumptions.
+            "-v" => options.verbosity = Verbosity::Bindings,
+            "-vv" => options.verbosity = Verbosity::Assumptions,
+            "--explain" => options.explain = Some(tuple_arg(args.next())?),
+            "--all-proofs" => options.all_proofs = true,
+            "--param" => {
+                let (name, value) = param_arg(args.next())?;
+                params.insert(name, value);
+            }
+            "--facts-dir" => {
+                options.facts_dir = Some(PathBuf::from(dir_arg(&arg, args.next())?));
+            }
+            "--output-dir" => {
+                options.output_dir = Some(PathBuf::from(dir_arg(&arg, args.next())?));
+            }
+            // Writes the program's dependency graph as a DOT file, for rendering with Graphviz.
+            "--emit-depgraph" => match args.next() {
+                Some(file) => options.depgraph = Some(PathBuf::from(file)),
+                None => return Err(Error::other("--emit-depgraph expects a file")),
+            },
+            // Writes the derivation hypergraph of the model as JSON to files ending in `.json`, and
+            // as a DOT file otherwise.
+            "--emit-provenance" => match args.next() {
+                Some(file) => options.provenance = Some(PathBuf::from(file)),
+                None => return Err(Error::other("--emit-provenance expects a file")),
+            },
+            _ => return Err(Error::other(format!("unknown argument `{}`", arg))),
+        }
+    }
+
+    if options.stream {
+        if matches!(syntax, Syntax::Souffle) || !matches!(mode, Mode::Interpret) {
+            return Err(Error::other("--stream only interprets implog syntax"));
+        }
+        return match semiring {
+            Semiring::Dnf | Semiring::Auto => stream::<DNFAssumption>(&options, &params),
+            Semiring::Boolean => stream::<BooleanAssumption>(&options, &params),
+            Semiring::Tropical => stream::<TropicalAssumption>(&options, &params),
+            Semiring::TopK => stream::<TopKAssumption<TOP_K>>(&options, &params),
+        };
+    }
+
+    if let Mode::Diff(old, new) = &mode {
+        if matches!(syntax, Syntax::Souffle) {
+            return Err(Error::other("diff only compares implog programs"));
+        }
+        return match semiring {
+            Semiring::Dnf | Semiring::Auto => diff::<DNFAssumption>(old, new, &options, &params),
+            Semiring::Boolean => diff::<BooleanAssumption>(old, new, &options, &params),
+            Semiring::Tropical => diff::<TropicalAssumption>(old, new, &options, &params),
+            Semiring::TopK => diff::<TopKAssumption<TOP_K>>(old, new, &options, &params),
+        };
+    }
+    if let Some(file) = &options.watch {
+        if matches!(syntax, Syntax::Souffle) || !matches!(mode, Mode::Interpret) {
+            return Err(Error::other("--watch only interprets implog syntax"));
+        }
+        return match semiring {
+            Semiring::Dnf | Semiring::Auto => watch::<DNFAssumption>(file, &options, &params),
+            Semiring::Boolean => watch::<BooleanAssumption>(file, &options, &params),
+            Semiring::Tropical => watch::<TropicalAssumption>(file, &options, &params),
+            Semiring::TopK => watch::<TopKAssumption<TOP_K>>(file, &options, &params),
+        };
+    }
+
     let mut program = String::new();
     stdin().read_to_string(&mut program)?;
-    let ast = ProgramParser::new().parse(&program).unwrap();
-    for stmt in &ast {
-        assert!(check(stmt));
+    let start = Instant::now();
+    // Statements parsed from Soufflé syntax have no spans, since lowering rewrites them. Files
+    // included by programs read from stdin are found relative to the working directory.
+    let mut failed = false;
+    let (ast, mut sources, input_facts) = match syntax {
+        Syntax::Implog => {
+            let (mut sources, errors) = expand(STDIN, Path::new("."), program.clone());
+            for error in &errors {
+                eprintln!("{}", error);
+            }
+            failed |= !errors.is_empty();
+            if failed && !options.keep_going {
+                exit(1);
+            }
+            (std::mem::take(&mut sources.stmts), Some(sources), vec![])
+        }
+        Syntax::Souffle => {
+            let parsed = SouffleParser::new()
+                .parse(&program)
+                .unwrap_or_else(|err| fail(&locate_parse_error(STDIN, &program, err)));
+            let (ast, input_facts) = lower(&parsed).map_err(Error::other)?;
+            (ast, None, input_facts)
+        }
+    };
+    if options.time {
+        eprintln!("parse: {:?}", start.elapsed());
+    }
+    // Programs are formatted as written, before parameters are substituted.
+    // Included files are formatted on their own, so programs including others can't be.
+    if let Mode::Fmt = mode {
+        let Some(sources) = &sources else {
+            return Err(Error::other("fmt only formats implog syntax"));
+        };
+        if sources.files.len() > 1 {
+            return Err(Error::other("fmt doesn't format programs with includes"));
+        }
+        let spans: Vec<_> = sources.origins.iter().map(|(_, span)| *span).collect();
+        print!("{}", pretty(&program, &spans, &ast));
+        return Ok(());
+    }
+    let mut ast = substitute_params(&ast, &params).map_err(Error::other)?;
+    let ill_formed: Vec<_> = ast.iter().map(|stmt| why_ill_formed(stmt)).collect();
+    for (idx, reason) in ill_formed.iter().enumerate() {
+        if let Some(reason) = reason {
+            eprintln!("{}", locate(sources.as_ref(), idx, reason));
+        }
     }
+    if ill_formed.iter().any(Option::is_some) {
+        failed = true;
+        if !options.keep_going {
+            exit(1);
+        }
+        let mut well_formed = ill_formed.iter().map(Option::is_none);
+        ast.retain(|_| well_formed.next().unwrap());
+        if let Some(sources) = &mut sources {
+            let mut well_formed = ill_formed.iter().map(Option::is_none);
+            sources.origins.retain(|_| well_formed.next().unwrap());
+        }
+    }
+    let locate = |err: &InterpretError| locate_error(sources.as_ref(), STDIN, err);
+
+    match mode {
+        Mode::Interpret => {}
+        Mode::Fmt | Mode::Diff(_, _) => unreachable!(),
+        Mode::Check => {
+            let diags = lint(&ast);
+            for diag in &diags {
+                match options.format {
+                    Format::Text => eprintln!("{}", diag),
+                    Format::Json => eprintln!("{}", diag.to_json()),
+                }
+            }
+            if failed || diags.iter().any(|diag| diag.level == Level::Deny) {
+                exit(1);
+            }
+            return Ok(());
+        }
+        Mode::Lint => {
+            let diags = lint(&ast);
+            for diag in &diags {
+                match options.format {
+                    Format::Text => println!("{}", diag),
+                    Format::Json => println!("{}", diag.to_json()),
+                }
+            }
+            if diags.iter().any(|diag| diag.level == Level::Deny) {
+                exit(1);
+            }
+            return Ok(());
+        }
+        Mode::Fingerprint => {
+            println!("{:016x}", fingerprint(&ast));
+            return Ok(());
+        }
+        Mode::Desugar => {
+            for stmt in &lower_head_arrows(&ast) {
+                println!("{}", stmt);
+            }
+            return Ok(());
+        }
+        Mode::Compile => {
+            print!("{}", compile(&ast).map_err(Error::other)?);
+            return Ok(());
+        }
+    }
+
+    let (ast, facts) = (ast.as_slice(), input_facts.as_slice());
+    match semiring {
+        Semiring::Auto if !uses_assumptions(ast) => {
+            interpret::<BooleanAssumption>(ast, facts, &options, &locate)
+        }
+        Semiring::Dnf | Semiring::Auto => interpret::<DNFAssumption>(ast, facts, &options, &locate),
+        Semiring::Boolean => interpret::<BooleanAssumption>(ast, facts, &options, &locate),
+        Semiring::Tropical => interpret::<TropicalAssumption>(ast, facts, &options, &locate),
+        Semiring::TopK => interpret::<TopKAssumption<TOP_K>>(ast, facts, &options, &locate),
+    }
+}
 
-    //let mut env = Environment::<DNFAssumption>::new(interner);
-    //env.interpret(&ast);
+// Programs are read from stdin, so that's the file errors are located in.
+const STDIN: &str = "<stdin>";
+
+// Locate a message about the statement at an index (counted from 0) in the program, by its span if
+// it has one.
+fn locate(sources: Option<&Program>, idx: usize, message: &str) -> String {
+    match sources {
+        Some(sources) => sources.locate(idx, message),
+        None => format!("{}: statement {}: {}", STDIN, idx + 1, message),
+    }
+}
+
+// Locate an error interpreting the program: at the literal or term it's about, if known, or else at
+// its statement, if it has one.
+fn locate_error(sources: Option<&Program>, name: &str, err: &InterpretError) -> String {
+    let message = err.to_string();
+    match (sources, err.location()) {
+        (
+            Some(sources),
+            Some(Location {
+                statement: Some(statement),
+                span,
+            }),
+        ) => sources.locate_at(statement - 1, span, &message),
+        (
+            None,
+            Some(Location {
+                statement: Some(statement),
+                ..
+            }),
+        ) => {
+            format!("{}: statement {}: {}", STDIN, statement, message)
+        }
+        _ => format!("{}: {}", name, message),
+    }
+}
+
+// Locate an error interpreting the program.
+type Locate<'a> = dyn Fn(&InterpretError) -> String + 'a;
+
+fn environment<A: Assumption>(options: &Options) -> Environment<A> {
+    let mut env = Environment::<A>::new();
+    env.set_strategy(options.strategy);
+    env.set_simplify(options.simplify);
+    env.set_rank(options.rank);
+    env.set_sort_answers(options.sort);
+    env.set_record_provenance(options.provenance.is_some());
+    env.set_probabilities(options.probabilities);
+    env.set_consistency(options.consistency);
+    env.set_verbosity(options.verbosity);
+    env.set_why_not(options.why_not);
+    env.set_stats(options.stats);
+    env.set_index_advice(options.index_advice);
+    env.set_threads(options.threads);
+    if let Some(limit) = options.max_iterations {
+        env.set_max_iterations(limit);
+    }
+    if let Some(size) = options.assumption_budget {
+        env.set_assumption_budget(size);
+    }
+    if let Some(timeout) = options.timeout {
+        env.set_deadline(Instant::now() + timeout);
+    }
+    if options.progress {
+        env.set_progress(|progress| match progress {
+            Progress::Iteration {
+                iteration,
+                changed,
+                tuples,
+            } => eprintln!(
+                "iteration {}: {} tuples changed, {} tuples",
+                iteration, changed, tuples
+            ),
+            Progress::Fixpoint { iterations, tuples } => eprintln!(
+                "fixpoint after {} iterations: {} tuples",
+                iterations, tuples
+            ),
+        });
+    }
+    // Traces log which rules fired in each iteration, and which values they changed.
+    if options.trace {
+        env.set_trace(|event| eprintln!("{}", event));
+    }
+    if let Some(dir) = &options.facts_dir {
+        env.set_facts_dir(dir);
+    }
+    if let Some(dir) = &options.output_dir {
+        env.set_output_dir(dir);
+    }
+    env
+}
+
+// Facts written inline for the input relations of Soufflé programs are added before the program is
+// interpreted, so they hold alongside the facts loaded for those relations.
+fn interpret<A: Assumption + ToJson>(
+    ast: &[StatementAST],
+    input_facts: &[(String, GroundTuple)],
+    options: &Options,
+    locate: &Locate,
+) -> Result<()> {
+    let mut env = environment::<A>(options);
+    stop_on_interrupt(env.cancellation_token());
+    for (relation, tuple) in input_facts {
+        if let Err(err) = env.add_fact(relation, tuple.clone(), A::one()) {
+            fail(&format!("{}: {}", STDIN, err));
+        }
+    }
+    // Answers to the questions before a failed expectation are still printed. Diagnostics go to
+    // stderr, so stdout only holds answers.
+    let start = Instant::now();
+    let mut result = env.interpret(ast).map(|_| ());
+    if options.time {
+        for timing in env.timings() {
+            eprintln!("{}", timing);
+        }
+        eprintln!("evaluation: {:?}", start.elapsed());
+    }
+    if let Some(file) = &options.depgraph {
+        write(file, env.to_dot())?;
+    }
+    if let (Some(file), Some(provenance)) = (&options.provenance, env.provenance()) {
+        match file.extension().is_some_and(|ext| ext == "json") {
+            true => write(file, provenance.to_json())?,
+            false => write(file, provenance.to_dot())?,
+        }
+    }
+    for diag in env.diagnostics() {
+        match options.format {
+            Format::Text => eprintln!("{}", diag),
+            Format::Json => eprintln!("{}", diag.to_json()),
+        }
+    }
+    print_answers(&env, options);
+    if let (Ok(()), Some((relation, tuple))) = (&result, &options.explain) {
+        result = env
+            .explain(relation, tuple, options.all_proofs)
+            .map(|proofs| {
+                for proof in proofs {
+                    match options.format {
+                        Format::Text => print!("{}", proof),
+                        Format::Json => println!("{}", proof.to_json()),
+                    }
+                }
+            });
+    }
+    if options.index_advice {
+        for advice in env.index_advice() {
+            eprintln!("{}", advice);
+        }
+    }
+    // The output relations of an evaluation stopped by Ctrl-C are dumped from the partial model.
+    if let Err(InterpretError::Stopped { .. }) = &result {
+        env.dump_outputs()?;
+    }
+    if let Err(err) = &result {
+        fail(&locate(err))
+    }
 
     Ok(())
 }
+
+// Interpret statements as lines arrive on stdin. Lines are read until they hold whole statements,
+// which are then interpreted one at a time, and the answers to each are printed right away. Errors
+// are reported without stopping, so later statements are still interpreted.
+fn stream<A: Assumption + ToJson>(
+    options: &Options,
+    params: &BTreeMap<String, Symbol>,
+) -> Result<()> {
+    let mut env = environment::<A>(options);
+    let mut text = String::new();
+    // Where the statements not yet parsed start in the text read so far.
+    let mut start = 0;
+    for line in stdin().lock().lines() {
+        text.push_str(&line?);
+        text.push('\n');
+        let located = match LocatedProgramParser::new().parse(&text[start..]) {
+            Ok(located) => located,
+            Err(lalrpop_util::ParseError::UnrecognizedEof { .. }) => continue,
+            Err(err) => {
+                let err = err.map_location(|location| location + start);
+                eprintln!("{}", locate_parse_error(STDIN, &text, err));
+                start = text.len();
+                continue;
+            }
+        };
+        for (span, stmt) in located {
+            let span_of_stmt = Span {
+                start: span.start + start,
+                end: span.end + start,
+            };
+            let locate = |message: &str| span_of_stmt.locate(STDIN, &text, message);
+            let stmt = match substitute_params(&[stmt], params) {
+                Ok(mut stmts) => stmts.remove(0),
+                Err(err) => {
+                    eprintln!("{}", locate(&err));
+                    continue;
+                }
+            };
+            if let Some(reason) = why_ill_formed(&stmt) {
+                eprintln!("{}", locate(reason));
+                continue;
+            }
+            let result = env.feed_statement(&stmt).map(|_| ());
+            print_answers(&env, options);
+            stdout().flush()?;
+            // Spans in the statement are relative to where it was parsed from.
+            if let Err(err) = result {
+                let span = err.location().and_then(|location| location.span);
+                let span = span.map_or(span_of_stmt, |span| Span {
+                    start: span.start + start,
+                    end: span.end + start,
+                });
+                eprintln!("{}", span.locate(STDIN, &text, &err.to_string()));
+            }
+        }
+        start = text.len();
+    }
+    if !text[start..].trim().is_empty() {
+        eprintln!("{}: the last statement is incomplete", STDIN);
+    }
+    Ok(())
+}
+
+// Interpret a program file whenever it or a file it includes changes, which is checked for a few
+// times a second. When the statements of the program were only appended to, and the last program
+// was interpreted without errors, only the new statements are interpreted (see `feed_statement`),
+// so the model computed so far is reused. Otherwise, the program is interpreted from scratch.
+// Programs with errors are reported and not interpreted, until they change again.
+fn watch<A: Assumption + ToJson>(
+    file: &Path,
+    options: &Options,
+    params: &BTreeMap<String, Symbol>,
+) -> Result<()> {
+    let modified = |path: &Path| metadata(path).and_then(|meta| meta.modified()).ok();
+    let mut env = environment::<A>(options);
+    // The statements interpreted so far, printed, and the files read, with when they were modified.
+    let mut interpreted: Vec<String> = vec![];
+    let mut watched: Vec<(PathBuf, Option<SystemTime>)> = vec![];
+    loop {
+        if !watched.is_empty() && watched.iter().all(|(path, time)| modified(path) == *time) {
+            sleep(Duration::from_millis(200));
+            continue;
+        }
+        let name = file.display().to_string();
+        let dir = file.parent().unwrap_or(Path::new("."));
+        if !watched.is_empty() {
+            eprintln!("{} changed", name);
+        }
+        watched = vec![(file.to_path_buf(), modified(file))];
+        let text = match read_to_string(file) {
+            Ok(text) => text,
+            Err(err) => {
+                eprintln!("can't read {}: {}", name, err);
+                continue;
+            }
+        };
+        let (sources, mut errors) = expand(&name, dir, text);
+        for source in &sources.files[1..] {
+            let path = PathBuf::from(&source.name);
+            watched.push((path.clone(), modified(&path)));
+        }
+        let stmts = substitute_params(&sources.stmts, params).unwrap_or_else(|err| {
+            errors.push(format!("{}: {}", name, err));
+            vec![]
+        });
+        for (idx, stmt) in stmts.iter().enumerate() {
+            if let Some(reason) = why_ill_formed(stmt) {
+                errors.push(sources.locate(idx, reason));
+            }
+        }
+        if !errors.is_empty() {
+            for error in &errors {
+                eprintln!("{}", error);
+            }
+            continue;
+        }
+
+        let printed: Vec<_> = stmts.iter().map(ToString::to_string).collect();
+        let appended = !interpreted.is_empty() && printed.starts_with(&interpreted);
+        let from = if appended { interpreted.len() } else { 0 };
+        let mut result = Ok(());
+        if appended {
+            for stmt in &stmts[from..] {
+                result = env.feed_statement(stmt).map(|_| ());
+                print_answers(&env, options);
+                if result.is_err() {
+                    break;
+                }
+            }
+        } else {
+            result = env.interpret(&stmts).map(|_| ());
+            print_answers(&env, options);
+        }
+        stdout().flush()?;
+        interpreted = printed;
+        match result {
+            Ok(()) => {}
+            Err(err) => {
+                eprintln!("{}", locate_error(Some(&sources), &name, &err));
+                interpreted.clear();
+            }
+        }
+    }
+}
+
+// Interpret an old and a new program, and report how the tuples of each relation either outputs or
+// asks a question about changed: which were added, which were removed, and which hold under a
+// different assumption value. `-v` lists the tuples, and `-vv` their assumption values too. Like
+// diff(1), exits with 1 when anything changed.
+fn diff<A: Assumption + ToJson>(
+    old: &Path,
+    new: &Path,
+    options: &Options,
+    params: &BTreeMap<String, Symbol>,
+) -> Result<()> {
+    let run = |file: &Path| -> Result<(Vec<StatementAST>, Environment<A>)> {
+        let name = file.display().to_string();
+        let text = read_to_string(file)?;
+        let (sources, errors) = expand(&name, file.parent().unwrap_or(Path::new(".")), text);
+        let mut failed = !errors.is_empty();
+        for error in &errors {
+            eprintln!("{}", error);
+        }
+        let stmts = substitute_params(&sources.stmts, params).map_err(Error::other)?;
+        for (idx, stmt) in stmts.iter().enumerate() {
+            if let Some(reason) = why_ill_formed(stmt) {
+                eprintln!("{}", sources.locate(idx, reason));
+                failed = true;
+            }
+        }
+        if failed {
+            exit(1);
+        }
+        let mut env = environment::<A>(options);
+        if let Err(err) = env.interpret(&stmts) {
+            fail(&locate_error(Some(&sources), &name, &err));
+        }
+        Ok((stmts, env))
+    };
+    let (old_stmts, old) = run(old)?;
+    let (new_stmts, new) = run(new)?;
+
+    let mut relations = BTreeSet::new();
+    for stmt in old_stmts.iter().chain(&new_stmts) {
+        match stmt {
+            StatementAST::Output(relation) => {
+                relations.insert(relation.clone());
+            }
+            StatementAST::Question(body, _, _, _) => relations.extend(
+                body.iter()
+                    .flat_map(AtomAST::literals)
+                    .map(|lit| lit.relation.clone()),
+            ),
+            _ => {}
+        }
+    }
+    let tuples = |env: &Environment<A>, relation: &str| -> Vec<(GroundTuple, A)> {
+        let Some(view) = env.relation(relation) else {
+            return vec![];
+        };
+        view.iter()
+            .map(|(tuple, value)| (tuple.iter().map(|value| value.0).collect(), value.clone()))
+            .collect()
+    };
+    let mut changed = false;
+    for relation in &relations {
+        // Relations without tables in either model, like built-ins, have nothing to compare.
+        if old.relation(relation).is_none() && new.relation(relation).is_none() {
+            continue;
+        }
+        let diff = Diff::new(tuples(&old, relation), tuples(&new, relation));
+        changed |= !diff.is_empty();
+        if let Format::Json = options.format {
+            println!("{}", relation_diff_to_json(relation, &diff));
+            continue;
+        }
+        println!(
+            "{}: {} added, {} removed, {} changed",
+            relation,
+            diff.added.len(),
+            diff.removed.len(),
+            diff.changed.len()
+        );
+        if options.verbosity == Verbosity::Count {
+            continue;
+        }
+        let show = |tuple: &GroundTuple| {
+            let tuple: Vec<_> = tuple
+                .iter()
+                .map(|symbol| Value(*symbol).to_string())
+                .collect();
+            format!("{}({})", relation, tuple.join(", "))
+        };
+        let assumptions = options.verbosity == Verbosity::Assumptions;
+        for (sign, tuples) in [("+", &diff.added), ("-", &diff.removed)] {
+            for (tuple, value) in tuples {
+                match assumptions {
+                    true => println!("  {} {}  [{}]", sign, show(tuple), value),
+                    false => println!("  {} {}", sign, show(tuple)),
+                }
+            }
+        }
+        for (tuple, old, new) in &diff.changed {
+            match assumptions {
+                true => println!("  ~ {}  [{}] -> [{}]", show(tuple), old, new),
+                false => println!("  ~ {}", show(tuple)),
+            }
+        }
+    }
+    if changed {
+        exit(1);
+    }
+    Ok(())
+}
+
+// Print the answers to the statements last interpreted by an environment.
+fn print_answers<A: Assumption + ToJson>(env: &Environment<A>, options: &Options) {
+    match options.format {
+        Format::Text => print!("{}", env.log()),
+        Format::Json => {
+            for answers in env.answers() {
+                println!("{}", answers_to_json(answers));
+            }
+        }
+    }
+}
+
+// Stop evaluation at the end of the fixpoint iteration it's in on Ctrl-C (see
+// `CancellationToken::stop`). A second Ctrl-C during the same evaluation, or one with no evaluation
+// to stop, exits right away, for iterations that take too long. A single handler is installed, and
+// each evaluation hands it its token.
+fn stop_on_interrupt(token: CancellationToken) {
+    static CURRENT: Mutex<Option<(CancellationToken, bool)>> = Mutex::new(None);
+    static HANDLER: Once = Once::new();
+    *CURRENT.lock().unwrap() = Some((token, false));
+    HANDLER.call_once(|| {
+        let handled = ctrlc::set_handler(|| match &mut *CURRENT.lock().unwrap() {
+            Some((token, interrupted)) if !*interrupted => {
+                token.stop();
+                *interrupted = true;
+            }
+            _ => exit(130),
+        });
+        if let Err(err) = handled {
+            eprintln!("can't handle Ctrl-C: {}", err);
+        }
+    });
+}
+
+fn fail(message: &str) -> ! {
+    eprintln!("{}", message);
+    exit(1)
+}
+
+fn dir_arg(flag: &str, dir: Option<String>) -> Result<String> {
+    dir.ok_or_else(|| Error::other(format!("{} expects a directory", flag)))
+}
+
+// Tuples to explain are written like `--explain 'P(1, 3)'`, or `--explain P` or `--explain 'P()'`
+// for nullary relations.
+fn tuple_arg(tuple: Option<String>) -> Result<(String, GroundTuple)> {
+    let error = || Error::other("--explain expects a tuple like `P(1, 3)`");
+    let tuple = tuple.ok_or_else(error)?;
+    let Some((relation, symbols)) = tuple.split_once('(') else {
+        return Ok((tuple.trim().to_string(), vec![]));
+    };
+    let symbols = symbols.trim_end().strip_suffix(')').ok_or_else(error)?;
+    if symbols.trim().is_empty() {
+        return Ok((relation.trim().to_string(), vec![]));
+    }
+    let symbols = symbols
+        .split(',')
+        .map(|symbol| parse_value(symbol.trim()).ok_or_else(error))
+        .collect::<Result<_>>()?;
+    Ok((relation.trim().to_string(), symbols))
+}
+
+// Parameters are bound like `--param src=3`.
+fn param_arg(param: Option<String>) -> Result<(String, Symbol)> {
+    param
+        .as_deref()
+        .and_then(|param| param.split_once('='))
+        .and_then(|(name, value)| Some((name.to_string(), parse_value(value)?)))
+        .ok_or_else(|| {
+            Error::other("--param expects `name=value`, where value is a number or quoted string")
+        })
+}
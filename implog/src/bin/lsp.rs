@@ -0,0 +1,226 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::error::Error;
+
+use lsp_server::{Connection, Message, Notification, Request, Response};
+use lsp_types::notification::{
+    DidChangeTextDocument, DidCloseTextDocument, DidOpenTextDocument, Notification as _,
+    PublishDiagnostics,
+};
+use lsp_types::request::{Completion, GotoDefinition, Request as _};
+use lsp_types::{
+    CompletionItem, CompletionItemKind, CompletionOptions, CompletionParams, CompletionResponse,
+    Diagnostic, DiagnosticSeverity, DidChangeTextDocumentParams, DidCloseTextDocumentParams,
+    DidOpenTextDocumentParams, GotoDefinitionParams, GotoDefinitionResponse, Location, OneOf,
+    Position, PublishDiagnosticsParams, Range, ServerCapabilities, TextDocumentSyncCapability,
+    TextDocumentSyncKind, Uri,
+};
+
+use implog::ast::{Span, parse_error_span, why_ill_formed};
+use implog::grammar::LocatedProgramParser;
+use implog::index::SymbolIndex;
+
+// A language server for implog programs, speaking LSP over stdin and stdout. It reports parse
+// errors and ill-formed statements as diagnostics, jumps to the definitions of relations, and
+// completes relation names.
+
+type Result<T> = std::result::Result<T, Box<dyn Error + Send + Sync>>;
+
+struct Document {
+    source: String,
+    // The index of the source, if it parses.
+    index: Option<SymbolIndex>,
+    // The relations of the last version of the source that parsed, so names can still be completed
+    // while a statement is half typed.
+    relations: BTreeSet<String>,
+}
+
+pub fn main() -> Result<()> {
+    let (connection, io_threads) = Connection::stdio();
+    let capabilities = ServerCapabilities {
+        text_document_sync: Some(TextDocumentSyncCapability::Kind(TextDocumentSyncKind::FULL)),
+        definition_provider: Some(OneOf::Left(true)),
+        completion_provider: Some(CompletionOptions::default()),
+        ..Default::default()
+    };
+    connection.initialize(serde_json::to_value(capabilities)?)?;
+    // The connection must be dropped before joining, so the thread writing to stdout finishes.
+    serve(connection)?;
+    io_threads.join()?;
+    Ok(())
+}
+
+fn serve(connection: Connection) -> Result<()> {
+    // Open documents, by URI.
+    let mut documents: BTreeMap<String, Document> = BTreeMap::new();
+    for msg in &connection.receiver {
+        match msg {
+            Message::Request(req) => {
+                if connection.handle_shutdown(&req)? {
+                    return Ok(());
+                }
+                let resp = respond(&documents, req)?;
+                connection.sender.send(Message::Response(resp))?;
+            }
+            Message::Notification(not) => {
+                let uri = match not.method.as_str() {
+                    DidOpenTextDocument::METHOD => {
+                        let params: DidOpenTextDocumentParams = serde_json::from_value(not.params)?;
+                        let uri = params.text_document.uri;
+                        update(&mut documents, &uri, params.text_document.text);
+                        uri
+                    }
+                    // Documents are synced in full, so the last change holds the whole source.
+                    DidChangeTextDocument::METHOD => {
+                        let params: DidChangeTextDocumentParams =
+                            serde_json::from_value(not.params)?;
+                        let uri = params.text_document.uri;
+                        if let Some(change) = params.content_changes.into_iter().last() {
+                            update(&mut documents, &uri, change.text);
+                        }
+                        uri
+                    }
+                    DidCloseTextDocument::METHOD => {
+                        let params: DidCloseTextDocumentParams =
+                            serde_json::from_value(not.params)?;
+                        documents.remove(params.text_document.uri.as_str());
+                        continue;
+                    }
+                    _ => continue,
+                };
+                let diagnostics = diagnose(&documents[uri.as_str()].source);
+                let params = PublishDiagnosticsParams {
+                    uri,
+                    diagnostics,
+                    version: None,
+                };
+                let not = Notification::new(PublishDiagnostics::METHOD.to_string(), params);
+                connection.sender.send(Message::Notification(not))?;
+            }
+            Message::Response(_) => {}
+        }
+    }
+    Ok(())
+}
+
+fn update(documents: &mut BTreeMap<String, Document>, uri: &Uri, source: String) {
+    let index = LocatedProgramParser::new()
+        .parse(&source)
+        .ok()
+        .map(|stmts| SymbolIndex::new(&source, &stmts));
+    let relations = match &index {
+        Some(index) => index.relations().into_iter().map(str::to_string).collect(),
+        None => documents
+            .remove(uri.as_str())
+            .map(|doc| doc.relations)
+            .unwrap_or_default(),
+    };
+    let doc = Document {
+        source,
+        index,
+        relations,
+    };
+    documents.insert(uri.as_str().to_string(), doc);
+}
+
+fn respond(documents: &BTreeMap<String, Document>, req: Request) -> Result<Response> {
+    match req.method.as_str() {
+        GotoDefinition::METHOD => {
+            let params: GotoDefinitionParams = serde_json::from_value(req.params)?;
+            let uri = params.text_document_position_params.text_document.uri;
+            let position = params.text_document_position_params.position;
+            let locations: Vec<_> = documents
+                .get(uri.as_str())
+                .and_then(|doc| {
+                    let index = doc.index.as_ref()?;
+                    let occ = index.occurrence_at(offset(&doc.source, position))?;
+                    let locations = index
+                        .definitions(&occ.relation)
+                        .map(|span| Location {
+                            uri: uri.clone(),
+                            range: range(&doc.source, span),
+                        })
+                        .collect();
+                    Some(locations)
+                })
+                .unwrap_or_default();
+            let resp = GotoDefinitionResponse::Array(locations);
+            Ok(Response::new_ok(req.id, resp))
+        }
+        Completion::METHOD => {
+            let params: CompletionParams = serde_json::from_value(req.params)?;
+            let uri = params.text_document_position.text_document.uri;
+            let items: Vec<_> = documents
+                .get(uri.as_str())
+                .into_iter()
+                .flat_map(|doc| &doc.relations)
+                .map(|relation| CompletionItem {
+                    label: relation.clone(),
+                    kind: Some(CompletionItemKind::STRUCT),
+                    ..Default::default()
+                })
+                .collect();
+            Ok(Response::new_ok(req.id, CompletionResponse::Array(items)))
+        }
+        _ => Ok(Response::new_err(
+            req.id,
+            lsp_server::ErrorCode::MethodNotFound as i32,
+            format!("unsupported request `{}`", req.method),
+        )),
+    }
+}
+
+// A parse error, or else every statement that isn't well formed.
+fn diagnose(source: &str) -> Vec<Diagnostic> {
+    let error = |span: Span, message: String| Diagnostic {
+        range: range(source, span),
+        severity: Some(DiagnosticSeverity::ERROR),
+        source: Some("implog".to_string()),
+        message,
+        ..Default::default()
+    };
+    match LocatedProgramParser::new().parse(source) {
+        Ok(stmts) => stmts
+            .iter()
+            .filter_map(|(span, stmt)| {
+                why_ill_formed(stmt).map(|reason| error(*span, reason.to_string()))
+            })
+            .collect(),
+        Err(err) => {
+            let span = parse_error_span(&err).unwrap_or(Span { start: 0, end: 0 });
+            vec![error(span, err.to_string())]
+        }
+    }
+}
+
+// LSP positions count lines from 0, and characters within a line in UTF-16 code units.
+fn position(source: &str, offset: usize) -> Position {
+    let before = &source[..offset];
+    let line_start = before.rfind('\n').map_or(0, |idx| idx + 1);
+    Position {
+        line: before.matches('\n').count() as u32,
+        character: before[line_start..].encode_utf16().count() as u32,
+    }
+}
+
+fn offset(source: &str, position: Position) -> usize {
+    let line_start = source
+        .split_inclusive('\n')
+        .take(position.line as usize)
+        .map(str::len)
+        .sum::<usize>();
+    let mut units = 0;
+    for (idx, c) in source[line_start..].char_indices() {
+        if units >= position.character as usize || c == '\n' {
+            return line_start + idx;
+        }
+        units += c.len_utf16();
+    }
+    source.len()
+}
+
+fn range(source: &str, span: Span) -> Range {
+    Range {
+        start: position(source, span.start),
+        end: position(source, span.end),
+    }
+}
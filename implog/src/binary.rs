@@ -0,0 +1,243 @@
+use std::io::{Error, ErrorKind, Read, Result, Write};
+
+use crate::representation::{
+    BooleanAssumption, Conjunct, DNFAssumption, GroundTuple, LeafAssumption, Symbol,
+    TopKAssumption, TropicalAssumption,
+};
+use crate::value::SymbolTable;
+
+// A compact binary encoding of tables, for saving and loading materialized databases quickly. All
+// integers are little-endian, and strings and sequences are prefixed by their lengths as u64s.
+pub trait Encode: Sized {
+    fn encode(&self, out: &mut impl Write) -> Result<()>;
+    fn decode(input: &mut impl Read) -> Result<Self>;
+}
+
+pub fn write_u64(out: &mut impl Write, n: u64) -> Result<()> {
+    out.write_all(&n.to_le_bytes())
+}
+
+pub fn read_u64(input: &mut impl Read) -> Result<u64> {
+    let mut bytes = [0; 8];
+    input.read_exact(&mut bytes)?;
+    Ok(u64::from_le_bytes(bytes))
+}
+
+pub fn write_len(out: &mut impl Write, len: usize) -> Result<()> {
+    write_u64(out, len as u64)
+}
+
+pub fn read_len(input: &mut impl Read) -> Result<usize> {
+    usize::try_from(read_u64(input)?).map_err(|_| invalid("length doesn't fit in memory"))
+}
+
+pub fn write_str(out: &mut impl Write, s: &str) -> Result<()> {
+    write_len(out, s.len())?;
+    out.write_all(s.as_bytes())
+}
+
+pub fn read_str(input: &mut impl Read) -> Result<String> {
+    let len = read_len(input)?;
+    let mut bytes = vec![];
+    input.take(len as u64).read_to_end(&mut bytes)?;
+    if bytes.len() != len {
+        return Err(Error::from(ErrorKind::UnexpectedEof));
+    }
+    String::from_utf8(bytes).map_err(|_| invalid("string isn't UTF-8"))
+}
+
+// Tuples are written without their lengths, since every tuple of a table has the table's arity.
+pub fn write_tuple(out: &mut impl Write, tuple: &GroundTuple) -> Result<()> {
+    for symbol in tuple {
+        out.write_all(&symbol.to_le_bytes())?;
+    }
+    Ok(())
+}
+
+pub fn read_tuple(input: &mut impl Read, arity: usize) -> Result<GroundTuple> {
+    (0..arity)
+        .map(|_| {
+            let mut bytes = [0; 8];
+            input.read_exact(&mut bytes)?;
+            Ok(Symbol::from_le_bytes(bytes))
+        })
+        .collect()
+}
+
+pub fn invalid(message: &str) -> Error {
+    Error::new(ErrorKind::InvalidData, message.to_string())
+}
+
+impl Encode for LeafAssumption {
+    fn encode(&self, out: &mut impl Write) -> Result<()> {
+        let (relation, tuple) = self;
+        write_str(out, relation)?;
+        write_len(out, tuple.len())?;
+        write_tuple(out, tuple)
+    }
+
+    fn decode(input: &mut impl Read) -> Result<Self> {
+        let relation = read_str(input)?;
+        let arity = read_len(input)?;
+        Ok((relation, read_tuple(input, arity)?))
+    }
+}
+
+// A symbol table is written as its strings, each as its symbol and the string, then its terms, each
+// as its symbol, its functor, and its arguments (prefixed by how many there are).
+impl Encode for SymbolTable {
+    fn encode(&self, out: &mut impl Write) -> Result<()> {
+        write_len(out, self.strings.len())?;
+        for (symbol, s) in &self.strings {
+            out.write_all(&symbol.to_le_bytes())?;
+            write_str(out, s)?;
+        }
+        write_len(out, self.terms.len())?;
+        for (symbol, functor, args) in &self.terms {
+            out.write_all(&symbol.to_le_bytes())?;
+            write_str(out, functor)?;
+            write_len(out, args.len())?;
+            write_tuple(out, args)?;
+        }
+        Ok(())
+    }
+
+    fn decode(input: &mut impl Read) -> Result<Self> {
+        let strings = (0..read_len(input)?)
+            .map(|_| Ok((read_tuple(input, 1)?[0], read_str(input)?)))
+            .collect::<Result<_>>()?;
+        let terms = (0..read_len(input)?)
+            .map(|_| {
+                let symbol = read_tuple(input, 1)?[0];
+                let functor = read_str(input)?;
+                let arity = read_len(input)?;
+                Ok((symbol, functor, read_tuple(input, arity)?))
+            })
+            .collect::<Result<_>>()?;
+        Ok(Self { strings, terms })
+    }
+}
+
+// A DNF is written as its number of conjunctions, then each conjunction as its number of leaves
+// and its leaves.
+impl Encode for DNFAssumption {
+    fn encode(&self, out: &mut impl Write) -> Result<()> {
+        write_len(out, self.dnf.len())?;
+        for conj in &self.dnf {
+            write_len(out, conj.len())?;
+            for leaf in conj.iter() {
+                leaf.encode(out)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn decode(input: &mut impl Read) -> Result<Self> {
+        let conjs = read_len(input)?;
+        let dnf = (0..conjs)
+            .map(|_| {
+                let leaves = read_len(input)?;
+                (0..leaves)
+                    .map(|_| LeafAssumption::decode(input))
+                    .collect::<Result<_>>()
+                    .map(Conjunct::new)
+            })
+            .collect::<Result<_>>()?;
+        Ok(Self { dnf })
+    }
+}
+
+impl Encode for BooleanAssumption {
+    fn encode(&self, out: &mut impl Write) -> Result<()> {
+        out.write_all(&[u8::from(self.0)])
+    }
+
+    fn decode(input: &mut impl Read) -> Result<Self> {
+        let mut byte = [0];
+        input.read_exact(&mut byte)?;
+        match byte[0] {
+            0 => Ok(Self(false)),
+            1 => Ok(Self(true)),
+            _ => Err(invalid("boolean isn't 0 or 1")),
+        }
+    }
+}
+
+// Top-k values are written like the DNFs they keep.
+impl<const K: usize> Encode for TopKAssumption<K> {
+    fn encode(&self, out: &mut impl Write) -> Result<()> {
+        self.0.encode(out)
+    }
+
+    fn decode(input: &mut impl Read) -> Result<Self> {
+        DNFAssumption::decode(input).map(Self)
+    }
+}
+
+// Infinite costs are written as u64::MAX.
+impl Encode for TropicalAssumption {
+    fn encode(&self, out: &mut impl Write) -> Result<()> {
+        write_u64(out, self.0.map_or(u64::MAX, |cost| cost as u64))
+    }
+
+    fn decode(input: &mut impl Read) -> Result<Self> {
+        match read_u64(input)? {
+            u64::MAX => Ok(Self(None)),
+            cost => Ok(Self(Some(
+                usize::try_from(cost).map_err(|_| invalid("cost doesn't fit in memory"))?,
+            ))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::representation::Assumption;
+
+    use super::*;
+
+    fn round_trip<A: Encode>(value: &A) -> A {
+        let mut bytes = vec![];
+        value.encode(&mut bytes).unwrap();
+        A::decode(&mut bytes.as_slice()).unwrap()
+    }
+
+    #[test]
+    fn encode_assumptions() {
+        let a = DNFAssumption::singleton(("A".to_string(), vec![1, -2]));
+        let b = DNFAssumption::singleton(("B".to_string(), vec![]));
+        let value = a
+            .times(&b)
+            .plus(&DNFAssumption::singleton(("C".to_string(), vec![3])));
+        assert_eq!(round_trip(&value), value);
+        assert_eq!(
+            round_trip(&BooleanAssumption(true)),
+            BooleanAssumption(true)
+        );
+        assert_eq!(
+            round_trip(&TropicalAssumption(None)),
+            TropicalAssumption(None)
+        );
+        assert_eq!(
+            round_trip(&TropicalAssumption(Some(3))),
+            TropicalAssumption(Some(3))
+        );
+    }
+
+    #[test]
+    fn encode_symbol_tables() {
+        let table = SymbolTable {
+            strings: vec![(i64::MIN, "a".to_string())],
+            terms: vec![(i64::MIN / 2, "pair".to_string(), vec![1, i64::MIN])],
+        };
+        assert_eq!(round_trip(&table), table);
+    }
+
+    #[test]
+    fn decode_truncated() {
+        let mut bytes = vec![];
+        DNFAssumption::one().encode(&mut bytes).unwrap();
+        bytes.pop();
+        assert!(DNFAssumption::decode(&mut bytes.as_slice()).is_err());
+    }
+}
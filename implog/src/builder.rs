@@ -0,0 +1,166 @@
+use crate::ast::{AtomAST, LiteralAST, Modifiers, StatementAST, TermAST, why_ill_formed};
+use crate::representation::Symbol;
+use crate::value::intern;
+
+// Builders for statements, so code generators and embedders can construct programs without
+// emitting text and parsing it back. Strings are interned as they're turned into terms, and built
+// statements are checked like parsed ones (see `why_ill_formed`). E.g., the rule
+// `P(x, z) :- P(x, y), E(y, z).`:
+//   rule("P")
+//       .head(vars!["x", "z"])
+//       .body(atom("P", vars!["x", "y"]))
+//       .body(atom("E", vars!["y", "z"]))
+//       .build()
+
+// The variables named by some strings, as terms.
+#[macro_export]
+macro_rules! vars {
+    ($($var:expr),* $(,)?) => {
+        vec![$($crate::builder::var($var)),*]
+    };
+}
+
+pub fn var(name: &str) -> TermAST {
+    TermAST::Variable(name.to_string())
+}
+
+pub fn int(value: Symbol) -> TermAST {
+    TermAST::Constant(value)
+}
+
+pub fn string(value: &str) -> TermAST {
+    TermAST::Constant(intern(value))
+}
+
+pub fn compound(functor: &str, args: impl IntoIterator<Item = TermAST>) -> TermAST {
+    TermAST::Compound(functor.to_string(), args.into_iter().collect())
+}
+
+fn literal(relation: &str, terms: impl IntoIterator<Item = TermAST>) -> LiteralAST {
+    LiteralAST {
+        relation: relation.to_string(),
+        terms: terms.into_iter().collect(),
+        span: None,
+        term_spans: vec![],
+    }
+}
+
+// An atom reading a relation, like `E(x, y)`.
+pub fn atom(relation: &str, terms: impl IntoIterator<Item = TermAST>) -> AtomAST {
+    AtomAST::Literal(literal(relation, terms))
+}
+
+// An atom assuming a tuple, like `[A(x)]`.
+pub fn assume(relation: &str, terms: impl IntoIterator<Item = TermAST>) -> AtomAST {
+    AtomAST::Brackets(literal(relation, terms))
+}
+
+pub fn rule(relation: &str) -> RuleBuilder {
+    RuleBuilder {
+        head: literal(relation, []),
+        assumed: false,
+        body: vec![],
+    }
+}
+
+// A question with some body, like `? P(x, y).`.
+pub fn question(body: impl IntoIterator<Item = AtomAST>) -> Result<StatementAST, &'static str> {
+    checked(StatementAST::Question(
+        body.into_iter().collect(),
+        None,
+        None,
+        Modifiers::default(),
+    ))
+}
+
+pub struct RuleBuilder {
+    head: LiteralAST,
+    // Whether the head is bracketed, so the tuples the rule derives are assumed.
+    assumed: bool,
+    body: Vec<AtomAST>,
+}
+
+impl RuleBuilder {
+    pub fn head(mut self, terms: impl IntoIterator<Item = TermAST>) -> Self {
+        self.head.terms = terms.into_iter().collect();
+        self
+    }
+
+    pub fn assumed(mut self) -> Self {
+        self.assumed = true;
+        self
+    }
+
+    pub fn body(mut self, atom: AtomAST) -> Self {
+        self.body.push(atom);
+        self
+    }
+
+    pub fn build(self) -> Result<StatementAST, &'static str> {
+        let head = if self.assumed {
+            AtomAST::Brackets(self.head)
+        } else {
+            AtomAST::Literal(self.head)
+        };
+        checked(StatementAST::Rule(head, self.body))
+    }
+}
+
+fn checked(stmt: StatementAST) -> Result<StatementAST, &'static str> {
+    match why_ill_formed(&stmt) {
+        Some(reason) => Err(reason),
+        None => Ok(stmt),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::interpret::Environment;
+    use crate::representation::DNFAssumption;
+
+    use super::*;
+
+    #[test]
+    fn build_and_interpret_rules() {
+        let stmts = vec![
+            rule("E").head([int(1), string("a")]).build().unwrap(),
+            rule("E").head([string("a"), int(2)]).build().unwrap(),
+            rule("P")
+                .head(vars!["x", "y"])
+                .body(atom("E", vars!["x", "y"]))
+                .build()
+                .unwrap(),
+            rule("P")
+                .head(vars!["x", "z"])
+                .body(atom("P", vars!["x", "y"]))
+                .body(atom("E", vars!["y", "z"]))
+                .build()
+                .unwrap(),
+            rule("Q")
+                .head([compound("pair", vars!["x", "y"])])
+                .assumed()
+                .body(atom("P", vars!["x", "y"]))
+                .build()
+                .unwrap(),
+            question([atom("P", [int(1), var("y")])]).unwrap(),
+        ];
+        assert_eq!(stmts[1].to_string(), "E(\"a\", 2) :- .");
+        assert_eq!(stmts[3].to_string(), "P(x, z) :- P(x, y), E(y, z).");
+        assert_eq!(stmts[4].to_string(), "[Q(pair(x, y))] :- P(x, y).");
+        let mut env = Environment::<DNFAssumption>::new();
+        assert_eq!(env.interpret(&stmts).unwrap(), "Num rows: 2\n");
+    }
+
+    #[test]
+    fn reject_ill_formed_statements() {
+        assert!(question([atom("length", vars!["xs", "n"])]).is_err());
+        assert!(
+            rule("P")
+                .head([])
+                .body(atom("E", vars!["x", "y"]))
+                .body(atom("member", vars!["z", "w"]))
+                .build()
+                .is_err()
+        );
+    }
+}
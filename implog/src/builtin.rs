@@ -0,0 +1,641 @@
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, BTreeSet, BinaryHeap};
+use std::str::FromStr;
+
+use crate::ast::{AtomAST, BuiltinAST, EQUAL, LiteralAST, TermAST};
+use crate::interpret::{Bindings, eval_term, unify_term};
+use crate::representation::{Assumption, GroundTuple, Symbol, Table};
+use crate::value::{Value, intern_list, nil, resolve_list};
+
+// Graph algorithms that can stand in for the textbook recursive rules computing the same relation.
+// Each built-in reads the full values of a source relation and produces ground tuples for the
+// target relation along with their assumption values. The assumption value of a derived tuple is
+// the sum over its paths of the product of the edges' assumption values, exactly as if it were
+// derived by the equivalent rules.
+// - transitive_closure(E): binary E(x, y) -> binary P(x, y) for every path of length >= 1.
+// - scc(E): binary E(x, y) -> binary S(x, y) when x and y are in the same strongly connected
+//   component. Every node appearing in E is in the same component as itself.
+// - shortest_path(W): ternary W(x, y, w) -> ternary D(x, y, d) where d is the smallest total
+//   weight over paths of length >= 1 from x to y. Edges with negative weights are ignored. The
+//   assumption value only accounts for paths of weight d.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum GraphBuiltin {
+    TransitiveClosure,
+    StronglyConnected,
+    ShortestPath,
+}
+
+impl FromStr for GraphBuiltin {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        use GraphBuiltin::*;
+        match s {
+            "transitive_closure" => Ok(TransitiveClosure),
+            "scc" => Ok(StronglyConnected),
+            "shortest_path" => Ok(ShortestPath),
+            _ => Err("unknown built-in"),
+        }
+    }
+}
+
+type Graph<A> = BTreeMap<Symbol, Vec<(Symbol, A)>>;
+
+impl GraphBuiltin {
+    pub fn name(&self) -> &'static str {
+        use GraphBuiltin::*;
+        match self {
+            TransitiveClosure => "transitive_closure",
+            StronglyConnected => "scc",
+            ShortestPath => "shortest_path",
+        }
+    }
+
+    // Arities of the source and target relations.
+    pub fn arities(&self) -> (usize, usize) {
+        use GraphBuiltin::*;
+        match self {
+            TransitiveClosure | StronglyConnected => (2, 2),
+            ShortestPath => (3, 3),
+        }
+    }
+
+    pub fn evaluate<A: Assumption>(&self, source: &Table<A>) -> Vec<(GroundTuple, A)> {
+        use GraphBuiltin::*;
+        match self {
+            TransitiveClosure => {
+                let graph = graph(source);
+                let mut tuples = vec![];
+                for (node, edges) in &graph {
+                    for (target, value) in reach(&graph, edges.clone()) {
+                        tuples.push((vec![*node, target], value));
+                    }
+                }
+                tuples
+            }
+            StronglyConnected => strongly_connected(&graph(source)),
+            ShortestPath => shortest_path(source),
+        }
+    }
+}
+
+fn graph<A: Assumption>(source: &Table<A>) -> Graph<A> {
+    let mut graph: Graph<A> = BTreeMap::new();
+    for (tuple, (value, _)) in source {
+        if !value.is_zero() {
+            graph
+                .entry(tuple[0])
+                .or_default()
+                .push((tuple[1], value.clone()));
+        }
+    }
+    graph
+}
+
+// Sum over all paths starting with one of the `seed` edges to each reachable node. Each node is
+// revisited only while the value flowing into it isn't already subsumed, so this terminates
+// whenever the equivalent rules reach a fixpoint.
+fn reach<A: Assumption>(graph: &Graph<A>, seed: Vec<(Symbol, A)>) -> BTreeMap<Symbol, A> {
+    let mut values: BTreeMap<Symbol, A> = BTreeMap::new();
+    let mut worklist = seed;
+    while let Some((node, value)) = worklist.pop() {
+        let old = values.entry(node).or_insert_with(A::zero);
+        let delta = old.delta(&value);
+        if delta.is_zero() {
+            continue;
+        }
+        *old = old.plus(&delta);
+        for (next, edge) in graph.get(&node).into_iter().flatten() {
+            worklist.push((*next, delta.times(edge)));
+        }
+    }
+    values
+}
+
+fn strongly_connected<A: Assumption>(graph: &Graph<A>) -> Vec<(GroundTuple, A)> {
+    let mut tuples = vec![];
+    for component in tarjan(graph) {
+        // Any path between two nodes of the same component stays inside the component, so the
+        // closure only needs to look at edges within it.
+        let inner: Graph<A> = component
+            .iter()
+            .map(|node| {
+                let edges = graph.get(node).into_iter().flatten();
+                let edges = edges.filter(|(next, _)| component.contains(next)).cloned();
+                (*node, edges.collect())
+            })
+            .collect();
+        let reached: BTreeMap<Symbol, BTreeMap<Symbol, A>> = component
+            .iter()
+            .map(|node| (*node, reach(&inner, inner[node].clone())))
+            .collect();
+        for x in &component {
+            for y in &component {
+                let value = if x == y {
+                    A::one()
+                } else {
+                    reached[x][y].times(&reached[y][x])
+                };
+                tuples.push((vec![*x, *y], value));
+            }
+        }
+    }
+    tuples
+}
+
+// Iterative version of Tarjan's algorithm, so deep graphs don't overflow the stack.
+fn tarjan<A>(graph: &Graph<A>) -> Vec<BTreeSet<Symbol>> {
+    let mut nodes = BTreeSet::new();
+    for (node, edges) in graph {
+        nodes.insert(*node);
+        nodes.extend(edges.iter().map(|(next, _)| *next));
+    }
+
+    let mut index: BTreeMap<Symbol, usize> = BTreeMap::new();
+    let mut low: BTreeMap<Symbol, usize> = BTreeMap::new();
+    let mut stack = vec![];
+    let mut on_stack = BTreeSet::new();
+    let mut components = vec![];
+    for root in nodes {
+        if index.contains_key(&root) {
+            continue;
+        }
+        let mut call_stack = vec![(root, 0)];
+        while let Some((node, edge_idx)) = call_stack.pop() {
+            if edge_idx == 0 {
+                index.insert(node, index.len());
+                low.insert(node, index[&node]);
+                stack.push(node);
+                on_stack.insert(node);
+            }
+            let edges = graph.get(&node).map(Vec::as_slice).unwrap_or_default();
+            if let Some((next, _)) = edges.get(edge_idx) {
+                call_stack.push((node, edge_idx + 1));
+                if !index.contains_key(next) {
+                    call_stack.push((*next, 0));
+                } else if on_stack.contains(next) {
+                    low.insert(node, low[&node].min(index[next]));
+                }
+                continue;
+            }
+            if low[&node] == index[&node] {
+                let mut component = BTreeSet::new();
+                while let Some(member) = stack.pop() {
+                    on_stack.remove(&member);
+                    component.insert(member);
+                    if member == node {
+                        break;
+                    }
+                }
+                components.push(component);
+            }
+            if let Some((parent, _)) = call_stack.last() {
+                low.insert(*parent, low[parent].min(low[&node]));
+            }
+        }
+    }
+    components
+}
+
+fn shortest_path<A: Assumption>(source: &Table<A>) -> Vec<(GroundTuple, A)> {
+    let mut graph: BTreeMap<Symbol, Vec<(Symbol, Symbol, A)>> = BTreeMap::new();
+    for (tuple, (value, _)) in source {
+        if !value.is_zero() && tuple[2] >= 0 {
+            graph
+                .entry(tuple[0])
+                .or_default()
+                .push((tuple[1], tuple[2], value.clone()));
+        }
+    }
+
+    let mut tuples = vec![];
+    for start in graph.keys() {
+        // Dijkstra's algorithm, seeded with the edges out of the start node so that a path back
+        // to the start node must have at least one edge.
+        let mut dist: BTreeMap<Symbol, Symbol> = BTreeMap::new();
+        let mut heap = BinaryHeap::new();
+        for (next, weight, _) in &graph[start] {
+            heap.push(Reverse((*weight, *next)));
+        }
+        while let Some(Reverse((d, node))) = heap.pop() {
+            if dist.contains_key(&node) {
+                continue;
+            }
+            dist.insert(node, d);
+            for (next, weight, _) in graph.get(&node).into_iter().flatten() {
+                if !dist.contains_key(next) {
+                    heap.push(Reverse((d.saturating_add(*weight), *next)));
+                }
+            }
+        }
+
+        // Sum the assumption values over only the tight edges, which lie on shortest paths.
+        let tight: Graph<A> = graph
+            .iter()
+            .filter_map(|(node, edges)| Some((*node, dist.get(node)?, edges)))
+            .map(|(node, d, edges)| {
+                let edges = edges
+                    .iter()
+                    .filter(|(next, weight, _)| dist.get(next) == Some(&d.saturating_add(*weight)))
+                    .map(|(next, _, value)| (*next, value.clone()));
+                (node, edges.collect())
+            })
+            .collect();
+        // The start node, as the origin of paths, has distance 0 rather than `dist[start]`.
+        let seed = graph[start]
+            .iter()
+            .filter(|(next, weight, _)| dist[next] == *weight)
+            .map(|(next, _, value)| (*next, value.clone()))
+            .collect();
+        for (target, value) in reach(&tight, seed) {
+            tuples.push((vec![*start, target, dist[&target]], value));
+        }
+    }
+    tuples
+}
+
+// Recognize a relation defined by exactly a base rule and a linear or non-linear recursive rule
+// computing the transitive closure of some other relation, e.g.:
+//   P(x, y) :- E(x, y).
+//   P(x, z) :- E(x, y), P(y, z).
+// Returns the built-in replacing each such pair of rules, along with the indices of those rules.
+// The built-in only derives the closure of the other relation, so it can't replace the rules of a
+// relation with rows of its own, like added facts, which callers check for.
+pub fn recognize(rules: &[(&AtomAST, &Vec<AtomAST>)]) -> Vec<(BuiltinAST, [usize; 2])> {
+    let mut by_head: BTreeMap<&str, Vec<usize>> = BTreeMap::new();
+    for (idx, (head, _)) in rules.iter().enumerate() {
+        let (AtomAST::Literal(lit) | AtomAST::Brackets(lit) | AtomAST::Arrow(_, lit)) = head;
+        by_head.entry(&lit.relation).or_default().push(idx);
+    }
+
+    let mut recognized = vec![];
+    for (relation, idxs) in by_head {
+        let &[first, second] = idxs.as_slice() else {
+            continue;
+        };
+        for (base, recursive) in [(first, second), (second, first)] {
+            if let Some(source) = base_rule(rules[base], relation)
+                && source != relation
+                && recursive_rule(rules[recursive], relation, source)
+            {
+                let builtin = BuiltinAST {
+                    relation: relation.to_string(),
+                    kind: GraphBuiltin::TransitiveClosure,
+                    source: source.to_string(),
+                };
+                recognized.push((builtin, [first, second]));
+                break;
+            }
+        }
+    }
+    recognized
+}
+
+// The variables of a literal, if every term is a variable.
+fn binary_vars(lit: &LiteralAST) -> Option<(&str, &str)> {
+    match lit.terms.as_slice() {
+        [TermAST::Variable(x), TermAST::Variable(y)] => Some((x, y)),
+        _ => None,
+    }
+}
+
+fn plain_literal<'a>(atom: &'a AtomAST, relation: Option<&str>) -> Option<&'a LiteralAST> {
+    match atom {
+        AtomAST::Literal(lit) if relation.is_none_or(|relation| lit.relation == relation) => {
+            Some(lit)
+        }
+        _ => None,
+    }
+}
+
+// P(x, y) :- E(x, y). Returns E.
+fn base_rule<'a>(rule: (&AtomAST, &'a Vec<AtomAST>), relation: &str) -> Option<&'a str> {
+    let (head, body) = rule;
+    let head = binary_vars(plain_literal(head, Some(relation))?)?;
+    let [atom] = body.as_slice() else {
+        return None;
+    };
+    let lit = plain_literal(atom, None)?;
+    (head.0 != head.1 && binary_vars(lit)? == head).then_some(&lit.relation)
+}
+
+// P(x, z) :- E(x, y), P(y, z). or P(x, z) :- P(x, y), E(y, z). or P(x, z) :- P(x, y), P(y, z).
+// The body literals may appear in either order.
+fn recursive_rule(rule: (&AtomAST, &Vec<AtomAST>), relation: &str, source: &str) -> bool {
+    let (head, body) = rule;
+    let Some((x, z)) = plain_literal(head, Some(relation)).and_then(binary_vars) else {
+        return false;
+    };
+    let [first, second] = body.as_slice() else {
+        return false;
+    };
+    let (Some(first), Some(second)) = (plain_literal(first, None), plain_literal(second, None))
+    else {
+        return false;
+    };
+    let shapes = [(source, relation), (relation, source), (relation, relation)];
+    [(first, second), (second, first)]
+        .into_iter()
+        .any(|(left, right)| {
+            let (Some((a, y)), Some((b, c))) = (binary_vars(left), binary_vars(right)) else {
+                return false;
+            };
+            a == x
+                && y == b
+                && c == z
+                && BTreeSet::from([x, y, z]).len() == 3
+                && shapes.contains(&(left.relation.as_str(), right.relation.as_str()))
+        })
+}
+
+// Built-ins called like relations in rule bodies, and evaluated during joins rather than read from
+// tables. Their inputs must be bound by the atoms before them.
+// - x = y: x and y are equal. Either side must be bound, and the other is unified with it, which
+//   binds its variables, destructures compound terms, or just compares symbols.
+// - member(x, xs): x is an element of the list xs (see `value`).
+// - append(xs, ys, zs): zs is xs followed by ys. Either xs and ys must be bound, in which case zs
+//   is computed, or zs must be bound, in which case it's split every way into a list xs and a list
+//   ys.
+// - length(xs, n): n is the number of elements of the list xs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CallBuiltin {
+    Equal,
+    Member,
+    Append,
+    Length,
+}
+
+impl CallBuiltin {
+    // The built-in a literal calls, if any.
+    pub fn of(lit: &LiteralAST) -> Option<Self> {
+        use CallBuiltin::*;
+        match lit.relation.as_str() {
+            EQUAL => Some(Equal),
+            "member" => Some(Member),
+            "append" => Some(Append),
+            "length" => Some(Length),
+            _ => None,
+        }
+    }
+
+    pub fn arity(&self) -> usize {
+        use CallBuiltin::*;
+        match self {
+            Equal | Member | Length => 2,
+            Append => 3,
+        }
+    }
+
+    // Whether a call can be evaluated, given which of its terms are bound.
+    pub fn callable(&self, terms: &[TermAST], is_bound: impl Fn(&TermAST) -> bool) -> bool {
+        use CallBuiltin::*;
+        match self {
+            Equal => is_bound(&terms[0]) || is_bound(&terms[1]),
+            Member => is_bound(&terms[1]),
+            Append => (is_bound(&terms[0]) && is_bound(&terms[1])) || is_bound(&terms[2]),
+            Length => is_bound(&terms[0]),
+        }
+    }
+
+    // Extend bindings with every way of satisfying a call. Calls whose inputs aren't bound, or
+    // whose lists aren't lists, have no solutions.
+    pub fn solve(&self, terms: &[TermAST], bindings: &Bindings) -> Vec<Bindings> {
+        use CallBuiltin::*;
+        let bound = |term: &TermAST| {
+            let bound = term.vars().iter().all(|var| bindings.contains_key(*var));
+            bound.then(|| eval_term(term, bindings)).flatten()
+        };
+        let list = |term: &TermAST| bound(term).and_then(resolve_list);
+        let mut solutions = vec![];
+        let mut unify = |pairs: &[(&TermAST, Symbol)]| {
+            let mut bindings = bindings.clone();
+            if pairs
+                .iter()
+                .all(|(term, symbol)| unify_term(term, *symbol, &mut bindings))
+            {
+                solutions.push(bindings);
+            }
+        };
+        match self {
+            Equal => {
+                if let Some(x) = bound(&terms[0]) {
+                    unify(&[(&terms[1], x)]);
+                } else if let Some(y) = bound(&terms[1]) {
+                    unify(&[(&terms[0], y)]);
+                }
+            }
+            Member => {
+                for elem in list(&terms[1]).unwrap_or_default() {
+                    unify(&[(&terms[0], elem)]);
+                }
+            }
+            Append => {
+                if let Some(xs) = list(&terms[0])
+                    && let Some(ys) = bound(&terms[1])
+                {
+                    if let Some(zs) = intern_list(&xs, ys) {
+                        unify(&[(&terms[2], zs)]);
+                    }
+                } else if let Some(zs) = list(&terms[2]) {
+                    for split in 0..=zs.len() {
+                        let xs = intern_list(&zs[..split], nil()).unwrap();
+                        let ys = intern_list(&zs[split..], nil()).unwrap();
+                        unify(&[(&terms[0], xs), (&terms[1], ys)]);
+                    }
+                }
+            }
+            Length => {
+                if let Some(xs) = list(&terms[0]) {
+                    unify(&[(&terms[1], xs.len() as Symbol)]);
+                }
+            }
+        }
+        solutions
+    }
+}
+
+// A relation computed by Rust code: a function registered with `Environment::register_builtin`,
+// or an external relation registered with `Environment::register_external`. It's scanned with the
+// pattern of a literal reading the relation: the values of the positions bound when it's read, and
+// `None` for the others. It returns tuples of the relation, which should match the pattern. Tuples
+// that don't are skipped, so relations can ignore the pattern, e.g. to enumerate a small relation.
+pub struct NativeBuiltin {
+    arity: usize,
+    relation: Box<dyn ExternalRelation>,
+}
+
+// The tuples of an external relation are produced on demand, as joins scan it, rather than copied
+// into a table, so relations can be backed by large host-side data structures. The iterator
+// returned by `scan` may borrow the relation, and is consumed lazily by bottom-up joins. Relations
+// are shared with the environment, which may be queried from other threads.
+pub trait ExternalRelation: Send + Sync {
+    fn scan<'a>(&'a self, pattern: &[Option<Value>]) -> Box<dyn Iterator<Item = Vec<Value>> + 'a>;
+}
+
+impl<F, I> ExternalRelation for F
+where
+    F: Fn(&[Option<Value>]) -> I + Send + Sync,
+    I: IntoIterator<Item = Vec<Value>>,
+    I::IntoIter: 'static,
+{
+    fn scan<'a>(&'a self, pattern: &[Option<Value>]) -> Box<dyn Iterator<Item = Vec<Value>> + 'a> {
+        Box::new(self(pattern).into_iter())
+    }
+}
+
+impl NativeBuiltin {
+    pub fn new(arity: usize, relation: impl ExternalRelation + 'static) -> Self {
+        Self {
+            arity,
+            relation: Box::new(relation),
+        }
+    }
+
+    pub fn arity(&self) -> usize {
+        self.arity
+    }
+
+    pub fn call<'a>(
+        &'a self,
+        relation: &'a str,
+        pattern: &[Option<Symbol>],
+    ) -> impl Iterator<Item = GroundTuple> + 'a {
+        let pattern: Vec<_> = pattern.iter().map(|bound| bound.map(Value)).collect();
+        self.relation
+            .scan(&pattern)
+            .map(move |tuple| {
+                assert_eq!(
+                    tuple.len(),
+                    self.arity,
+                    "Built-in {} returned a tuple of the wrong arity.",
+                    relation
+                );
+                tuple
+                    .into_iter()
+                    .map(|value| value.0)
+                    .collect::<GroundTuple>()
+            })
+            .filter(move |tuple| {
+                pattern
+                    .iter()
+                    .zip(tuple)
+                    .all(|(bound, symbol)| bound.is_none_or(|bound| bound.0 == *symbol))
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::grammar::ProgramParser;
+    use crate::representation::DNFAssumption;
+
+    use super::*;
+
+    fn table(tuples: &[&[Symbol]]) -> Table<DNFAssumption> {
+        tuples
+            .iter()
+            .map(|tuple| {
+                (
+                    tuple.to_vec(),
+                    (DNFAssumption::one(), DNFAssumption::zero()),
+                )
+            })
+            .collect()
+    }
+
+    fn tuples(derived: Vec<(GroundTuple, DNFAssumption)>) -> BTreeSet<GroundTuple> {
+        derived.into_iter().map(|(tuple, _)| tuple).collect()
+    }
+
+    #[test]
+    fn transitive_closure() {
+        let edges = table(&[&[1, 2], &[2, 3], &[3, 1], &[3, 4]]);
+        let closure = tuples(GraphBuiltin::TransitiveClosure.evaluate(&edges));
+        assert_eq!(closure.len(), 12);
+        assert!(closure.contains(&vec![1, 1]));
+        assert!(closure.contains(&vec![1, 4]));
+        assert!(!closure.contains(&vec![4, 1]));
+    }
+
+    #[test]
+    fn transitive_closure_assumptions() {
+        let leaf = ("E".to_string(), vec![2, 3]);
+        let mut edges = table(&[&[1, 2]]);
+        edges.insert(
+            vec![2, 3],
+            (
+                DNFAssumption::singleton(leaf.clone()),
+                DNFAssumption::zero(),
+            ),
+        );
+        let closure: BTreeMap<_, _> = GraphBuiltin::TransitiveClosure
+            .evaluate(&edges)
+            .into_iter()
+            .collect();
+        assert_eq!(closure[&vec![1, 2]], DNFAssumption::one());
+        assert_eq!(closure[&vec![1, 3]], DNFAssumption::singleton(leaf));
+    }
+
+    #[test]
+    fn strongly_connected() {
+        let edges = table(&[&[1, 2], &[2, 1], &[2, 3], &[3, 4], &[4, 3], &[4, 5]]);
+        let same = tuples(GraphBuiltin::StronglyConnected.evaluate(&edges));
+        let expected = [
+            [1, 1],
+            [1, 2],
+            [2, 1],
+            [2, 2],
+            [3, 3],
+            [3, 4],
+            [4, 3],
+            [4, 4],
+            [5, 5],
+        ];
+        assert_eq!(same, expected.iter().map(|tuple| tuple.to_vec()).collect());
+    }
+
+    #[test]
+    fn shortest_path() {
+        let edges = table(&[&[1, 2, 5], &[1, 3, 1], &[3, 2, 1], &[2, 1, 2], &[2, 4, -1]]);
+        let dist = tuples(GraphBuiltin::ShortestPath.evaluate(&edges));
+        assert!(dist.contains(&vec![1, 2, 2]));
+        assert!(dist.contains(&vec![1, 1, 4]));
+        assert!(dist.contains(&vec![2, 3, 3]));
+        assert!(!dist.contains(&vec![1, 2, 5]));
+        assert!(!dist.iter().any(|tuple| tuple[1] == 4));
+    }
+
+    #[test]
+    fn recognize_transitive_closure() {
+        for recursive in [
+            "P(x, z) :- E(x, y), P(y, z).",
+            "P(x, z) :- P(x, y), E(y, z).",
+            "P(x, z) :- P(x, y), P(y, z).",
+            "P(x, z) :- P(y, z), E(x, y).",
+        ] {
+            let program = format!("P(x, y) :- E(x, y).\n{}", recursive);
+            let parsed = ProgramParser::new().parse(&program).unwrap();
+            let rules: Vec<_> = parsed
+                .iter()
+                .map(|stmt| (stmt.head().unwrap(), stmt.body()))
+                .collect();
+            let recognized = recognize(&rules);
+            assert_eq!(recognized.len(), 1, "{}", recursive);
+            assert_eq!(recognized[0].0.source, "E");
+        }
+
+        for recursive in [
+            "P(x, z) :- E(x, y), P(z, y).",
+            "P(x, x) :- E(x, y), P(y, x).",
+        ] {
+            let program = format!("P(x, y) :- E(x, y).\n{}", recursive);
+            let parsed = ProgramParser::new().parse(&program).unwrap();
+            let rules: Vec<_> = parsed
+                .iter()
+                .map(|stmt| (stmt.head().unwrap(), stmt.body()))
+                .collect();
+            assert!(recognize(&rules).is_empty(), "{}", recursive);
+        }
+    }
+}
@@ -0,0 +1,330 @@
+use std::collections::BTreeMap;
+use std::fmt::Write;
+
+use crate::ast::{AtomAST, LiteralAST, StatementAST, TermAST};
+use crate::builtin::CallBuiltin;
+use crate::representation::Symbol;
+use crate::value::{resolve, resolve_term};
+
+// Ahead-of-time compilation of programs to Rust, for embedding a fixed set of rules without the
+// interpreter. The generated module defines a `Model` struct with a set of tuples per relation,
+// typed by its arity, and a `run` method joining the rules' bodies with nested loops specialized
+// to each rule, evaluated semi-naively to a fixpoint. Atoms whose leading columns are bound read
+// the range of their relation's set with those columns, instead of the whole set.
+//
+// Only plain Datalog is compiled: rules whose heads and bodies are literals of variables and
+// integers. Assumptions, constraints, built-ins, compound terms, and strings are errors, since the
+// generated code only holds integers under the boolean semiring. Questions, outputs, and the like
+// don't change the model, so they're skipped. Facts are inserted by `Model::new`, and relations
+// are public, so facts can also be added before running.
+
+pub fn compile(stmts: &[StatementAST]) -> Result<String, String> {
+    let mut arities: BTreeMap<&str, usize> = BTreeMap::new();
+    let mut facts = vec![];
+    let mut rules = vec![];
+    for stmt in stmts {
+        let unsupported = |what: &str| Err(format!("can't compile `{}`: {}", stmt, what));
+        match stmt {
+            StatementAST::Rule(head, body) => {
+                let AtomAST::Literal(head) = head else {
+                    return unsupported("only literals can be compiled");
+                };
+                let mut lits = vec![];
+                for atom in body {
+                    match atom {
+                        AtomAST::Literal(lit) if CallBuiltin::of(lit).is_none() => lits.push(lit),
+                        AtomAST::Literal(_) => return unsupported("built-ins can't be compiled"),
+                        _ => return unsupported("only literals can be compiled"),
+                    }
+                }
+                for lit in std::iter::once(head).chain(lits.iter().copied()) {
+                    if let Some(reason) = why_not_compiled(lit) {
+                        return unsupported(reason);
+                    }
+                    let arity = *arities.entry(&lit.relation).or_insert(lit.terms.len());
+                    if arity != lit.terms.len() {
+                        return unsupported(&format!(
+                            "relation {} has arity {}, but is used with {} terms",
+                            lit.relation,
+                            arity,
+                            lit.terms.len()
+                        ));
+                    }
+                }
+                let bound: Vec<_> = lits.iter().flat_map(|lit| lit.vars()).collect();
+                if head.vars().any(|var| !bound.contains(&var)) {
+                    return unsupported("a variable of the head isn't bound by the body");
+                }
+                if lits.is_empty() {
+                    facts.push(head);
+                } else {
+                    rules.push((stmt, head, lits));
+                }
+            }
+            StatementAST::Input(relation, arity) => {
+                if *arities.entry(relation).or_insert(*arity) != *arity {
+                    return unsupported("the relation is used with another arity");
+                }
+            }
+            StatementAST::Decl(_, _, Some(_)) => {
+                return unsupported("lattice columns can't be compiled");
+            }
+            StatementAST::Question(_, _, _, _)
+            | StatementAST::Output(_)
+            | StatementAST::LintLevel(_, _)
+            | StatementAST::Pragma(_)
+            | StatementAST::Decl(_, _, None) => {}
+            StatementAST::Abduce(_)
+            | StatementAST::Diff(_, _)
+            | StatementAST::Expect(_, _)
+            | StatementAST::Builtin(_)
+            | StatementAST::Constraint(_)
+            | StatementAST::Abducible(_)
+            | StatementAST::Weight(_, _)
+            | StatementAST::Probability(_, _) => {
+                return unsupported("only rules can be compiled");
+            }
+        }
+    }
+
+    let mut out = String::from(
+        "// Generated by `implog-cli compile`. Relations are sets of tuples, and `Model::run` \
+         derives\n// every tuple the program's rules derive from the tuples in the model.\n\n\
+         use std::collections::BTreeSet;\n\npub type Symbol = i64;\n\n\
+         #[allow(non_snake_case)]\n#[derive(Debug, Clone, Default, PartialEq, Eq)]\n\
+         pub struct Model {\n",
+    );
+    for (relation, arity) in &arities {
+        writeln!(
+            out,
+            "    pub {}: BTreeSet<{}>,",
+            field(relation),
+            tuple(&vec!["Symbol".to_string(); *arity])
+        )
+        .unwrap();
+    }
+    out.push_str("}\n\nimpl Model {\n    // The model holding the program's facts.\n");
+    out.push_str("    pub fn new() -> Self {\n        let mut model = Self::default();\n");
+    for fact in facts {
+        let terms: Vec<_> = fact.terms.iter().map(|term| expr(term, "")).collect();
+        let (relation, terms) = (field(&fact.relation), tuple(&terms));
+        writeln!(out, "        model.{}.insert({});", relation, terms).unwrap();
+    }
+    out.push_str("        model\n    }\n\n");
+    let empty: Vec<_> = arities
+        .keys()
+        .map(|relation| format!("self.{}.is_empty()", field(relation)))
+        .collect();
+    let empty = if empty.is_empty() {
+        "true".to_string()
+    } else {
+        empty.join("\n            && ")
+    };
+    writeln!(
+        out,
+        "    pub fn is_empty(&self) -> bool {{\n        {}\n    }}\n",
+        empty
+    )
+    .unwrap();
+
+    // Each round joins each rule once per body literal, reading the tuples new in the last round
+    // with that literal, and every tuple with the others. Every tuple is new in the first round.
+    out.push_str("    pub fn run(&mut self) {\n        let mut delta = self.clone();\n");
+    out.push_str("        while !delta.is_empty() {\n            let mut new = Self::default();\n");
+    for (stmt, head, lits) in &rules {
+        writeln!(out, "            // {}", stmt).unwrap();
+        for idx in 0..lits.len() {
+            join(&mut out, head, lits, idx);
+        }
+    }
+    for relation in arities.keys() {
+        let relation = field(relation);
+        writeln!(
+            out,
+            "            new.{0}.retain(|tuple| !self.{0}.contains(tuple));\n            \
+             self.{0}.extend(new.{0}.iter().copied());",
+            relation
+        )
+        .unwrap();
+    }
+    out.push_str("            delta = new;\n        }\n    }\n}\n");
+    Ok(out)
+}
+
+fn why_not_compiled(lit: &LiteralAST) -> Option<&'static str> {
+    for term in &lit.terms {
+        match term {
+            TermAST::Variable(_) => {}
+            TermAST::Constant(symbol) if compiled_constant(*symbol) => {}
+            TermAST::Constant(_) => return Some("only integer constants can be compiled"),
+            TermAST::Parameter(_) => return Some("parameters must be substituted first"),
+            TermAST::Compound(_, _) => return Some("compound terms can't be compiled"),
+        }
+    }
+    None
+}
+
+fn compiled_constant(symbol: Symbol) -> bool {
+    resolve(symbol).is_none() && resolve_term(symbol).is_none()
+}
+
+// Emit the join of a rule's body reading the last round's new tuples with the literal at `delta`.
+// Each literal opens a loop over the tuples it matches (or a membership test, if all its columns
+// are bound), and the head's tuple is inserted in the innermost.
+fn join(out: &mut String, head: &LiteralAST, lits: &[&LiteralAST], delta: usize) {
+    // Variables used once are matched by `_`, so the generated code has no unused variables.
+    let mut uses: BTreeMap<&str, usize> = BTreeMap::new();
+    for var in head.vars().chain(lits.iter().flat_map(|lit| lit.vars())) {
+        *uses.entry(var).or_default() += 1;
+    }
+    let mut bound: Vec<&str> = vec![];
+    let mut fresh = 0;
+    let mut depth = 3;
+    for (idx, lit) in lits.iter().enumerate() {
+        let source = if idx == delta { "delta" } else { "self" };
+        let set = format!("{}.{}", source, field(&lit.relation));
+        let is_bound = |term: &TermAST| match term {
+            TermAST::Variable(var) => bound.contains(&var.as_str()),
+            _ => true,
+        };
+        let indent = "    ".repeat(depth);
+        if lit.terms.iter().all(is_bound) {
+            let terms: Vec<_> = lit.terms.iter().map(|term| expr(term, "v_")).collect();
+            writeln!(out, "{}if {}.contains(&{}) {{", indent, set, tuple(&terms)).unwrap();
+            depth += 1;
+            continue;
+        }
+
+        let prefix = lit.terms.iter().take_while(|term| is_bound(term)).count();
+        let mut pattern = vec![];
+        let mut tests = vec![];
+        for (pos, term) in lit.terms.iter().enumerate() {
+            if pos < prefix {
+                pattern.push("_".to_string());
+                continue;
+            }
+            match term {
+                TermAST::Variable(var) if !bound.contains(&var.as_str()) => {
+                    bound.push(var);
+                    if uses[var.as_str()] == 1 {
+                        pattern.push("_".to_string());
+                    } else {
+                        pattern.push(format!("v_{}", var));
+                    }
+                }
+                _ => {
+                    fresh += 1;
+                    pattern.push(format!("t{}", fresh));
+                    tests.push(format!("t{} != {}", fresh, expr(term, "v_")));
+                }
+            }
+        }
+        let rows = if prefix == 0 {
+            format!("&{}", set)
+        } else {
+            let bound: Vec<_> = lit.terms[..prefix]
+                .iter()
+                .map(|term| expr(term, "v_"))
+                .collect();
+            let bounds = |extreme: &str| {
+                let rest = vec![format!("Symbol::{}", extreme); lit.terms.len() - prefix];
+                tuple(&[bound.clone(), rest].concat())
+            };
+            format!("{}.range({}..={})", set, bounds("MIN"), bounds("MAX"))
+        };
+        writeln!(out, "{}for &{} in {} {{", indent, tuple(&pattern), rows).unwrap();
+        if !tests.is_empty() {
+            writeln!(out, "{}    if {} {{", indent, tests.join(" || ")).unwrap();
+            writeln!(out, "{}        continue;\n{}    }}", indent, indent).unwrap();
+        }
+        depth += 1;
+    }
+    let terms: Vec<_> = head.terms.iter().map(|term| expr(term, "v_")).collect();
+    let indent = "    ".repeat(depth);
+    let relation = field(&head.relation);
+    writeln!(out, "{}new.{}.insert({});", indent, relation, tuple(&terms)).unwrap();
+    for depth in (3..depth).rev() {
+        writeln!(out, "{}}}", "    ".repeat(depth)).unwrap();
+    }
+}
+
+// Variables are prefixed, so they can't clash with keywords or the generated code's own names.
+fn expr(term: &TermAST, prefix: &str) -> String {
+    match term {
+        TermAST::Variable(var) => format!("{}{}", prefix, var),
+        TermAST::Constant(symbol) => symbol.to_string(),
+        TermAST::Parameter(_) | TermAST::Compound(_, _) => unreachable!(),
+    }
+}
+
+fn tuple(items: &[String]) -> String {
+    match items {
+        [item] => format!("({},)", item),
+        _ => format!("({})", items.join(", ")),
+    }
+}
+
+// Relations qualified by modules, like `graph::Edge`, become fields like `graph__Edge`.
+fn field(relation: &str) -> String {
+    let field = relation.replace("::", "__");
+    if KEYWORDS.contains(&field.as_str()) {
+        format!("r#{}", field)
+    } else {
+        field
+    }
+}
+
+const KEYWORDS: [&str; 38] = [
+    "as", "async", "await", "break", "const", "continue", "dyn", "else", "enum", "extern", "false",
+    "fn", "for", "gen", "if", "impl", "in", "let", "loop", "match", "mod", "move", "mut", "pub",
+    "ref", "return", "static", "struct", "trait", "true", "type", "unsafe", "use", "where",
+    "while", "abstract", "become", "box",
+];
+
+#[cfg(test)]
+mod tests {
+    use crate::grammar::ProgramParser;
+
+    use super::*;
+
+    #[test]
+    fn compile_rules() {
+        let program = "E(1, 2) :- .\nE(2, 3) :- .\nP(x, y) :- E(x, y).\n\
+                       P(x, z) :- P(x, y), E(y, z).\nL(x) :- E(x, x), P(x, 1).\n? P(1, y).\n";
+        let parsed = ProgramParser::new().parse(program).unwrap();
+        let compiled = compile(&parsed).unwrap();
+        assert!(
+            compiled.contains(
+                "    pub E: BTreeSet<(Symbol, Symbol)>,\n    pub L: BTreeSet<(Symbol,)>,\n"
+            )
+        );
+        assert!(compiled.contains("        model.E.insert((1, 2));\n"));
+        // The second literal of the recursive rule reads the range of edges from `y`.
+        assert!(compiled.contains(
+            "            for &(v_x, v_y) in &delta.P {\n                \
+             for &(_, v_z) in self.E.range((v_y, Symbol::MIN)..=(v_y, Symbol::MAX)) {\n                    \
+             new.P.insert((v_x, v_z));\n"
+        ));
+        assert!(compiled.contains(
+            "            for &(v_x, t1) in &self.E {\n                if t1 != v_x {\n                    \
+             continue;\n                }\n                if delta.P.contains(&(v_x, 1)) {\n"
+        ));
+
+        let program = "P(x) :- E(x), x = 1.\n";
+        let parsed = ProgramParser::new().parse(program).unwrap();
+        assert_eq!(
+            compile(&parsed).unwrap_err(),
+            "can't compile `P(x) :- E(x), x = 1.`: built-ins can't be compiled"
+        );
+        let program = "P(\"a\") :- .\n";
+        let parsed = ProgramParser::new().parse(program).unwrap();
+        assert!(
+            compile(&parsed)
+                .unwrap_err()
+                .ends_with("only integer constants can be compiled")
+        );
+        let program = "P(x) :- E(x), [A(x)].\n";
+        let parsed = ProgramParser::new().parse(program).unwrap();
+        assert!(compile(&parsed).is_err());
+    }
+}
@@ -0,0 +1,335 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::Write;
+
+use crate::ast::{AtomAST, StatementAST, module_of};
+use crate::lint::writes_reads;
+
+// The dependency graph of a program's relations: a relation depends on the relations read by the
+// rules and built-ins deriving it. Relations are grouped into strongly connected components
+// (mutually recursive relations), and each component is assigned a stratum, so that every
+// relation's dependencies are in its own stratum or an earlier one. Relations depending on
+// nothing, like those only holding facts, are in stratum 0.
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RuleDependencies {
+    // The relations the rule derives and reads. Rules are counted from 0 among the program's
+    // rules, like in proofs.
+    pub writes: Vec<String>,
+    pub reads: Vec<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DependencyGraph {
+    pub rules: Vec<RuleDependencies>,
+    // The relations each relation depends on directly.
+    pub dependencies: BTreeMap<String, BTreeSet<String>>,
+    // Strongly connected components, each listed after every component it depends on.
+    pub components: Vec<BTreeSet<String>>,
+    pub strata: BTreeMap<String, usize>,
+}
+
+impl DependencyGraph {
+    // Whether a relation depends on itself, directly or through other relations.
+    pub fn is_recursive(&self, relation: &str) -> bool {
+        let in_cycle = self
+            .components
+            .iter()
+            .any(|component| component.len() > 1 && component.contains(relation));
+        in_cycle
+            || self
+                .dependencies
+                .get(relation)
+                .is_some_and(|deps| deps.contains(relation))
+    }
+
+    // The modules each module's relations depend on directly, other than itself (see
+    // `qualify_module`). Relations that aren't in a module aren't counted.
+    pub fn module_dependencies(&self) -> BTreeMap<&str, BTreeSet<&str>> {
+        let mut modules: BTreeMap<&str, BTreeSet<&str>> = BTreeMap::new();
+        for (relation, deps) in &self.dependencies {
+            let Some(module) = module_of(relation) else {
+                continue;
+            };
+            let deps = deps
+                .iter()
+                .filter_map(|dep| module_of(dep))
+                .filter(|dep| *dep != module);
+            modules.entry(module).or_default().extend(deps);
+        }
+        modules
+    }
+}
+
+pub fn dependency_graph(stmts: &[StatementAST]) -> DependencyGraph {
+    let mut rules = vec![];
+    let mut dependencies: BTreeMap<String, BTreeSet<String>> = BTreeMap::new();
+    for stmt in stmts {
+        let (StatementAST::Rule(_, _) | StatementAST::Builtin(_)) = stmt else {
+            continue;
+        };
+        let (writes, reads) = writes_reads(stmt);
+        for relation in writes.iter().chain(&reads) {
+            dependencies.entry(relation.to_string()).or_default();
+        }
+        for relation in &writes {
+            let deps = dependencies.get_mut(*relation).unwrap();
+            deps.extend(reads.iter().map(|read| read.to_string()));
+        }
+        if let StatementAST::Rule(_, _) = stmt {
+            rules.push(RuleDependencies {
+                writes: writes.iter().map(|write| write.to_string()).collect(),
+                reads: reads.iter().map(|read| read.to_string()).collect(),
+            });
+        }
+    }
+
+    let components = Tarjan::new(&dependencies).components();
+    let mut strata = BTreeMap::new();
+    for component in &components {
+        let stratum = component
+            .iter()
+            .flat_map(|relation| &dependencies[relation])
+            .filter(|dep| !component.contains(*dep))
+            .map(|dep| strata[dep] + 1)
+            .max()
+            .unwrap_or(0);
+        for relation in component {
+            strata.insert(relation.clone(), stratum);
+        }
+    }
+
+    DependencyGraph {
+        rules,
+        dependencies,
+        components,
+        strata,
+    }
+}
+
+// Render the dependency graph of a program in Graphviz's DOT language. Relations are nodes, and
+// each rule or built-in adds an edge from every relation it reads to every relation it derives.
+// Edges are labeled with the rule (counted from 0 among the program's rules) or built-in, and with
+// how the relation is read: bracket reads are dashed, and reads on either side of an arrow are
+// dotted. Recursive components are drawn as clusters.
+pub fn dependency_dot(stmts: &[StatementAST]) -> String {
+    let graph = dependency_graph(stmts);
+    let mut dot = String::from("digraph dependencies {\n");
+    let mut clustered = BTreeSet::new();
+    for (idx, component) in graph.components.iter().enumerate() {
+        let first = component.first().unwrap();
+        if !graph.is_recursive(first) {
+            continue;
+        }
+        writeln!(dot, "  subgraph cluster_{} {{", idx).unwrap();
+        writeln!(dot, "    label = \"recursive\";").unwrap();
+        for relation in component {
+            writeln!(dot, "    \"{}\";", relation).unwrap();
+            clustered.insert(relation);
+        }
+        dot.push_str("  }\n");
+    }
+    for relation in graph.dependencies.keys() {
+        if !clustered.contains(relation) {
+            writeln!(dot, "  \"{}\";", relation).unwrap();
+        }
+    }
+
+    let mut rule = 0;
+    for stmt in stmts {
+        match stmt {
+            StatementAST::Rule(_, body) => {
+                let (writes, _) = writes_reads(stmt);
+                for atom in body {
+                    let (reads, usage, style) = match atom {
+                        AtomAST::Literal(lit) => (vec![lit], "", "solid"),
+                        AtomAST::Brackets(lit) => (vec![lit], ", bracket", "dashed"),
+                        AtomAST::Arrow(lhs, rhs) => (vec![lhs, rhs], ", arrow", "dotted"),
+                    };
+                    for read in reads {
+                        for write in &writes {
+                            writeln!(
+                                dot,
+                                "  \"{}\" -> \"{}\" [label = \"rule {}{}\", style = {}];",
+                                read.relation, write, rule, usage, style
+                            )
+                            .unwrap();
+                        }
+                    }
+                }
+                rule += 1;
+            }
+            StatementAST::Builtin(builtin) => {
+                writeln!(
+                    dot,
+                    "  \"{}\" -> \"{}\" [label = \"{}\"];",
+                    builtin.source,
+                    builtin.relation,
+                    builtin.kind.name()
+                )
+                .unwrap();
+            }
+            _ => {}
+        }
+    }
+    dot.push_str("}\n");
+    dot
+}
+
+// Tarjan's algorithm, which finds components after every component they depend on.
+struct Tarjan<'a> {
+    graph: &'a BTreeMap<String, BTreeSet<String>>,
+    index: BTreeMap<&'a str, usize>,
+    low: BTreeMap<&'a str, usize>,
+    stack: Vec<&'a str>,
+    on_stack: BTreeSet<&'a str>,
+    components: Vec<BTreeSet<String>>,
+}
+
+impl<'a> Tarjan<'a> {
+    fn new(graph: &'a BTreeMap<String, BTreeSet<String>>) -> Self {
+        Self {
+            graph,
+            index: BTreeMap::new(),
+            low: BTreeMap::new(),
+            stack: vec![],
+            on_stack: BTreeSet::new(),
+            components: vec![],
+        }
+    }
+
+    fn components(mut self) -> Vec<BTreeSet<String>> {
+        for relation in self.graph.keys() {
+            if !self.index.contains_key(relation.as_str()) {
+                self.visit(relation);
+            }
+        }
+        self.components
+    }
+
+    fn visit(&mut self, relation: &'a str) {
+        let index = self.index.len();
+        self.index.insert(relation, index);
+        self.low.insert(relation, index);
+        self.stack.push(relation);
+        self.on_stack.insert(relation);
+
+        for dep in &self.graph[relation] {
+            if !self.index.contains_key(dep.as_str()) {
+                self.visit(dep);
+                let low = self.low[relation].min(self.low[dep.as_str()]);
+                self.low.insert(relation, low);
+            } else if self.on_stack.contains(dep.as_str()) {
+                let low = self.low[relation].min(self.index[dep.as_str()]);
+                self.low.insert(relation, low);
+            }
+        }
+
+        if self.low[relation] == index {
+            let mut component = BTreeSet::new();
+            loop {
+                let member = self.stack.pop().unwrap();
+                self.on_stack.remove(member);
+                component.insert(member.to_string());
+                if member == relation {
+                    break;
+                }
+            }
+            self.components.push(component);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::grammar::ProgramParser;
+
+    use super::*;
+
+    #[test]
+    fn stratify_program() {
+        let program = r#"
+E(1, 2) :- .
+P(x, y) :- E(x, y).
+P(x, z) :- P(x, y), Q(y, z).
+Q(x, y) :- P(y, x).
+R(x) :- P(x, x).
+.builtin S = scc(E).
+"#;
+        let parsed = ProgramParser::new().parse(program).unwrap();
+        let graph = dependency_graph(&parsed);
+        assert_eq!(graph.rules.len(), 5);
+        assert_eq!(graph.rules[2].reads, vec!["P", "Q"]);
+        assert_eq!(
+            graph.components,
+            vec![
+                BTreeSet::from(["E".to_string()]),
+                BTreeSet::from(["P".to_string(), "Q".to_string()]),
+                BTreeSet::from(["R".to_string()]),
+                BTreeSet::from(["S".to_string()]),
+            ]
+        );
+        let strata: Vec<_> = ["E", "P", "Q", "R", "S"]
+            .iter()
+            .map(|relation| graph.strata[*relation])
+            .collect();
+        assert_eq!(strata, vec![0, 1, 1, 2, 1]);
+        assert!(graph.is_recursive("Q"));
+        assert!(!graph.is_recursive("R"));
+    }
+
+    #[test]
+    fn module_dependencies() {
+        let program = r#"
+module graph {
+    Edge(1, 2) :- .
+    Path(x, y) :- Edge(x, y).
+}
+module query {
+    Reach(y) :- graph::Path(0, y), Start(y).
+    module local { Seen(y) :- Reach(y). }
+}
+Start(1) :- .
+"#;
+        let parsed = ProgramParser::new().parse(program).unwrap();
+        let graph = dependency_graph(&parsed);
+        assert!(graph.dependencies["query::Reach"].contains("Start"));
+        assert_eq!(
+            graph.module_dependencies(),
+            BTreeMap::from([
+                ("graph", BTreeSet::new()),
+                ("query", BTreeSet::from(["graph"])),
+                ("query::local", BTreeSet::from(["query"])),
+            ])
+        );
+    }
+
+    #[test]
+    fn render_dot() {
+        let program = r#"
+E(1, 2) :- .
+P(x, y) :- E(x, y), [A(y)].
+P(x, z) :- P(x, y), A(y) -> E(y, z).
+.builtin S = scc(P).
+"#;
+        let parsed = ProgramParser::new().parse(program).unwrap();
+        assert_eq!(
+            dependency_dot(&parsed),
+            r#"digraph dependencies {
+  subgraph cluster_2 {
+    label = "recursive";
+    "P";
+  }
+  "A";
+  "E";
+  "S";
+  "E" -> "P" [label = "rule 1", style = solid];
+  "A" -> "P" [label = "rule 1, bracket", style = dashed];
+  "P" -> "P" [label = "rule 2", style = solid];
+  "A" -> "P" [label = "rule 2, arrow", style = dotted];
+  "E" -> "P" [label = "rule 2, arrow", style = dotted];
+  "P" -> "S" [label = "scc"];
+}
+"#
+        );
+    }
+}
@@ -0,0 +1,93 @@
+use std::fs::{read_to_string, write};
+use std::io::{Error, ErrorKind, Result};
+use std::path::{Path, PathBuf};
+
+use crate::representation::GroundTuple;
+use crate::value::{Value, parse_value};
+
+// Fact files hold one ground tuple per line, with symbols separated by tabs (the same layout as
+// Soufflé's `.facts` files). Strings are written in double quotes. Input relations are loaded from
+// `<Rel>.facts` and output relations are dumped to `<Rel>.csv`.
+pub fn input_path(dir: &Path, relation: &str) -> PathBuf {
+    dir.join(format!("{}.facts", relation))
+}
+
+pub fn output_path(dir: &Path, relation: &str) -> PathBuf {
+    dir.join(format!("{}.csv", relation))
+}
+
+pub fn parse_facts(contents: &str, arity: usize) -> Result<Vec<GroundTuple>> {
+    let mut tuples = vec![];
+    for (line_idx, line) in contents.lines().enumerate() {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let invalid = |msg: String| Error::new(ErrorKind::InvalidData, msg);
+        let tuple = line
+            .split('\t')
+            .map(|symbol| {
+                parse_value(symbol.trim()).ok_or_else(|| {
+                    invalid(format!(
+                        "line {}: invalid symbol `{}`",
+                        line_idx + 1,
+                        symbol
+                    ))
+                })
+            })
+            .collect::<Result<GroundTuple>>()?;
+        if tuple.len() != arity {
+            return Err(invalid(format!(
+                "line {}: expected {} symbols, found {}",
+                line_idx + 1,
+                arity,
+                tuple.len()
+            )));
+        }
+        tuples.push(tuple);
+    }
+    Ok(tuples)
+}
+
+pub fn load_facts(path: &Path, arity: usize) -> Result<Vec<GroundTuple>> {
+    parse_facts(&read_to_string(path)?, arity)
+}
+
+pub fn dump_facts<'a>(path: &Path, tuples: impl Iterator<Item = &'a GroundTuple>) -> Result<()> {
+    let mut contents = String::new();
+    for tuple in tuples {
+        let symbols: Vec<String> = tuple
+            .iter()
+            .map(|symbol| Value(*symbol).to_string())
+            .collect();
+        contents.push_str(&symbols.join("\t"));
+        contents.push('\n');
+    }
+    write(path, contents)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_facts_tabs() {
+        let facts = parse_facts("1\t2\n\n-3\t4\n", 2).unwrap();
+        assert_eq!(facts, vec![vec![1, 2], vec![-3, 4]]);
+    }
+
+    #[test]
+    fn parse_facts_strings() {
+        let facts = parse_facts("\"alice\"\t1\n", 2).unwrap();
+        assert_eq!(facts, vec![vec![parse_value("\"alice\"").unwrap(), 1]]);
+    }
+
+    #[test]
+    fn parse_facts_bad_arity() {
+        assert!(parse_facts("1\t2\t3\n", 2).is_err());
+    }
+
+    #[test]
+    fn parse_facts_bad_symbol() {
+        assert!(parse_facts("1\tx\n", 2).is_err());
+    }
+}
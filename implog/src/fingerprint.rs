@@ -0,0 +1,73 @@
+use std::collections::BTreeMap;
+
+use crate::ast::{StatementAST, TermAST};
+
+// Stable fingerprints of programs. Programs that differ only in the names of variables, or in the
+// order of consecutive rules, have the same fingerprint. Questions only see the rules before them,
+// so rules aren't reordered across other statements.
+//
+// Programs are normalized, displayed in surface syntax, and hashed with 64-bit FNV-1a, which
+// (unlike the standard library's hasher) is stable across platforms and compiler versions.
+
+pub fn fingerprint(stmts: &[StatementAST]) -> u64 {
+    let mut lines = vec![];
+    let mut rules = vec![];
+    for stmt in stmts {
+        let line = normalize(stmt).to_string();
+        if let StatementAST::Rule(_, _) = stmt {
+            rules.push(line);
+        } else {
+            rules.sort();
+            lines.append(&mut rules);
+            lines.push(line);
+        }
+    }
+    rules.sort();
+    lines.append(&mut rules);
+
+    let mut hash: u64 = 0xcbf29ce484222325;
+    for byte in lines.join("\n").bytes() {
+        hash ^= u64::from(byte);
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+    hash
+}
+
+// Rename the variables of a statement to `v0`, `v1`, ..., in order of first occurrence.
+fn normalize(stmt: &StatementAST) -> StatementAST {
+    let mut stmt = stmt.clone();
+    let mut names: BTreeMap<String, String> = BTreeMap::new();
+    for atom in stmt.atoms_mut() {
+        for lit in atom.literals_mut() {
+            for term in lit.terms.iter_mut().flat_map(TermAST::leaves_mut) {
+                if let TermAST::Variable(var) = term {
+                    let next = format!("v{}", names.len());
+                    *var = names.entry(var.clone()).or_insert(next).clone();
+                }
+            }
+        }
+    }
+    stmt
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::grammar::ProgramParser;
+
+    use super::*;
+
+    fn fingerprint_of(program: &str) -> u64 {
+        fingerprint(&ProgramParser::new().parse(program).unwrap())
+    }
+
+    #[test]
+    fn fingerprint_normalizes() {
+        let program = "E(1, 2) :- .\nP(x, y) :- E(x, y).\n? P(1, x).\n";
+        let renamed = "P(a, b) :- E(a, b).\nE(1, 2) :- .\n? P(1, y).\n";
+        let moved = "E(1, 2) :- .\n? P(1, x).\nP(x, y) :- E(x, y).\n";
+        let changed = "E(1, 2) :- .\nP(x, y) :- E(y, x).\n? P(1, x).\n";
+        assert_eq!(fingerprint_of(program), fingerprint_of(renamed));
+        assert_ne!(fingerprint_of(program), fingerprint_of(moved));
+        assert_ne!(fingerprint_of(program), fingerprint_of(changed));
+    }
+}
@@ -0,0 +1,203 @@
+use std::fs::read_to_string;
+use std::path::{Path, PathBuf};
+
+use crate::ast::{Span, StatementAST, parse_recovering};
+
+// Programs spread over several files. A line `#include "lib.dl"` splices the statements of another
+// file into a program where the line is, so libraries of rules can be shared between programs.
+// Included paths are resolved relative to the directory of the including file. A file included
+// more than once is only read the first time, and a file including itself (through any chain of
+// includes) is an error. Directives are blanked out before a file is parsed, so the spans of its
+// statements still point into the file as written.
+
+pub struct SourceFile {
+    pub name: String,
+    pub text: String,
+}
+
+// The statements of a program, with the file (an index into `files`) and span each was parsed from.
+pub struct Program {
+    pub files: Vec<SourceFile>,
+    pub stmts: Vec<StatementAST>,
+    pub origins: Vec<(usize, Span)>,
+}
+
+impl Program {
+    // A message about the statement at an index (counted from 0), located in its file.
+    pub fn locate(&self, idx: usize, message: &str) -> String {
+        self.locate_at(idx, None, message)
+    }
+
+    // A message about a span of the statement at an index, such as a literal or term of it, or
+    // the whole statement if there's no span.
+    pub fn locate_at(&self, idx: usize, span: Option<Span>, message: &str) -> String {
+        let (file, stmt_span) = self.origins[idx];
+        let file = &self.files[file];
+        span.unwrap_or(stmt_span)
+            .locate(&file.name, &file.text, message)
+    }
+}
+
+// Expand the includes of a program read from a file named `name` in the directory `dir`. Returns
+// the statements that parsed, and every error found, located in the file it was found in.
+pub fn expand(name: &str, dir: &Path, text: String) -> (Program, Vec<String>) {
+    let mut expander = Expander {
+        program: Program {
+            files: vec![],
+            stmts: vec![],
+            origins: vec![],
+        },
+        included: vec![],
+        errors: vec![],
+    };
+    expander.expand(name.to_string(), dir, text, &mut vec![]);
+    (expander.program, expander.errors)
+}
+
+struct Expander {
+    program: Program,
+    // The canonical paths of the files read so far.
+    included: Vec<PathBuf>,
+    errors: Vec<String>,
+}
+
+impl Expander {
+    // `stack` holds the canonical paths of the files including this one.
+    fn expand(&mut self, name: String, dir: &Path, text: String, stack: &mut Vec<PathBuf>) {
+        let directives = directives(&text);
+        let mut blanked = text.clone();
+        for (span, _) in &directives {
+            blanked.replace_range(span.start..span.end, &" ".repeat(span.end - span.start));
+        }
+        let file = self.program.files.len();
+        let (located, errors) = parse_recovering(&name, &blanked);
+        self.errors.extend(errors);
+        self.program.files.push(SourceFile { name, text });
+
+        let mut located = located.into_iter().peekable();
+        for (span, path) in directives {
+            while let Some((stmt_span, stmt)) =
+                located.next_if(|(stmt_span, _)| stmt_span.start < span.start)
+            {
+                self.program.stmts.push(stmt);
+                self.program.origins.push((file, stmt_span));
+            }
+            let located_error = |message: &str| {
+                let file = &self.program.files[file];
+                span.locate(&file.name, &file.text, message)
+            };
+            let Some(path) = path else {
+                self.errors
+                    .push(located_error("an include isn't a quoted path"));
+                continue;
+            };
+            let path = dir.join(path);
+            let canonical = match path.canonicalize() {
+                Ok(canonical) => canonical,
+                Err(err) => {
+                    let message = format!("can't include {}: {}", path.display(), err);
+                    self.errors.push(located_error(&message));
+                    continue;
+                }
+            };
+            if stack.contains(&canonical) {
+                let message = format!("{} includes itself", path.display());
+                self.errors.push(located_error(&message));
+                continue;
+            }
+            if self.included.contains(&canonical) {
+                continue;
+            }
+            let included = match read_to_string(&canonical) {
+                Ok(included) => included,
+                Err(err) => {
+                    let message = format!("can't include {}: {}", path.display(), err);
+                    self.errors.push(located_error(&message));
+                    continue;
+                }
+            };
+            self.included.push(canonical.clone());
+            let included_dir = path.parent().unwrap_or(dir).to_path_buf();
+            stack.push(canonical);
+            self.expand(path.display().to_string(), &included_dir, included, stack);
+            stack.pop();
+        }
+        for (stmt_span, stmt) in located {
+            self.program.stmts.push(stmt);
+            self.program.origins.push((file, stmt_span));
+        }
+    }
+}
+
+// The include directives of a file: the span of each directive's line, and the path it includes,
+// or `None` if the path isn't quoted.
+fn directives(text: &str) -> Vec<(Span, Option<String>)> {
+    let mut directives = vec![];
+    let mut start = 0;
+    for line in text.split_inclusive('\n') {
+        let end = start + line.trim_end().len();
+        if let Some(rest) = line.trim_start().strip_prefix("#include") {
+            let path = rest
+                .trim()
+                .strip_prefix('"')
+                .and_then(|rest| rest.strip_suffix('"'))
+                .filter(|path| !path.contains('"'))
+                .map(str::to_string);
+            directives.push((Span { start, end }, path));
+        }
+        start += line.len();
+    }
+    directives
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs::{create_dir_all, write};
+
+    use super::*;
+
+    #[test]
+    fn include_files() {
+        let dir = std::env::temp_dir().join(format!("implog-include-{}", std::process::id()));
+        create_dir_all(dir.join("lib")).unwrap();
+        write(
+            dir.join("lib/path.dl"),
+            "#include \"edges.dl\"\nP(x, y) :- E(x, y).\nP(x, z) :- P(x, y), E(y, z).\n",
+        )
+        .unwrap();
+        write(dir.join("lib/edges.dl"), "E(1, 2) :- .\nE(2, 3) :- .\n").unwrap();
+        write(dir.join("lib/cycle.dl"), "#include \"cycle.dl\"\n").unwrap();
+
+        let main = "N(0) :- .\n#include \"lib/path.dl\"\n#include \"lib/edges.dl\"\n? P(x, y).\n";
+        let (program, errors) = expand("main.dl", &dir, main.to_string());
+        assert!(errors.is_empty());
+        let stmts: Vec<_> = program.stmts.iter().map(ToString::to_string).collect();
+        assert_eq!(
+            stmts,
+            vec![
+                "N(0) :- .",
+                "E(1, 2) :- .",
+                "E(2, 3) :- .",
+                "P(x, y) :- E(x, y).",
+                "P(x, z) :- P(x, y), E(y, z).",
+                "? P(x, y).",
+            ]
+        );
+        assert!(
+            program
+                .locate(4, "here")
+                .ends_with("lib/path.dl:3:1: here\n  | P(x, z) :- P(x, y), E(y, z).")
+        );
+
+        let main = "#include \"lib/cycle.dl\"\n#include \"missing.dl\"\n#include lib\nP(x :- .\n";
+        let (program, errors) = expand("main.dl", &dir, main.to_string());
+        assert!(program.stmts.is_empty());
+        assert_eq!(errors.len(), 4);
+        assert!(errors[0].starts_with("main.dl:4:5: "));
+        assert!(errors[1].contains("lib/cycle.dl:1:1: "));
+        assert!(errors[1].ends_with("includes itself\n  | #include \"cycle.dl\""));
+        assert!(errors[2].starts_with("main.dl:2:1: can't include"));
+        assert!(errors[3].starts_with("main.dl:3:1: an include isn't a quoted path"));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,216 @@
+use std::collections::BTreeSet;
+
+use crate::ast::{AtomAST, EQUAL, LiteralAST, Span, StatementAST};
+
+// An index of where relations are defined and used in a parsed program, for editor tooling (see
+// the `implog-lsp` binary). A relation is defined by the heads of rules and facts, and by `.input`,
+// `.decl`, and `.builtin` statements. Every other mention of a relation is a use.
+pub struct SymbolIndex {
+    occurrences: Vec<Occurrence>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Occurrence {
+    // The span of the relation's name.
+    pub span: Span,
+    pub relation: String,
+    pub definition: bool,
+}
+
+impl SymbolIndex {
+    // Index a program parsed by `LocatedProgramParser` from `source`.
+    pub fn new(source: &str, stmts: &[(Span, StatementAST)]) -> Self {
+        let mut occurrences = vec![];
+        for (span, stmt) in stmts {
+            statement_occurrences(source, *span, stmt, &mut occurrences);
+        }
+        Self { occurrences }
+    }
+
+    // The occurrence of a relation at a byte offset, including the offset just past its name, where
+    // an editor's cursor is after typing it.
+    pub fn occurrence_at(&self, offset: usize) -> Option<&Occurrence> {
+        self.occurrences
+            .iter()
+            .find(|occ| occ.span.start <= offset && offset <= occ.span.end)
+    }
+
+    // The spans of the names of a relation where it's defined, in source order.
+    pub fn definitions<'a>(&'a self, relation: &'a str) -> impl Iterator<Item = Span> + 'a {
+        self.occurrences
+            .iter()
+            .filter(move |occ| occ.definition && occ.relation == relation)
+            .map(|occ| occ.span)
+    }
+
+    // Every relation the program mentions.
+    pub fn relations(&self) -> BTreeSet<&str> {
+        self.occurrences
+            .iter()
+            .map(|occ| occ.relation.as_str())
+            .collect()
+    }
+}
+
+fn statement_occurrences(
+    source: &str,
+    span: Span,
+    stmt: &StatementAST,
+    occurrences: &mut Vec<Occurrence>,
+) {
+    use StatementAST::*;
+    let mut push = |span: Span, relation: &str, definition: bool| {
+        occurrences.push(Occurrence {
+            span,
+            relation: relation.to_string(),
+            definition,
+        })
+    };
+    match stmt {
+        Rule(head, body) => {
+            // The premise of an arrow in a head is assumed, not defined.
+            let (premise, conclusion) = match head {
+                AtomAST::Literal(lit) | AtomAST::Brackets(lit) => (None, lit),
+                AtomAST::Arrow(lhs, rhs) => (Some(lhs), rhs),
+            };
+            if let Some(lit) = premise
+                && let Some(span) = name_span(lit)
+            {
+                push(span, &lit.relation, false);
+            }
+            if let Some(span) = name_span(conclusion) {
+                push(span, &conclusion.relation, true);
+            }
+            for lit in body.iter().flat_map(atom_literals) {
+                if let Some(span) = name_span(lit) {
+                    push(span, &lit.relation, false);
+                }
+            }
+        }
+        Question(body, _, _, _) | Abduce(body) | Constraint(body) | Expect(body, _) => {
+            for lit in body.iter().flat_map(atom_literals) {
+                if let Some(span) = name_span(lit) {
+                    push(span, &lit.relation, false);
+                }
+            }
+        }
+        Diff(left, right) => {
+            for lit in left.iter().chain(right).flat_map(atom_literals) {
+                if let Some(span) = name_span(lit) {
+                    push(span, &lit.relation, false);
+                }
+            }
+        }
+        // Directives only name relations, so their names are found among the identifiers of the
+        // statement. The first identifier is the directive's keyword.
+        Input(relation, _) | Decl(relation, _, _) => {
+            if let Some(span) = identifiers(source, span).get(1) {
+                push(*span, relation, true);
+            }
+        }
+        Output(relation) | Abducible(relation) => {
+            if let Some(span) = identifiers(source, span).get(1) {
+                push(*span, relation, false);
+            }
+        }
+        Builtin(builtin) => {
+            let idens = identifiers(source, span);
+            if let [_, relation, _, source, ..] = idens.as_slice() {
+                push(*relation, &builtin.relation, true);
+                push(*source, &builtin.source, false);
+            }
+        }
+        Weight(lit, _) | Probability(lit, _) => {
+            if let Some(span) = name_span(lit) {
+                push(span, &lit.relation, false);
+            }
+        }
+        LintLevel(..) | Pragma(_) => {}
+    }
+}
+
+fn atom_literals(atom: &AtomAST) -> Vec<&LiteralAST> {
+    match atom {
+        AtomAST::Literal(lit) | AtomAST::Brackets(lit) => vec![lit],
+        AtomAST::Arrow(lhs, rhs) => vec![lhs, rhs],
+    }
+}
+
+// A parsed literal starts with its relation's name, unless it's an equality.
+fn name_span(lit: &LiteralAST) -> Option<Span> {
+    if lit.relation == EQUAL {
+        return None;
+    }
+    lit.span.map(|span| Span {
+        start: span.start,
+        end: span.start + lit.relation.len(),
+    })
+}
+
+// The spans of the identifiers in a span of the source, skipping over strings and parameters.
+fn identifiers(source: &str, span: Span) -> Vec<Span> {
+    let text = &source[span.start..span.end];
+    let mut idens = vec![];
+    let mut chars = text.char_indices().peekable();
+    while let Some((idx, c)) = chars.next() {
+        if c == '"' {
+            for (_, c) in chars.by_ref() {
+                if c == '"' {
+                    break;
+                }
+            }
+        } else if c == '$' || c.is_ascii_digit() || c.is_ascii_alphabetic() || c == '_' {
+            let mut end = idx + c.len_utf8();
+            while let Some((idx, c)) =
+                chars.next_if(|(_, c)| c.is_ascii_alphanumeric() || *c == '_')
+            {
+                end = idx + c.len_utf8();
+            }
+            if c.is_ascii_alphabetic() || c == '_' {
+                idens.push(Span {
+                    start: span.start + idx,
+                    end: span.start + end,
+                });
+            }
+        }
+    }
+    idens
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::grammar::LocatedProgramParser;
+
+    use super::*;
+
+    #[test]
+    fn index_relations() {
+        let program = ".decl E(from: int, to: int).\n\
+                       .input E(2).\n\
+                       P(x, y) :- E(x, y).\n\
+                       P(x, z) :- P(x, y), E(y, z).\n\
+                       .builtin R = transitive_closure(E).\n\
+                       ? P(1, x).\n";
+        let parsed = LocatedProgramParser::new().parse(program).unwrap();
+        let index = SymbolIndex::new(program, &parsed);
+        let text = |span: Span| &program[span.start..span.end];
+
+        let decl: Vec<_> = index.definitions("E").collect();
+        assert_eq!(decl.len(), 2);
+        assert!(decl.iter().all(|span| text(*span) == "E"));
+        assert_eq!(index.definitions("P").count(), 2);
+        assert_eq!(index.definitions("R").map(text).collect::<Vec<_>>(), ["R"]);
+
+        let question = program.find("? P").unwrap() + 2;
+        let occ = index.occurrence_at(question + 1).unwrap();
+        assert_eq!((occ.relation.as_str(), occ.definition), ("P", false));
+        let source = program.find("(E)").unwrap() + 1;
+        assert_eq!(index.occurrence_at(source).unwrap().relation, "E");
+        assert!(index.occurrence_at(program.find("to").unwrap()).is_none());
+
+        assert_eq!(
+            index.relations().into_iter().collect::<Vec<_>>(),
+            ["E", "P", "R"]
+        );
+    }
+}
@@ -1,88 +1,6553 @@
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
 
-use crate::ast::{AtomAST, LiteralAST, StatementAST};
-use crate::representation::{Assumption, Symbol, Table};
+use crate::ast::{
+    AtomAST, BuiltinAST, Filter, LiteralAST, Location, Merge, Modifiers, Pragma, Span,
+    StatementAST, TermAST, lower_head_arrows,
+};
+use crate::binary::{
+    Encode, invalid, read_len, read_str, read_tuple, write_len, write_str, write_tuple,
+};
+use crate::builtin;
+use crate::builtin::{CallBuiltin, ExternalRelation, NativeBuiltin};
+use crate::dependency::{DependencyGraph, dependency_dot, dependency_graph};
+use crate::facts::{dump_facts, input_path, load_facts, output_path};
+use crate::lint::{Diagnostic, arity_mismatches, lint_with};
+use crate::magic;
+use crate::proof::{Proof, Sampler, Weighting};
+use crate::provenance::{Derivation, Provenance, Source};
+use crate::representation::{
+    Assumption, Conjunct, DNFAssumption, GroundTuple, LeafAssumption, Symbol, Table,
+};
+use crate::snapshot::QueryHandle;
+use crate::topdown::TopDown;
+use crate::triejoin::{Trie, TrieIndex, is_cyclic, is_flat, trie_columns, triejoin};
+use crate::value::{
+    SymbolTable, Value, intern_list, intern_term, interned, nil, resolve, resolve_list,
+    resolve_term,
+};
+use crate::view::RelationView;
+
+// A binding of the variables of a query to ground symbols.
+pub type Bindings = BTreeMap<String, Symbol>;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum InterpretError {
+    // An `!expect` (with no count) or `!expect_count` statement doesn't hold. Statements are
+    // counted from 1.
+    ExpectationFailed {
+        statement: usize,
+        expected: Option<usize>,
+        found: usize,
+    },
+    // Bottom-up evaluation didn't reach a fixpoint within the limit set by `set_max_iterations`.
+    IterationLimit {
+        limit: usize,
+    },
+    // Evaluation was cancelled through a cancellation token.
+    Cancelled,
+    // Evaluation was stopped through a cancellation token at the end of a fixpoint iteration. The
+    // tables are left as they were after the iterations completed, so they hold a partial model.
+    Stopped {
+        iterations: usize,
+    },
+    // Evaluation was still running at the deadline set by `set_deadline`.
+    TimedOut,
+    // A relation is used with an arity other than its declared one, or with several arities. The
+    // program isn't evaluated at all. Errors about the statements of a program are located in
+    // them (see `InterpretError::location`).
+    ArityMismatch(String, Location),
+    // An input relation is also derived by a rule or a built-in.
+    DerivedInput(String, Location),
+    // A built-in relation registered from Rust is derived by a rule, or given facts. Its tuples
+    // are computed as it's read, so it has no table to hold them.
+    BuiltinTable(String, Location),
+    // The facts of an input relation couldn't be loaded.
+    LoadFacts {
+        path: PathBuf,
+        message: String,
+    },
+    // An output relation isn't used by any statement, so it has no table to dump.
+    UnusedOutput(String),
+    // The tuples of an output relation couldn't be dumped.
+    DumpFacts {
+        path: PathBuf,
+        message: String,
+    },
+    // A lattice column is declared with a merge function that isn't registered (see
+    // `register_lattice`).
+    UnknownLattice(String),
+    // A relation asked about from Rust isn't used by the last program interpreted, and has no
+    // facts added.
+    UnknownRelation(String),
+}
+
+impl fmt::Display for InterpretError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            InterpretError::ExpectationFailed {
+                statement,
+                expected: None,
+                ..
+            } => write!(f, "expectation in statement {} failed: no rows", statement),
+            InterpretError::ExpectationFailed {
+                statement,
+                expected: Some(expected),
+                found,
+            } => write!(
+                f,
+                "expectation in statement {} failed: expected {} rows, found {}",
+                statement, expected, found
+            ),
+            InterpretError::IterationLimit { limit } => {
+                write!(
+                    f,
+                    "evaluation didn't reach a fixpoint in {} iterations",
+                    limit
+                )
+            }
+            InterpretError::Cancelled => write!(f, "evaluation was cancelled"),
+            InterpretError::Stopped { iterations } => write!(
+                f,
+                "evaluation was stopped after {} iterations, so the model is partial",
+                iterations
+            ),
+            InterpretError::TimedOut => write!(f, "evaluation timed out"),
+            InterpretError::ArityMismatch(message, _) => write!(f, "{}", message),
+            InterpretError::DerivedInput(relation, _) => {
+                write!(f, "input relation {} is derived by a rule", relation)
+            }
+            InterpretError::BuiltinTable(relation, _) => write!(
+                f,
+                "built-in relation {} can't be derived or hold facts",
+                relation
+            ),
+            InterpretError::LoadFacts { path, message } => {
+                write!(
+                    f,
+                    "couldn't load facts from {}: {}",
+                    path.display(),
+                    message
+                )
+            }
+            InterpretError::UnusedOutput(relation) => {
+                write!(f, "output relation {} is never used", relation)
+            }
+            InterpretError::DumpFacts { path, message } => {
+                write!(f, "couldn't dump facts to {}: {}", path.display(), message)
+            }
+            InterpretError::UnknownLattice(name) => write!(f, "lattice {} isn't registered", name),
+            InterpretError::UnknownRelation(relation) => {
+                write!(f, "relation {} isn't used by the program", relation)
+            }
+        }
+    }
+}
+
+impl std::error::Error for InterpretError {}
+
+impl InterpretError {
+    // Where in the program interpreted the error was found, for failed expectations, and errors
+    // about the relations statements use.
+    pub fn location(&self) -> Option<Location> {
+        match self {
+            InterpretError::ExpectationFailed { statement, .. } => Some(Location {
+                statement: Some(*statement),
+                span: None,
+            }),
+            InterpretError::ArityMismatch(_, location)
+            | InterpretError::DerivedInput(_, location)
+            | InterpretError::BuiltinTable(_, location) => Some(*location),
+            _ => None,
+        }
+    }
+
+    // Fill in where an error about a relation was found, where it isn't known yet.
+    fn at(mut self, at: Location) -> Self {
+        if let InterpretError::ArityMismatch(_, location)
+        | InterpretError::DerivedInput(_, location)
+        | InterpretError::BuiltinTable(_, location) = &mut self
+        {
+            location.statement = location.statement.or(at.statement);
+            location.span = location.span.or(at.span);
+        }
+        self
+    }
+
+    // Locate the error in the source of the program interpreted, given the spans of its
+    // statements: at the literal or term it's about, or else at its statement, if it has one.
+    pub fn locate(&self, file: &str, source: &str, spans: &[Span]) -> String {
+        let location = self.location().unwrap_or_default();
+        let statement = location.statement.map(|statement| spans[statement - 1]);
+        match location.span.or(statement) {
+            Some(span) => span.locate(file, source, &self.to_string()),
+            None => format!("{}: {}", file, self),
+        }
+    }
+}
+
+// The difference between the answers to the left and right bodies of a `?diff`, or between the
+// tuples of a relation in two models, keyed by their tuples.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Diff<A, K = Bindings> {
+    // Answers only to the right body.
+    pub added: Vec<(K, A)>,
+    // Answers only to the left body.
+    pub removed: Vec<(K, A)>,
+    // Answers to both bodies, with different assumption values (left, then right).
+    pub changed: Vec<(K, A, A)>,
+}
+
+impl<A: Assumption, K: Ord> Diff<A, K> {
+    pub fn new(
+        left: impl IntoIterator<Item = (K, A)>,
+        right: impl IntoIterator<Item = (K, A)>,
+    ) -> Self {
+        let mut left: BTreeMap<_, _> = left.into_iter().collect();
+        let mut diff = Diff {
+            added: vec![],
+            removed: vec![],
+            changed: vec![],
+        };
+        for (key, value) in right {
+            match left.remove(&key) {
+                None => diff.added.push((key, value)),
+                Some(old) if !old.delta(&value).is_zero() || !value.delta(&old).is_zero() => {
+                    diff.changed.push((key, old, value))
+                }
+                Some(_) => {}
+            }
+        }
+        diff.removed.extend(left);
+        diff
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.changed.is_empty()
+    }
+}
+
+// A suggested index: a relation was read this many times with these positions bound (by constants,
+// or by variables bound by earlier atoms).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IndexAdvice {
+    pub relation: String,
+    pub columns: Vec<usize>,
+    pub probes: usize,
+}
+
+impl fmt::Display for IndexAdvice {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let columns: Vec<_> = self.columns.iter().map(usize::to_string).collect();
+        write!(
+            f,
+            "index {}({}): {} probes",
+            self.relation,
+            columns.join(", "),
+            self.probes
+        )
+    }
+}
+
+// The sizes of the tables of an environment and of the string interner, for capacity planning.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Stats {
+    pub relations: Vec<RelationStats>,
+    pub interned: usize,
+}
+
+// Bytes count the symbols of a relation's tuples, not the overhead of the table holding them. The
+// formula size is the total size of the assumption values of its tuples.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RelationStats {
+    pub relation: String,
+    pub rows: usize,
+    pub bytes: usize,
+    pub formula_size: usize,
+}
+
+impl Stats {
+    pub fn rows(&self) -> usize {
+        self.relations.iter().map(|stats| stats.rows).sum()
+    }
+
+    pub fn bytes(&self) -> usize {
+        self.relations.iter().map(|stats| stats.bytes).sum()
+    }
+
+    pub fn formula_size(&self) -> usize {
+        self.relations.iter().map(|stats| stats.formula_size).sum()
+    }
+}
+
+impl fmt::Display for Stats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} rows in {} relations, {} bytes of tuples, formula size {}, {} interned strings",
+            self.rows(),
+            self.relations.len(),
+            self.bytes(),
+            self.formula_size(),
+            self.interned
+        )
+    }
+}
+
+impl fmt::Display for RelationStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}: {} rows, {} bytes of tuples, formula size {}",
+            self.relation, self.rows, self.bytes, self.formula_size
+        )
+    }
+}
+
+// How long a question of the last program interpreted took to answer. Iterations count those of
+// every fixpoint computed for the question, which is none if the model was already computed, or
+// if the question was evaluated top-down.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Timing {
+    // The question's statement, counted from 1.
+    pub statement: usize,
+    pub iterations: usize,
+    pub elapsed: Duration,
+}
+
+impl fmt::Display for Timing {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "statement {}: {} iterations in {:?}",
+            self.statement, self.iterations, self.elapsed
+        )
+    }
+}
+
+// Why a question has no answers, found from the most complete partial bindings of its atoms (the
+// ones binding the most atoms, left to right).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum WhyNot {
+    // No tuple of the relation read by an atom (counted from 0) matches the partial bindings of
+    // the atoms before it. Adding a tuple matching the pattern (where `None` is any symbol) would
+    // let the atom match.
+    Missing {
+        atom: usize,
+        bindings: Bindings,
+        relation: String,
+        pattern: Vec<Option<Symbol>>,
+    },
+    // Every atom matches, but the assumption values of the answers are all zero, or are ruled out
+    // by integrity constraints.
+    Inconsistent {
+        bindings: Bindings,
+    },
+}
+
+impl fmt::Display for WhyNot {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let bindings = match self {
+            WhyNot::Missing {
+                atom,
+                bindings,
+                relation,
+                pattern,
+            } => {
+                let pattern: Vec<_> = pattern
+                    .iter()
+                    .map(|symbol| {
+                        symbol.map_or("_".to_string(), |symbol| Value(symbol).to_string())
+                    })
+                    .collect();
+                write!(
+                    f,
+                    "atom {} has no tuple {}({})",
+                    atom,
+                    relation,
+                    pattern.join(", ")
+                )?;
+                bindings
+            }
+            WhyNot::Inconsistent { bindings } => {
+                write!(f, "every atom matches, but no assumption holds")?;
+                bindings
+            }
+        };
+        for (idx, (var, symbol)) in bindings.iter().enumerate() {
+            let sep = if idx == 0 { " with" } else { "," };
+            write!(f, "{} {} = {}", sep, var, Value(*symbol))?;
+        }
+        Ok(())
+    }
+}
+
+// Progress of bottom-up evaluation, reported to the callback set by `set_progress`. Iterations
+// are counted from 1 in each fixpoint computation, and tuples are counted over every table.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Progress {
+    // An iteration finished, deriving (or adding to the value of) `changed` tuples.
+    Iteration {
+        iteration: usize,
+        changed: usize,
+        tuples: usize,
+    },
+    // A fixpoint was reached: the last iteration changed no tuple.
+    Fixpoint {
+        iterations: usize,
+        tuples: usize,
+    },
+}
+
+// Events of bottom-up evaluation, reported to the callback set by `set_trace`. Iterations are
+// counted like in `Progress`. In each iteration, every rule or built-in that derived a tuple is
+// reported first, then every tuple already in its table whose value changed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Trace<A> {
+    // A rule (or built-in) made `derived` derivations, `new` of which added tuples that weren't in
+    // their tables yet.
+    Fired {
+        iteration: usize,
+        rule: String,
+        derived: usize,
+        new: usize,
+    },
+    // The value of a tuple already in its table changed from `old` to `new`.
+    Merged {
+        iteration: usize,
+        relation: String,
+        tuple: GroundTuple,
+        old: A,
+        new: A,
+    },
+}
+
+impl<A: Assumption> fmt::Display for Trace<A> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Trace::Fired {
+                iteration,
+                rule,
+                derived,
+                new,
+            } => write!(
+                f,
+                "iteration {}: {} derived {} tuples, {} new",
+                iteration, rule, derived, new
+            ),
+            Trace::Merged {
+                iteration,
+                relation,
+                tuple,
+                old,
+                new,
+            } => {
+                let tuple: Vec<_> = tuple
+                    .iter()
+                    .map(|symbol| Value(*symbol).to_string())
+                    .collect();
+                write!(
+                    f,
+                    "iteration {}: {}({}) changed from {} to {}",
+                    iteration,
+                    relation,
+                    tuple.join(", "),
+                    old,
+                    new
+                )
+            }
+        }
+    }
+}
+
+// Cancels an environment's evaluation from another thread. Once cancelled, every evaluation fails
+// with `InterpretError::Cancelled` (keeping the answers to questions already interpreted) until the
+// token is reset. Stopping is gentler: fixpoints finish the iteration they're in, then fail with
+// `InterpretError::Stopped`, leaving the tables they computed for reading or dumping.
+#[derive(Debug, Clone, Default)]
+pub struct CancellationToken {
+    cancelled: Arc<AtomicBool>,
+    stopped: Arc<AtomicBool>,
+}
+
+impl CancellationToken {
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+
+    pub fn stop(&self) {
+        self.stopped.store(true, Ordering::Relaxed);
+    }
+
+    pub fn reset(&self) {
+        self.cancelled.store(false, Ordering::Relaxed);
+        self.stopped.store(false, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Relaxed)
+    }
+
+    pub fn is_stopped(&self) -> bool {
+        self.stopped.load(Ordering::Relaxed)
+    }
+}
+
+// How much of each question's answers the log shows, besides how many there are.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Verbosity {
+    Count,
+    // The bindings of each answer, like `x=1, y=4`.
+    Bindings,
+    // The bindings of each answer, followed by its assumption value, like `x=1, y=4  [A(1) & B]`.
+    Assumptions,
+}
+
+// What becomes of answers whose assumptions entail a contradiction, when integrity constraints
+// exist.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Consistency {
+    // Conjuncts entailing a contradiction are pruned during evaluation, so such answers are
+    // excluded.
+    Exclude,
+    // Nothing is pruned. Answers are kept, and those entailing a contradiction are marked
+    // `inconsistent` in the log.
+    Flag,
+}
+
+// Joins whose outermost literal reads at least this many rows are split across threads, when the
+// environment has more than one (see `set_threads`). Smaller joins aren't worth spawning threads.
+const MIN_PARALLEL_ROWS: usize = 1 << 12;
+
+// How questions are evaluated. Every strategy finds the same answers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Strategy {
+    BottomUp,
+    Magic,
+    TopDown,
+}
+
+impl From<Pragma> for Strategy {
+    fn from(pragma: Pragma) -> Self {
+        match pragma {
+            Pragma::BottomUp => Strategy::BottomUp,
+            Pragma::Magic => Strategy::Magic,
+            Pragma::TopDown => Strategy::TopDown,
+        }
+    }
+}
 
 pub struct Environment<A: Assumption> {
     tables: BTreeMap<String, Table<A>>,
+    // The relations whose tables may hold non-zero deltas. Every relation with a delta is in it,
+    // though pruning can zero the deltas of a relation still in it.
+    delta_relations: BTreeSet<String>,
+    arities: BTreeMap<String, usize>,
+    answers: Vec<Vec<(Bindings, A)>>,
+    // The minimal support sets of the answers to each `?abduce` statement.
+    abductions: Vec<Vec<(Bindings, Vec<SupportSet>)>>,
+    diffs: Vec<Diff<A>>,
+    // Extensional relations declared with `.input`, and the facts loaded for them.
+    inputs: BTreeMap<String, Vec<GroundTuple>>,
+    // Facts added through `add_fact`, which outlive calls to `interpret`.
+    added: BTreeMap<String, Vec<(GroundTuple, A)>>,
+    // Relations computed by Rust functions, registered with `register_builtin`. They have no
+    // tables, and are computed as they're read.
+    natives: BTreeMap<String, NativeBuiltin>,
+    // Merge functions registered with `register_lattice`, for `@name` lattice columns.
+    lattices: BTreeMap<String, Box<dyn Fn(Value, Value) -> Value + Send + Sync>>,
+    // The sources facts added through `add_sourced_fact` came from, by relation and tuple. Sources
+    // are kept apart from the facts' values, so sourced facts hold like any other.
+    sources: BTreeMap<(String, GroundTuple), BTreeSet<String>>,
+    // The last program interpreted, and whether the tables hold its model, up to the facts added
+    // since it was computed (which are marked as deltas).
+    program: Vec<StatementAST>,
+    live: bool,
+    // How many of the rules, built-ins, and constraints seen so far by `interpret` the tables are
+    // a fixpoint of, if they're a fixpoint of any.
+    evaluated: Option<(usize, usize, usize)>,
+    // The assumption value under which some integrity constraint is violated. Conjuncts entailing
+    // it are pruned from every assumption value computed during evaluation.
+    inconsistent: A,
+    consistency: Consistency,
+    // The assumption value under which some integrity constraint is violated, as of the last
+    // question answered without pruning.
+    contradiction: A,
+    facts_dir: PathBuf,
+    output_dir: PathBuf,
+    // How questions are evaluated, unless a pragma says otherwise.
+    strategy: Strategy,
+    // Whether assumption values are simplified against derived tuples during bottom-up evaluation.
+    simplify: bool,
+    // Whether reads are counted for `index_advice`, and how many times each relation was read
+    // with each set of positions bound.
+    index_advice: bool,
+    probes: Mutex<BTreeMap<(String, Vec<usize>), usize>>,
+    // The plans of the bodies joined by fixpoints, kept between them until the bodies leave the
+    // program.
+    plans: Mutex<Plans>,
+    // The sorted indexes of tables joined by leapfrog triejoin, kept between joins (see `Tries`).
+    tries: Mutex<Tries>,
+    verbosity: Verbosity,
+    // How many threads each join may use.
+    threads: usize,
+    max_iterations: Option<usize>,
+    // The size assumption values of tuples are widened to once they grow beyond it, and the tuples
+    // widened since the tables were last cleared.
+    assumption_budget: Option<usize>,
+    widened: BTreeSet<(String, GroundTuple)>,
+    // Evaluation is interrupted once the token is cancelled, or once the deadline passes. Queries
+    // check for interruptions as they join, and fixpoints between iterations.
+    cancellation: CancellationToken,
+    deadline: Option<Instant>,
+    progress: Option<Box<dyn FnMut(Progress) + Send + Sync>>,
+    trace: Option<Box<dyn FnMut(Trace<A>) + Send + Sync>>,
+    // Whether `interpret` explains why questions have no answers.
+    why_not: bool,
+    // Whether `interpret` logs the sizes of the tables after each question.
+    stats: bool,
+    // Fixpoint iterations computed since the last call to `interpret`, and the timings of the
+    // questions it answered.
+    iterations: usize,
+    timings: Vec<Timing>,
+    // The deepest atom (and the bindings reaching it) at which the last question answered
+    // bottom-up found no answers, recorded while answering it when questions are explained.
+    failure: Option<Failure>,
+    // Warnings about the last program interpreted, kept apart from the answers to its questions.
+    diagnostics: Vec<Diagnostic>,
+    // The models published to query handles.
+    snapshots: QueryHandle<A>,
+    // The relations whose tables changed since the model was last published.
+    changed: BTreeSet<String>,
+    // The column names of relations declared by the last program interpreted.
+    columns: BTreeMap<String, Vec<String>>,
+    // The merge functions of the lattice columns declared by the last program interpreted.
+    merges: BTreeMap<String, Merge>,
+    // The weights of leaf assumptions set by the last program interpreted, in program order.
+    weights: Vec<(LiteralAST, usize)>,
+    rank: bool,
+    // The derivations found while computing the tables, if they're being recorded.
+    provenance: Option<Provenance>,
+    // Whether answers are sorted canonically, by the values they bind, before modifiers apply.
+    sort_answers: bool,
+    // The probabilities of leaf assumptions set by the last program interpreted, in program order.
+    probabilities: Vec<(LiteralAST, f64)>,
+    show_probabilities: bool,
     log: String,
 }
 
+impl<A: Assumption> Default for Environment<A> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl<A: Assumption> Environment<A> {
     pub fn new() -> Self {
         Self {
             tables: BTreeMap::new(),
+            delta_relations: BTreeSet::new(),
+            arities: BTreeMap::new(),
+            answers: vec![],
+            abductions: vec![],
+            diffs: vec![],
+            inputs: BTreeMap::new(),
+            added: BTreeMap::new(),
+            natives: BTreeMap::new(),
+            lattices: BTreeMap::new(),
+            sources: BTreeMap::new(),
+            program: vec![],
+            live: false,
+            evaluated: None,
+            inconsistent: A::zero(),
+            consistency: Consistency::Exclude,
+            contradiction: A::zero(),
+            facts_dir: PathBuf::from("."),
+            output_dir: PathBuf::from("."),
+            strategy: Strategy::BottomUp,
+            simplify: false,
+            index_advice: false,
+            probes: Mutex::new(BTreeMap::new()),
+            plans: Mutex::new(Plans::new()),
+            tries: Mutex::new(Tries::new()),
+            verbosity: Verbosity::Count,
+            threads: 1,
+            max_iterations: None,
+            assumption_budget: None,
+            widened: BTreeSet::new(),
+            cancellation: CancellationToken::default(),
+            deadline: None,
+            progress: None,
+            trace: None,
+            why_not: false,
+            stats: false,
+            iterations: 0,
+            timings: vec![],
+            failure: None,
+            diagnostics: vec![],
+            snapshots: QueryHandle::new(),
+            changed: BTreeSet::new(),
+            columns: BTreeMap::new(),
+            merges: BTreeMap::new(),
+            weights: vec![],
+            rank: false,
+            provenance: None,
+            sort_answers: false,
+            probabilities: vec![],
+            show_probabilities: false,
             log: String::new(),
         }
     }
 
-    pub fn interpret(&mut self, stmts: &[StatementAST]) -> &str {
+    // Directory `.input` relations are loaded from.
+    pub fn set_facts_dir(&mut self, dir: &Path) {
+        self.facts_dir = dir.to_path_buf();
+    }
+
+    // Directory `.output` relations are dumped to.
+    pub fn set_output_dir(&mut self, dir: &Path) {
+        self.output_dir = dir.to_path_buf();
+    }
+
+    pub fn set_strategy(&mut self, strategy: Strategy) {
+        self.strategy = strategy;
+    }
+
+    pub fn set_consistency(&mut self, consistency: Consistency) {
+        self.consistency = consistency;
+    }
+
+    // The assumption value under which some integrity constraint is violated. When excluding
+    // inconsistent answers, this is the value pruned during the last evaluation; when flagging
+    // them, it's the value computed for the last question.
+    pub fn contradiction(&self) -> &A {
+        match self.consistency {
+            Consistency::Exclude => &self.inconsistent,
+            Consistency::Flag => &self.contradiction,
+        }
+    }
+
+    // Whether an answer holds only under assumptions entailing a contradiction.
+    pub fn is_inconsistent(&self, value: &A) -> bool {
+        !value.is_zero() && value.prune(self.contradiction()).is_zero()
+    }
+
+    // Simplify assumption values modulo the rules: assuming a tuple that's derived with no
+    // assumptions is redundant, so its leaf assumption is discharged from every value. Values are
+    // only simplified against the tuples evaluation derives, so different strategies may find
+    // answers with different (but equivalent) assumption values.
+    pub fn set_simplify(&mut self, simplify: bool) {
+        self.simplify = simplify;
+    }
+
+    // Rank the answers to each question by the weighted cost of their cheapest assumptions (see
+    // `weighted_cost`), cheapest first, and print their costs. Ordering modifiers still apply, so
+    // answers that agree on the ordering variables stay ranked.
+    pub fn set_rank(&mut self, rank: bool) {
+        self.rank = rank;
+    }
+
+    // Sort the answers to each question, and those returned by `ask`, by the values they bind,
+    // compared like by `order by` over every variable in alphabetical order, instead of leaving them
+    // in the order of the tables. The order of the tables depends on the order rules fired in, so
+    // sorted answers stay the same across runs of programs that derive the same tuples. Ranking and
+    // ordering modifiers still apply, so answers they consider equal stay sorted.
+    pub fn set_sort_answers(&mut self, sort_answers: bool) {
+        self.sort_answers = sort_answers;
+    }
+
+    // The weight of a leaf assumption, set by the first `.weight` statement matching it.
+    pub fn weight(&self, leaf: &LeafAssumption) -> usize {
+        self.weights
+            .iter()
+            .find(|(lit, _)| matches_leaf(lit, leaf))
+            .map_or(1, |(_, weight)| *weight)
+    }
+
+    // The total weight of the cheapest assumptions a value holds under.
+    pub fn weighted_cost(&self, value: &A) -> usize {
+        value.weighted_cost(&|leaf| self.weight(leaf))
+    }
+
+    // Print the probability of each answer to a question (see `probability`).
+    pub fn set_probabilities(&mut self, show_probabilities: bool) {
+        self.show_probabilities = show_probabilities;
+    }
+
+    // The probability of a leaf assumption, set by the first `.probability` statement matching it.
+    pub fn leaf_probability(&self, leaf: &LeafAssumption) -> f64 {
+        self.probabilities
+            .iter()
+            .find(|(lit, _)| matches_leaf(lit, leaf))
+            .map_or(1.0, |(_, p)| *p)
+    }
+
+    // The probability that a value holds, with leaf assumptions holding independently.
+    pub fn probability(&self, value: &A) -> f64 {
+        value.probability(&|leaf| self.leaf_probability(leaf))
+    }
+
+    // Interpret a program, returning the log of answers to its questions. Interpretation stops at
+    // the first expectation that doesn't hold.
+    pub fn interpret(&mut self, stmts: &[StatementAST]) -> Result<&str, InterpretError> {
+        self.log.clear();
+        self.answers.clear();
+        self.abductions.clear();
+        self.diffs.clear();
+        self.inputs.clear();
+        self.iterations = 0;
+        self.timings.clear();
+        let natives = self.natives.keys().map(String::as_str).collect();
+        self.diagnostics = lint_with(stmts, &natives);
+        // Rules with arrows in their heads are interpreted as rewritten by `lower_head_arrows`.
+        let stmts = &lower_head_arrows(stmts);
+        let bodies: BTreeSet<_> = stmts
+            .iter()
+            .flat_map(StatementAST::bodies)
+            .map(|body| body_key(body))
+            .collect();
+        let plans = self.plans.get_mut().unwrap();
+        plans.retain(|(body, _), _| bodies.contains(body));
+        self.program = stmts.to_vec();
+        self.columns.clear();
+        self.merges.clear();
+        self.weights.clear();
+        self.probabilities.clear();
+        if let Some((mismatch, location)) = arity_mismatches(stmts).into_iter().next() {
+            return Err(InterpretError::ArityMismatch(mismatch, location));
+        }
+        self.live = false;
+        self.evaluated = None;
+        self.probes.get_mut().unwrap().clear();
+        self.run(stmts, 0)?;
+        Ok(&self.log)
+    }
+
+    // Interpret one more statement after the last program interpreted (or the statements fed so
+    // far), returning the log of answers to it, so programs can be interpreted as they arrive.
+    // The tables are kept between statements, so a question only evaluates the rules fed since the
+    // last one. Statements fed aren't linted, and one using a relation with the wrong arity is
+    // rejected without being added to the program.
+    pub fn feed_statement(&mut self, stmt: &StatementAST) -> Result<&str, InterpretError> {
         self.log.clear();
+        self.answers.clear();
+        self.abductions.clear();
+        self.diffs.clear();
+        self.iterations = 0;
+        self.timings.clear();
+        let mut program = std::mem::take(&mut self.program);
+        let from = program.len();
+        program.extend(lower_head_arrows(std::slice::from_ref(stmt)));
+        if let Some((mismatch, location)) = arity_mismatches(&program).into_iter().next() {
+            program.truncate(from);
+            self.program = program;
+            return Err(InterpretError::ArityMismatch(mismatch, location));
+        }
+        let result = self.run(&program, from);
+        self.program = program;
+        result.map(|_| self.log.as_str())
+    }
+
+    // Interpret the statements of a program from an index on. The statements before it were
+    // already interpreted, so only the rules, built-ins, constraints, and pragmas among them are
+    // read again. Errors about the relations a statement uses are located in the statement.
+    fn run(&mut self, stmts: &[StatementAST], from: usize) -> Result<(), InterpretError> {
+        let mut statement = None;
+        self.run_statements(stmts, from, &mut statement)
+            .map_err(|err| {
+                err.at(Location {
+                    statement,
+                    span: None,
+                })
+            })
+    }
+
+    // Like `run`, keeping the statement (counted from 1) being interpreted.
+    fn run_statements(
+        &mut self,
+        stmts: &[StatementAST],
+        from: usize,
+        statement: &mut Option<usize>,
+    ) -> Result<(), InterpretError> {
         let mut rules = vec![];
+        let mut builtins = vec![];
+        let mut constraints = vec![];
+        let mut derived = BTreeSet::new();
+        let mut outputs = vec![];
+        let mut strategy = self.strategy;
+        let abducible: BTreeSet<&str> = stmts
+            .iter()
+            .filter_map(|stmt| match stmt {
+                StatementAST::Abducible(relation) => Some(relation.as_str()),
+                _ => None,
+            })
+            .collect();
 
-        for stmt in stmts {
+        for (idx, stmt) in stmts.iter().enumerate() {
+            let replayed = matches!(
+                stmt,
+                StatementAST::Rule(_, _)
+                    | StatementAST::Builtin(_)
+                    | StatementAST::Constraint(_)
+                    | StatementAST::Pragma(_)
+            );
+            if idx < from && !replayed {
+                continue;
+            }
+            *statement = Some(idx + 1);
             match stmt {
                 StatementAST::Rule(head, body) => {
-                    self.register_table_for_atom(head);
+                    let (AtomAST::Literal(lit) | AtomAST::Brackets(lit) | AtomAST::Arrow(_, lit)) =
+                        head;
+                    let at = Location {
+                        statement: None,
+                        span: lit.span,
+                    };
+                    self.check_derivable(&lit.relation)
+                        .map_err(|err| err.at(at))?;
+                    derived.insert(lit.relation.clone());
+                    self.register_table_for_atom(head)?;
                     for atom in body {
-                        self.register_table_for_atom(atom);
+                        self.register_table_for_atom(atom)?;
                     }
                     rules.push((head, body));
                 }
-                StatementAST::Question(body) => {
+                StatementAST::Question(body, pragma, select, modifiers) => {
+                    for atom in body {
+                        self.register_table_for_atom(atom)?;
+                    }
+                    let strategy = pragma.map_or(strategy, Strategy::from);
+                    let (start, iterations) = (Instant::now(), self.iterations);
+                    // Constraints prune nothing when inconsistent answers are flagged. The
+                    // contradiction is then found by answering their bodies as questions.
+                    let pruning = match self.consistency {
+                        Consistency::Exclude => constraints.as_slice(),
+                        Consistency::Flag => &[],
+                    };
+                    self.failure = None;
+                    let mut answers = if modifiers.hypotheses.is_empty() {
+                        self.answer(&rules, &builtins, pruning, body, strategy)?
+                    } else {
+                        self.answer_hypothetically(
+                            &rules,
+                            &builtins,
+                            pruning,
+                            body,
+                            strategy,
+                            &modifiers.hypotheses,
+                        )?
+                    };
+                    let failure = self.failure.take();
+                    if self.consistency == Consistency::Flag {
+                        self.contradiction = A::zero();
+                        for constraint in &constraints {
+                            for (_, value) in
+                                self.answer(&rules, &builtins, &[], constraint, strategy)?
+                            {
+                                self.contradiction = self.contradiction.plus(&value);
+                            }
+                        }
+                    }
+                    self.timings.push(Timing {
+                        statement: idx + 1,
+                        iterations: self.iterations - iterations,
+                        elapsed: start.elapsed(),
+                    });
+                    if let Some(select) = select {
+                        answers = project(answers, select);
+                    }
+                    if self.sort_answers {
+                        sort_canonically(&mut answers);
+                    }
+                    if self.rank {
+                        answers.sort_by_cached_key(|(_, value)| self.weighted_cost(value));
+                    }
+                    let answers = apply_modifiers(answers, modifiers);
+                    let empty = answers.is_empty();
+                    self.interpret_question(body, answers);
+                    if empty && self.why_not {
+                        // Failures are recorded by bottom-up evaluation, so questions answered by
+                        // other strategies are queried again against the bottom-up model.
+                        let why_not = if strategy == Strategy::BottomUp {
+                            failure.map(|failure| why_not(body, failure))
+                        } else {
+                            self.evaluate(&rules, &builtins, &constraints)?;
+                            let why_not = self.failed_query(body);
+                            self.check_interrupted()?;
+                            why_not
+                        };
+                        if let Some(why_not) = why_not {
+                            self.log.push_str(&format!("Why not: {}\n", why_not));
+                        }
+                    }
+                    if !self.widened.is_empty() {
+                        // Answers may depend on widened tuples, so they're approximate.
+                        let widened = format!("Widened rows: {}\n", self.widened.len());
+                        self.log.push_str(&widened);
+                    }
+                    if self.stats {
+                        self.log.push_str(&format!("Stats: {}\n", self.stats()));
+                    }
+                }
+                StatementAST::Abduce(body) => {
+                    for atom in body {
+                        self.register_table_for_atom(atom)?;
+                    }
+                    // Support sets are found in the tables, so answers are found bottom-up.
+                    let answers =
+                        self.answer(&rules, &builtins, &constraints, body, Strategy::BottomUp)?;
+                    let abductions = self.abduce(answers, &abducible);
+                    self.interpret_abduction(body, abductions);
+                }
+                StatementAST::Diff(left, right) => {
+                    for atom in left.iter().chain(right) {
+                        self.register_table_for_atom(atom)?;
+                    }
+                    let left = self.answer(&rules, &builtins, &constraints, left, strategy)?;
+                    let right = self.answer(&rules, &builtins, &constraints, right, strategy)?;
+                    self.interpret_diff(left, right);
+                }
+                StatementAST::Expect(body, expected) => {
+                    for atom in body {
+                        self.register_table_for_atom(atom)?;
+                    }
+                    let found = self
+                        .answer(&rules, &builtins, &constraints, body, strategy)?
+                        .len();
+                    let holds = match expected {
+                        None => found > 0,
+                        Some(expected) => found == *expected,
+                    };
+                    if !holds {
+                        return Err(InterpretError::ExpectationFailed {
+                            statement: idx + 1,
+                            expected: *expected,
+                            found,
+                        });
+                    }
+                }
+                StatementAST::Builtin(builtin) => {
+                    self.check_derivable(&builtin.relation)?;
+                    derived.insert(builtin.relation.clone());
+                    let (source_arity, arity) = builtin.kind.arities();
+                    self.register_table(&builtin.source, source_arity)?;
+                    self.register_table(&builtin.relation, arity)?;
+                    builtins.push(builtin.clone());
+                }
+                StatementAST::Input(relation, arity) => {
+                    if derived.contains(relation) {
+                        let location = Location::default();
+                        return Err(InterpretError::DerivedInput(relation.clone(), location));
+                    }
+                    self.register_table(relation, *arity)?;
+                    let path = input_path(&self.facts_dir, relation);
+                    let facts =
+                        load_facts(&path, *arity).map_err(|err| InterpretError::LoadFacts {
+                            path,
+                            message: err.to_string(),
+                        })?;
+                    self.inputs.insert(relation.clone(), facts);
+                    // Loaded facts only reach the tables when they're cleared.
+                    self.evaluated = None;
+                }
+                StatementAST::Output(relation) => outputs.push(relation),
+                StatementAST::Abducible(_) | StatementAST::LintLevel(_, _) => {}
+                StatementAST::Weight(lit, weight) => self.weights.push((lit.clone(), *weight)),
+                StatementAST::Probability(lit, p) => self.probabilities.push((lit.clone(), *p)),
+                StatementAST::Pragma(pragma) => strategy = Strategy::from(*pragma),
+                StatementAST::Decl(relation, columns, merge) => {
+                    self.register_table(relation, columns.len())?;
+                    let names = columns.iter().map(|(name, _)| name.clone()).collect();
+                    self.columns.insert(relation.clone(), names);
+                    if let Some(merge) = merge {
+                        if let Merge::Lattice(name) = merge
+                            && !self.lattices.contains_key(name)
+                        {
+                            return Err(InterpretError::UnknownLattice(name.clone()));
+                        }
+                        self.merges.insert(relation.clone(), merge.clone());
+                    }
+                }
+                StatementAST::Constraint(body) => {
                     for atom in body {
-                        self.register_table_for_atom(atom);
+                        self.register_table_for_atom(atom)?;
                     }
-                    self.clear_tables();
-                    self.interpret_rules(&rules);
-                    self.interpret_question(body);
+                    constraints.push(body);
                 }
             }
         }
 
+        // The model of every rule in the program, including those after the last question, is
+        // published to query handles, and output relations are dumped from it.
+        *statement = None;
+        self.evaluate(&rules, &builtins, &constraints)?;
+        self.publish();
+        for relation in outputs {
+            if !self.tables.contains_key(relation) {
+                return Err(InterpretError::UnusedOutput(relation.clone()));
+            }
+            let path = output_path(&self.output_dir, relation);
+            if let Err(err) = self.dump_output(relation, &path) {
+                let message = err.to_string();
+                return Err(InterpretError::DumpFacts { path, message });
+            }
+        }
+        Ok(())
+    }
+
+    // Dump the `.output` relations of the last program interpreted from the tables as they are, like
+    // after evaluation was stopped (see `CancellationToken::stop`), when they hold a partial model.
+    pub fn dump_outputs(&self) -> io::Result<()> {
+        for stmt in &self.program {
+            if let StatementAST::Output(relation) = stmt
+                && self.tables.contains_key(relation)
+            {
+                self.dump_output(relation, &output_path(&self.output_dir, relation))?;
+            }
+        }
+        Ok(())
+    }
+
+    fn dump_output(&self, relation: &str, path: &Path) -> io::Result<()> {
+        let tuples = self.table(relation).iter();
+        let tuples = tuples.filter(|(_, (value, _))| !value.is_zero());
+        dump_facts(path, tuples.map(|(tuple, _)| tuple))
+    }
+
+    // The timings of the questions interpreted so far by the last call to `interpret`, in order.
+    pub fn timings(&self) -> &[Timing] {
+        &self.timings
+    }
+
+    // The log of answers to questions interpreted so far by the last call to `interpret`.
+    pub fn log(&self) -> &str {
         &self.log
     }
 
-    fn table(&self, relation: &str) -> &Table<A> {
-        self.tables.get(relation).unwrap()
+    // The answers to each question interpreted by the last call to `interpret`, in order.
+    pub fn answers(&self) -> &[Vec<(Bindings, A)>] {
+        &self.answers
     }
 
-    fn table_mut(&mut self, relation: &str) -> &mut Table<A> {
-        self.tables.get_mut(relation).unwrap()
+    pub fn abductions(&self) -> &[Vec<(Bindings, Vec<SupportSet>)>] {
+        &self.abductions
     }
 
-    fn register_table_for_atom(&mut self, atom: &AtomAST) {
-        match atom {
-            AtomAST::Literal(lit) | AtomAST::Brackets(lit) => self.register_table_for_literal(lit),
-            AtomAST::Arrow(lit1, lit2) => {
-                self.register_table_for_literal(lit1);
-                self.register_table_for_literal(lit2);
-            }
+    pub fn set_verbosity(&mut self, verbosity: Verbosity) {
+        self.verbosity = verbosity;
+    }
+
+    // Split joins whose outermost literal reads a large table across threads: each thread joins a
+    // range of the table's rows, and their answers are concatenated in order, so answers are the
+    // same however many threads there are. Joins run on one thread by default.
+    pub fn set_threads(&mut self, threads: usize) {
+        assert!(threads > 0, "Joins need at least one thread.");
+        self.threads = threads;
+    }
+
+    // Abort bottom-up evaluation that doesn't reach a fixpoint within a number of iterations, like
+    // that of programs deriving ever more tuples. Evaluation is unbounded by default.
+    pub fn set_max_iterations(&mut self, limit: usize) {
+        self.max_iterations = Some(limit);
+    }
+
+    // Bound the size of the assumption values of derived tuples (see `Assumption::size`). A value
+    // growing beyond the budget is widened to a smaller one it entails, so the tuple holds under at
+    // least the assumptions it really holds under. Answers are then approximate, so questions log
+    // how many rows were widened. Assumption values are unbounded by default.
+    pub fn set_assumption_budget(&mut self, size: usize) {
+        self.assumption_budget = Some(size);
+    }
+
+    // The tuples whose assumption values were widened to fit the budget, by relation.
+    pub fn widened(&self) -> &BTreeSet<(String, GroundTuple)> {
+        &self.widened
+    }
+
+    // Record every derivation found while evaluating rules bottom-up, to export the derivation
+    // hypergraph of the model (see `provenance`). Rules matching a recursive pattern are then
+    // evaluated as written, instead of by built-in algorithms, so their derivations are recorded too.
+    pub fn set_record_provenance(&mut self, record: bool) {
+        self.provenance = record.then(Provenance::default);
+    }
+
+    // The derivations of the tuples in the tables, if they're being recorded. The hypergraph covers
+    // the model computed last, so it's only complete once a fixpoint is reached.
+    pub fn provenance(&self) -> Option<&Provenance> {
+        self.provenance.as_ref()
+    }
+
+    // A token cancelling this environment's evaluation.
+    pub fn cancellation_token(&self) -> CancellationToken {
+        self.cancellation.clone()
+    }
+
+    // Interrupt evaluation still running at a deadline.
+    pub fn set_deadline(&mut self, deadline: Instant) {
+        self.deadline = Some(deadline);
+    }
+
+    // Expose a Rust function as a relation of some arity, which rules and questions can read like
+    // any other relation (see `NativeBuiltin`). Its tuples hold with assumption value one. E.g., a
+    // relation `Succ(x, y)` computing `y = x + 1`:
+    //   env.register_builtin("Succ", 2, |args| match args {
+    //       [Some(x), _] => vec![vec![*x, Value(x.0 + 1)]],
+    //       [None, Some(y)] => vec![vec![Value(y.0 - 1), *y]],
+    //       _ => vec![],
+    //   });
+    // Built-in relations can't also be derived by rules or given facts.
+    pub fn register_builtin<I>(
+        &mut self,
+        name: &str,
+        arity: usize,
+        function: impl Fn(&[Option<Value>]) -> I + Send + Sync + 'static,
+    ) where
+        I: IntoIterator<Item = Vec<Value>>,
+        I::IntoIter: 'static,
+    {
+        self.register_external(name, arity, function);
+    }
+
+    // Declare a relation as external: its tuples are produced by scanning a host-side data
+    // structure each time a join reads the relation, instead of being loaded into a table (see
+    // `ExternalRelation`). Otherwise, external relations are like built-in relations.
+    pub fn register_external(
+        &mut self,
+        name: &str,
+        arity: usize,
+        relation: impl ExternalRelation + 'static,
+    ) {
+        assert!(
+            !self.tables.contains_key(name),
+            "Relation {} already has a table.",
+            name
+        );
+        self.natives
+            .insert(name.to_string(), NativeBuiltin::new(arity, relation));
+    }
+
+    // Register a merge function for lattice columns declared with `@name` (see `Merge`). It should
+    // be associative, commutative, and idempotent, so the tuples of a relation can be merged in any
+    // order. E.g., a lattice merging lists by keeping the longer one:
+    //   env.register_lattice("longest", |x, y| {
+    //       let len = |v: Value| resolve_list(v.0).map_or(0, |elems| elems.len());
+    //       if len(y) > len(x) { y } else { x }
+    //   });
+    pub fn register_lattice(
+        &mut self,
+        name: &str,
+        merge: impl Fn(Value, Value) -> Value + Send + Sync + 'static,
+    ) {
+        self.lattices.insert(name.to_string(), Box::new(merge));
+    }
+
+    // Call a function as bottom-up evaluation progresses, after every iteration and once a fixpoint
+    // is reached. Other strategies derive tuples outside of the tables, and don't report progress.
+    pub fn set_progress(&mut self, progress: impl FnMut(Progress) + Send + Sync + 'static) {
+        self.progress = Some(Box::new(progress));
+    }
+
+    // Call a function with the events of bottom-up evaluation (see `Trace`). Like progress, only
+    // reported by bottom-up evaluation.
+    pub fn set_trace(&mut self, trace: impl FnMut(Trace<A>) + Send + Sync + 'static) {
+        self.trace = Some(Box::new(trace));
+    }
+
+    pub fn set_why_not(&mut self, why_not: bool) {
+        self.why_not = why_not;
+    }
+
+    pub fn set_stats(&mut self, stats: bool) {
+        self.stats = stats;
+    }
+
+    // Count the reads of each relation by the positions they bind, for `index_advice`. Off by
+    // default, since every read pays for it.
+    pub fn set_index_advice(&mut self, index_advice: bool) {
+        self.index_advice = index_advice;
+    }
+
+    // The sizes of the tables, as left by the last evaluation.
+    pub fn stats(&self) -> Stats {
+        let relations = self
+            .tables
+            .iter()
+            .map(|(relation, table)| RelationStats {
+                relation: relation.clone(),
+                rows: table.len(),
+                bytes: table.len() * self.arities[relation] * size_of::<Symbol>(),
+                formula_size: table.values().map(|(old, _)| old.size()).sum(),
+            })
+            .collect();
+        Stats {
+            relations,
+            interned: interned(),
         }
     }
 
-    fn register_table_for_literal(&mut self, lit: &LiteralAST) {
-        if !self.tables.contains_key(&lit.relation) {
-            self.tables.insert(lit.relation.clone(), Table::new());
+    // The diagnostics found by linting the last program interpreted with `interpret`. Lints are only
+    // reported, even at the `deny` level, they don't stop interpretation.
+    pub fn diagnostics(&self) -> &[Diagnostic] {
+        &self.diagnostics
+    }
+
+    // A view of a relation, as evaluated for the last question (or for `.output` relations)
+    // interpreted by the last call to `interpret`.
+    pub fn relation(&self, relation: &str) -> Option<RelationView<'_, A>> {
+        let columns = self.columns.get(relation).map(Vec::as_slice);
+        Some(RelationView::new(self.tables.get(relation)?, columns))
+    }
+
+    // The dependency graph of the relations of the last program interpreted.
+    pub fn dependency_graph(&self) -> DependencyGraph {
+        dependency_graph(&self.program)
+    }
+
+    // The dependency graph of the last program interpreted, in Graphviz's DOT language (see
+    // `dependency_dot`).
+    pub fn to_dot(&self) -> String {
+        dependency_dot(&self.program)
+    }
+
+    // Indexes worth declaring for the reads made by the last call to `interpret`, most probed
+    // first, if reads are counted (see `set_index_advice`). Reads with no position bound scan the whole table whatever the indexes, so they're
+    // left out.
+    pub fn index_advice(&self) -> Vec<IndexAdvice> {
+        let mut advice: Vec<_> = self
+            .probes
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|((_, columns), _)| !columns.is_empty())
+            .map(|((relation, columns), probes)| IndexAdvice {
+                relation: relation.clone(),
+                columns: columns.clone(),
+                probes: *probes,
+            })
+            .collect();
+        advice.sort_by_key(|advice| std::cmp::Reverse(advice.probes));
+        advice
+    }
+
+    // The result of each diff interpreted by the last call to `interpret`, in order.
+    pub fn diffs(&self) -> &[Diff<A>] {
+        &self.diffs
+    }
+
+    // Sample proofs of a tuple from the model of every rule in the last program interpreted, and
+    // the facts added so far. Returns fewer than `samples` proofs only if the tuple has no proof.
+    pub fn sample_proofs(
+        &mut self,
+        relation: &str,
+        tuple: &GroundTuple,
+        samples: usize,
+        weighting: Weighting,
+        seed: u64,
+    ) -> Result<Vec<Proof>, InterpretError> {
+        if !self.tables.contains_key(relation) {
+            return Err(InterpretError::UnknownRelation(relation.to_string()));
+        }
+        let arity = self.arities[relation];
+        if arity != tuple.len() {
+            return Err(InterpretError::ArityMismatch(
+                format!(
+                    "relation {} has {} columns, but is used with {} terms",
+                    relation,
+                    arity,
+                    tuple.len()
+                ),
+                Location::default(),
+            ));
+        }
+        let program = std::mem::take(&mut self.program);
+        let mut rules = vec![];
+        let mut builtins = vec![];
+        let mut constraints = vec![];
+        for stmt in &program {
+            match stmt {
+                StatementAST::Rule(head, body) => rules.push((head, body)),
+                StatementAST::Builtin(builtin) => builtins.push(builtin.clone()),
+                StatementAST::Constraint(body) => constraints.push(body),
+                _ => {}
+            }
         }
+        let proofs = self.update_model(&rules, &builtins, &constraints).map(|_| {
+            let mut sampler = Sampler::new(&rules, &builtins, weighting, seed);
+            (0..samples)
+                .map_while(|_| sampler.sample(self, relation, tuple))
+                .collect()
+        });
+        self.program = program;
+        proofs
     }
 
-    fn clear_tables(&mut self) {
-        for (_, table) in self.tables.iter_mut() {
-            table.clear();
+    // Explain how a tuple is derived in the model of every rule in the last program interpreted, and
+    // the facts added so far, with one of its proofs, or all of them if `all` is set. Proofs are
+    // tried in the order the program lists the ways of deriving each tuple. Returns no proofs if
+    // the tuple isn't derived.
+    pub fn explain(
+        &mut self,
+        relation: &str,
+        tuple: &GroundTuple,
+        all: bool,
+    ) -> Result<Vec<Proof>, InterpretError> {
+        if !self.tables.contains_key(relation) {
+            return Ok(vec![]);
+        }
+        let program = std::mem::take(&mut self.program);
+        let mut rules = vec![];
+        let mut builtins = vec![];
+        let mut constraints = vec![];
+        for stmt in &program {
+            match stmt {
+                StatementAST::Rule(head, body) => rules.push((head, body)),
+                StatementAST::Builtin(builtin) => builtins.push(builtin.clone()),
+                StatementAST::Constraint(body) => constraints.push(body),
+                _ => {}
+            }
         }
+        let proofs = self.update_model(&rules, &builtins, &constraints).map(|_| {
+            let limit = if all { usize::MAX } else { 1 };
+            Sampler::new(&rules, &builtins, Weighting::Uniform, 0)
+                .explain(self, relation, tuple, limit)
+        });
+        self.program = program;
+        proofs
     }
 
-    fn interpret_rules(&mut self, rules: &[(&AtomAST, &Vec<AtomAST>)]) {
-        loop {
+    // Explain why a question has no answers in the model of every rule in the last program
+    // interpreted, and the facts added so far. Returns `None` if the question has answers.
+    pub fn why_not(&mut self, question: &[AtomAST]) -> Result<Option<WhyNot>, InterpretError> {
+        for atom in question {
+            self.register_table_for_atom(atom)?;
+        }
+        let program = std::mem::take(&mut self.program);
+        let mut rules = vec![];
+        let mut builtins = vec![];
+        let mut constraints = vec![];
+        for stmt in &program {
+            match stmt {
+                StatementAST::Rule(head, body) => rules.push((head, body)),
+                StatementAST::Builtin(builtin) => builtins.push(builtin.clone()),
+                StatementAST::Constraint(body) => constraints.push(body),
+                _ => {}
+            }
+        }
+        let result = self.update_model(&rules, &builtins, &constraints);
+        self.program = program;
+        result.map(|_| self.failed_query(question))
+    }
 
+    // Query the tables, and explain why the query has no answers if it has none.
+    fn failed_query(&self, query: &[AtomAST]) -> Option<WhyNot> {
+        let (answers, failure) = self.query_tracking(query, None, Bindings::new(), true);
+        if !answers.is_empty() {
+            return None;
         }
+        Some(why_not(query, failure?))
     }
 
-    fn interpret_question(&mut self, question: &[AtomAST]) {}
+    // Add a fact to a relation. The fact is kept by later calls to `interpret`. Questions asked
+    // with `ask` only propagate the facts added since the last question, rather than evaluating the
+    // program from scratch.
+    pub fn add_fact(
+        &mut self,
+        relation: &str,
+        tuple: GroundTuple,
+        assumption: A,
+    ) -> Result<(), InterpretError> {
+        self.register_table(relation, tuple.len())?;
+        if self.live && !subsumed(self.table(relation), &tuple, &assumption) {
+            self.index_row(relation, &tuple);
+            let (old, new) = self
+                .table_mut(relation)
+                .entry(tuple.clone())
+                .or_insert_with(|| (A::zero(), A::zero()));
+            let delta = old.delta(&assumption);
+            *old = old.plus(&delta);
+            *new = new.plus(&delta);
+            self.mark_delta(relation);
+        }
+        self.added
+            .entry(relation.to_string())
+            .or_default()
+            .push((tuple, assumption));
+        Ok(())
+    }
 
-    // Queries just take a set of literals.
-    fn query(&self, query: &[LiteralAST]) -> BTreeMap<String, Vec<Symbol>> {
-        todo!()
+    // Add many facts to a relation at once, like calls to `add_fact` with each of them. The
+    // relation's table is found once, and the log of added facts grows once, rather than per fact,
+    // which adds up when loading millions of facts. A fact with another arity than the first is
+    // rejected, with the facts before it added.
+    pub fn add_facts(
+        &mut self,
+        relation: &str,
+        facts: impl IntoIterator<Item = (GroundTuple, A)>,
+    ) -> Result<(), InterpretError> {
+        let mut facts = facts.into_iter().peekable();
+        let Some((first, _)) = facts.peek() else {
+            return Ok(());
+        };
+        let arity = first.len();
+        self.register_table(relation, arity)?;
+        self.mark_changed(relation);
+        let table = self.tables.get_mut(relation).unwrap();
+        let mut indexes = self.tries.get_mut().unwrap().get_mut(relation);
+        let added = self.added.entry(relation.to_string()).or_default();
+        added.reserve(facts.size_hint().0);
+        let mut has_delta = false;
+        let mut result = Ok(());
+        for (tuple, assumption) in facts {
+            if tuple.len() != arity {
+                result = Err(InterpretError::ArityMismatch(
+                    format!(
+                        "relation {} has {} columns, but is used with {} terms",
+                        relation,
+                        arity,
+                        tuple.len()
+                    ),
+                    Location::default(),
+                ));
+                break;
+            }
+            if self.live && !subsumed(table, &tuple, &assumption) {
+                for index in indexes.iter_mut().flat_map(|indexes| indexes.iter_mut()) {
+                    index.insert(&tuple);
+                }
+                let (old, new) = table
+                    .entry(tuple.clone())
+                    .or_insert_with(|| (A::zero(), A::zero()));
+                let delta = old.delta(&assumption);
+                *old = old.plus(&delta);
+                *new = new.plus(&delta);
+                has_delta = true;
+            }
+            added.push((tuple, assumption));
+        }
+        if has_delta {
+            self.mark_delta(relation);
+        }
+        result
+    }
+
+    // Declare a relation with some arity, so programs can read it before any facts are added to it.
+    // Like relations declared with `.decl`, every atom of the relation must have `arity` terms.
+    pub fn declare_relation(&mut self, relation: &str, arity: usize) -> Result<(), InterpretError> {
+        self.register_table(relation, arity)
+    }
+
+    // Add facts to a relation from host data, holding with assumption value one (see `add_fact`).
+    pub fn insert_facts(
+        &mut self,
+        relation: &str,
+        facts: impl IntoIterator<Item = Vec<Value>>,
+    ) -> Result<(), InterpretError> {
+        let facts = facts.into_iter().map(|fact| {
+            let tuple = fact.into_iter().map(|value| value.0).collect();
+            (tuple, A::one())
+        });
+        self.add_facts(relation, facts)
+    }
+
+    // Add a fact that came from some source (a file, a feed, an API call, ...), so
+    // `answer_sources` can attribute answers depending on it to the source. The fact holds with no
+    // assumptions, like facts added by `add_fact`.
+    pub fn add_sourced_fact(
+        &mut self,
+        relation: &str,
+        tuple: GroundTuple,
+        source: &str,
+    ) -> Result<(), InterpretError> {
+        self.sources
+            .entry((relation.to_string(), tuple.clone()))
+            .or_default()
+            .insert(source.to_string());
+        self.add_fact(relation, tuple, A::one())
+    }
+
+    // Load the facts of a relation from a fact file, tagged with a source.
+    pub fn load_sourced_facts(
+        &mut self,
+        relation: &str,
+        arity: usize,
+        path: &Path,
+        source: &str,
+    ) -> Result<(), InterpretError> {
+        let facts = load_facts(path, arity).map_err(|err| InterpretError::LoadFacts {
+            path: path.to_path_buf(),
+            message: err.to_string(),
+        })?;
+        for tuple in facts {
+            self.add_sourced_fact(relation, tuple, source)?;
+        }
+        Ok(())
+    }
+
+    // The sources of the sourced facts an answer to a question depends on, in the model of every
+    // rule in the last program interpreted, and the facts added so far: those of the facts in the
+    // lineage of the tuples the question reads with the answer's bindings (see
+    // `Sampler::lineage`).
+    pub fn answer_sources(
+        &mut self,
+        question: &[AtomAST],
+        bindings: &Bindings,
+    ) -> Result<BTreeSet<String>, InterpretError> {
+        let program = std::mem::take(&mut self.program);
+        let mut rules = vec![];
+        let mut builtins = vec![];
+        let mut constraints = vec![];
+        for stmt in &program {
+            match stmt {
+                StatementAST::Rule(head, body) => rules.push((head, body)),
+                StatementAST::Builtin(builtin) => builtins.push(builtin.clone()),
+                StatementAST::Constraint(body) => constraints.push(body),
+                _ => {}
+            }
+        }
+        let lineage = self.update_model(&rules, &builtins, &constraints).map(|_| {
+            let read = question
+                .iter()
+                .filter_map(read_literal)
+                .filter(|lit| self.tables.contains_key(&lit.relation))
+                .filter_map(|lit| Some((lit.relation.clone(), ground(lit, bindings)?)));
+            Sampler::new(&rules, &builtins, Weighting::Uniform, 0).lineage(self, read)
+        });
+        self.program = program;
+        Ok(lineage?
+            .iter()
+            .filter_map(|fact| self.sources.get(fact))
+            .flatten()
+            .cloned()
+            .collect())
+    }
+
+    // Retract a fact added through `add_fact` or loaded for an input relation. Input facts are
+    // loaded again by the next call to `interpret`. Tuples derived from the fact are deleted or
+    // weakened before the next question asked with `ask`. If evaluation fails while the fact is
+    // retracted, the retraction is rolled back, so the environment is as it was before the call.
+    pub fn retract_fact(
+        &mut self,
+        relation: &str,
+        tuple: &GroundTuple,
+    ) -> Result<(), InterpretError> {
+        let program = std::mem::take(&mut self.program);
+        let mut rules = vec![];
+        let mut builtins = vec![];
+        let mut has_constraints = false;
+        for stmt in &program {
+            match stmt {
+                StatementAST::Rule(head, body) => rules.push((head, body)),
+                StatementAST::Builtin(builtin) => builtins.push(builtin.clone()),
+                StatementAST::Constraint(_) => has_constraints = true,
+                _ => {}
+            }
+        }
+        // The facts added since the model was computed are evaluated before anything is retracted,
+        // so the tables hold a fixpoint to delete from, and a failure leaves nothing to roll back.
+        let mut result = Ok(());
+        if self.live && !has_constraints {
+            result = self.fixpoint(&rules, &builtins, &[], false);
+        }
+        if result.is_ok() {
+            result = self.retract_from(&rules, &builtins, has_constraints, relation, tuple);
+        }
+        self.program = program;
+        result
+    }
+
+    fn retract_from(
+        &mut self,
+        rules: &[(&AtomAST, &Vec<AtomAST>)],
+        builtins: &[BuiltinAST],
+        has_constraints: bool,
+        relation: &str,
+        tuple: &GroundTuple,
+    ) -> Result<(), InterpretError> {
+        let key = (relation.to_string(), tuple.clone());
+        let saved_facts = (
+            self.added.get(relation).cloned(),
+            self.sources.get(&key).cloned(),
+            self.inputs.get(relation).cloned(),
+        );
+        if let Some(facts) = self.added.get_mut(relation) {
+            facts.retain(|(fact, _)| fact != tuple);
+        }
+        self.sources.remove(&key);
+        if let Some(facts) = self.inputs.get_mut(relation) {
+            facts.retain(|fact| fact != tuple);
+        }
+        if !self.live {
+            return Ok(());
+        }
+        if has_constraints {
+            // Retracting a fact can make fewer assumptions inconsistent, which would bring back
+            // conjuncts already pruned from every table, so the model is computed from scratch.
+            self.live = false;
+            return Ok(());
+        }
+
+        // Only the tables of relations derived from the retracted one can change.
+        let affected = downstream(rules, builtins, relation);
+        let saved = (
+            affected
+                .into_iter()
+                .filter_map(|relation| {
+                    let table = self.tables.get(&relation)?.clone();
+                    Some((relation, table))
+                })
+                .collect::<Vec<_>>(),
+            self.provenance.clone(),
+            self.widened.clone(),
+        );
+        let result = self.delete_and_rederive(rules, builtins, relation, tuple);
+        if result.is_ok() {
+            self.publish();
+            return result;
+        }
+
+        let (added, sources, inputs) = saved_facts;
+        if let Some(added) = added {
+            self.added.insert(relation.to_string(), added);
+        }
+        if let Some(sources) = sources {
+            self.sources.insert(key, sources);
+        }
+        if let Some(inputs) = inputs {
+            self.inputs.insert(relation.to_string(), inputs);
+        }
+        let (tables, provenance, widened) = saved;
+        let tries = self.tries.get_mut().unwrap();
+        for (relation, table) in tables {
+            tries.remove(&relation);
+            self.changed.insert(relation.clone());
+            self.tables.insert(relation, table);
+        }
+        self.clear_deltas();
+        self.provenance = provenance;
+        self.widened = widened;
+        self.live = true;
+        result
+    }
+
+    // Delete and rederive (DRed): delete every tuple with a derivation reading the retracted tuple,
+    // even if it has other derivations, then derive the deleted tuples again from the tuples left.
+    // Only the deleted tuples are rederived: those derived in one step from the tuples left are
+    // added as deltas, and the rest are derived from them semi-naively. Tables are expected to
+    // hold a fixpoint, with no deltas.
+    fn delete_and_rederive(
+        &mut self,
+        rules: &[(&AtomAST, &Vec<AtomAST>)],
+        builtins: &[BuiltinAST],
+        relation: &str,
+        tuple: &GroundTuple,
+    ) -> Result<(), InterpretError> {
+        let mut deleted = BTreeSet::new();
+        let mut found = vec![(relation.to_string(), tuple.clone())];
+        loop {
+            // Deleted tuples are marked as deltas while looking for tuples derived from them.
+            for (relation, tuple) in found {
+                if let Some((old, new)) = self.table_mut(&relation).get_mut(&tuple)
+                    && !deleted.contains(&(relation.clone(), tuple.clone()))
+                {
+                    *new = old.clone();
+                    self.mark_delta(&relation);
+                    deleted.insert((relation, tuple));
+                }
+            }
+
+            found = vec![];
+            for (head, body) in rules {
+                for delta in self.deltas(body, false) {
+                    for (bindings, _) in self.query(body, delta) {
+                        if let Some((relation, tuple, _)) = derive_head(head, &bindings, A::one()) {
+                            found.push((relation, tuple));
+                        }
+                    }
+                }
+            }
+            for builtin in builtins {
+                if self.has_delta(&builtin.source) {
+                    let tuples = self.table(&builtin.relation).keys().cloned();
+                    found.extend(tuples.map(|tuple| (builtin.relation.clone(), tuple)));
+                }
+            }
+
+            self.clear_deltas();
+            found.retain(|key| !deleted.contains(key));
+            if found.is_empty() {
+                break;
+            }
+        }
+
+        for (relation, tuple) in &deleted {
+            self.table_mut(relation).remove(tuple);
+            self.unindex_row(relation, tuple);
+        }
+        self.rederive(rules, builtins, &deleted);
+        self.fixpoint(rules, builtins, &[], false)
+    }
+
+    // The built-ins to substitute for rules matching a known recursive pattern, with the indices of
+    // those rules (see `builtin::recognize`). Nothing is substituted when derivations are recorded,
+    // which rules are indexed by. A built-in only derives the closure of its source, so the rules
+    // of a relation with facts added or loaded for it are kept, to compose those with the source.
+    fn recognize(&self, rules: &[(&AtomAST, &Vec<AtomAST>)]) -> Vec<(BuiltinAST, [usize; 2])> {
+        if self.provenance.is_some() {
+            return vec![];
+        }
+        let mut recognized = builtin::recognize(rules);
+        recognized.retain(|(builtin, _)| {
+            let relation = &builtin.relation;
+            self.added.get(relation).is_none_or(Vec::is_empty)
+                && self.inputs.get(relation).is_none_or(Vec::is_empty)
+        });
+        recognized
+    }
+
+    // The indices of the rules fused into built-ins.
+    fn fused(&self, rules: &[(&AtomAST, &Vec<AtomAST>)]) -> BTreeSet<usize> {
+        self.recognize(rules)
+            .into_iter()
+            .flat_map(|(_, idxs)| idxs)
+            .collect()
+    }
+
+    // Substitute specialized algorithms for rules matching a known recursive pattern (see
+    // `recognize`). Returns the rules left, and the built-ins with those substituted.
+    fn substitute_builtins<'a>(
+        &self,
+        rules: &[(&'a AtomAST, &'a Vec<AtomAST>)],
+        builtins: &[BuiltinAST],
+    ) -> (Vec<(&'a AtomAST, &'a Vec<AtomAST>)>, Vec<BuiltinAST>) {
+        let recognized = self.recognize(rules);
+        let rules = rules
+            .iter()
+            .enumerate()
+            .filter(|(idx, _)| {
+                !recognized
+                    .iter()
+                    .any(|(_, rule_idxs)| rule_idxs.contains(idx))
+            })
+            .map(|(_, rule)| *rule)
+            .collect();
+        let builtins = builtins
+            .iter()
+            .cloned()
+            .chain(recognized.into_iter().map(|(builtin, _)| builtin))
+            .collect();
+        (rules, builtins)
+    }
+
+    // Add the deleted tuples that are facts, or have a derivation from the tuples left, as deltas.
+    // The deleted tuples are gone from the tables, so a derivation found reads none of them. Heads are matched to the
+    // deleted tuples on the variables their bodies bind, except for the lattice columns of
+    // relations merging them, whose rows merge the values of several derivations. Rules are
+    // substituted by built-ins as when evaluated, and the built-ins deriving deleted tuples are
+    // evaluated again in full, since they only run again when their sources have deltas.
+    fn rederive(
+        &mut self,
+        rules: &[(&AtomAST, &Vec<AtomAST>)],
+        builtins: &[BuiltinAST],
+        deleted: &BTreeSet<(String, GroundTuple)>,
+    ) {
+        let (rules, builtins) = self.substitute_builtins(rules, builtins);
+        let mut pending = vec![];
+        for (relation, tuple) in deleted {
+            let added = self.added.get(relation).into_iter().flatten();
+            for (fact, value) in added.filter(|(fact, _)| fact == tuple) {
+                pending.push((relation.clone(), fact.clone(), value.clone()));
+            }
+            if self
+                .inputs
+                .get(relation)
+                .is_some_and(|facts| facts.contains(tuple))
+            {
+                pending.push((relation.clone(), tuple.clone(), A::one()));
+            }
+        }
+
+        for (idx, (head, body)) in rules.iter().enumerate() {
+            let (AtomAST::Literal(lit) | AtomAST::Brackets(lit) | AtomAST::Arrow(_, lit)) = head;
+            let vars: BTreeSet<&str> = body.iter().flat_map(AtomAST::vars).collect();
+            let matched = if self.merges.contains_key(&lit.relation) {
+                lit.terms.len().saturating_sub(1)
+            } else {
+                lit.terms.len()
+            };
+            let lower = (lit.relation.clone(), vec![]);
+            let tuples = deleted
+                .range(lower..)
+                .take_while(|(relation, _)| *relation == lit.relation);
+            let mut tried = BTreeSet::new();
+            for (_, tuple) in tuples {
+                let mut bindings = Bindings::new();
+                let mut terms = lit.terms[..matched].iter().zip(tuple);
+                if !terms.all(|(term, symbol)| unify_term(term, *symbol, &mut bindings)) {
+                    continue;
+                }
+                bindings.retain(|var, _| vars.contains(var.as_str()));
+                if !tried.insert(bindings.clone()) {
+                    continue;
+                }
+                for (bindings, value) in self.query_from(body, None, bindings) {
+                    if let Some((relation, tuple, value)) = derive_head(head, &bindings, value) {
+                        self.record(&relation, &tuple, Source::Rule(idx), body, &bindings);
+                        pending.push((relation, tuple, value));
+                    }
+                }
+            }
+        }
+        for builtin in &builtins {
+            let lower = (builtin.relation.clone(), vec![]);
+            let first = deleted.range(lower..).next();
+            if first.is_none_or(|(relation, _)| *relation != builtin.relation) {
+                continue;
+            }
+            for (tuple, value) in builtin.kind.evaluate(self.table(&builtin.source)) {
+                let source = Source::Builtin(builtin.kind.name().to_string());
+                self.record(&builtin.relation, &tuple, source, &[], &Bindings::new());
+                pending.push((builtin.relation.clone(), tuple, value));
+            }
+        }
+
+        for (relation, tuple, value) in pending {
+            for (tuple, value) in self.merge_tuple(&relation, tuple, value) {
+                if subsumed(self.table(&relation), &tuple, &value) {
+                    continue;
+                }
+                self.index_row(&relation, &tuple);
+                let (old, new) = self
+                    .table_mut(&relation)
+                    .entry(tuple.clone())
+                    .or_insert_with(|| (A::zero(), A::zero()));
+                let delta = old.delta(&value);
+                if !delta.is_zero() {
+                    *old = old.plus(&delta);
+                    *new = new.plus(&delta);
+                    self.mark_delta(&relation);
+                    self.fit_budget(&relation, &tuple);
+                }
+            }
+        }
+    }
+
+    // Answer a question against the model of every rule in the last program interpreted, and the
+    // facts added so far. Questions are evaluated bottom-up, from the model computed for the
+    // previous call to `ask` when there is one.
+    pub fn ask(&mut self, question: &[AtomAST]) -> Result<Vec<(Bindings, A)>, InterpretError> {
+        for atom in question {
+            self.register_table_for_atom(atom)?;
+        }
+        let program = std::mem::take(&mut self.program);
+        let mut rules = vec![];
+        let mut builtins = vec![];
+        let mut constraints = vec![];
+        for stmt in &program {
+            match stmt {
+                StatementAST::Rule(head, body) => rules.push((head, body)),
+                StatementAST::Builtin(builtin) => builtins.push(builtin.clone()),
+                StatementAST::Constraint(body) => constraints.push(body),
+                _ => {}
+            }
+        }
+        let result = self.update_model(&rules, &builtins, &constraints);
+        self.program = program;
+        result?;
+        let mut answers = merge_answers(self.query(question, None));
+        if self.sort_answers {
+            sort_canonically(&mut answers);
+        }
+        self.check_interrupted().map(|_| answers)
+    }
+
+    // Bring the tables up to the model of every rule in the last program interpreted, and the facts
+    // added so far, then publish them to query handles.
+    fn update_model(
+        &mut self,
+        rules: &[(&AtomAST, &Vec<AtomAST>)],
+        builtins: &[BuiltinAST],
+        constraints: &[&Vec<AtomAST>],
+    ) -> Result<(), InterpretError> {
+        if self.live {
+            self.fixpoint(rules, builtins, constraints, false)?;
+        } else {
+            self.clear_tables();
+            self.interpret_rules(rules, builtins, constraints)?;
+            self.live = true;
+        }
+        self.publish();
+        Ok(())
+    }
+
+    // A handle answering lookups from other threads against the last model published, while this
+    // environment keeps evaluating. Models are published by `interpret`, `feed_statement`, `ask`,
+    // `explain`, and `retract_fact`.
+    pub fn query_handle(&self) -> QueryHandle<A> {
+        self.snapshots.clone()
+    }
+
+    pub(crate) fn table(&self, relation: &str) -> &Table<A> {
+        self.tables.get(relation).unwrap()
+    }
+
+    fn table_mut(&mut self, relation: &str) -> &mut Table<A> {
+        self.mark_changed(relation);
+        self.tables.get_mut(relation).unwrap()
+    }
+
+    fn mark_changed(&mut self, relation: &str) {
+        if !self.changed.contains(relation) {
+            self.changed.insert(relation.to_string());
+        }
+    }
+
+    // Publish the tables to query handles, copying those changed since they were last published.
+    fn publish(&mut self) {
+        let changed = std::mem::take(&mut self.changed);
+        self.snapshots.publish(&self.tables, &changed);
+    }
+
+    // Merge a tuple derived for a relation with a lattice column into the tuples of its table that
+    // agree with it on every other column, returning the tuples to add, with their values. A tuple
+    // merged from a row and the derived tuple holds when both do, so its value is their product,
+    // and a derived tuple below a row that holds whenever it does derives nothing. Rows below an
+    // added tuple holding whenever they do are superseded, and removed. Other rows stay, under their
+    // own values, since they still hold where the added tuples don't. Returns nothing if the last
+    // columns can't be merged.
+    fn merge_tuple(
+        &mut self,
+        relation: &str,
+        tuple: GroundTuple,
+        value: A,
+    ) -> Vec<(GroundTuple, A)> {
+        let Some(merge) = self.merges.get(relation) else {
+            return vec![(tuple, value)];
+        };
+        let Some((last, key)) = tuple.split_last() else {
+            return vec![];
+        };
+        let rows: Vec<(Symbol, A)> = self.tables[relation]
+            .range(key.to_vec()..)
+            .take_while(|(existing, _)| existing.starts_with(key))
+            .map(|(existing, (existing_value, _))| (existing[key.len()], existing_value.clone()))
+            .collect();
+        let mut added = BTreeMap::from([(*last, value.clone())]);
+        for (existing, existing_value) in &rows {
+            let Some(merged) = self.merge_last(merge, *existing, *last) else {
+                return vec![];
+            };
+            if merged == *existing && existing_value.subsumes(&value) {
+                return vec![];
+            } else if merged != *existing && merged != *last {
+                let value = existing_value.times(&value);
+                let entry = added.entry(merged).or_insert_with(A::zero);
+                *entry = entry.plus(&value);
+            }
+        }
+
+        // Whether a tuple with some last column and value is superseded by one of the added tuples.
+        let superseded = |last: Symbol, value: &A| {
+            added.iter().any(|(merged, merged_value)| {
+                *merged != last
+                    && self.merge_last(merge, last, *merged) == Some(*merged)
+                    && merged_value.subsumes(value)
+            })
+        };
+        let removed: Vec<_> = rows
+            .iter()
+            .filter(|(existing, existing_value)| superseded(*existing, existing_value))
+            .map(|(existing, _)| *existing)
+            .collect();
+        let added: Vec<_> = added
+            .iter()
+            .filter(|(last, value)| !superseded(**last, value))
+            .map(|(last, value)| {
+                let mut tuple = key.to_vec();
+                tuple.push(*last);
+                (tuple, value.clone())
+            })
+            .collect();
+        for existing in removed {
+            let mut tuple = key.to_vec();
+            tuple.push(existing);
+            self.table_mut(relation).remove(&tuple);
+            self.unindex_row(relation, &tuple);
+        }
+        added
+    }
+
+    // The last column of a lattice tuple merged from two, or `None` if they can't be merged.
+    fn merge_last(&self, merge: &Merge, x: Symbol, y: Symbol) -> Option<Symbol> {
+        match merge {
+            Merge::Min => Some(x.min(y)),
+            Merge::Max => Some(x.max(y)),
+            Merge::Union => {
+                let mut elems: BTreeSet<Symbol> = resolve_list(x)?.into_iter().collect();
+                elems.extend(resolve_list(y)?);
+                intern_list(&elems.into_iter().collect::<Vec<_>>(), nil())
+            }
+            Merge::Lattice(name) => Some(self.lattices[name](Value(x), Value(y)).0),
+        }
+    }
+
+    fn register_table_for_atom(&mut self, atom: &AtomAST) -> Result<(), InterpretError> {
+        match atom {
+            AtomAST::Literal(lit) | AtomAST::Brackets(lit) => self.register_table_for_literal(lit),
+            AtomAST::Arrow(lit1, lit2) => {
+                self.register_table_for_literal(lit1)?;
+                self.register_table_for_literal(lit2)
+            }
+        }
+    }
+
+    fn register_table_for_literal(&mut self, lit: &LiteralAST) -> Result<(), InterpretError> {
+        // Built-in calls and relations have no tables.
+        if let Some(native) = self.natives.get(&lit.relation) {
+            if native.arity() != lit.terms.len() {
+                return Err(InterpretError::ArityMismatch(
+                    format!(
+                        "built-in relation {} has {} columns, but is used with {} terms",
+                        lit.relation,
+                        native.arity(),
+                        lit.terms.len()
+                    ),
+                    Location::past_arity(lit, native.arity()),
+                ));
+            }
+        } else if CallBuiltin::of(lit).is_none() {
+            let arity = self.arities.get(&lit.relation).copied();
+            let at = Location::past_arity(lit, arity.unwrap_or(lit.terms.len()));
+            self.register_table(&lit.relation, lit.terms.len())
+                .map_err(|err| err.at(at))?;
+        }
+        Ok(())
+    }
+
+    fn register_table(&mut self, relation: &str, arity: usize) -> Result<(), InterpretError> {
+        if self.natives.contains_key(relation) {
+            let location = Location::default();
+            return Err(InterpretError::BuiltinTable(relation.to_string(), location));
+        }
+        match self.arities.get(relation) {
+            Some(existing) if *existing != arity => Err(InterpretError::ArityMismatch(
+                format!(
+                    "relation {} has {} columns, but is used with {} terms",
+                    relation, existing, arity
+                ),
+                Location::default(),
+            )),
+            Some(_) => Ok(()),
+            None => {
+                self.arities.insert(relation.to_string(), arity);
+                self.tables.insert(relation.to_string(), Table::new());
+                self.changed.insert(relation.to_string());
+                Ok(())
+            }
+        }
+    }
+
+    // Check that a rule or built-in can derive tuples of a relation.
+    fn check_derivable(&self, relation: &str) -> Result<(), InterpretError> {
+        if self.inputs.contains_key(relation) {
+            let location = Location::default();
+            Err(InterpretError::DerivedInput(relation.to_string(), location))
+        } else if self.natives.contains_key(relation) {
+            let location = Location::default();
+            Err(InterpretError::BuiltinTable(relation.to_string(), location))
+        } else {
+            Ok(())
+        }
+    }
+
+    // Clear every table, except for the facts loaded for input relations and added facts.
+    fn clear_tables(&mut self) {
+        if let Some(provenance) = &mut self.provenance {
+            provenance.derivations.clear();
+        }
+        self.inconsistent = A::zero();
+        self.widened.clear();
+        self.live = false;
+        self.evaluated = None;
+        self.delta_relations.clear();
+        self.tries.get_mut().unwrap().clear();
+        self.changed.extend(self.tables.keys().cloned());
+        for (relation, table) in self.tables.iter_mut() {
+            table.clear();
+            for tuple in self.inputs.get(relation).into_iter().flatten() {
+                table.insert(tuple.clone(), (A::one(), A::zero()));
+            }
+            for (tuple, value) in self.added.get(relation).into_iter().flatten() {
+                let (old, _) = table
+                    .entry(tuple.clone())
+                    .or_insert_with(|| (A::zero(), A::zero()));
+                *old = old.plus(value);
+            }
+        }
+    }
+
+    // Semi-naive evaluation of the rules to a fixpoint. The first iteration evaluates every rule
+    // against the full tables. Subsequent iterations evaluate each rule once per atom reading a
+    // table, where that atom reads only the delta produced by the previous iteration.
+    fn interpret_rules(
+        &mut self,
+        rules: &[(&AtomAST, &Vec<AtomAST>)],
+        builtins: &[BuiltinAST],
+        constraints: &[&Vec<AtomAST>],
+    ) -> Result<(), InterpretError> {
+        self.fixpoint(rules, builtins, constraints, true)
+    }
+
+    // Evaluate the rules to a fixpoint. Unless `first` is set, evaluation starts from the deltas
+    // already in the tables, extending a fixpoint computed before they were added. Evaluation
+    // aborting leaves the tables partially evaluated, so the next model is computed from scratch.
+    fn fixpoint(
+        &mut self,
+        rules: &[(&AtomAST, &Vec<AtomAST>)],
+        builtins: &[BuiltinAST],
+        constraints: &[&Vec<AtomAST>],
+        mut first: bool,
+    ) -> Result<(), InterpretError> {
+        let (rules, builtins) = self.substitute_builtins(rules, builtins);
+
+        let mut iteration = 0;
+        'outer: loop {
+            iteration += 1;
+            self.iterations += 1;
+            if let Some(limit) = self.max_iterations
+                && iteration > limit
+            {
+                self.live = false;
+                return Err(InterpretError::IterationLimit { limit });
+            }
+            if let Err(err) = self.check_interrupted() {
+                self.live = false;
+                return Err(err);
+            }
+            if self.cancellation.is_stopped() {
+                self.live = false;
+                return Err(InterpretError::Stopped {
+                    iterations: iteration - 1,
+                });
+            }
+            // Derivations are tagged with the index of the rule, or of the built-in after the
+            // rules, that made them.
+            let mut pending: Vec<(usize, String, GroundTuple, A)> = vec![];
+
+            // Constraints are evaluated first, so that conjuncts they rule out are pruned before
+            // being joined by rules in this iteration.
+            let mut inconsistent = self.inconsistent.clone();
+            for body in constraints {
+                for delta in self.deltas(body, first) {
+                    for (_, value) in self.join_cached(body, delta).1 {
+                        inconsistent = inconsistent.plus(&value);
+                    }
+                }
+            }
+            if !self.inconsistent.delta(&inconsistent).is_zero() {
+                self.inconsistent = inconsistent;
+                self.changed.extend(self.tables.keys().cloned());
+                for (_, table) in self.tables.iter_mut() {
+                    for (_, (old, new)) in table.iter_mut() {
+                        *old = old.prune(&self.inconsistent);
+                        *new = new.prune(&self.inconsistent);
+                    }
+                }
+            }
+
+            for (idx, (head, body)) in rules.iter().enumerate() {
+                let (AtomAST::Literal(lit) | AtomAST::Brackets(lit) | AtomAST::Arrow(_, lit)) =
+                    head;
+                for delta in self.deltas(body, first) {
+                    let (compiled, answers) = self.join_cached(body, delta);
+                    // Heads are grounded from the slots of answers, unless they're skolemized or
+                    // derivations are recorded, which need the bindings by name.
+                    let terms = compiled.head(head).filter(|_| self.provenance.is_none());
+                    let Some(terms) = terms else {
+                        for (bindings, value) in compiled.bindings_of(answers) {
+                            if let Some((relation, tuple, value)) =
+                                derive_head(head, &bindings, value)
+                            {
+                                self.record(&relation, &tuple, Source::Rule(idx), body, &bindings);
+                                pending.push((idx, relation, tuple, value));
+                            }
+                        }
+                        continue;
+                    };
+                    for (symbols, value) in answers {
+                        let tuple: Option<GroundTuple> =
+                            terms.iter().map(|term| term.eval(&symbols)).collect();
+                        if let Some(tuple) = tuple {
+                            let value = head_value(head, &tuple, value);
+                            pending.push((idx, lit.relation.clone(), tuple, value));
+                        }
+                    }
+                }
+            }
+
+            for (idx, builtin) in builtins.iter().enumerate() {
+                if first || self.has_delta(&builtin.source) {
+                    for (tuple, value) in builtin.kind.evaluate(self.table(&builtin.source)) {
+                        let source = Source::Builtin(builtin.kind.name().to_string());
+                        self.record(&builtin.relation, &tuple, source, &[], &Bindings::new());
+                        pending.push((rules.len() + idx, builtin.relation.clone(), tuple, value));
+                    }
+                }
+            }
+
+            self.clear_deltas();
+
+            let tracing = self.trace.is_some();
+            let mut fired: BTreeMap<usize, (usize, usize)> = BTreeMap::new();
+            let mut merged = vec![];
+            let mut changed = BTreeSet::new();
+            for (source, relation, tuple, value) in pending {
+                for (tuple, value) in self.merge_tuple(&relation, tuple, value) {
+                    if subsumed(self.table(&relation), &tuple, &value) {
+                        if tracing {
+                            fired.entry(source).or_default().0 += 1;
+                        }
+                        continue;
+                    }
+                    self.index_row(&relation, &tuple);
+                    let (old, new) = self
+                        .table_mut(&relation)
+                        .entry(tuple.clone())
+                        .or_insert_with(|| (A::zero(), A::zero()));
+                    let delta = old.delta(&value);
+                    if tracing {
+                        let (derived, added) = fired.entry(source).or_default();
+                        *derived += 1;
+                        if old.is_zero() && !delta.is_zero() {
+                            *added += 1;
+                        } else if !delta.is_zero() {
+                            let value = old.plus(&delta);
+                            merged.push((relation.clone(), tuple.clone(), old.clone(), value));
+                        }
+                    }
+                    if !delta.is_zero() {
+                        *old = old.plus(&delta);
+                        *new = new.plus(&delta);
+                        self.mark_delta(&relation);
+                        self.fit_budget(&relation, &tuple);
+                        changed.insert((relation.clone(), tuple));
+                    }
+                }
+            }
+            if self.simplify {
+                self.simplify_assumptions();
+            }
+            if let Some(trace) = &mut self.trace {
+                for (source, (derived, new)) in fired {
+                    let rule = match rules.get(source) {
+                        Some((head, body)) => {
+                            StatementAST::Rule((*head).clone(), (*body).clone()).to_string()
+                        }
+                        None => {
+                            let builtin = builtins[source - rules.len()].clone();
+                            StatementAST::Builtin(builtin).to_string()
+                        }
+                    };
+                    trace(Trace::Fired {
+                        iteration,
+                        rule,
+                        derived,
+                        new,
+                    });
+                }
+                for (relation, tuple, old, new) in merged {
+                    trace(Trace::Merged {
+                        iteration,
+                        relation,
+                        tuple,
+                        old,
+                        new,
+                    });
+                }
+            }
+            self.report_progress(iteration, changed.len());
+
+            first = false;
+            if changed.is_empty() {
+                break 'outer;
+            }
+        }
+        Ok(())
+    }
+
+    // Record a derivation of a tuple, if derivations are being recorded.
+    fn record(
+        &mut self,
+        relation: &str,
+        tuple: &GroundTuple,
+        source: Source,
+        body: &[AtomAST],
+        bindings: &Bindings,
+    ) {
+        let Some(provenance) = &mut self.provenance else {
+            return;
+        };
+        let premises = body
+            .iter()
+            .filter_map(read_literal)
+            .filter_map(|lit| Some((lit.relation.clone(), ground(lit, bindings)?)))
+            .collect();
+        provenance.derivations.insert(Derivation {
+            relation: relation.to_string(),
+            tuple: tuple.clone(),
+            source,
+            premises,
+        });
+    }
+
+    fn report_progress(&mut self, iteration: usize, changed: usize) {
+        let Some(progress) = &mut self.progress else {
+            return;
+        };
+        let tuples = self
+            .tables
+            .values()
+            .flat_map(|table| table.values())
+            .filter(|(value, _)| !value.is_zero())
+            .count();
+        progress(Progress::Iteration {
+            iteration,
+            changed,
+            tuples,
+        });
+        if changed == 0 {
+            progress(Progress::Fixpoint {
+                iterations: iteration,
+                tuples,
+            });
+        }
+    }
+
+    // Discharge the leaf assumptions of tuples derived with no assumptions from every value.
+    // Discharging distributes over plus and times, so values derived later from the simplified
+    // values are the same as if they'd been derived before, then simplified.
+    fn simplify_assumptions(&mut self) {
+        let mut facts = vec![];
+        for (relation, table) in &self.tables {
+            if magic::is_magic(relation) {
+                continue;
+            }
+            for (tuple, (value, _)) in table {
+                if value.subsumes(&A::one()) {
+                    facts.push((magic::original(relation).to_string(), tuple.clone()));
+                }
+            }
+        }
+        if facts.is_empty() {
+            return;
+        }
+
+        let simplify = |value: &A, inconsistent: &A| {
+            let value = facts
+                .iter()
+                .fold(value.clone(), |value, leaf| value.discharge(leaf.clone()));
+            value.prune(inconsistent)
+        };
+        let inconsistent = facts.iter().fold(self.inconsistent.clone(), |value, leaf| {
+            value.discharge(leaf.clone())
+        });
+        self.changed.extend(self.tables.keys().cloned());
+        for table in self.tables.values_mut() {
+            for (old, new) in table.values_mut() {
+                *old = simplify(old, &inconsistent);
+                *new = simplify(new, &inconsistent);
+            }
+        }
+        self.inconsistent = inconsistent;
+    }
+
+    // The atoms reading deltas when evaluating a body. The first iteration is evaluated naively.
+    fn deltas(&self, body: &[AtomAST], first: bool) -> Vec<Option<usize>> {
+        if first {
+            return vec![None];
+        }
+        body.iter()
+            .enumerate()
+            .filter_map(|(idx, atom)| {
+                self.table_literal(atom)
+                    .filter(|lit| self.has_delta(&lit.relation))
+                    .map(|_| Some(idx))
+            })
+            .collect()
+    }
+
+    // Order the atoms of a body for joining. Table-reading atoms are greedily picked by their
+    // estimated number of matching rows: the number of rows they read (only the delta for the atom
+    // reading the delta), cut by a factor of 10 for each position bound by a constant or by a
+    // variable of a previously picked atom. Ties are broken by the syntactic order. Brackets,
+    // built-in calls, and built-in relations don't read tables, so they go last, in their
+    // syntactic order; the inputs of a call are bound by the atoms before it, which still come
+    // before it. Returns the reordered body, and the new index of the atom reading the delta.
+    fn plan(&self, body: &[AtomAST], delta: Option<usize>) -> (Vec<AtomAST>, Option<usize>) {
+        self.plan_for(body, delta, &self.rows(body, delta))
+    }
+
+    // The rows each atom of a body reads: the tuples of its table, or their deltas for the atom
+    // reading the delta. Atoms that don't read tables read no rows.
+    fn rows(&self, body: &[AtomAST], delta: Option<usize>) -> Vec<usize> {
+        body.iter()
+            .enumerate()
+            .map(|(idx, atom)| match self.table_literal(atom) {
+                Some(lit) if delta == Some(idx) => self
+                    .table(&lit.relation)
+                    .values()
+                    .filter(|(_, delta)| !delta.is_zero())
+                    .count(),
+                Some(lit) => self.table(&lit.relation).len(),
+                None => 0,
+            })
+            .collect()
+    }
+
+    // Plan a body (see `plan`), given the rows each of its atoms reads.
+    fn plan_for(
+        &self,
+        body: &[AtomAST],
+        delta: Option<usize>,
+        rows: &[usize],
+    ) -> (Vec<AtomAST>, Option<usize>) {
+        let mut bound = BTreeSet::new();
+        let mut remaining: Vec<usize> = (0..body.len())
+            .filter(|idx| self.table_literal(&body[*idx]).is_some())
+            .collect();
+        let mut order = vec![];
+        while !remaining.is_empty() {
+            let estimate = |idx: &usize| {
+                let lit = self.table_literal(&body[*idx]).unwrap();
+                let bound_terms = lit
+                    .terms
+                    .iter()
+                    .filter(|term| term.vars().iter().all(|var| bound.contains(*var)))
+                    .count();
+                rows[*idx] as f64 * 0.1f64.powi(bound_terms as i32)
+            };
+            let pos = (0..remaining.len())
+                .min_by(|a, b| estimate(&remaining[*a]).total_cmp(&estimate(&remaining[*b])))
+                .unwrap();
+            let idx = remaining.remove(pos);
+            bound.extend(self.table_literal(&body[idx]).unwrap().vars());
+            order.push(idx);
+        }
+        order.extend((0..body.len()).filter(|idx| self.table_literal(&body[*idx]).is_none()));
+
+        let delta = delta.map(|delta| order.iter().position(|idx| *idx == delta).unwrap());
+        (
+            order.into_iter().map(|idx| body[idx].clone()).collect(),
+            delta,
+        )
+    }
+
+    // Join a body planned by `plan`. Cyclic joins of table-reading atoms, like the triangle
+    // `E(x, y), E(y, z), E(z, x)`, are joined by leapfrog triejoin (see `triejoin`), when their
+    // atoms only hold variables and constants. The atoms after them are joined by nested loops, as
+    // are other bodies.
+    fn join(&self, body: &[AtomAST], delta: Option<usize>) -> Vec<(Bindings, A)> {
+        let (body, delta) = self.plan(body, delta);
+        let compiled = self.compile(&body, &Bindings::new());
+        let answers = self.join_planned(&compiled, delta);
+        compiled.bindings_of(answers)
+    }
+
+    // Join a body with a plan kept from an earlier join of it (in this fixpoint or an earlier one),
+    // unless the tables the body reads have since grown or shrunk past a power of two rows, when
+    // the body is planned and compiled again. Answers bind the slots of the compiled body.
+    fn join_cached(
+        &self,
+        body: &[AtomAST],
+        delta: Option<usize>,
+    ) -> (Arc<Compiled>, Vec<SlotAnswer<A>>) {
+        let rows = self.rows(body, delta);
+        let sizes: Vec<u32> = rows
+            .iter()
+            .map(|rows| usize::BITS - rows.leading_zeros())
+            .collect();
+        let key = (body_key(body), delta);
+        let cached = self.plans.lock().unwrap().get(&key).cloned();
+        if let Some(plan) = cached
+            && plan.sizes == sizes
+        {
+            let answers = self.join_planned(&plan.compiled, plan.delta);
+            return (plan.compiled, answers);
+        }
+        let (planned, planned_delta) = self.plan_for(body, delta, &rows);
+        let compiled = Arc::new(self.compile(&planned, &Bindings::new()));
+        let answers = self.join_planned(&compiled, planned_delta);
+        let plan = Plan {
+            sizes,
+            compiled: compiled.clone(),
+            delta: planned_delta,
+        };
+        self.plans.lock().unwrap().insert(key, plan);
+        (compiled, answers)
+    }
+
+    fn join_planned(&self, compiled: &Compiled, delta: Option<usize>) -> Vec<SlotAnswer<A>> {
+        let lits: Vec<_> = compiled
+            .query
+            .iter()
+            .map_while(|atom| self.table_literal(atom))
+            .collect();
+        if !lits.iter().all(|lit| is_flat(lit)) || !is_cyclic(&lits) {
+            return self
+                .join_compiled(compiled, delta, &Bindings::new(), false)
+                .0;
+        }
+        let mut order: Vec<String> = vec![];
+        for var in lits.iter().flat_map(|lit| lit.vars()) {
+            if !order.iter().any(|bound| bound == var) {
+                order.push(var.to_string());
+            }
+        }
+        // The atom reading the delta is joined with an index of the delta only, which is small.
+        let mut indexes = self.tries.lock().unwrap();
+        let mut delta_index = None;
+        for (idx, lit) in lits.iter().enumerate() {
+            let columns = trie_columns(lit, &order);
+            let table = self.table(&lit.relation);
+            if delta == Some(idx) {
+                let rows = table.iter().filter(|(_, (_, new))| !new.is_zero());
+                delta_index = Some(TrieIndex::new(columns, rows.map(|(tuple, _)| tuple)));
+            } else {
+                trie_index(&mut indexes, &lit.relation, table, columns);
+            }
+        }
+        let tries: Vec<_> = lits
+            .iter()
+            .enumerate()
+            .map(|(idx, lit)| {
+                let index = match delta == Some(idx) {
+                    true => delta_index.as_ref().unwrap(),
+                    false => {
+                        let columns = trie_columns(lit, &order);
+                        let indexes = &indexes[&lit.relation];
+                        indexes
+                            .iter()
+                            .find(|index| index.columns() == columns)
+                            .unwrap()
+                    }
+                };
+                Trie::new(lit, &order, index)
+            })
+            .collect();
+        let scan = self.scan(compiled, delta);
+        let mut answers = vec![];
+        triejoin(&order, &tries, &mut |bindings| {
+            // Indexes hold tuples not matching repeated variables, and rows whose values are zero,
+            // so the rows of an answer are read from the tables.
+            let values = lits.iter().enumerate().map(|(idx, lit)| {
+                let (full, new) = self.table(&lit.relation).get(&ground(lit, &bindings)?)?;
+                let value = if delta == Some(idx) { new } else { full };
+                (!value.is_zero()).then(|| value.clone())
+            });
+            let Some(mut values) = values.collect::<Option<Vec<_>>>() else {
+                return;
+            };
+            if self.check_interrupted().is_ok() {
+                let mut frame = scan.frame(&bindings);
+                self.execute(
+                    &scan,
+                    lits.len(),
+                    vec![],
+                    &mut frame,
+                    &mut values,
+                    &mut answers,
+                );
+                self.count_probes(&scan, frame.probes);
+            }
+        });
+        answers
+    }
+
+    // Evaluate the rules from scratch and answer a question.
+    fn answer(
+        &mut self,
+        rules: &[(&AtomAST, &Vec<AtomAST>)],
+        builtins: &[BuiltinAST],
+        constraints: &[&Vec<AtomAST>],
+        question: &[AtomAST],
+        strategy: Strategy,
+    ) -> Result<Vec<(Bindings, A)>, InterpretError> {
+        let answers = match strategy {
+            Strategy::BottomUp => {
+                self.evaluate(rules, builtins, constraints)?;
+                let (answers, failure) =
+                    self.query_tracking(question, None, Bindings::new(), self.why_not);
+                self.check_interrupted()?;
+                self.failure = failure;
+                answers
+            }
+            Strategy::Magic => self.answer_magic(rules, builtins, constraints, question)?,
+            Strategy::TopDown => {
+                let mut top_down = TopDown::new(rules, builtins, &self.inputs, &self.natives);
+                top_down.answer(question, constraints)
+            }
+        };
+        Ok(merge_answers(answers))
+    }
+
+    // Answer a question as if its hypotheses were assumed by rules like `[A(1)] :- .`, then
+    // discharge the hypotheses from the answers. The tables are restored afterwards, so the
+    // hypotheses don't hold for later questions.
+    fn answer_hypothetically(
+        &mut self,
+        rules: &[(&AtomAST, &Vec<AtomAST>)],
+        builtins: &[BuiltinAST],
+        constraints: &[&Vec<AtomAST>],
+        question: &[AtomAST],
+        strategy: Strategy,
+        hypotheses: &[LiteralAST],
+    ) -> Result<Vec<(Bindings, A)>, InterpretError> {
+        let assumed: Vec<_> = hypotheses
+            .iter()
+            .map(|lit| (AtomAST::Brackets(lit.clone()), vec![]))
+            .collect();
+        for (head, _) in &assumed {
+            self.register_table_for_atom(head)?;
+        }
+        let rules: Vec<_> = rules
+            .iter()
+            .copied()
+            .chain(assumed.iter().map(|(head, body)| (head, body)))
+            .collect();
+        let saved = (
+            self.tables.clone(),
+            self.delta_relations.clone(),
+            self.evaluated,
+            self.inconsistent.clone(),
+            self.live,
+            self.provenance.clone(),
+            self.widened.clone(),
+            std::mem::take(self.tries.get_mut().unwrap()),
+        );
+        let answers = self.answer(&rules, builtins, constraints, question, strategy);
+        let tries;
+        (
+            self.tables,
+            self.delta_relations,
+            self.evaluated,
+            self.inconsistent,
+            self.live,
+            self.provenance,
+            self.widened,
+            tries,
+        ) = saved;
+        *self.tries.get_mut().unwrap() = tries;
+        // The hypothetical model may have been published, so every table is published again.
+        self.changed.extend(self.tables.keys().cloned());
+        let mut answers = answers?;
+        for lit in hypotheses {
+            let Some(leaf) = leaf(lit, &Bindings::new()) else {
+                continue;
+            };
+            for (_, value) in answers.iter_mut() {
+                *value = value.discharge(leaf.clone());
+            }
+        }
+        Ok(answers)
+    }
+
+    // Bring the tables to the model of the rules, built-ins, and constraints seen so far by
+    // `interpret`. These only grow between questions, so when the tables are already a fixpoint of
+    // a prefix of them, the rules added since are evaluated once against the full tables, and the
+    // fixpoint continues from the tuples they derive. New built-ins and constraints need the full
+    // tables too, as do rules fused into built-ins, so adding those evaluates from scratch.
+    fn evaluate(
+        &mut self,
+        rules: &[(&AtomAST, &Vec<AtomAST>)],
+        builtins: &[BuiltinAST],
+        constraints: &[&Vec<AtomAST>],
+    ) -> Result<(), InterpretError> {
+        let counts = (rules.len(), builtins.len(), constraints.len());
+        match self.evaluated.take() {
+            Some(evaluated) if evaluated == counts => {}
+            Some((evaluated, builtin_count, constraint_count))
+                if builtin_count == builtins.len()
+                    && constraint_count == constraints.len()
+                    && self.fused(rules) == self.fused(&rules[..evaluated]) =>
+            {
+                self.seed(rules, evaluated);
+                self.fixpoint(rules, builtins, constraints, false)?;
+            }
+            _ => {
+                self.clear_tables();
+                self.interpret_rules(rules, builtins, constraints)?;
+            }
+        }
+        self.evaluated = Some(counts);
+        Ok(())
+    }
+
+    // Evaluate the rules from an index on once against the full tables, marking the tuples they
+    // derive as deltas, so a fixpoint can continue from them.
+    fn seed(&mut self, rules: &[(&AtomAST, &Vec<AtomAST>)], from: usize) {
+        let mut pending = vec![];
+        for (idx, (head, body)) in rules.iter().enumerate().skip(from) {
+            for delta in self.deltas(body, true) {
+                for (bindings, value) in self.join(body, delta) {
+                    let Some((relation, tuple, value)) = derive_head(head, &bindings, value) else {
+                        continue;
+                    };
+                    self.record(&relation, &tuple, Source::Rule(idx), body, &bindings);
+                    pending.push((relation, tuple, value));
+                }
+            }
+        }
+        for (relation, tuple, value) in pending {
+            for (tuple, value) in self.merge_tuple(&relation, tuple, value) {
+                if subsumed(self.table(&relation), &tuple, &value) {
+                    continue;
+                }
+                self.index_row(&relation, &tuple);
+                let (old, new) = self
+                    .table_mut(&relation)
+                    .entry(tuple.clone())
+                    .or_insert_with(|| (A::zero(), A::zero()));
+                let delta = old.delta(&value);
+                *old = old.plus(&delta);
+                *new = new.plus(&delta);
+                self.mark_delta(&relation);
+                self.fit_budget(&relation, &tuple);
+            }
+        }
+    }
+
+    // Widen the value of a tuple that outgrew the assumption budget. What the value gains by
+    // widening is added to its delta, so rules read it in the next iteration.
+    fn fit_budget(&mut self, relation: &str, tuple: &GroundTuple) {
+        let Some(budget) = self.assumption_budget else {
+            return;
+        };
+        let (old, new) = self.table_mut(relation).get_mut(tuple).unwrap();
+        if old.size() <= budget {
+            return;
+        }
+        let widened = old.widen(budget);
+        *new = new.plus(&old.delta(&widened));
+        *old = widened;
+        self.widened.insert((relation.to_string(), tuple.clone()));
+    }
+
+    // Answer a question using rules specialized to it by the magic-set transformation. Integrity
+    // constraints are goals too, so that every conjunct they rule out is still pruned.
+    fn answer_magic(
+        &mut self,
+        rules: &[(&AtomAST, &Vec<AtomAST>)],
+        builtins: &[BuiltinAST],
+        constraints: &[&Vec<AtomAST>],
+        question: &[AtomAST],
+    ) -> Result<Vec<(Bindings, A)>, InterpretError> {
+        let mut goals = vec![question];
+        goals.extend(constraints.iter().map(|body| body.as_slice()));
+        let (rules, goals) = magic::transform(rules, builtins, &goals);
+        for (head, body) in &rules {
+            self.register_table_for_atom(head)?;
+            for atom in body {
+                self.register_table_for_atom(atom)?;
+            }
+        }
+        for atom in goals.iter().flatten() {
+            self.register_table_for_atom(atom)?;
+        }
+
+        let rules: Vec<_> = rules.iter().map(|(head, body)| (head, body)).collect();
+        let constraints: Vec<_> = goals[1..].iter().collect();
+        self.clear_tables();
+        self.interpret_rules(&rules, builtins, &constraints)?;
+        let answers = self.query(&goals[0], None);
+        self.check_interrupted().map(|_| answers)
+    }
+
+    pub(crate) fn input_facts(&self, relation: &str) -> &[GroundTuple] {
+        self.inputs.get(relation).map_or(&[], Vec::as_slice)
+    }
+
+    fn has_delta(&self, relation: &str) -> bool {
+        self.delta_relations.contains(relation)
+    }
+
+    fn mark_delta(&mut self, relation: &str) {
+        if !self.delta_relations.contains(relation) {
+            self.delta_relations.insert(relation.to_string());
+        }
+    }
+
+    // Add a row added to a table to the table's indexes, if it has any.
+    fn index_row(&mut self, relation: &str, tuple: &GroundTuple) {
+        let tries = self.tries.get_mut().unwrap();
+        for index in tries.get_mut(relation).into_iter().flatten() {
+            index.insert(tuple);
+        }
+    }
+
+    // Remove a row removed from a table from the table's indexes. Every row removed from a table
+    // is, so indexes never hold rows their table doesn't.
+    fn unindex_row(&mut self, relation: &str, tuple: &GroundTuple) {
+        let tries = self.tries.get_mut().unwrap();
+        for index in tries.get_mut(relation).into_iter().flatten() {
+            index.remove(tuple);
+        }
+    }
+
+    fn clear_deltas(&mut self) {
+        for relation in std::mem::take(&mut self.delta_relations) {
+            for (_, (_, delta)) in self.tables.get_mut(&relation).into_iter().flatten() {
+                *delta = A::zero();
+            }
+        }
+    }
+
+    // The strings and terms the symbols of the database stand for: those of the tables, the facts,
+    // the sources, the program, and the leaf assumptions of every value.
+    fn symbol_table(&self) -> SymbolTable {
+        let leaves = |value: &A| value.leaves().into_iter().flat_map(|(_, tuple)| tuple);
+        let mut symbols = vec![];
+        for (tuple, (old, new)) in self.tables.values().flatten() {
+            symbols.extend(tuple);
+            symbols.extend(leaves(old).chain(leaves(new)));
+        }
+        symbols.extend(self.inputs.values().flatten().flatten());
+        for (tuple, value) in self.added.values().flatten() {
+            symbols.extend(tuple.iter().copied().chain(leaves(value)));
+        }
+        symbols.extend(self.sources.keys().flat_map(|(_, tuple)| tuple));
+        for stmt in &self.program {
+            symbols.extend(
+                stmt.clone()
+                    .constants_mut()
+                    .into_iter()
+                    .map(|symbol| *symbol),
+            );
+        }
+        symbols.extend(leaves(&self.inconsistent));
+        SymbolTable::of(symbols)
+    }
+
+    // Find the relations with deltas in tables built in one go, like loaded ones.
+    fn find_deltas(&mut self) {
+        self.delta_relations = self
+            .tables
+            .iter()
+            .filter(|(_, table)| table.values().any(|(_, delta)| !delta.is_zero()))
+            .map(|(relation, _)| relation.clone())
+            .collect();
+    }
+
+    fn interpret_question(&mut self, body: &[AtomAST], answers: Vec<(Bindings, A)>) {
+        self.log.push_str(&format!("Num rows: {}\n", answers.len()));
+        if self.verbosity >= Verbosity::Bindings {
+            let labels = column_labels(&self.columns, body);
+            for (bindings, value) in &answers {
+                let bindings: Vec<_> = bindings
+                    .iter()
+                    .map(|(var, symbol)| format!("{}={}", labels[var.as_str()], Value(*symbol)))
+                    .collect();
+                // Answers to questions without variables are shown as `()`.
+                let bindings = if bindings.is_empty() {
+                    "()".to_string()
+                } else {
+                    bindings.join(", ")
+                };
+                self.log.push_str(&format!("  {}", bindings));
+                if self.verbosity == Verbosity::Assumptions {
+                    self.log.push_str(&format!("  [{}]", value));
+                }
+                if self.rank {
+                    self.log
+                        .push_str(&format!("  cost {}", self.weighted_cost(value)));
+                }
+                if self.show_probabilities {
+                    self.log
+                        .push_str(&format!("  probability {}", self.probability(value)));
+                }
+                if self.consistency == Consistency::Flag && self.is_inconsistent(value) {
+                    self.log.push_str("  inconsistent");
+                }
+                self.log.push('\n');
+            }
+        }
+        self.answers.push(answers);
+    }
+
+    fn interpret_diff(&mut self, left: Vec<(Bindings, A)>, right: Vec<(Bindings, A)>) {
+        let diff = Diff::new(left, right);
+        self.log.push_str(&format!(
+            "Diff rows: {} added, {} removed, {} changed\n",
+            diff.added.len(),
+            diff.removed.len(),
+            diff.changed.len()
+        ));
+        self.diffs.push(diff);
+    }
+
+    // Queries just take a set of atoms. When `delta` is set, the atom at that index reads only the
+    // delta values of its table, and every other atom reads the full values.
+    fn query(&self, query: &[AtomAST], delta: Option<usize>) -> Vec<(Bindings, A)> {
+        self.query_from(query, delta, Bindings::new())
+    }
+
+    pub(crate) fn query_from(
+        &self,
+        query: &[AtomAST],
+        delta: Option<usize>,
+        bindings: Bindings,
+    ) -> Vec<(Bindings, A)> {
+        self.query_tracking(query, delta, bindings, false).0
+    }
+
+    // Like `query_from`, also returning the deepest atom (and the bindings reaching it) at which
+    // the query found no answers, when failures are tracked.
+    fn query_tracking(
+        &self,
+        query: &[AtomAST],
+        delta: Option<usize>,
+        bindings: Bindings,
+        track: bool,
+    ) -> (Vec<(Bindings, A)>, Option<Failure>) {
+        let compiled = self.compile(query, &bindings);
+        let (answers, failure) = self.join_compiled(&compiled, delta, &bindings, track);
+        (compiled.bindings_of(answers), failure)
+    }
+
+    // Join a compiled query by nested loops, from some bindings of its variables.
+    fn join_compiled(
+        &self,
+        compiled: &Compiled,
+        delta: Option<usize>,
+        bindings: &Bindings,
+        track: bool,
+    ) -> (Vec<SlotAnswer<A>>, Option<Failure>) {
+        let mut scan = self.scan(compiled, delta);
+        scan.track = track;
+        let mut answers = vec![];
+        let mut values = vec![];
+        let mut frame = scan.frame(bindings);
+        self.execute(&scan, 0, vec![], &mut frame, &mut values, &mut answers);
+        self.count_probes(&scan, frame.probes);
+        (answers, frame.failure)
+    }
+
+    // Compile a query for joining by nested loops, given the variables bound before it's joined.
+    // Which slots are bound when each atom is read is known from the atoms before it, so each
+    // column of a literal is matched by binding its slot, testing it, or comparing a constant,
+    // rather than by unifying its term. Only slots that built-in calls may or may not bind, and
+    // compound terms, are unified.
+    fn compile(&self, query: &[AtomAST], bindings: &Bindings) -> Compiled {
+        let mut vars: Vec<String> = bindings.keys().cloned().collect();
+        for var in query.iter().flat_map(AtomAST::vars) {
+            if !vars.iter().any(|slot| slot == var) {
+                vars.push(var.to_string());
+            }
+        }
+        // Slots bound whichever way an atom is reached, and slots bound by some solutions of
+        // built-in calls only.
+        let mut bound: BTreeSet<usize> = (0..bindings.len()).collect();
+        let mut maybe = BTreeSet::new();
+        let mut compiled = Compiled {
+            query: query.to_vec(),
+            terms: vec![],
+            program: vec![],
+            leaves: vec![],
+            probed: vec![],
+            vars: vec![],
+        };
+        for atom in query {
+            let terms: Vec<SlotTerm> = read_literal(atom).map_or(vec![], |lit| {
+                lit.terms
+                    .iter()
+                    .map(|term| SlotTerm::new(term, &vars))
+                    .collect()
+            });
+            // Tables are hashed on the positions earlier atoms may have bound, when some of them
+            // aren't bound by the initial bindings, which are the same for every read.
+            let probed = self.table_literal(atom).and_then(|_| {
+                let columns: Vec<usize> = (0..terms.len())
+                    .filter(|pos| {
+                        terms[*pos]
+                            .slots()
+                            .iter()
+                            .all(|slot| bound.contains(slot) || maybe.contains(slot))
+                    })
+                    .collect();
+                let probed = columns.iter().any(|pos| {
+                    terms[*pos]
+                        .slots()
+                        .iter()
+                        .any(|slot| *slot >= bindings.len())
+                });
+                probed.then_some(columns)
+            });
+            let call = match atom {
+                AtomAST::Literal(lit) => CallBuiltin::of(lit).map(|builtin| (lit, builtin)),
+                AtomAST::Brackets(_) | AtomAST::Arrow(_, _) => None,
+            };
+            let instruction = if let Some((lit, builtin)) = call {
+                let slots = lit.terms.iter().map(|term| SlotTerm::new(term, &vars));
+                maybe.extend(slots.flat_map(|term| term.slots()));
+                Instruction::Call(builtin)
+            } else if let Some(lit) = read_literal(atom) {
+                let matches = terms
+                    .iter()
+                    .map(|term| Match::new(term, &mut bound, &maybe))
+                    .collect();
+                match self.natives.contains_key(&lit.relation) {
+                    true => Instruction::Native(matches),
+                    false => Instruction::Read(matches),
+                }
+            } else {
+                Instruction::Skip
+            };
+            let leaf = match atom {
+                AtomAST::Brackets(lit) | AtomAST::Arrow(lit, _) => {
+                    let relation = magic::original(&lit.relation).to_string();
+                    let terms = lit.terms.iter().map(|term| SlotTerm::new(term, &vars));
+                    Some((relation, terms.collect()))
+                }
+                AtomAST::Literal(_) => None,
+            };
+            compiled.terms.push(terms);
+            compiled.program.push(instruction);
+            compiled.leaves.push(leaf);
+            compiled.probed.push(probed);
+        }
+        compiled.vars = vars;
+        compiled
+    }
+
+    // Prepare a compiled query for joining. Atoms reading a table with positions bound by earlier
+    // atoms are read once for each of their bindings, so their tables are hashed on those
+    // positions, once per join, and probed instead of scanned.
+    fn scan<'a>(&'a self, compiled: &'a Compiled, delta: Option<usize>) -> Scan<'a, A> {
+        let indexes = compiled
+            .query
+            .iter()
+            .zip(&compiled.probed)
+            .enumerate()
+            .map(|(idx, (atom, probed))| {
+                let lit = read_literal(atom)?;
+                let columns = probed.clone()?;
+                Some(HashIndex::new(
+                    columns,
+                    self.table(&lit.relation),
+                    delta == Some(idx),
+                ))
+            })
+            .collect();
+        Scan {
+            compiled,
+            delta,
+            indexes,
+            track: false,
+        }
+    }
+
+    // Run the instructions of a compiled query (see `Instruction`) from some atom on, without
+    // recursion. Each atom that reads something pushes a choice of its candidates (see `Choice`), and
+    // once an answer is emitted, or a choice runs out of candidates, the join backtracks to the
+    // latest choice. Joins given choices already made start by backtracking into them. Returns
+    // whether the earliest choice matched any candidate.
+    fn execute<'b>(
+        &'b self,
+        scan: &'b Scan<'b, A>,
+        mut idx: usize,
+        mut choices: Vec<Choice<'b, A>>,
+        frame: &mut Frame,
+        values: &mut Vec<A>,
+        answers: &mut Vec<SlotAnswer<A>>,
+    ) -> bool {
+        let compiled = scan.compiled;
+        let query = &compiled.query;
+        let mut descend = choices.is_empty();
+        loop {
+            if descend && idx == query.len() {
+                let assumption = compiled.assumption(&frame.symbols, values, &self.inconsistent);
+                if !assumption.is_zero() {
+                    answers.push((frame.symbols.clone(), assumption));
+                } else {
+                    scan.track_failure(idx, frame);
+                }
+            } else if descend {
+                let candidates = match &compiled.program[idx] {
+                    Instruction::Skip => {
+                        idx += 1;
+                        continue;
+                    }
+                    Instruction::Call(builtin) => {
+                        let AtomAST::Literal(lit) = &query[idx] else {
+                            unreachable!()
+                        };
+                        let bindings = compiled.bindings(&frame.symbols);
+                        Candidates::Solutions(builtin.solve(&lit.terms, &bindings).into_iter())
+                    }
+                    Instruction::Native(_) => {
+                        let lit = read_literal(&query[idx]).unwrap();
+                        let pattern: Vec<_> = compiled.terms[idx]
+                            .iter()
+                            .map(|term| term.eval(&frame.symbols))
+                            .collect();
+                        let native = &self.natives[&lit.relation];
+                        Candidates::Tuples(Box::new(native.call(&lit.relation, &pattern)))
+                    }
+                    Instruction::Read(_) => {
+                        // Interrupted queries stop early, so their answers are partial. Callers
+                        // check for interruptions before using them.
+                        if self.check_interrupted().is_err() {
+                            return false;
+                        }
+                        // A read split across threads joins the rest of the query itself, and only
+                        // the outermost read is split, so no choices are left to backtrack into.
+                        match self.read(scan, idx, frame, values, answers) {
+                            Ok(candidates) => candidates,
+                            Err(matched) => return matched,
+                        }
+                    }
+                };
+                choices.push(Choice {
+                    atom: idx,
+                    candidates,
+                    mark: frame.mark(),
+                    values: values.len(),
+                    report: true,
+                    matched: false,
+                });
+            }
+
+            let Some(choice) = choices.last_mut() else {
+                return true;
+            };
+            frame.undo(choice.mark);
+            values.truncate(choice.values);
+            if let Some(value) = choice.next(scan, frame) {
+                choice.matched = true;
+                values.push(value);
+                idx = choice.atom + 1;
+                descend = true;
+            } else {
+                let choice = choices.pop().unwrap();
+                if !choice.matched && choice.report {
+                    scan.track_failure(choice.atom, frame);
+                }
+                if choices.is_empty() {
+                    return choice.matched;
+                }
+                descend = false;
+            }
+        }
+    }
+
+    // The candidates of the atom at some index reading a table: the rows of its hash index
+    // matching its bound positions, or else every row of its table. Reads of large tables by the
+    // outermost atom are instead split across threads, each joining the rest of the query for a
+    // range of the rows. Their answers are added here, and whether any row matched is returned
+    // instead of candidates, since none are left to choose from.
+    fn read<'b>(
+        &'b self,
+        scan: &'b Scan<'b, A>,
+        idx: usize,
+        frame: &mut Frame,
+        values: &[A],
+        answers: &mut Vec<SlotAnswer<A>>,
+    ) -> Result<Candidates<'b, A>, bool> {
+        let lit = read_literal(&scan.compiled.query[idx]).unwrap();
+        let terms = &scan.compiled.terms[idx];
+        if self.index_advice {
+            let columns = (0..terms.len())
+                .filter(|pos| terms[*pos].is_bound(&frame.symbols))
+                .collect();
+            *frame.probes.entry((idx, columns)).or_default() += 1;
+        }
+        let table = self.table(&lit.relation);
+        // Only the outermost literal is split, so each thread's join is independent.
+        if values.is_empty() && self.threads > 1 && table.len() >= MIN_PARALLEL_ROWS {
+            let rows: Vec<_> = table.iter().collect();
+            let chunks: Vec<_> = thread::scope(|scope| {
+                let workers: Vec<_> = rows
+                    .chunks(rows.len().div_ceil(self.threads))
+                    .map(|rows| {
+                        let mut frame = frame.fork();
+                        scope.spawn(move || {
+                            let mut answers = vec![];
+                            // Each thread only reads some of the rows, so failing to match any
+                            // of them isn't a failure of the read.
+                            let choice = Choice {
+                                atom: idx,
+                                candidates: Candidates::Rows(rows.iter()),
+                                mark: frame.mark(),
+                                values: 0,
+                                report: false,
+                                matched: false,
+                            };
+                            let matched = self.execute(
+                                scan,
+                                idx,
+                                vec![choice],
+                                &mut frame,
+                                &mut vec![],
+                                &mut answers,
+                            );
+                            (matched, answers, frame.probes, frame.failure)
+                        })
+                    })
+                    .collect();
+                workers
+                    .into_iter()
+                    .map(|worker| worker.join().unwrap())
+                    .collect()
+            });
+            let mut matched = false;
+            for (chunk_matched, mut chunk, probes, failure) in chunks {
+                matched |= chunk_matched;
+                answers.append(&mut chunk);
+                for (key, count) in probes {
+                    *frame.probes.entry(key).or_default() += count;
+                }
+                if let Some((atom, bindings)) = failure
+                    && frame.is_deeper(atom)
+                {
+                    frame.failure = Some((atom, bindings));
+                }
+            }
+            if !matched {
+                scan.track_failure(idx, frame);
+            }
+            return Err(matched);
+        }
+        match scan.indexes[idx]
+            .as_ref()
+            .and_then(|index| index.probe(terms, &frame.symbols))
+        {
+            Some(rows) => Ok(Candidates::Rows(rows.iter())),
+            None => Ok(Candidates::Table(table.iter())),
+        }
+    }
+
+    fn check_interrupted(&self) -> Result<(), InterpretError> {
+        if self.cancellation.is_cancelled() {
+            Err(InterpretError::Cancelled)
+        } else if self
+            .deadline
+            .is_some_and(|deadline| Instant::now() >= deadline)
+        {
+            Err(InterpretError::TimedOut)
+        } else {
+            Ok(())
+        }
+    }
+
+    // The literal whose table an atom reads from, if it reads from a table rather than a built-in
+    // relation.
+    fn table_literal<'b>(&self, atom: &'b AtomAST) -> Option<&'b LiteralAST> {
+        read_literal(atom).filter(|lit| !self.natives.contains_key(&lit.relation))
+    }
+
+    pub(crate) fn is_native(&self, relation: &str) -> bool {
+        self.natives.contains_key(relation)
+    }
+
+    // Add the reads counted while joining a query to the reads of each relation.
+    fn count_probes(&self, scan: &Scan<A>, counted: BTreeMap<(usize, Vec<usize>), usize>) {
+        if counted.is_empty() {
+            return;
+        }
+        let mut probes = self.probes.lock().unwrap();
+        for ((idx, columns), count) in counted {
+            let relation = &read_literal(&scan.compiled.query[idx]).unwrap().relation;
+            *probes.entry((relation.clone(), columns)).or_default() += count;
+        }
+    }
+}
+
+// A compiled query being joined by nested loops: the atom reading the delta, if any, and the hash
+// indexes of the atoms reading tables with bound positions (see `Environment::scan`). Failures are
+// only tracked for queries explaining why they have no answers.
+struct Scan<'a, A> {
+    compiled: &'a Compiled,
+    delta: Option<usize>,
+    indexes: Vec<Option<HashIndex<'a, A>>>,
+    track: bool,
+}
+
+// A query compiled for joining by nested loops (see `Environment::compile`): the variables of the
+// query, by slot, and for each atom, the terms of the literal it reads with their variables
+// resolved to slots, the instruction joining it, the leaf assumption of its brackets or arrow, and
+// the positions its table is hashed on, if it's probed. Compiled queries don't depend on the rows
+// of tables, so fixpoints keep them with the plans of bodies (see `Plans`).
+struct Compiled {
+    query: Vec<AtomAST>,
+    vars: Vec<String>,
+    terms: Vec<Vec<SlotTerm>>,
+    program: Vec<Instruction>,
+    leaves: Vec<Option<(String, Vec<SlotTerm>)>>,
+    probed: Vec<Option<Vec<usize>>>,
+}
+
+// An answer found by a join, as the symbols bound to the slots of the compiled query, with its
+// assumption value. Answers are only turned into bindings by name for callers that need them.
+type SlotAnswer<A> = (Vec<Option<Symbol>>, A);
+
+impl Compiled {
+    fn bindings(&self, symbols: &[Option<Symbol>]) -> Bindings {
+        self.vars
+            .iter()
+            .zip(symbols)
+            .filter_map(|(var, symbol)| Some((var.clone(), (*symbol)?)))
+            .collect()
+    }
+
+    fn bindings_of<A>(&self, answers: Vec<SlotAnswer<A>>) -> Vec<(Bindings, A)> {
+        answers
+            .into_iter()
+            .map(|(symbols, value)| (self.bindings(&symbols), value))
+            .collect()
+    }
+
+    // Like `body_assumption`, for the symbols bound to the slots of the query.
+    fn assumption<A: Assumption>(
+        &self,
+        symbols: &[Option<Symbol>],
+        values: &[A],
+        inconsistent: &A,
+    ) -> A {
+        let mut values = values.iter();
+        let mut assumption = A::one();
+        for (atom, leaf) in self.query.iter().zip(&self.leaves) {
+            // Assumptions too deep to intern can't hold.
+            let leaf = leaf.as_ref().and_then(|(relation, terms)| {
+                let tuple: Option<GroundTuple> =
+                    terms.iter().map(|term| term.eval(symbols)).collect();
+                Some((relation.clone(), tuple?))
+            });
+            let value = match (atom, leaf) {
+                (AtomAST::Literal(_), _) => values.next().unwrap().clone(),
+                (AtomAST::Brackets(_), Some(leaf)) => A::singleton(leaf),
+                (AtomAST::Arrow(_, _), Some(leaf)) => values.next().unwrap().discharge(leaf),
+                (AtomAST::Brackets(_) | AtomAST::Arrow(_, _), None) => A::zero(),
+            };
+            assumption = assumption.times(&value).prune(inconsistent);
+        }
+        assumption
+    }
+
+    // The terms of a rule's head with their variables resolved to slots, if every variable of the
+    // head is bound by the query (rather than by skolemization, see `derive_head`).
+    fn head(&self, head: &AtomAST) -> Option<Vec<SlotTerm>> {
+        let (AtomAST::Literal(lit) | AtomAST::Brackets(lit) | AtomAST::Arrow(_, lit)) = head;
+        if !lit
+            .vars()
+            .all(|var| self.vars.iter().any(|slot| slot == var))
+        {
+            return None;
+        }
+        Some(
+            lit.terms
+                .iter()
+                .map(|term| SlotTerm::new(term, &self.vars))
+                .collect(),
+        )
+    }
+}
+
+// How a join reads an atom of a query, compiled once per query by `Environment::compile`. A join
+// runs the instructions of a query's atoms in order (see `Environment::execute`), and emits an
+// answer once it runs past the last.
+enum Instruction {
+    // Read the rows of the atom's table, matching each column as compiled.
+    Read(Vec<Match>),
+    // Solve a built-in call like `x = y + 1`.
+    Call(CallBuiltin),
+    // Read a built-in relation registered from Rust, matching each column of its tuples.
+    Native(Vec<Match>),
+    // Brackets don't read anything, they're handled once all variables are bound.
+    Skip,
+}
+
+impl Instruction {
+    fn matches(&self) -> &[Match] {
+        match self {
+            Instruction::Read(matches) | Instruction::Native(matches) => matches,
+            Instruction::Call(_) | Instruction::Skip => &[],
+        }
+    }
+}
+
+// How a column of a row is matched, compiled from which slots are bound when the row is read.
+#[derive(Clone)]
+enum Match {
+    // Bind a slot nothing bound before the row.
+    Bind(usize),
+    // Compare with a slot bound before the column.
+    Test(usize),
+    Constant(Symbol),
+    // Unify with a compound term, or with a slot only some solutions of a built-in call bind.
+    Unify(SlotTerm),
+}
+
+impl Match {
+    // Compile the match of a column holding a term, given the slots bound before it, and the slots
+    // built-in calls may have bound. The slots of the term are bound after it.
+    fn new(term: &SlotTerm, bound: &mut BTreeSet<usize>, maybe: &BTreeSet<usize>) -> Self {
+        let compiled = match term {
+            SlotTerm::Constant(cons) => Match::Constant(*cons),
+            SlotTerm::Slot(slot) if bound.contains(slot) => Match::Test(*slot),
+            SlotTerm::Slot(slot) if !maybe.contains(slot) => Match::Bind(*slot),
+            SlotTerm::Slot(_) | SlotTerm::Compound(_, _) => Match::Unify(term.clone()),
+        };
+        bound.extend(term.slots());
+        compiled
+    }
+}
+
+// Match the columns of a tuple. Slots bound before a failed match stay bound, so callers undo to a
+// mark.
+fn match_tuple(matches: &[Match], tuple: &[Symbol], frame: &mut Frame) -> bool {
+    matches
+        .iter()
+        .zip(tuple)
+        .all(|(column, symbol)| match column {
+            Match::Bind(slot) => {
+                frame.bind(*slot, *symbol);
+                true
+            }
+            Match::Test(slot) => frame.symbols[*slot] == Some(*symbol),
+            Match::Constant(cons) => cons == symbol,
+            Match::Unify(term) => term.unify(*symbol, frame),
+        })
+}
+
+// The candidates an atom of a query matches, which a join chooses from one at a time.
+enum Candidates<'a, A> {
+    // Rows of a hash index, or of a table split across threads.
+    Rows(std::slice::Iter<'a, Row<'a, A>>),
+    Table(std::collections::btree_map::Iter<'a, GroundTuple, (A, A)>),
+    // The bindings solving a built-in call.
+    Solutions(std::vec::IntoIter<Bindings>),
+    // The tuples of a built-in relation registered from Rust.
+    Tuples(Box<dyn Iterator<Item = GroundTuple> + 'a>),
+}
+
+// A choice point of a join: the candidates of an atom left to try, and how much of the frame and
+// of the values read were there before the atom, to backtrack to before trying each.
+struct Choice<'a, A> {
+    atom: usize,
+    candidates: Candidates<'a, A>,
+    mark: usize,
+    values: usize,
+    // Whether matching no candidates is tracked as a failure (see `Environment::track_failure`).
+    report: bool,
+    matched: bool,
+}
+
+impl<A: Assumption> Choice<'_, A> {
+    // Bind the next candidate matching the atom, returning the value it's read with.
+    fn next(&mut self, scan: &Scan<A>, frame: &mut Frame) -> Option<A> {
+        let matches = scan.compiled.program[self.atom].matches();
+        let delta = scan.delta == Some(self.atom);
+        loop {
+            let value = match &mut self.candidates {
+                Candidates::Rows(rows) => match_row(matches, *rows.next()?, delta, frame),
+                Candidates::Table(rows) => match_row(matches, rows.next()?, delta, frame),
+                Candidates::Solutions(solutions) => {
+                    let solution = solutions.next()?;
+                    for (slot, var) in scan.compiled.vars.iter().enumerate() {
+                        if frame.symbols[slot].is_none()
+                            && let Some(symbol) = solution.get(var)
+                        {
+                            frame.bind(slot, *symbol);
+                        }
+                    }
+                    Some(A::one())
+                }
+                Candidates::Tuples(tuples) => {
+                    let tuple = tuples.next()?;
+                    match_tuple(matches, &tuple, frame).then(A::one)
+                }
+            };
+            if value.is_some() {
+                return value;
+            }
+            frame.undo(self.mark);
+        }
+    }
+}
+
+// The value a row is read with, if it's not zero and the row matches the columns of a literal.
+fn match_row<A: Assumption>(
+    matches: &[Match],
+    (tuple, (full, new)): Row<A>,
+    delta: bool,
+    frame: &mut Frame,
+) -> Option<A> {
+    let value = if delta { new } else { full };
+    (!value.is_zero() && match_tuple(matches, tuple, frame)).then(|| value.clone())
+}
+
+impl<A> Scan<'_, A> {
+    // A frame holding some bindings of the query's variables.
+    fn frame(&self, bindings: &Bindings) -> Frame {
+        let symbols = self
+            .compiled
+            .vars
+            .iter()
+            .map(|var| bindings.get(var).copied());
+        Frame {
+            symbols: symbols.collect(),
+            trail: vec![],
+            probes: BTreeMap::new(),
+            failure: None,
+        }
+    }
+
+    // Record that the join found no answers past some atom with the frame's bindings, if failures
+    // are tracked.
+    fn track_failure(&self, atom: usize, frame: &mut Frame) {
+        if self.track && frame.is_deeper(atom) {
+            frame.failure = Some((atom, self.compiled.bindings(&frame.symbols)));
+        }
+    }
+}
+
+// A term whose variables are resolved to slots of a frame, the index of the variable in the
+// query's variables. Joins bind and compare slots, rather than looking up variables by name.
+#[derive(Clone)]
+enum SlotTerm {
+    Constant(Symbol),
+    Slot(usize),
+    Compound(String, Vec<SlotTerm>),
+}
+
+impl SlotTerm {
+    fn new(term: &TermAST, vars: &[String]) -> Self {
+        match term {
+            TermAST::Variable(var) => {
+                SlotTerm::Slot(vars.iter().position(|slot| slot == var).unwrap())
+            }
+            TermAST::Constant(cons) => SlotTerm::Constant(*cons),
+            TermAST::Parameter(param) => panic!("Parameter ${} isn't substituted.", param),
+            TermAST::Compound(functor, args) => SlotTerm::Compound(
+                functor.clone(),
+                args.iter().map(|arg| SlotTerm::new(arg, vars)).collect(),
+            ),
+        }
+    }
+
+    fn slots(&self) -> Vec<usize> {
+        match self {
+            SlotTerm::Constant(_) => vec![],
+            SlotTerm::Slot(slot) => vec![*slot],
+            SlotTerm::Compound(_, args) => args.iter().flat_map(SlotTerm::slots).collect(),
+        }
+    }
+
+    fn is_bound(&self, symbols: &[Option<Symbol>]) -> bool {
+        match self {
+            SlotTerm::Constant(_) => true,
+            SlotTerm::Slot(slot) => symbols[*slot].is_some(),
+            SlotTerm::Compound(_, args) => args.iter().all(|arg| arg.is_bound(symbols)),
+        }
+    }
+
+    // Like `eval_term`, and `None` when a variable is unbound.
+    fn eval(&self, symbols: &[Option<Symbol>]) -> Option<Symbol> {
+        match self {
+            SlotTerm::Constant(cons) => Some(*cons),
+            SlotTerm::Slot(slot) => symbols[*slot],
+            SlotTerm::Compound(functor, args) => {
+                let args = args
+                    .iter()
+                    .map(|arg| arg.eval(symbols))
+                    .collect::<Option<Vec<_>>>()?;
+                intern_term(functor, &args)
+            }
+        }
+    }
+
+    // Like `unify_term`. Slots bound before a failed match stay bound, so callers undo to a mark.
+    fn unify(&self, symbol: Symbol, frame: &mut Frame) -> bool {
+        match self {
+            SlotTerm::Constant(cons) => *cons == symbol,
+            SlotTerm::Slot(slot) => match frame.symbols[*slot] {
+                Some(bound) => bound == symbol,
+                None => {
+                    frame.bind(*slot, symbol);
+                    true
+                }
+            },
+            SlotTerm::Compound(functor, args) => match resolve_term(symbol) {
+                Some((name, syms)) if name == *functor && syms.len() == args.len() => args
+                    .iter()
+                    .zip(syms)
+                    .all(|(arg, sym)| arg.unify(sym, frame)),
+                _ => false,
+            },
+        }
+    }
+}
+
+// The bindings of a query being joined, by slot, and the slots bound so far in the order they were
+// bound. Joins bind slots in place and undo them when backtracking, so rows are matched without
+// copying the bindings. Each thread joining the query also counts its reads in its own frame, by
+// the index of the atom read and the positions bound, when index advice is collected, and keeps
+// the deepest failure it found, when failures are tracked.
+struct Frame {
+    symbols: Vec<Option<Symbol>>,
+    trail: Vec<usize>,
+    probes: BTreeMap<(usize, Vec<usize>), usize>,
+    failure: Option<Failure>,
+}
+
+impl Frame {
+    // A copy of the bindings for another thread, which counts its own reads and failures.
+    fn fork(&self) -> Self {
+        Frame {
+            symbols: self.symbols.clone(),
+            trail: self.trail.clone(),
+            probes: BTreeMap::new(),
+            failure: None,
+        }
+    }
+
+    // Whether a failure at some atom is deeper than any found so far.
+    fn is_deeper(&self, atom: usize) -> bool {
+        self.failure
+            .as_ref()
+            .is_none_or(|(deepest, _)| atom > *deepest)
+    }
+
+    fn bind(&mut self, slot: usize, symbol: Symbol) {
+        self.symbols[slot] = Some(symbol);
+        self.trail.push(slot);
+    }
+
+    fn mark(&self) -> usize {
+        self.trail.len()
+    }
+
+    // Unbind the slots bound since a mark.
+    fn undo(&mut self, mark: usize) {
+        for slot in self.trail.drain(mark..) {
+            self.symbols[slot] = None;
+        }
+    }
+}
+
+// The plans of bodies joined by fixpoints, by the body (see `body_key`) and the atom reading the
+// delta.
+type Plans = BTreeMap<(String, Option<usize>), Plan>;
+
+// The sorted indexes of tables, by relation, one for each order of columns joined. Indexes hold
+// a subset of the tuples of their table: rows added to a table are added to its indexes, when
+// they're added one at a time, and rows removed from a table are removed from them. Indexes with
+// fewer rows than their table are rebuilt before joins (see `trie_index`).
+type Tries = BTreeMap<String, Vec<TrieIndex>>;
+
+// Make sure a table has an index on some order of columns, holding every tuple of the table.
+fn trie_index<A>(tries: &mut Tries, relation: &str, table: &Table<A>, columns: Vec<usize>) {
+    let indexes = tries.entry(relation.to_string()).or_default();
+    match indexes.iter_mut().find(|index| index.columns() == columns) {
+        Some(index) if index.len() == table.len() => {}
+        Some(index) => *index = TrieIndex::new(columns, table.keys()),
+        None => indexes.push(TrieIndex::new(columns, table.keys())),
+    }
+}
+
+// A planned body: the bit length of the rows each atom read when the body was planned, the planned
+// body compiled for joining, and the atom reading the delta in it (see
+// `Environment::join_cached`).
+#[derive(Clone)]
+struct Plan {
+    sizes: Vec<u32>,
+    compiled: Arc<Compiled>,
+    delta: Option<usize>,
+}
+
+// Bodies are keyed by their text, so rules with the same body share plans, and a body's plans
+// stay valid whatever other rules are added or removed.
+fn body_key(body: &[AtomAST]) -> String {
+    let atoms: Vec<_> = body.iter().map(AtomAST::to_string).collect();
+    atoms.join(", ")
+}
+
+// The deepest atom of a query at which a join found no answers, and the bindings reaching it.
+type Failure = (usize, Bindings);
+
+// A row of a table, with its full and delta values.
+type Row<'a, A> = (&'a GroundTuple, &'a (A, A));
+
+// The rows of a table, hashed by the symbols at some positions. Rows whose value the atom reading
+// them wouldn't read (zero, or a zero delta) are left out.
+struct HashIndex<'a, A> {
+    columns: Vec<usize>,
+    rows: HashMap<Vec<Symbol>, Vec<Row<'a, A>>>,
+}
+
+impl<'a, A: Assumption> HashIndex<'a, A> {
+    fn new(columns: Vec<usize>, table: &'a Table<A>, delta: bool) -> Self {
+        let mut rows: HashMap<_, Vec<_>> = HashMap::new();
+        for (tuple, values) in table {
+            let (full, new) = values;
+            if (if delta { new } else { full }).is_zero() {
+                continue;
+            }
+            let key = columns.iter().map(|pos| tuple[*pos]).collect();
+            rows.entry(key).or_default().push((tuple, values));
+        }
+        Self { columns, rows }
+    }
+
+    // The rows matching a literal's bound positions, in the order of the table, or `None` if some
+    // of those positions aren't bound after all (built-in calls don't always bind every variable).
+    fn probe(&self, terms: &[SlotTerm], symbols: &[Option<Symbol>]) -> Option<&[Row<'a, A>]> {
+        let mut key = vec![];
+        for pos in &self.columns {
+            let term = &terms[*pos];
+            if !term.is_bound(symbols) {
+                return None;
+            }
+            // Terms too deep to intern match no rows.
+            let Some(symbol) = term.eval(symbols) else {
+                return Some(&[]);
+            };
+            key.push(symbol);
+        }
+        Some(self.rows.get(&key).map_or(&[], Vec::as_slice))
+    }
+}
+
+// The literal whose table an atom reads from, if any. Brackets and built-in calls don't read
+// tables.
+pub(crate) fn read_literal(atom: &AtomAST) -> Option<&LiteralAST> {
+    match atom {
+        AtomAST::Literal(lit) | AtomAST::Arrow(_, lit) => {
+            Some(lit).filter(|lit| CallBuiltin::of(lit).is_none())
+        }
+        AtomAST::Brackets(_) => None,
+    }
+}
+
+// Why a query has no answers, given the deepest atom at which it found none and the bindings
+// reaching it.
+fn why_not(query: &[AtomAST], (atom, bindings): Failure) -> WhyNot {
+    let Some(lit) = query.get(atom).and_then(read_literal) else {
+        return WhyNot::Inconsistent { bindings };
+    };
+    let pattern = pattern(lit, &bindings);
+    WhyNot::Missing {
+        atom,
+        bindings,
+        relation: lit.relation.clone(),
+        pattern,
+    }
+}
+
+// The symbols a literal's terms are bound to, or `None` for terms with unbound variables.
+pub(crate) fn pattern(lit: &LiteralAST, bindings: &Bindings) -> Vec<Option<Symbol>> {
+    lit.terms
+        .iter()
+        .map(|term| {
+            let bound = term.vars().iter().all(|var| bindings.contains_key(*var));
+            bound.then(|| eval_term(term, bindings)).flatten()
+        })
+        .collect()
+}
+
+pub(crate) fn unify(
+    lit: &LiteralAST,
+    tuple: &GroundTuple,
+    bindings: &Bindings,
+) -> Option<Bindings> {
+    let mut bindings = bindings.clone();
+    for (term, symbol) in lit.terms.iter().zip(tuple) {
+        if !unify_term(term, *symbol, &mut bindings) {
+            return None;
+        }
+    }
+    Some(bindings)
+}
+
+// Match a term against a symbol, binding the term's unbound variables. Compound terms only match
+// the symbols of compound terms with the same functor and arity, whose arguments match in turn.
+pub(crate) fn unify_term(term: &TermAST, symbol: Symbol, bindings: &mut Bindings) -> bool {
+    match term {
+        TermAST::Constant(cons) => *cons == symbol,
+        TermAST::Parameter(param) => panic!("Parameter ${} isn't substituted.", param),
+        TermAST::Variable(var) => match bindings.get(var) {
+            Some(bound) => *bound == symbol,
+            None => {
+                bindings.insert(var.clone(), symbol);
+                true
+            }
+        },
+        TermAST::Compound(functor, args) => match resolve_term(symbol) {
+            Some((name, syms)) if name == *functor && syms.len() == args.len() => args
+                .iter()
+                .zip(syms)
+                .all(|(arg, sym)| unify_term(arg, sym, bindings)),
+            _ => false,
+        },
+    }
+}
+
+// Whether a literal matches a leaf assumption, with its variables matching anything.
+fn matches_leaf(lit: &LiteralAST, leaf: &LeafAssumption) -> bool {
+    let (relation, tuple) = leaf;
+    let mut bindings = Bindings::new();
+    lit.relation == *relation
+        && lit.terms.len() == tuple.len()
+        && lit
+            .terms
+            .iter()
+            .zip(tuple)
+            .all(|(term, symbol)| unify_term(term, *symbol, &mut bindings))
+}
+
+// The symbol of a term whose variables are bound. Compound terms are interned, so this is `None`
+// when a compound term would be nested deeper than `MAX_TERM_DEPTH`.
+pub(crate) fn eval_term(term: &TermAST, bindings: &Bindings) -> Option<Symbol> {
+    match term {
+        TermAST::Variable(var) => Some(bindings[var]),
+        TermAST::Constant(cons) => Some(*cons),
+        TermAST::Parameter(param) => panic!("Parameter ${} isn't substituted.", param),
+        TermAST::Compound(functor, args) => {
+            let args = args
+                .iter()
+                .map(|arg| eval_term(arg, bindings))
+                .collect::<Option<Vec<_>>>()?;
+            intern_term(functor, &args)
+        }
+    }
+}
+
+pub(crate) fn ground(lit: &LiteralAST, bindings: &Bindings) -> Option<GroundTuple> {
+    lit.terms
+        .iter()
+        .map(|term| eval_term(term, bindings))
+        .collect()
+}
+
+fn leaf(lit: &LiteralAST, bindings: &Bindings) -> Option<LeafAssumption> {
+    let relation = magic::original(&lit.relation).to_string();
+    Some((relation, ground(lit, bindings)?))
+}
+
+// Combine the values read by each table-reading atom with the contributions of brackets (which
+// introduce a leaf assumption) and arrows (which discharge a leaf assumption from the value read
+// for their RHS literal).
+pub(crate) fn body_assumption<A: Assumption>(
+    query: &[AtomAST],
+    bindings: &Bindings,
+    values: &[A],
+    inconsistent: &A,
+) -> A {
+    let mut values = values.iter();
+    let mut assumption = A::one();
+    for atom in query {
+        let value = match atom {
+            AtomAST::Literal(_) => values.next().unwrap().clone(),
+            // Assumptions too deep to intern can't hold.
+            AtomAST::Brackets(lit) => match leaf(lit, bindings) {
+                Some(leaf) => A::singleton(leaf),
+                None => A::zero(),
+            },
+            AtomAST::Arrow(lhs, _) => {
+                let value = values.next().unwrap();
+                match leaf(lhs, bindings) {
+                    Some(leaf) => value.discharge(leaf),
+                    None => A::zero(),
+                }
+            }
+        };
+        assumption = assumption.times(&value).prune(inconsistent);
+    }
+    assumption
+}
+
+// The tuple and value derived for a rule's head, or `None` if the head would hold a compound term
+// nested deeper than `MAX_TERM_DEPTH`, in which case nothing is derived. Existential variables of
+// the head are bound to skolem terms (see `existential_vars`).
+pub(crate) fn derive_head<A: Assumption>(
+    head: &AtomAST,
+    bindings: &Bindings,
+    value: A,
+) -> Option<(String, GroundTuple, A)> {
+    let (AtomAST::Literal(lit) | AtomAST::Brackets(lit) | AtomAST::Arrow(_, lit)) = head;
+    let skolemized;
+    let bindings = if lit.vars().all(|var| bindings.contains_key(var)) {
+        bindings
+    } else {
+        skolemized = skolemize(lit, bindings)?;
+        &skolemized
+    };
+    let tuple = ground(lit, bindings)?;
+    let value = head_value(head, &tuple, value);
+    Some((lit.relation.clone(), tuple, value))
+}
+
+// The value a head's tuple is derived with, from the value of the body's answer.
+fn head_value<A: Assumption>(head: &AtomAST, tuple: &GroundTuple, value: A) -> A {
+    match head {
+        // Magic relations are only filters, so their tuples don't carry assumptions.
+        AtomAST::Literal(lit) if magic::is_magic(&lit.relation) => A::one(),
+        AtomAST::Literal(_) => value,
+        AtomAST::Brackets(lit) => {
+            let relation = magic::original(&lit.relation).to_string();
+            value.times(&A::singleton((relation, tuple.clone())))
+        }
+        AtomAST::Arrow(_, _) => panic!("Arrows in the head are rewritten by lower_head_arrows()."),
+    }
+}
+
+// Whether a table's row for a tuple already subsumes a value, in which case adding the value to
+// the row would change nothing. Checking this first spares redundant derivations from cloning the
+// tuple, building a delta, and rewriting the row.
+fn subsumed<A: Assumption>(table: &Table<A>, tuple: &GroundTuple, value: &A) -> bool {
+    table.get(tuple).is_some_and(|(old, _)| old.subsumes(value))
+}
+
+// Bind the unbound variables of a head to skolem terms of the body's bindings.
+fn skolemize(lit: &LiteralAST, bindings: &Bindings) -> Option<Bindings> {
+    let args: Vec<Symbol> = bindings.values().copied().collect();
+    let mut skolemized = bindings.clone();
+    for var in lit.vars() {
+        if !bindings.contains_key(var) {
+            let functor = format!("{}#{}", magic::original(&lit.relation), var);
+            skolemized.insert(var.to_string(), intern_term(&functor, &args)?);
+        }
+    }
+    Some(skolemized)
+}
+
+// Minimal support sets: the smallest sets of leaf assumptions a tuple is derivable under. The
+// conjunctions of a DNF value are only minimal w.r.t. each other (see `weak_simplify`), not modulo
+// the rules: a leaf assumption whose own tuple is derivable under other leaf assumptions can be
+// replaced by them, and one whose tuple is derived with no assumptions can be dropped.
+pub type SupportSet = BTreeSet<LeafAssumption>;
+
+impl<A: Assumption> Environment<A> {
+    // The minimal support sets of a tuple in the model of every rule in the last program
+    // interpreted, and the facts added so far, smallest first. Only the `k` smallest are returned,
+    // if given. Returns no sets if the tuple isn't derived.
+    pub fn support_sets(
+        &mut self,
+        relation: &str,
+        tuple: &GroundTuple,
+        k: Option<usize>,
+    ) -> Result<Vec<SupportSet>, InterpretError> {
+        if !self.tables.contains_key(relation) {
+            return Ok(vec![]);
+        }
+        let program = std::mem::take(&mut self.program);
+        let mut rules = vec![];
+        let mut builtins = vec![];
+        let mut constraints = vec![];
+        for stmt in &program {
+            match stmt {
+                StatementAST::Rule(head, body) => rules.push((head, body)),
+                StatementAST::Builtin(builtin) => builtins.push(builtin.clone()),
+                StatementAST::Constraint(body) => constraints.push(body),
+                _ => {}
+            }
+        }
+        let result = self.update_model(&rules, &builtins, &constraints);
+        self.program = program;
+        result?;
+        let Some((value, _)) = self.table(relation).get(tuple) else {
+            return Ok(vec![]);
+        };
+        let mut minimal = self.minimal_support(value);
+        if let Some(k) = k {
+            minimal.truncate(k);
+        }
+        Ok(minimal)
+    }
+
+    // The minimal support sets of a value, smallest first. The tables are expected to hold a model.
+    fn minimal_support(&self, value: &A) -> Vec<SupportSet> {
+        // Replace leaf assumptions by the other sets their tuples are derivable under, until no new
+        // sets are found. Sets with a known subset are skipped, since nothing derived from them can
+        // be minimal.
+        let mut found: BTreeSet<SupportSet> = value.conjuncts();
+        let mut worklist: Vec<SupportSet> = found.iter().cloned().collect();
+        while let Some(set) = worklist.pop() {
+            if found
+                .iter()
+                .any(|other| other.len() < set.len() && other.is_subset(&set))
+            {
+                continue;
+            }
+            for leaf in &set {
+                let (leaf_relation, leaf_tuple) = leaf;
+                let Some((leaf_value, _)) = self
+                    .tables
+                    .get(leaf_relation)
+                    .and_then(|table| table.get(leaf_tuple))
+                else {
+                    continue;
+                };
+                for conj in leaf_value.conjuncts() {
+                    if conj.contains(leaf) {
+                        continue;
+                    }
+                    let mut replaced = set.clone();
+                    replaced.remove(leaf);
+                    replaced.extend(conj);
+                    if found.insert(replaced.clone()) {
+                        worklist.push(replaced);
+                    }
+                }
+            }
+        }
+        let mut minimal: Vec<SupportSet> = found
+            .iter()
+            .filter(|set| {
+                !found
+                    .iter()
+                    .any(|other| other.len() < set.len() && other.is_subset(set))
+            })
+            .cloned()
+            .collect();
+        minimal.sort_by_key(BTreeSet::len);
+        minimal
+    }
+
+    // Abduce the answers to a question: the minimal support sets of each answer whose leaf
+    // assumptions are all of abducible relations. Answers with no such sets are dropped.
+    fn abduce(
+        &self,
+        answers: Vec<(Bindings, A)>,
+        abducible: &BTreeSet<&str>,
+    ) -> Vec<(Bindings, Vec<SupportSet>)> {
+        answers
+            .into_iter()
+            .filter_map(|(bindings, value)| {
+                let sets: Vec<_> = self
+                    .minimal_support(&value)
+                    .into_iter()
+                    .filter(|set| {
+                        abducible.is_empty()
+                            || set
+                                .iter()
+                                .all(|(relation, _)| abducible.contains(relation.as_str()))
+                    })
+                    .collect();
+                (!sets.is_empty()).then_some((bindings, sets))
+            })
+            .collect()
+    }
+
+    fn interpret_abduction(
+        &mut self,
+        body: &[AtomAST],
+        abductions: Vec<(Bindings, Vec<SupportSet>)>,
+    ) {
+        self.log
+            .push_str(&format!("Num rows: {}\n", abductions.len()));
+        if self.verbosity >= Verbosity::Bindings {
+            let labels = column_labels(&self.columns, body);
+            for (bindings, sets) in &abductions {
+                let bindings: Vec<_> = bindings
+                    .iter()
+                    .map(|(var, symbol)| format!("{}={}", labels[var.as_str()], Value(*symbol)))
+                    .collect();
+                let bindings = if bindings.is_empty() {
+                    "()".to_string()
+                } else {
+                    bindings.join(", ")
+                };
+                // Sets are shown as a DNF of their leaf assumptions, like `A(1) & B | C`.
+                let sets = DNFAssumption {
+                    dnf: sets.iter().cloned().map(Conjunct::new).collect(),
+                };
+                self.log.push_str(&format!("  {}  [{}]\n", bindings, sets));
+            }
+        }
+        self.abductions.push(abductions);
+    }
+}
+
+// Binary snapshots of tables, for materialized databases too large to serialize quickly in other
+// formats. A snapshot holds the symbol table of the strings and terms its symbols stand for, and
+// every table with its arity, tuples, and assumption values. Symbols are remapped to the ones their
+// strings and terms have in the loading process. Loaded environments answer `ask` from the loaded
+// tables, with no rules; interpreting a program recomputes the tables from scratch.
+const SNAPSHOT_MAGIC: &[u8; 8] = b"IMPLOG\0\x02";
+
+impl<A: Assumption + Encode> Environment<A> {
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        let mut out = BufWriter::new(File::create(path)?);
+        out.write_all(SNAPSHOT_MAGIC)?;
+        self.symbol_table().encode(&mut out)?;
+        write_len(&mut out, self.tables.len())?;
+        for (relation, table) in &self.tables {
+            write_str(&mut out, relation)?;
+            write_len(&mut out, self.arities[relation])?;
+            write_len(&mut out, table.len())?;
+            for (tuple, (old, new)) in table {
+                write_tuple(&mut out, tuple)?;
+                old.encode(&mut out)?;
+                new.encode(&mut out)?;
+            }
+        }
+        out.flush()
+    }
+
+    pub fn load(path: &Path) -> io::Result<Self> {
+        let mut input = BufReader::new(File::open(path)?);
+        let mut magic = [0; 8];
+        input.read_exact(&mut magic)?;
+        if &magic != SNAPSHOT_MAGIC {
+            return Err(invalid("not an implog snapshot"));
+        }
+        let remap = SymbolTable::decode(&mut input)?
+            .intern()
+            .map_err(|err| invalid(&err))?;
+        let mut env = Environment::new();
+        for _ in 0..read_len(&mut input)? {
+            let relation = read_str(&mut input)?;
+            let arity = read_len(&mut input)?;
+            let table = (0..read_len(&mut input)?)
+                .map(|_| {
+                    let tuple = remap.tuple(&read_tuple(&mut input, arity)?);
+                    let old = remap.value(&A::decode(&mut input)?);
+                    let new = remap.value(&A::decode(&mut input)?);
+                    Ok((tuple, (old, new)))
+                })
+                .collect::<io::Result<Table<A>>>()?;
+            env.arities.insert(relation.clone(), arity);
+            env.tables.insert(relation, table);
+        }
+        env.find_deltas();
+        env.live = true;
+        env.publish();
+        Ok(env)
+    }
+}
+
+// Relations stored in SQLite tables, so implog can act as a deductive layer over an existing
+// database and keep derived tables between runs. Each relation is stored in a table of the same
+// name, with a column per position (named after the relation's `.decl` columns, if it has any)
+// and a final `assumption` column holding the encoded assumption value of each tuple. Integers
+// are stored as integers and strings as text. Compound terms are stored as the text they're
+// printed as, so they're loaded back as strings. Tables without an `assumption` column, like those
+// of databases not written by implog, hold facts with assumption value one.
+//
+// Leaf assumptions hold interned symbols, so the symbol table of the strings and terms they stand
+// for is saved in `implog_strings` and `implog_terms` tables, and the symbols of loaded assumption
+// values are remapped to the ones their strings and terms have now (see `SymbolTable`).
+#[cfg(feature = "sqlite")]
+const SQLITE_STRINGS: &str = "implog_strings";
+#[cfg(feature = "sqlite")]
+const SQLITE_TERMS: &str = "implog_terms";
+#[cfg(feature = "sqlite")]
+const SQLITE_ASSUMPTION: &str = "assumption";
+
+#[cfg(feature = "sqlite")]
+fn sqlite_error(err: rusqlite::Error) -> io::Error {
+    io::Error::other(err)
+}
+
+#[cfg(feature = "sqlite")]
+fn quote_identifier(name: &str) -> String {
+    format!("\"{}\"", name.replace('"', "\"\""))
+}
+
+// The symbol table saved by `save_sqlite`, in the order the strings and terms were interned.
+#[cfg(feature = "sqlite")]
+fn read_sqlite_symbols(conn: &rusqlite::Connection) -> rusqlite::Result<SymbolTable> {
+    let strings = conn
+        .prepare(&format!(
+            "SELECT symbol, string FROM {} ORDER BY symbol",
+            SQLITE_STRINGS
+        ))?
+        .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?
+        .collect::<rusqlite::Result<_>>()?;
+    let terms = conn
+        .prepare(&format!(
+            "SELECT symbol, functor, args FROM {} ORDER BY symbol",
+            SQLITE_TERMS
+        ))?
+        .query_map([], |row| {
+            let args: Vec<u8> = row.get(2)?;
+            let args = args
+                .chunks_exact(8)
+                .map(|bytes| Symbol::from_le_bytes(bytes.try_into().unwrap()))
+                .collect();
+            Ok((row.get(0)?, row.get(1)?, args))
+        })?
+        .collect::<rusqlite::Result<_>>()?;
+    Ok(SymbolTable { strings, terms })
+}
+
+#[cfg(feature = "sqlite")]
+impl<A: Assumption + Encode> Environment<A> {
+    // Add the tuples of SQLite tables to relations as facts, as with `add_fact`.
+    pub fn load_sqlite(&mut self, path: &Path, relations: &[&str]) -> io::Result<()> {
+        use rusqlite::types::ValueRef;
+
+        let conn = rusqlite::Connection::open(path).map_err(sqlite_error)?;
+        let mut remap = None;
+        for relation in relations {
+            let table = quote_identifier(relation);
+            let mut stmt = conn
+                .prepare(&format!("SELECT * FROM {}", table))
+                .map_err(sqlite_error)?;
+            let has_assumption = stmt.column_names().last() == Some(&SQLITE_ASSUMPTION);
+            let arity = stmt.column_count() - usize::from(has_assumption);
+            if has_assumption && remap.is_none() {
+                let symbols = read_sqlite_symbols(&conn).map_err(sqlite_error)?;
+                remap = Some(symbols.intern().map_err(|err| invalid(&err))?);
+            }
+            let mut rows = stmt.query([]).map_err(sqlite_error)?;
+            while let Some(row) = rows.next().map_err(sqlite_error)? {
+                let tuple = (0..arity)
+                    .map(|idx| match row.get_ref(idx).map_err(sqlite_error)? {
+                        ValueRef::Integer(n) => Ok(n),
+                        ValueRef::Text(text) => std::str::from_utf8(text)
+                            .map(crate::value::intern)
+                            .map_err(|_| invalid("text isn't UTF-8")),
+                        ValueRef::Null | ValueRef::Real(_) | ValueRef::Blob(_) => {
+                            Err(invalid("column isn't an integer or text"))
+                        }
+                    })
+                    .collect::<io::Result<GroundTuple>>()?;
+                let assumption = if has_assumption {
+                    let mut blob = row
+                        .get_ref(arity)
+                        .map_err(sqlite_error)?
+                        .as_blob()
+                        .map_err(|_| invalid("assumption isn't a blob"))?;
+                    remap.as_ref().unwrap().value(&A::decode(&mut blob)?)
+                } else {
+                    A::one()
+                };
+                self.add_fact(relation, tuple, assumption)
+                    .map_err(io::Error::other)?;
+            }
+        }
+        Ok(())
+    }
+
+    // Store the tables of relations in SQLite, replacing any tables of the same names.
+    pub fn save_sqlite(&self, path: &Path, relations: &[&str]) -> io::Result<()> {
+        let mut conn = rusqlite::Connection::open(path).map_err(sqlite_error)?;
+        let tx = conn.transaction().map_err(sqlite_error)?;
+        tx.execute_batch(&format!(
+            "DROP TABLE IF EXISTS {0}; CREATE TABLE {0} (symbol INTEGER PRIMARY KEY, string TEXT);
+             DROP TABLE IF EXISTS {1};
+             CREATE TABLE {1} (symbol INTEGER PRIMARY KEY, functor TEXT, args BLOB);",
+            SQLITE_STRINGS, SQLITE_TERMS
+        ))
+        .map_err(sqlite_error)?;
+        let symbols = self.symbol_table();
+        for (symbol, s) in &symbols.strings {
+            tx.execute(
+                &format!("INSERT INTO {} VALUES (?1, ?2)", SQLITE_STRINGS),
+                rusqlite::params![symbol, s],
+            )
+            .map_err(sqlite_error)?;
+        }
+        for (symbol, functor, args) in &symbols.terms {
+            let mut blob = vec![];
+            write_tuple(&mut blob, args)?;
+            tx.execute(
+                &format!("INSERT INTO {} VALUES (?1, ?2, ?3)", SQLITE_TERMS),
+                rusqlite::params![symbol, functor, blob],
+            )
+            .map_err(sqlite_error)?;
+        }
+        for relation in relations {
+            let Some(arity) = self.arities.get(*relation) else {
+                return Err(invalid(&format!("relation {} has no table", relation)));
+            };
+            let columns: Vec<String> = match self.columns.get(*relation) {
+                Some(names) => names.clone(),
+                None => (0..*arity).map(|idx| format!("c{}", idx)).collect(),
+            };
+            let table = quote_identifier(relation);
+            let definitions: Vec<String> = columns
+                .iter()
+                .map(|name| quote_identifier(name))
+                .chain([format!("{} BLOB", SQLITE_ASSUMPTION)])
+                .collect();
+            tx.execute_batch(&format!(
+                "DROP TABLE IF EXISTS {0}; CREATE TABLE {0} ({1});",
+                table,
+                definitions.join(", ")
+            ))
+            .map_err(sqlite_error)?;
+            let placeholders = vec!["?"; arity + 1].join(", ");
+            let mut insert = tx
+                .prepare(&format!("INSERT INTO {} VALUES ({})", table, placeholders))
+                .map_err(sqlite_error)?;
+            for (tuple, (value, _)) in &self.tables[*relation] {
+                if value.is_zero() {
+                    continue;
+                }
+                let mut row: Vec<rusqlite::types::Value> = tuple
+                    .iter()
+                    .map(|symbol| match resolve(*symbol) {
+                        Some(s) => rusqlite::types::Value::Text(s),
+                        None if resolve_term(*symbol).is_some() => {
+                            rusqlite::types::Value::Text(Value(*symbol).to_string())
+                        }
+                        None => rusqlite::types::Value::Integer(*symbol),
+                    })
+                    .collect();
+                let mut blob = vec![];
+                value.encode(&mut blob)?;
+                row.push(rusqlite::types::Value::Blob(blob));
+                insert
+                    .execute(rusqlite::params_from_iter(row))
+                    .map_err(sqlite_error)?;
+            }
+        }
+        tx.commit().map_err(sqlite_error)
+    }
+}
+
+// Environments are serialized as checkpoints of their databases: the tables, the facts, and the
+// program the tables are a model of, along with the symbol table of the strings and terms their
+// symbols stand for. Symbols are remapped to the ones those have in the deserializing process.
+// Configuration (directories, strategies, limits, and callbacks) isn't saved, so environments are
+// deserialized with the defaults. Tables and sources are keyed by tuples, which formats like JSON
+// can't use as map keys, so they're serialized as sequences of entries.
+#[cfg(feature = "serde")]
+#[derive(serde::Serialize)]
+struct CheckpointRef<'a, A: Assumption> {
+    tables: BTreeMap<&'a str, Entries<'a, GroundTuple, (A, A)>>,
+    arities: &'a BTreeMap<String, usize>,
+    inputs: &'a BTreeMap<String, Vec<GroundTuple>>,
+    added: &'a BTreeMap<String, Vec<(GroundTuple, A)>>,
+    sources: Entries<'a, (String, GroundTuple), BTreeSet<String>>,
+    program: &'a Vec<StatementAST>,
+    live: bool,
+    inconsistent: &'a A,
+    columns: &'a BTreeMap<String, Vec<String>>,
+    symbols: SymbolTable,
+}
+
+#[cfg(feature = "serde")]
+#[derive(serde::Deserialize)]
+struct Checkpoint<A: Assumption> {
+    tables: BTreeMap<String, Vec<TableEntry<A>>>,
+    arities: BTreeMap<String, usize>,
+    inputs: BTreeMap<String, Vec<GroundTuple>>,
+    added: BTreeMap<String, Vec<(GroundTuple, A)>>,
+    sources: Vec<((String, GroundTuple), BTreeSet<String>)>,
+    program: Vec<StatementAST>,
+    live: bool,
+    inconsistent: A,
+    columns: BTreeMap<String, Vec<String>>,
+    symbols: SymbolTable,
+}
+
+#[cfg(feature = "serde")]
+type TableEntry<A> = (GroundTuple, (A, A));
+
+#[cfg(feature = "serde")]
+struct Entries<'a, K, V>(&'a BTreeMap<K, V>);
+
+#[cfg(feature = "serde")]
+impl<K: serde::Serialize, V: serde::Serialize> serde::Serialize for Entries<'_, K, V> {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_seq(self.0)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<A: Assumption + serde::Serialize> serde::Serialize for Environment<A> {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        CheckpointRef {
+            tables: self
+                .tables
+                .iter()
+                .map(|(relation, table)| (relation.as_str(), Entries(table)))
+                .collect(),
+            arities: &self.arities,
+            inputs: &self.inputs,
+            added: &self.added,
+            sources: Entries(&self.sources),
+            program: &self.program,
+            live: self.live,
+            inconsistent: &self.inconsistent,
+            columns: &self.columns,
+            symbols: self.symbol_table(),
+        }
+        .serialize(serializer)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de, A: Assumption + serde::Deserialize<'de>> serde::Deserialize<'de> for Environment<A> {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let mut checkpoint = Checkpoint::<A>::deserialize(deserializer)?;
+        let remap = checkpoint
+            .symbols
+            .intern()
+            .map_err(serde::de::Error::custom)?;
+        let entry = |(tuple, value): (GroundTuple, A)| (remap.tuple(&tuple), remap.value(&value));
+        let mut env = Environment::new();
+        env.tables = checkpoint
+            .tables
+            .into_iter()
+            .map(|(relation, table)| {
+                let table = table.into_iter().map(|(tuple, (old, new))| {
+                    (remap.tuple(&tuple), (remap.value(&old), remap.value(&new)))
+                });
+                (relation, table.collect())
+            })
+            .collect();
+        env.arities = checkpoint.arities;
+        env.inputs = checkpoint
+            .inputs
+            .into_iter()
+            .map(|(relation, facts)| {
+                let facts = facts.iter().map(|tuple| remap.tuple(tuple)).collect();
+                (relation, facts)
+            })
+            .collect();
+        env.added = checkpoint
+            .added
+            .into_iter()
+            .map(|(relation, facts)| (relation, facts.into_iter().map(entry).collect()))
+            .collect();
+        env.sources = checkpoint
+            .sources
+            .into_iter()
+            .map(|((relation, tuple), sources)| ((relation, remap.tuple(&tuple)), sources))
+            .collect();
+        for stmt in &mut checkpoint.program {
+            for symbol in stmt.constants_mut() {
+                *symbol = remap.symbol(*symbol);
+            }
+        }
+        env.program = checkpoint.program;
+        env.live = checkpoint.live;
+        env.inconsistent = remap.value(&checkpoint.inconsistent);
+        env.columns = checkpoint.columns;
+        env.find_deltas();
+        env.publish();
+        Ok(env)
+    }
+}
+
+// The relations derived from a relation, including itself: those with a rule reading one of them,
+// or a built-in computed from one.
+fn downstream(
+    rules: &[(&AtomAST, &Vec<AtomAST>)],
+    builtins: &[BuiltinAST],
+    relation: &str,
+) -> BTreeSet<String> {
+    let mut found = BTreeSet::from([relation.to_string()]);
+    loop {
+        let before = found.len();
+        for (head, body) in rules {
+            let (AtomAST::Literal(lit) | AtomAST::Brackets(lit) | AtomAST::Arrow(_, lit)) = head;
+            let mut reads = body.iter().filter_map(read_literal);
+            if reads.any(|read| found.contains(&read.relation)) {
+                found.insert(lit.relation.clone());
+            }
+        }
+        for builtin in builtins {
+            if found.contains(&builtin.source) {
+                found.insert(builtin.relation.clone());
+            }
+        }
+        if found.len() == before {
+            return found;
+        }
+    }
+}
+
+// Restrict answers to the selected variables. Answers that agree on them are merged, and their
+// assumption values summed.
+fn project<A: Assumption>(answers: Vec<(Bindings, A)>, select: &[String]) -> Vec<(Bindings, A)> {
+    let projected = answers.into_iter().map(|(mut bindings, value)| {
+        bindings.retain(|var, _| select.contains(var));
+        (bindings, value)
+    });
+    merge_answers(projected)
+}
+
+// Merge answers with the same bindings, which queries find once per way of joining their bodies
+// (say, through a list with repeated elements), summing their assumption values. Answers come out
+// in the order of their bindings.
+fn merge_answers<A: Assumption>(
+    answers: impl IntoIterator<Item = (Bindings, A)>,
+) -> Vec<(Bindings, A)> {
+    let mut merged: BTreeMap<Bindings, A> = BTreeMap::new();
+    for (bindings, value) in answers {
+        let sum = merged.entry(bindings).or_insert_with(A::zero);
+        if !sum.subsumes(&value) {
+            *sum = sum.plus(&value);
+        }
+    }
+    merged.into_iter().collect()
+}
+
+// Sort answers by the values they bind, in the order of their variables' names, with values
+// compared like by `apply_modifiers`.
+fn sort_canonically<A: Assumption>(answers: &mut [(Bindings, A)]) {
+    answers.sort_by_cached_key(|(bindings, _)| {
+        bindings
+            .values()
+            .map(|symbol| (resolve(*symbol), *symbol))
+            .collect::<Vec<_>>()
+    });
+}
+
+// Keep the answers whose assumption values pass a question's filter, sort them by the variables
+// the question is ordered by, then keep the first `limit` of them. Integers and compound terms sort
+// before strings, and strings sort lexicographically. Answers that agree on every ordering variable
+// keep their order.
+fn apply_modifiers<A: Assumption>(
+    mut answers: Vec<(Bindings, A)>,
+    modifiers: &Modifiers,
+) -> Vec<(Bindings, A)> {
+    match &modifiers.filter {
+        None => {}
+        Some(Filter::Definite) => answers.retain(|(_, value)| value.subsumes(&A::one())),
+        Some(Filter::Conditional) => answers.retain(|(_, value)| !value.subsumes(&A::one())),
+        Some(Filter::Assuming(lit)) => {
+            let leaf = leaf(lit, &Bindings::new());
+            answers.retain(|(_, value)| {
+                leaf.as_ref()
+                    .is_some_and(|leaf| value.leaves().contains(leaf))
+            });
+        }
+        Some(Filter::Excluding(lit)) => {
+            let leaf = leaf(lit, &Bindings::new());
+            answers.retain(|(_, value)| {
+                leaf.as_ref()
+                    .is_none_or(|leaf| !value.leaves().contains(leaf))
+            });
+        }
+    }
+    if !modifiers.order_by.is_empty() {
+        answers.sort_by_cached_key(|(bindings, _)| {
+            modifiers
+                .order_by
+                .iter()
+                .map(|var| (resolve(bindings[var]), bindings[var]))
+                .collect::<Vec<_>>()
+        });
+    }
+    if let Some(limit) = modifiers.limit {
+        answers.truncate(limit);
+    }
+    answers
+}
+
+// Variables of a question are labeled with the name of the declared column they're bound to, when
+// they're bound to exactly one column and no other variable gets the same label. Otherwise, they're
+// labeled with their own name.
+fn column_labels<'a>(
+    columns: &'a BTreeMap<String, Vec<String>>,
+    body: &'a [AtomAST],
+) -> BTreeMap<&'a str, &'a str> {
+    let mut bound: BTreeMap<&str, BTreeSet<&str>> = BTreeMap::new();
+    for atom in body {
+        let (AtomAST::Literal(lit) | AtomAST::Brackets(lit) | AtomAST::Arrow(_, lit)) = atom;
+        let names = columns.get(&lit.relation);
+        for (idx, term) in lit.terms.iter().enumerate() {
+            if let Some(var) = term.try_var() {
+                let name = names.map_or(var, |names| &names[idx]);
+                bound.entry(var).or_default().insert(name);
+            }
+        }
+    }
+    let mut labels: BTreeMap<&str, &str> = bound
+        .iter()
+        .map(|(var, names)| match names.len() {
+            1 => (*var, *names.first().unwrap()),
+            _ => (*var, *var),
+        })
+        .collect();
+    // Falling back to a variable's name may clash with another label, so repeat until no labels
+    // clash.
+    loop {
+        let mut counts: BTreeMap<&str, usize> = BTreeMap::new();
+        for label in labels.values() {
+            *counts.entry(label).or_default() += 1;
+        }
+        let mut changed = false;
+        for (var, label) in labels.iter_mut() {
+            if counts[label] > 1 && label != var {
+                *label = var;
+                changed = true;
+            }
+        }
+        if !changed {
+            return labels;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::grammar::{LocatedProgramParser, ProgramParser};
+
+    use crate::ast::check;
+    use crate::lint::Lint;
+    use crate::representation::DNFAssumption;
+    use crate::value::{MAX_TERM_DEPTH, intern};
+
+    use super::*;
+
+    fn interpret(program: &str) -> String {
+        let parsed = ProgramParser::new().parse(program).unwrap();
+        let mut env = Environment::<DNFAssumption>::new();
+        env.interpret(&parsed).unwrap().to_string()
+    }
+
+    #[test]
+    fn interpret_path() {
+        let program = include_str!("../examples/path.dl");
+        assert_eq!(
+            interpret(program),
+            "Num rows: 1\nNum rows: 0\nNum rows: 2\nNum rows: 3\nNum rows: 6\nNum rows: 1\nNum rows: 0\n"
+        );
+    }
+
+    #[test]
+    fn interpret_basic_assume() {
+        let program = include_str!("../examples/basic_assume.dl");
+        assert_eq!(
+            interpret(program),
+            "Num rows: 0\nNum rows: 0\nNum rows: 0\nNum rows: 1\nNum rows: 1\nNum rows: 1\nNum rows: 1\nNum rows: 1\n"
+        );
+    }
+
+    #[test]
+    fn interpret_tricky() {
+        let program = include_str!("../examples/tricky.dl");
+        assert_eq!(
+            interpret(program),
+            "Num rows: 1\nNum rows: 1\nNum rows: 1\nNum rows: 1\nNum rows: 1\nNum rows: 2\n"
+        );
+    }
+
+    #[test]
+    fn interpret_builtin() {
+        let program = r#"
+E(1, 2) :- .
+E(2, 1) :- .
+E(2, 3) :- .
+W(1, 2, 4) :- .
+W(2, 3, 1) :- .
+W(1, 3, 7) :- .
+.builtin Reach = transitive_closure(E).
+.builtin Same = scc(E).
+.builtin Dist = shortest_path(W).
+
+? Reach(x, y).
+? Same(x, y).
+? Dist(1, 3, 5).
+"#;
+        assert_eq!(
+            interpret(program),
+            "Num rows: 6\nNum rows: 5\nNum rows: 1\n"
+        );
+    }
+
+    #[test]
+    fn interpret_input_output() {
+        let dir = std::env::temp_dir().join(format!("implog-io-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("E.facts"), "1\t2\n2\t3\n").unwrap();
+        let program = r#"
+.input E(2).
+.output P.
+? E(x, y).
+P(x, z) :- E(x, y), E(y, z).
+"#;
+        let parsed = ProgramParser::new().parse(program).unwrap();
+        let mut env = Environment::<DNFAssumption>::new();
+        env.set_facts_dir(&dir);
+        env.set_output_dir(&dir);
+        assert_eq!(env.interpret(&parsed).unwrap(), "Num rows: 2\n");
+        let dumped = std::fs::read_to_string(dir.join("P.csv")).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(dumped, "1\t3\n");
+    }
+
+    #[test]
+    fn interpret_io_errors() {
+        let error = |program: &str| {
+            let parsed = ProgramParser::new().parse(program).unwrap();
+            let mut env = Environment::<DNFAssumption>::new();
+            env.set_facts_dir(Path::new("/nonexistent"));
+            env.interpret(&parsed).unwrap_err()
+        };
+        assert_eq!(
+            error("E(1, 2) :- .\n.input E(2).\n"),
+            InterpretError::DerivedInput(
+                "E".to_string(),
+                Location {
+                    statement: Some(2),
+                    span: None
+                }
+            )
+        );
+        assert!(matches!(
+            error(".input E(2).\n"),
+            InterpretError::LoadFacts { path, .. } if path == Path::new("/nonexistent/E.facts")
+        ));
+        assert_eq!(
+            error(".output P.\n"),
+            InterpretError::UnusedOutput("P".to_string())
+        );
+    }
+
+    #[test]
+    fn interpret_constraint() {
+        let program = r#"
+[A] :- .
+[B] :- .
+P :- A, B.
+Q :- A.
+R :- A -> P.
+:- A, B.
+
+? P.
+? Q.
+? R.
+"#;
+        assert_eq!(
+            interpret(program),
+            "Num rows: 0\nNum rows: 1\nNum rows: 0\n"
+        );
+    }
+
+    #[test]
+    fn interpret_flagged_inconsistency() {
+        let program = r#"
+[A] :- .
+[B] :- .
+[C] :- .
+P :- A, B.
+P :- C.
+Q :- A, B.
+:- A, B.
+
+? P.
+? Q.
+"#;
+        let parsed = ProgramParser::new().parse(program).unwrap();
+        let mut env = Environment::<DNFAssumption>::new();
+        env.set_verbosity(Verbosity::Bindings);
+        assert_eq!(
+            env.interpret(&parsed).unwrap(),
+            "Num rows: 1\n  ()\nNum rows: 0\n"
+        );
+        assert_eq!(env.contradiction().to_string(), "A & B");
+
+        env.set_consistency(Consistency::Flag);
+        assert_eq!(
+            env.interpret(&parsed).unwrap(),
+            "Num rows: 1\n  ()\nNum rows: 1\n  ()  inconsistent\n"
+        );
+        assert_eq!(env.contradiction().to_string(), "A & B");
+        assert!(!env.is_inconsistent(&env.answers()[0][0].1));
+    }
+
+    #[test]
+    fn interpret_strategies() {
+        let programs = [
+            include_str!("../examples/path.dl"),
+            include_str!("../examples/basic_assume.dl"),
+            include_str!("../examples/tricky.dl"),
+            r#"
+E(1, 2) :- .
+E(2, 3) :- .
+E(3, 1) :- .
+E(5, 6) :- .
+[E(3, 4)] :- .
+P(x, y) :- E(x, y).
+P(x, z) :- P(x, y), P(y, z).
+Q(x) :- P(1, x), E(x, y).
+R(x) :- P(x, 1) -> Q(x).
+:- P(4, 4).
+.builtin S = scc(E).
+T(x) :- S(1, x), P(x, 4).
+
+? P(1, x).
+? P(x, 1).
+? Q(x).
+? R(x).
+? T(x).
+? P(5, 6).
+"#,
+            r#"
+E(1, 2) :- .
+E(2, 3) :- .
+E(3, 1) :- .
+[E(3, 4)] :- .
+E(4, 1) :- .
+E(2, 4) :- .
+E(5, 5) :- .
+T(x, y, z) :- E(x, y), E(y, z), E(z, x), [A(x)].
+R(x, y) :- E(x, y).
+R(x, z) :- R(x, y), R(y, z), R(z, x).
+:- T(5, 5, 5).
+
+? T(x, y, z).
+? R(x, y).
+"#,
+        ];
+        for program in programs {
+            let parsed = ProgramParser::new().parse(program).unwrap();
+            let mut env = Environment::<DNFAssumption>::new();
+            env.interpret(&parsed).unwrap();
+            let expected = env.answers().to_vec();
+            env.set_strategy(Strategy::Magic);
+            env.interpret(&parsed).unwrap();
+            assert_eq!(env.answers(), expected);
+            env.set_strategy(Strategy::TopDown);
+            env.interpret(&parsed).unwrap();
+            assert_eq!(env.answers(), expected);
+        }
+
+        let program = ".pragma magic.\nE(1, 2) :- .\nE(2, 3) :- .\n? E(1, x).\n";
+        assert_eq!(interpret(program), "Num rows: 1\n");
+        let program =
+            ".pragma top_down.\nE(1, 2) :- .\n? E(1, x).\n.pragma bottom_up.\n? E(x, y).\n";
+        assert_eq!(interpret(program), "Num rows: 1\nNum rows: 1\n");
+        let program =
+            "E(1, 2) :- .\nE(2, 3) :- .\n?td E(1, x).\n?magic E(x, 3).\n?naive E(x, y).\n";
+        assert_eq!(
+            interpret(program),
+            "Num rows: 1\nNum rows: 1\nNum rows: 2\n"
+        );
+    }
+
+    #[test]
+    fn interpret_expect() {
+        let program = include_str!("../examples/path.dl");
+        let parsed = ProgramParser::new().parse(program).unwrap();
+        let mut env = Environment::<DNFAssumption>::new();
+        env.interpret(&parsed).unwrap();
+
+        let program = r#"
+E(1, 2) :- .
+E(2, 3) :- .
+P(x, y) :- E(x, y).
+P(x, z) :- E(x, y), P(y, z).
+!expect P(1, 3).
+!expect_count P(x, y) = 3.
+? P(x, y).
+!expect P(3, 1).
+? P(x, y).
+"#;
+        let parsed = ProgramParser::new().parse(program).unwrap();
+        assert_eq!(
+            env.interpret(&parsed),
+            Err(InterpretError::ExpectationFailed {
+                statement: 8,
+                expected: None,
+                found: 0
+            })
+        );
+        assert_eq!(env.log(), "Num rows: 3\n");
+
+        let program = "E(1, 2) :- .\n!expect_count E(x, y) = 2.\n";
+        let parsed = ProgramParser::new().parse(program).unwrap();
+        assert_eq!(
+            env.interpret(&parsed),
+            Err(InterpretError::ExpectationFailed {
+                statement: 2,
+                expected: Some(2),
+                found: 1
+            })
+        );
+    }
+
+    #[test]
+    fn interpret_diff() {
+        let program = r#"
+E(1, 2) :- .
+E(2, 3) :- .
+E(3, 4) :- .
+[E(4, 5)] :- .
+F(x, y) :- E(x, y).
+F(1, 5) :- .
+F(3, 4) :- E(4, 5).
+
+?diff E(x, y); F(x, y).
+?diff E(x, 2); E(x, 2).
+"#;
+        let parsed = ProgramParser::new().parse(program).unwrap();
+        let mut env = Environment::<DNFAssumption>::new();
+        assert_eq!(
+            env.interpret(&parsed).unwrap(),
+            "Diff rows: 1 added, 0 removed, 0 changed\nDiff rows: 0 added, 0 removed, 0 changed\n"
+        );
+        let diff = &env.diffs()[0];
+        assert_eq!(
+            diff.added[0].0,
+            Bindings::from([("x".into(), 1), ("y".into(), 5)])
+        );
+
+        let program = r#"
+E(1, 2) :- .
+[A] :- .
+F(x, y) :- E(x, y), A.
+?diff E(x, y); F(x, y).
+?diff F(x, y); E(x, y).
+"#;
+        assert_eq!(
+            interpret(program),
+            "Diff rows: 0 added, 0 removed, 1 changed\nDiff rows: 0 added, 0 removed, 1 changed\n"
+        );
+    }
+
+    #[test]
+    fn plan_joins() {
+        let program = r#"
+E(1, 2) :- .
+E(2, 3) :- .
+E(3, 4) :- .
+S(2) :- .
+[A] :- .
+? E(x, y), E(y, z), S(y), A.
+"#;
+        let parsed = ProgramParser::new().parse(program).unwrap();
+        let mut env = Environment::<DNFAssumption>::new();
+        env.interpret(&parsed).unwrap();
+        let StatementAST::Question(body, _, _, _) = &parsed[5] else {
+            panic!()
+        };
+        let relations = |body: &[AtomAST]| {
+            body.iter()
+                .map(|atom| atom.vars().collect::<Vec<_>>().join(""))
+                .collect::<Vec<_>>()
+        };
+
+        let (planned, delta) = env.plan(body, None);
+        assert_eq!(relations(&planned), vec!["y", "xy", "yz", ""]);
+        assert_eq!(delta, None);
+        assert_eq!(env.query(&planned, None).len(), env.query(body, None).len());
+
+        // Nothing is left in the deltas after evaluation, so the atom reading the delta goes first.
+        let (planned, delta) = env.plan(body, Some(1));
+        assert_eq!(delta, Some(0));
+        assert_eq!(relations(&planned), vec!["yz", "y", "xy", ""]);
+    }
+
+    #[test]
+    fn cache_plans() {
+        let program = "E(1, 2) :- .\nE(2, 3) :- .\nS(2) :- .\n";
+        let parsed = ProgramParser::new().parse(program).unwrap();
+        let mut env = Environment::<DNFAssumption>::new();
+        env.interpret(&parsed).unwrap();
+        let body = ProgramParser::new().parse("? E(x, y), S(y).").unwrap()[0]
+            .body()
+            .clone();
+        env.ask(&body).unwrap();
+        let (compiled, answers) = env.join_cached(&body, None);
+        assert_eq!(compiled.bindings_of(answers), env.join(&body, None));
+        let key = ("E(x, y), S(y)".to_string(), None);
+        let sizes =
+            |env: &Environment<DNFAssumption>| env.plans.lock().unwrap()[&key].sizes.clone();
+        let planned = env.plans.lock().unwrap()[&key].compiled.query[0].to_string();
+        assert_eq!(planned, "S(y)");
+        assert_eq!(sizes(&env), vec![2, 1]);
+
+        // The plan is kept while the tables stay within a power of two rows, and made again once
+        // they grow past it.
+        env.add_fact("E", vec![3, 4], DNFAssumption::one()).unwrap();
+        env.join_cached(&body, None);
+        assert_eq!(sizes(&env), vec![2, 1]);
+        env.add_fact("E", vec![4, 5], DNFAssumption::one()).unwrap();
+        env.join_cached(&body, None);
+        assert_eq!(sizes(&env), vec![3, 1]);
+
+        // Plans of rules outlive the fixpoints that made them, until the rules leave the program.
+        let program = "E(1, 2) :- .\nP(x) :- E(x, y), E(y, z).\n? P(x).\n";
+        env.interpret(&ProgramParser::new().parse(program).unwrap())
+            .unwrap();
+        let bodies: BTreeSet<_> = env
+            .plans
+            .lock()
+            .unwrap()
+            .keys()
+            .map(|(body, _)| body.clone())
+            .collect();
+        assert_eq!(
+            bodies,
+            BTreeSet::from(["".to_string(), "E(x, y), E(y, z)".to_string()])
+        );
+    }
+
+    #[test]
+    fn keep_trie_indexes() {
+        let program = "E(1, 2) :- .\nE(2, 3) :- .\nE(3, 1) :- .\n";
+        let mut env = Environment::<DNFAssumption>::new();
+        env.interpret(&ProgramParser::new().parse(program).unwrap())
+            .unwrap();
+        let body = ProgramParser::new()
+            .parse("? E(x, y), E(y, z), E(z, x).")
+            .unwrap()[0]
+            .body()
+            .clone();
+        let sorted = |mut answers: Vec<(Bindings, DNFAssumption)>| {
+            answers.sort_by(|(a, _), (b, _)| a.cmp(b));
+            answers
+        };
+        env.ask(&body).unwrap();
+        assert_eq!(env.join(&body, None).len(), 3);
+        let lens = |env: &mut Environment<DNFAssumption>| {
+            let tries = env.tries.get_mut().unwrap();
+            tries["E"].iter().map(TrieIndex::len).collect::<Vec<_>>()
+        };
+        assert_eq!(lens(&mut env), vec![3, 3]);
+
+        // Rows added to a table are added to its indexes, which are kept between joins.
+        for (x, y) in [(3, 2), (2, 1), (1, 3)] {
+            env.add_fact("E", vec![x, y], DNFAssumption::one()).unwrap();
+        }
+        assert_eq!(lens(&mut env), vec![6, 6]);
+        let answers = sorted(env.join(&body, None));
+        assert_eq!(answers, sorted(env.query(&body, None)));
+        assert_eq!(answers.len(), 6);
+
+        // So are rows added in bulk.
+        let edges = [vec![1, 1], vec![2, 2]].map(|edge| (edge, DNFAssumption::one()));
+        env.add_facts("E", edges).unwrap();
+        assert_eq!(lens(&mut env), vec![8, 8]);
+    }
+
+    #[test]
+    fn compile_matches() {
+        let env = Environment::<DNFAssumption>::new();
+        let body = ProgramParser::new()
+            .parse("? E(x, x), E(x, f(y)), length(l, z), E(z, 2).")
+            .unwrap()[0]
+            .body()
+            .clone();
+        let bindings = Bindings::from([("y".to_string(), 3)]);
+        let compiled = env.compile(&body, &bindings);
+        assert_eq!(compiled.vars, vec!["y", "x", "l", "z"]);
+        // The first column binds a slot, which later columns and atoms test.
+        let matches = compiled.program[0].matches();
+        assert!(matches!(matches, [Match::Bind(1), Match::Test(1)]));
+        let matches = compiled.program[1].matches();
+        assert!(matches!(matches, [Match::Test(1), Match::Unify(_)]));
+        assert!(matches!(compiled.program[2], Instruction::Call(_)));
+        // Slots built-in calls may leave unbound are unified.
+        let matches = compiled.program[3].matches();
+        assert!(matches!(matches, [Match::Unify(_), Match::Constant(2)]));
+    }
+
+    #[test]
+    fn add_facts_incrementally() {
+        let program = r#"
+E(1, 2) :- .
+P(x, y) :- E(x, y).
+P(x, z) :- E(x, y), P(y, z).
+Q(x) :- P(x, 4), [A(x)].
+:- Q(1), B.
+"#;
+        let parsed = ProgramParser::new().parse(program).unwrap();
+        let question = ProgramParser::new().parse("? Q(x).").unwrap();
+        let StatementAST::Question(question, _, _, _) = &question[0] else {
+            panic!()
+        };
+        let mut env = Environment::<DNFAssumption>::new();
+        env.interpret(&parsed).unwrap();
+        assert_eq!(env.ask(question).unwrap().len(), 0);
+
+        env.add_fact("E", vec![2, 3], DNFAssumption::one()).unwrap();
+        let edges = [vec![3, 4], vec![4, 4]].map(|edge| (edge, DNFAssumption::one()));
+        env.add_facts("E", edges).unwrap();
+        env.add_facts("E", []).unwrap();
+        let ragged = [vec![1, 4], vec![1]].map(|edge| (edge, DNFAssumption::one()));
+        assert!(matches!(
+            env.add_facts("E", ragged),
+            Err(InterpretError::ArityMismatch(..))
+        ));
+        let answers = env.ask(question).unwrap();
+        assert_eq!(answers.len(), 4);
+        env.add_fact("B", vec![], DNFAssumption::one()).unwrap();
+        let incremental = env.ask(question).unwrap();
+        assert_eq!(incremental.len(), 3);
+
+        // Added facts are kept, so evaluating from scratch finds the same answers.
+        env.interpret(&parsed).unwrap();
+        assert_eq!(env.ask(question).unwrap(), incremental);
+    }
+
+    #[test]
+    fn retract_facts() {
+        let program = r#"
+E(1, 2) :- .
+P(x, y) :- E(x, y).
+P(x, z) :- E(x, y), P(y, z).
+[A(x)] :- P(x, 3).
+Q(x) :- P(x, 4), A(x).
+"#;
+        let parsed = ProgramParser::new().parse(program).unwrap();
+        let question = ProgramParser::new().parse("? P(x, y).").unwrap();
+        let StatementAST::Question(question, _, _, _) = &question[0] else {
+            panic!()
+        };
+        let mut env = Environment::<DNFAssumption>::new();
+        env.interpret(&parsed).unwrap();
+        for edge in [[1, 2], [2, 3], [3, 4], [1, 3]] {
+            env.add_fact("E", edge.to_vec(), DNFAssumption::one())
+                .unwrap();
+        }
+        assert_eq!(env.ask(question).unwrap().len(), 6);
+
+        env.retract_fact("E", &vec![1, 2]).unwrap();
+        env.retract_fact("E", &vec![2, 3]).unwrap();
+        let retracted = (
+            env.ask(question).unwrap(),
+            env.relation("Q").unwrap().table().clone(),
+        );
+        assert_eq!(retracted.0.len(), 4);
+
+        env.interpret(&parsed).unwrap();
+        assert_eq!(
+            (
+                env.ask(question).unwrap(),
+                env.relation("Q").unwrap().table().clone()
+            ),
+            retracted
+        );
+    }
+
+    #[test]
+    fn rederive_semi_naively() {
+        let program = ".decl Dist(node: int, @min cost: int).\n\
+                       Dist(1, 0) :- .\nDist(y, d) :- Dist(x, c), E(x, y, w), Add(c, w, d).\n\
+                       [Far(x)] :- Dist(x, d), Add(3, _, d).\n";
+        let parsed = ProgramParser::new().parse(program).unwrap();
+        let question = ProgramParser::new().parse("? Dist(x, d), Far(x).").unwrap();
+        let environment = |edges: &[[Symbol; 3]]| {
+            let mut env = Environment::<DNFAssumption>::new();
+            env.register_builtin("Add", 3, |args| match args {
+                [Some(x), Some(y), _] => vec![vec![*x, *y, Value(x.0 + y.0)]],
+                [Some(x), _, Some(z)] => vec![vec![*x, Value(z.0 - x.0), *z]],
+                _ => vec![],
+            });
+            env.interpret(&parsed).unwrap();
+            for edge in edges {
+                env.add_fact("E", edge.to_vec(), DNFAssumption::one())
+                    .unwrap();
+            }
+            env
+        };
+        let model = |env: &mut Environment<DNFAssumption>| {
+            let answers = env.ask(question[0].body()).unwrap();
+            let table = |relation| env.relation(relation).unwrap().table().clone();
+            (answers, table("Dist"), table("Far"))
+        };
+
+        let mut env = environment(&[[1, 2, 4], [1, 3, 1], [3, 2, 1], [2, 1, 1], [2, 4, 1]]);
+        assert_eq!(model(&mut env).1.len(), 4);
+        env.retract_fact("E", &vec![1, 3, 1]).unwrap();
+        let retracted = model(&mut env);
+        assert_eq!(
+            retracted.1.keys().cloned().collect::<Vec<_>>(),
+            vec![vec![1, 0], vec![2, 4], vec![4, 5]]
+        );
+        assert_eq!(
+            retracted,
+            model(&mut environment(&[
+                [1, 2, 4],
+                [3, 2, 1],
+                [2, 1, 1],
+                [2, 4, 1]
+            ]))
+        );
+
+        // Rules computing a transitive closure are evaluated by a built-in, which derives the
+        // deleted tuples of a cycle again.
+        let program = "P(x, y) :- E(x, y).\nP(x, z) :- P(x, y), E(y, z).\n";
+        let parsed = ProgramParser::new().parse(program).unwrap();
+        let question = ProgramParser::new().parse("? P(x, y).").unwrap();
+        let mut env = Environment::<DNFAssumption>::new();
+        env.interpret(&parsed).unwrap();
+        for edge in [[1, 2], [2, 3], [3, 1]] {
+            env.add_fact("E", edge.to_vec(), DNFAssumption::one())
+                .unwrap();
+        }
+        assert_eq!(env.ask(question[0].body()).unwrap().len(), 9);
+        env.retract_fact("E", &vec![3, 1]).unwrap();
+        let paths: Vec<_> = env
+            .ask(question[0].body())
+            .unwrap()
+            .into_iter()
+            .map(|(bindings, _)| (bindings["x"], bindings["y"]))
+            .collect();
+        assert_eq!(paths, vec![(1, 2), (1, 3), (2, 3)]);
+    }
+
+    #[test]
+    fn compose_added_rows_with_closures() {
+        let program = "P(x, y) :- E(x, y).\nP(x, z) :- P(x, y), E(y, z).\n";
+        let parsed = ProgramParser::new().parse(program).unwrap();
+        let question = ProgramParser::new().parse("? P(x, y).").unwrap();
+        let mut env = Environment::<DNFAssumption>::new();
+        env.interpret(&parsed).unwrap();
+        env.add_fact("P", vec![0, 1], DNFAssumption::one()).unwrap();
+        env.add_fact("E", vec![1, 2], DNFAssumption::one()).unwrap();
+        let paths = |env: &mut Environment<DNFAssumption>| -> Vec<_> {
+            let answers = env.ask(question[0].body()).unwrap();
+            answers
+                .into_iter()
+                .map(|(bindings, _)| (bindings["x"], bindings["y"]))
+                .collect()
+        };
+        assert_eq!(paths(&mut env), vec![(0, 1), (0, 2), (1, 2)]);
+        env.retract_fact("P", &vec![0, 1]).unwrap();
+        assert_eq!(paths(&mut env), vec![(1, 2)]);
+    }
+
+    #[test]
+    fn roll_back_failed_retractions() {
+        let program = "P(x, y) :- E(x, y).\nQ(x, y) :- P(x, y).\nP(x, z) :- Q(x, y), E(y, z).\n";
+        let parsed = ProgramParser::new().parse(program).unwrap();
+        let question = ProgramParser::new().parse("? P(x, y).").unwrap();
+        let mut env = Environment::<DNFAssumption>::new();
+        env.interpret(&parsed).unwrap();
+        for edge in [[1, 2], [2, 3], [3, 4], [1, 3]] {
+            env.add_fact("E", edge.to_vec(), DNFAssumption::one())
+                .unwrap();
+        }
+        let answers = env.ask(question[0].body()).unwrap();
+        let tables = |env: &Environment<DNFAssumption>| {
+            let table = |relation| env.relation(relation).unwrap().table().clone();
+            (table("E"), table("P"))
+        };
+        let before = tables(&env);
+
+        // Rederiving the paths from 1 takes more iterations than the limit.
+        env.set_max_iterations(1);
+        assert_eq!(
+            env.retract_fact("E", &vec![1, 2]),
+            Err(InterpretError::IterationLimit { limit: 1 })
+        );
+        assert_eq!(tables(&env), before);
+        env.set_max_iterations(100);
+        assert_eq!(env.ask(question[0].body()).unwrap(), answers);
+        env.retract_fact("E", &vec![1, 2]).unwrap();
+        assert_eq!(
+            env.ask(question[0].body()).unwrap().len(),
+            answers.len() - 1
+        );
+    }
+
+    #[test]
+    fn evaluate_incrementally() {
+        let program = "E(1, 2) :- .\nE(2, 3) :- .\nP(x, y) :- E(x, y).\n? P(x, y).\nQ(x) :- P(x, y).\nR(x) :- Q(x).\n? R(x).\n";
+        let parsed = ProgramParser::new().parse(program).unwrap();
+        let mut env = Environment::<DNFAssumption>::new();
+        let reports = Arc::new(Mutex::new(vec![]));
+        let sink = reports.clone();
+        env.set_progress(move |progress| sink.lock().unwrap().push(progress));
+        assert_eq!(
+            env.interpret(&parsed).unwrap(),
+            "Num rows: 2\nNum rows: 2\n"
+        );
+        // The second question only evaluates the rules for Q and R, starting from the model of the
+        // rules before it.
+        let iterations: Vec<_> = reports
+            .lock()
+            .unwrap()
+            .iter()
+            .filter_map(|progress| match progress {
+                Progress::Fixpoint { iterations, .. } => Some(*iterations),
+                Progress::Iteration { .. } => None,
+            })
+            .collect();
+        assert_eq!(iterations, vec![3, 2]);
+    }
+
+    #[test]
+    fn advise_indexes() {
+        let program = r#"
+E(1, 2) :- .
+E(2, 3) :- .
+P(x, z) :- E(x, y), E(y, z).
+? P(1, x).
+? E(x, 3).
+"#;
+        let parsed = ProgramParser::new().parse(program).unwrap();
+        let mut env = Environment::<DNFAssumption>::new();
+        env.set_index_advice(true);
+        env.interpret(&parsed).unwrap();
+        let advice: Vec<_> = env.index_advice().iter().map(ToString::to_string).collect();
+        assert_eq!(
+            advice,
+            vec![
+                "index E(0): 4 probes",
+                "index E(1): 1 probes",
+                "index P(0): 1 probes"
+            ]
+        );
+    }
+
+    #[test]
+    fn interpret_diagnostics() {
+        let program = "E(1, 2) :- .\nP(x, y) :- E(x, y).\n? E(x, y).\n";
+        let parsed = ProgramParser::new().parse(program).unwrap();
+        let mut env = Environment::<DNFAssumption>::new();
+        assert_eq!(env.interpret(&parsed).unwrap(), "Num rows: 1\n");
+        assert_eq!(env.diagnostics().len(), 1);
+        assert_eq!(env.diagnostics()[0].lint, Lint::UnusedRelation);
+
+        let parsed = ProgramParser::new()
+            .parse(".allow unused_relation.\n")
+            .unwrap();
+        env.interpret(&parsed).unwrap();
+        assert!(env.diagnostics().is_empty());
+    }
+
+    #[test]
+    fn explain_missing_answers() {
+        let program = r#"
+E(1, 2) :- .
+E(2, 3) :- .
+P(x, z) :- E(x, y), E(y, z).
+? P(1, 3).
+? P(1, z), E(z, w).
+"#;
+        let parsed = ProgramParser::new().parse(program).unwrap();
+        let mut env = Environment::<DNFAssumption>::new();
+        env.set_why_not(true);
+        assert_eq!(
+            env.interpret(&parsed).unwrap(),
+            "Num rows: 1\nNum rows: 0\nWhy not: atom 1 has no tuple E(3, _) with z = 3\n"
+        );
+
+        let question = ProgramParser::new().parse("? E(1, x), E(x, 1).").unwrap();
+        assert_eq!(
+            env.why_not(question[0].body()).unwrap(),
+            Some(WhyNot::Missing {
+                atom: 1,
+                bindings: Bindings::from([("x".to_string(), 2)]),
+                relation: "E".to_string(),
+                pattern: vec![Some(2), Some(1)],
+            })
+        );
+        let question = ProgramParser::new().parse("? E(1, x).").unwrap();
+        assert_eq!(env.why_not(question[0].body()).unwrap(), None);
+    }
+
+    #[test]
+    fn attribute_answers_to_sources() {
+        let program = "P(x, z) :- E(x, y), E(y, z).\n";
+        let parsed = ProgramParser::new().parse(program).unwrap();
+        let mut env = Environment::<DNFAssumption>::new();
+        env.interpret(&parsed).unwrap();
+        env.add_sourced_fact("E", vec![1, 2], "feed a").unwrap();
+        env.add_sourced_fact("E", vec![2, 3], "feed b").unwrap();
+        env.add_sourced_fact("E", vec![3, 4], "feed b").unwrap();
+        env.add_fact("E", vec![2, 5], DNFAssumption::one()).unwrap();
+
+        let question = ProgramParser::new().parse("? P(x, z).").unwrap();
+        let answers = env.ask(question[0].body()).unwrap();
+        // Sources are kept apart from values, so answers from sourced facts hold definitely.
+        assert!(
+            answers
+                .iter()
+                .all(|(_, value)| value.subsumes(&DNFAssumption::one()))
+        );
+        let sources: BTreeMap<_, _> = answers
+            .iter()
+            .map(|(bindings, _)| {
+                let sources = env.answer_sources(question[0].body(), bindings).unwrap();
+                (bindings["z"], sources)
+            })
+            .collect();
+        let set = |sources: &[&str]| sources.iter().map(|source| source.to_string()).collect();
+        assert_eq!(
+            sources,
+            BTreeMap::from([
+                (3, set(&["feed a", "feed b"])),
+                (4, set(&["feed b"])),
+                (5, set(&["feed a"])),
+            ])
+        );
+
+        env.retract_fact("E", &vec![1, 2]).unwrap();
+        let bindings = Bindings::from([("x".to_string(), 2), ("z".to_string(), 4)]);
+        let sources = env.answer_sources(question[0].body(), &bindings).unwrap();
+        assert_eq!(sources, set(&["feed b"]));
+    }
+
+    #[test]
+    fn interpret_verbosely() {
+        let program = "[A(1)] :- .\nE(1, 2) :- .\nE(x, 3) :- A(x).\n? E(1, y).\n? E(1, 2).\n";
+        let parsed = ProgramParser::new().parse(program).unwrap();
+        let mut env = Environment::<DNFAssumption>::new();
+        env.set_verbosity(Verbosity::Bindings);
+        assert_eq!(
+            env.interpret(&parsed).unwrap(),
+            "Num rows: 2\n  y=2\n  y=3\nNum rows: 1\n  ()\n"
+        );
+        env.set_verbosity(Verbosity::Assumptions);
+        assert_eq!(
+            env.interpret(&parsed).unwrap(),
+            "Num rows: 2\n  y=2  [true]\n  y=3  [A(1)]\nNum rows: 1\n  ()  [true]\n"
+        );
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn checkpoint_environment() {
+        let program =
+            ".decl E(from: symbol, to: int).\nE(\"a\", 2) :- .\nP(x) :- E(x, y).\n? P(x).\n";
+        let parsed = ProgramParser::new().parse(program).unwrap();
+        let mut env = Environment::<DNFAssumption>::new();
+        env.interpret(&parsed).unwrap();
+        let json = serde_json::to_string(&env).unwrap();
+        let mut loaded: Environment<DNFAssumption> = serde_json::from_str(&json).unwrap();
+        assert_eq!(loaded.tables, env.tables);
+        let question = ProgramParser::new().parse("? P(x).").unwrap();
+        let StatementAST::Question(question, _, _, _) = &question[0] else {
+            panic!()
+        };
+        assert_eq!(loaded.ask(question).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn save_and_load_snapshot() {
+        let program = "[A] :- .\nE(1, 2) :- .\nE(2, pair(\"c\", 1)) :- A.\nP(x, z) :- E(x, y), E(y, z).\n? P(x, z).\n";
+        let parsed = ProgramParser::new().parse(program).unwrap();
+        let mut env = Environment::<DNFAssumption>::new();
+        env.interpret(&parsed).unwrap();
+        let path = std::env::temp_dir().join(format!("implog-snapshot-{}", std::process::id()));
+        env.save(&path).unwrap();
+        let mut loaded = Environment::<DNFAssumption>::load(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(loaded.tables, env.tables);
+        let question = ProgramParser::new().parse("? P(x, z).").unwrap();
+        let StatementAST::Question(question, _, _, _) = &question[0] else {
+            panic!()
+        };
+        let answers = loaded.ask(question).unwrap();
+        assert_eq!(answers.len(), 1);
+        assert_eq!(answers[0].1.to_string(), "A");
+    }
+
+    #[cfg(feature = "sqlite")]
+    #[test]
+    fn save_and_load_sqlite() {
+        let program = ".decl E(src: int, dst: symbol).\n[A] :- .\nE(1, \"b\") :- A.\nP(x) :- E(x, y).\n? P(x).\n";
+        let parsed = ProgramParser::new().parse(program).unwrap();
+        let mut env = Environment::<DNFAssumption>::new();
+        env.interpret(&parsed).unwrap();
+        let path = std::env::temp_dir().join(format!("implog-sqlite-{}", std::process::id()));
+        env.save_sqlite(&path, &["E", "P"]).unwrap();
+
+        // Tables not written by implog hold facts with assumption value one.
+        let conn = rusqlite::Connection::open(&path).unwrap();
+        conn.execute_batch("CREATE TABLE Q (n INTEGER, s TEXT); INSERT INTO Q VALUES (3, 'c');")
+            .unwrap();
+        drop(conn);
+
+        let mut loaded = Environment::<DNFAssumption>::new();
+        loaded.load_sqlite(&path, &["P", "Q"]).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        let parsed = ProgramParser::new()
+            .parse("R(x) :- P(x).\nR(n) :- Q(n, \"c\").\n? R(x).\n")
+            .unwrap();
+        loaded.interpret(&parsed).unwrap();
+        let answers: Vec<_> = loaded.answers[0]
+            .iter()
+            .map(|(bindings, value)| (bindings["x"], value.to_string()))
+            .collect();
+        assert_eq!(answers, vec![(1, "A".to_string()), (3, "true".to_string())]);
+    }
+
+    #[test]
+    fn interpret_stats() {
+        let program = "[A] :- .\nE(1, 2) :- .\nE(2, 3) :- A.\n? E(x, y).\n";
+        let parsed = ProgramParser::new().parse(program).unwrap();
+        let mut env = Environment::<DNFAssumption>::new();
+        env.interpret(&parsed).unwrap();
+        let stats = env.stats();
+        assert_eq!(
+            stats.relations[1],
+            RelationStats {
+                relation: "E".to_string(),
+                rows: 2,
+                bytes: 32,
+                formula_size: 1
+            }
+        );
+        assert_eq!(stats.rows(), 3);
+
+        env.set_stats(true);
+        assert!(
+            env.interpret(&parsed)
+                .unwrap()
+                .starts_with("Num rows: 2\nStats: 3 rows in 2 relations, 32 bytes of tuples")
+        );
+    }
+
+    #[test]
+    fn interpret_projection() {
+        let program = "[A] :- .\nE(1, 2) :- .\nE(1, 3) :- A.\nE(2, 3) :- .\n? x : E(x, y).\n";
+        let parsed = ProgramParser::new().parse(program).unwrap();
+        let mut env = Environment::<DNFAssumption>::new();
+        env.set_verbosity(Verbosity::Assumptions);
+        assert_eq!(
+            env.interpret(&parsed).unwrap(),
+            "Num rows: 2\n  x=1  [true]\n  x=2  [true]\n"
+        );
+
+        let program = "E(1, 2) :- .\n? y : E(x, 2).\n";
+        let parsed = ProgramParser::new().parse(program).unwrap();
+        assert!(!check(&parsed[1]));
+    }
+
+    #[test]
+    fn interpret_order_and_limit() {
+        let program = r#"
+E(3, "b") :- .
+E(1, "c") :- .
+E(2, "a") :- .
+E(1, "a") :- .
+? E(x, y) order by y, x limit 3.
+? x : E(x, y) order by x.
+"#;
+        let parsed = ProgramParser::new().parse(program).unwrap();
+        let mut env = Environment::<DNFAssumption>::new();
+        env.set_verbosity(Verbosity::Bindings);
+        assert_eq!(
+            env.interpret(&parsed).unwrap(),
+            "Num rows: 3
+  x=1, y=\"a\"\n  x=2, y=\"a\"\n  x=3, y=\"b\"\nNum rows: 3\n  x=1\n  x=2\n  x=3\n"
+        );
+    }
+
+    #[test]
+    fn interpret_filters() {
+        let program = r#"
+[A(1)] :- .
+[B] :- .
+E(1) :- .
+E(2) :- A(1).
+E(3) :- B.
+? E(x) where definite.
+? E(x) where conditional.
+? E(x) where assuming A(1).
+? E(x) where excluding A(1).
+"#;
+        let parsed = ProgramParser::new().parse(program).unwrap();
+        let mut env = Environment::<DNFAssumption>::new();
+        env.interpret(&parsed).unwrap();
+        let answers: Vec<Vec<Symbol>> = env
+            .answers()
+            .iter()
+            .map(|answers| answers.iter().map(|(bindings, _)| bindings["x"]).collect())
+            .collect();
+        assert_eq!(answers, vec![vec![1], vec![2, 3], vec![2], vec![1, 3]]);
+    }
+
+    #[test]
+    fn interpret_hypothetical_questions() {
+        let program = r#"
+[B] :- .
+E(1) :- .
+E(2) :- A(2).
+E(3) :- A(3), B.
+? [A(2)], [A(3)] |- E(x).
+? E(x).
+"#;
+        let parsed = ProgramParser::new().parse(program).unwrap();
+        let mut env = Environment::<DNFAssumption>::new();
+        env.set_verbosity(Verbosity::Assumptions);
+        assert_eq!(
+            env.interpret(&parsed).unwrap(),
+            "Num rows: 3\n  x=1  [true]\n  x=2  [true]\n  x=3  [B]\nNum rows: 1\n  x=1  [true]\n"
+        );
+    }
+
+    #[test]
+    fn interpret_ranked_answers() {
+        let program = r#"
+.weight Cheap(x) = 1.
+.weight Pricey(1) = 10.
+[Cheap(x)] :- N(x).
+[Pricey(x)] :- N(x).
+N(1) :- .
+N(2) :- .
+P(x) :- Pricey(x).
+P(x) :- Cheap(x), Cheap(x), N(x), Pricey(2).
+? P(x).
+"#;
+        let parsed = ProgramParser::new().parse(program).unwrap();
+        let mut env = Environment::<DNFAssumption>::new();
+        env.set_verbosity(Verbosity::Bindings);
+        env.set_rank(true);
+        assert_eq!(
+            env.interpret(&parsed).unwrap(),
+            "Num rows: 2\n  x=2  cost 1\n  x=1  cost 2\n"
+        );
+        assert_eq!(env.weight(&("Pricey".to_string(), vec![1])), 10);
+        assert_eq!(env.weight(&("Other".to_string(), vec![])), 1);
+    }
+
+    #[test]
+    fn interpret_probabilities() {
+        let program = r#"
+.probability Edge(x, y) = 0.5.
+[Edge(1, 2)] :- .
+[Edge(2, 3)] :- .
+[Edge(1, 3)] :- .
+Path(x, y) :- Edge(x, y).
+Path(x, z) :- Path(x, y), Edge(y, z).
+? Path(1, 3).
+"#;
+        let parsed = ProgramParser::new().parse(program).unwrap();
+        let mut env = Environment::<DNFAssumption>::new();
+        env.set_verbosity(Verbosity::Bindings);
+        env.set_probabilities(true);
+        // Edge(1, 3), or Edge(1, 2) and Edge(2, 3): 0.5 + 0.5 * 0.25.
+        assert_eq!(
+            env.interpret(&parsed).unwrap(),
+            "Num rows: 1\n  ()  probability 0.625\n"
+        );
+    }
+
+    #[test]
+    fn minimal_support_sets() {
+        let program = r#"
+[A] :- .
+[B] :- .
+[C] :- .
+D :- A.
+E :- .
+P :- [B], [D], E.
+P :- [C], [D].
+"#;
+        let parsed = ProgramParser::new().parse(program).unwrap();
+        let mut env = Environment::<DNFAssumption>::new();
+        env.interpret(&parsed).unwrap();
+        let leaf = |relation: &str| (relation.to_string(), vec![]);
+        // D can be replaced by A, since D is derived from A.
+        let sets = env.support_sets("P", &vec![], None).unwrap();
+        assert_eq!(
+            sets,
+            vec![
+                BTreeSet::from([leaf("A"), leaf("B")]),
+                BTreeSet::from([leaf("A"), leaf("C")]),
+                BTreeSet::from([leaf("B"), leaf("D")]),
+                BTreeSet::from([leaf("C"), leaf("D")]),
+            ]
+        );
+        assert_eq!(env.support_sets("P", &vec![], Some(1)).unwrap().len(), 1);
+        assert!(env.support_sets("Q", &vec![], None).unwrap().is_empty());
+    }
+
+    #[test]
+    fn interpret_abduction() {
+        let program = r#"
+.abducible Rain.
+.abducible Sprinkler.
+[Rain] :- .
+[Sprinkler] :- .
+[Cloudy] :- .
+Wet(1) :- Rain.
+Wet(1) :- Sprinkler.
+Wet(2) :- Cloudy.
+Wet(3) :- .
+?abduce Wet(x).
+"#;
+        let parsed = ProgramParser::new().parse(program).unwrap();
+        let mut env = Environment::<DNFAssumption>::new();
+        env.set_verbosity(Verbosity::Bindings);
+        // Wet(2) is only derivable under Cloudy, which isn't abducible.
+        assert_eq!(
+            env.interpret(&parsed).unwrap(),
+            "Num rows: 2\n  x=1  [Rain | Sprinkler]\n  x=3  [true]\n"
+        );
+        assert_eq!(env.abductions()[0][0].1.len(), 2);
+    }
+
+    #[test]
+    fn interpret_strings() {
+        let program = r#"
+.decl Parent(parent: symbol, child: symbol).
+Parent("alice", "bob") :- .
+Parent("bob", "carol") :- .
+G(x, z) :- Parent(x, y), Parent(y, z).
+? G(x, "carol").
+"#;
+        let parsed = ProgramParser::new().parse(program).unwrap();
+        let mut env = Environment::<DNFAssumption>::new();
+        env.set_verbosity(Verbosity::Bindings);
+        assert_eq!(
+            env.interpret(&parsed).unwrap(),
+            "Num rows: 1\n  x=\"alice\"\n"
+        );
+    }
+
+    #[test]
+    fn interpret_declarations() {
+        let program =
+            ".decl E(from: int, to: int).\nE(1, 2) :- .\n? E(x, y).\n? E(x, y), E(y, z).\n";
+        let parsed = ProgramParser::new().parse(program).unwrap();
+        let mut env = Environment::<DNFAssumption>::new();
+        env.set_verbosity(Verbosity::Bindings);
+        assert_eq!(
+            env.interpret(&parsed).unwrap(),
+            "Num rows: 1\n  from=1, to=2\nNum rows: 0\n"
+        );
+
+        let parsed = ProgramParser::new()
+            .parse(".decl E(from: int, to: int).\nE(1) :- .\n")
+            .unwrap();
+        assert_eq!(
+            env.interpret(&parsed),
+            Err(InterpretError::ArityMismatch(
+                "relation E is declared with 2 columns, but used with 1 terms".to_string(),
+                Location {
+                    statement: Some(2),
+                    span: Some(Span { start: 29, end: 33 })
+                }
+            ))
+        );
+        assert!(
+            env.diagnostics()
+                .iter()
+                .any(|diag| diag.lint == Lint::ArityMismatch)
+        );
+    }
+
+    #[test]
+    fn locate_arity_mismatches() {
+        let program = "E(1, 2) :- .\nE(1, 2, 3) :- .\n";
+        let parsed = ProgramParser::new().parse(program).unwrap();
+        let spans: Vec<_> = LocatedProgramParser::new()
+            .parse(program)
+            .unwrap()
+            .into_iter()
+            .map(|(span, _)| span)
+            .collect();
+        let mut env = Environment::<DNFAssumption>::new();
+        let err = env.interpret(&parsed).unwrap_err();
+        assert_eq!(
+            err.location(),
+            Some(Location {
+                statement: Some(2),
+                span: Some(Span { start: 21, end: 22 })
+            })
+        );
+        assert!(
+            err.locate("e.dl", program, &spans)
+                .starts_with("e.dl:2:9: ")
+        );
+
+        // Relations with facts added from Rust are located at the term used past their arity.
+        env.add_fact("F", vec![1, 2], DNFAssumption::one()).unwrap();
+        let parsed = ProgramParser::new().parse("? F(x, y, z).").unwrap();
+        let err = env.interpret(&parsed).unwrap_err();
+        assert_eq!(
+            err.location(),
+            Some(Location {
+                statement: Some(1),
+                span: Some(Span { start: 10, end: 11 })
+            })
+        );
+    }
+
+    #[test]
+    fn query_from_threads() {
+        fn shareable<T: Send + Sync>(_: &T) {}
+        let program = "E(1, 2) :- .\nE(2, 3) :- .\nP(x, y) :- E(x, y).\nP(x, z) :- P(x, y), E(y, z).\n? P(x, y).\n";
+        let parsed = ProgramParser::new().parse(program).unwrap();
+        let mut env = Environment::<DNFAssumption>::new();
+        env.interpret(&parsed).unwrap();
+        shareable(&env);
+        let sizes: Vec<_> = std::thread::scope(|scope| {
+            let threads: Vec<_> = ["E", "P"]
+                .into_iter()
+                .map(|relation| scope.spawn(|| env.relation(relation).unwrap().len()))
+                .collect();
+            threads
+                .into_iter()
+                .map(|thread| thread.join().unwrap())
+                .collect()
+        });
+        assert_eq!(sizes, vec![2, 3]);
+    }
+
+    #[test]
+    fn join_in_parallel() {
+        let program = "S(1) :- .\nS(2) :- .\nP(x, y) :- E(x, y), S(y), [A(x)].\n? P(x, y).\n";
+        let parsed = ProgramParser::new().parse(program).unwrap();
+        let answers = |threads| {
+            let mut env = Environment::<DNFAssumption>::new();
+            env.set_threads(threads);
+            for x in 0..MIN_PARALLEL_ROWS as Symbol * 2 {
+                env.add_fact("E", vec![x, x % 3], DNFAssumption::one())
+                    .unwrap();
+            }
+            env.interpret(&parsed).unwrap();
+            env.answers()[0].clone()
+        };
+        let sequential = answers(1);
+        assert_eq!(sequential.len(), MIN_PARALLEL_ROWS * 4 / 3);
+        assert_eq!(answers(4), sequential);
+        assert_eq!(answers(3), sequential);
+    }
+
+    #[test]
+    fn split_reads_match_rows() {
+        // Every row matches the outermost atom, so reading it in parallel is no failure.
+        let mut env = Environment::<DNFAssumption>::new();
+        env.set_threads(4);
+        env.set_why_not(true);
+        for x in 0..MIN_PARALLEL_ROWS as Symbol * 2 {
+            env.add_fact("E", vec![x, x % 3], DNFAssumption::one())
+                .unwrap();
+        }
+        let question = ProgramParser::new().parse("? E(x, y).").unwrap();
+        env.ask(question[0].body()).unwrap();
+        let (answers, failure) =
+            env.query_tracking(question[0].body(), None, Bindings::new(), true);
+        assert_eq!(answers.len(), MIN_PARALLEL_ROWS * 2);
+        assert!(failure.is_none());
+    }
+
+    #[test]
+    fn hash_join_bound_positions() {
+        let program = "E(1, 2) :- .\nE(2, 3) :- .\nE(3, 3) :- .\n\
+                       F(pair(2, 1), 5) :- .\nF(pair(3, 1), 6) :- .\n\
+                       G(x, z) :- E(x, y), F(pair(y, 1), z).\n\
+                       ? G(x, z).\n? E(x, y), y = z, E(z, z).\n";
+        let parsed = ProgramParser::new().parse(program).unwrap();
+        let mut env = Environment::<DNFAssumption>::new();
+        env.interpret(&parsed).unwrap();
+        let answers: Vec<Vec<_>> = env
+            .answers()
+            .iter()
+            .map(|answers| {
+                answers
+                    .iter()
+                    .map(|(bindings, _)| bindings.values().copied().collect::<Vec<_>>())
+                    .collect()
+            })
+            .collect();
+        assert_eq!(
+            answers,
+            vec![
+                vec![vec![1, 5], vec![2, 6], vec![3, 6]],
+                vec![vec![2, 3, 3], vec![3, 3, 3]]
+            ]
+        );
+    }
+
+    #[test]
+    fn report_progress() {
+        let program = "E(1, 2) :- .\nE(2, 3) :- .\nP(x, y) :- E(x, y).\nP(x, z) :- P(x, y), E(y, z).\n? P(x, y).\n";
+        let parsed = ProgramParser::new().parse(program).unwrap();
+        let mut env = Environment::<DNFAssumption>::new();
+        let reports = Arc::new(Mutex::new(vec![]));
+        let sink = reports.clone();
+        env.set_progress(move |progress| sink.lock().unwrap().push(progress));
+        env.interpret(&parsed).unwrap();
+        let changed: Vec<_> = reports
+            .lock()
+            .unwrap()
+            .iter()
+            .filter_map(|progress| match progress {
+                Progress::Iteration { changed, .. } => Some(*changed),
+                Progress::Fixpoint { .. } => None,
+            })
+            .collect();
+        assert_eq!(changed, vec![2, 3, 0]);
+        assert_eq!(
+            reports.lock().unwrap().last(),
+            Some(&Progress::Fixpoint {
+                iterations: 3,
+                tuples: 5
+            })
+        );
+    }
+
+    #[test]
+    fn interpret_arrow_in_head() {
+        let program = "E(1) :- .\nA(x) -> B(x) :- E(x).\nC(x) :- A(x) -> B(x).\n? B(x).\n? C(x).\n";
+        let parsed = ProgramParser::new().parse(program).unwrap();
+        let mut env = Environment::<DNFAssumption>::new();
+        env.interpret(&parsed).unwrap();
+        let values: Vec<_> = env
+            .answers()
+            .iter()
+            .map(|answers| answers[0].1.to_string())
+            .collect();
+        assert_eq!(values, vec!["A(1)", "true"]);
+    }
+
+    #[test]
+    fn interpret_compound_terms() {
+        let program = "N(0) :- .\nN(succ(n)) :- N(n).\n\
+                       E(1, \"a\") :- .\nPair(pair(x, y)) :- E(x, y).\n\
+                       Swap(y, x) :- Pair(pair(x, y)).\n\
+                       ? N(x).\n? Swap(x, y).\n? Pair(succ(x)).\n";
+        let parsed = ProgramParser::new().parse(program).unwrap();
+        let mut env = Environment::<DNFAssumption>::new();
+        env.interpret(&parsed).unwrap();
+        let answers = env.answers();
+        // Terms deeper than `MAX_TERM_DEPTH` aren't derived, so the rule for `N` terminates.
+        assert_eq!(answers[0].len(), MAX_TERM_DEPTH + 1);
+        assert!(
+            answers[0]
+                .iter()
+                .any(|(bindings, _)| Value(bindings["x"]).to_string() == "succ(succ(0))")
+        );
+        let swapped: Vec<_> = answers[1][0]
+            .0
+            .values()
+            .map(|s| Value(*s).to_string())
+            .collect();
+        assert_eq!(swapped, vec!["\"a\"", "1"]);
+        assert!(answers[2].is_empty());
+    }
+
+    #[test]
+    fn interpret_lists() {
+        let program = "L([1, 2, 3]) :- .\n\
+                       M(x) :- L(xs), member(x, xs).\n\
+                       Split(xs, ys) :- L(zs), append(xs, ys, zs).\n\
+                       Joined(zs) :- L(xs), append(xs, [4], zs).\n\
+                       Len(n) :- L(xs), length(xs, n).\n\
+                       Head(x, rest) :- L([x | rest]).\n\
+                       ? M(x).\n? Split(xs, ys).\n? Joined(zs).\n? Len(n).\n? Head(x, rest).\n\
+                       ?td M(x).\n";
+        let parsed = ProgramParser::new().parse(program).unwrap();
+        let mut env = Environment::<DNFAssumption>::new();
+        env.interpret(&parsed).unwrap();
+        let answers: Vec<Vec<String>> = env
+            .answers()
+            .iter()
+            .map(|answers| {
+                answers
+                    .iter()
+                    .map(|(bindings, _)| {
+                        let values: Vec<_> =
+                            bindings.values().map(|s| Value(*s).to_string()).collect();
+                        values.join(" ")
+                    })
+                    .collect()
+            })
+            .collect();
+        assert_eq!(answers[0], vec!["1", "2", "3"]);
+        assert_eq!(answers[1].len(), 4);
+        assert!(answers[1].contains(&"[1] [2, 3]".to_string()));
+        assert_eq!(answers[2], vec!["[1, 2, 3, 4]"]);
+        assert_eq!(answers[3], vec!["3"]);
+        assert_eq!(answers[4], vec!["[2, 3] 1"]);
+        assert_eq!(answers[5], answers[0]);
+    }
+
+    #[test]
+    fn register_builtins() {
+        let program = "N(0) :- .\nN(y) :- N(x), Succ(x, y), Small(y).\n\
+                       ? N(x).\n?td N(x).\n?magic N(3).\n? Succ(x, 5).\n";
+        let parsed = ProgramParser::new().parse(program).unwrap();
+        let mut env = Environment::<DNFAssumption>::new();
+        env.register_builtin("Succ", 2, |args| match args {
+            [Some(x), _] => vec![vec![*x, Value(x.0 + 1)]],
+            [None, Some(y)] => vec![vec![Value(y.0 - 1), *y]],
+            _ => vec![],
+        });
+        // Functions may ignore the pattern they're called with.
+        env.register_builtin("Small", 1, |_| (0..5).map(|n| vec![Value(n)]));
+        assert_eq!(
+            env.interpret(&parsed).unwrap(),
+            "Num rows: 5\nNum rows: 5\nNum rows: 1\nNum rows: 1\n"
+        );
+        assert!(env.diagnostics().is_empty());
+    }
+
+    #[test]
+    fn merge_lattice_columns() {
+        let program = ".decl Dist(node: int, @min cost: int).\n\
+                       E(1, 2, 4) :- .\nE(1, 3, 1) :- .\nE(3, 2, 1) :- .\nE(2, 1, 1) :- .\n\
+                       Dist(1, 0) :- .\nDist(y, d) :- Dist(x, c), E(x, y, w), Add(c, w, d).\n\
+                       ? Dist(x, d).\n";
+        let parsed = ProgramParser::new().parse(program).unwrap();
+        let mut env = Environment::<DNFAssumption>::new();
+        env.register_builtin("Add", 3, |args| match args {
+            [Some(x), Some(y), _] => vec![vec![*x, *y, Value(x.0 + y.0)]],
+            _ => vec![],
+        });
+        env.interpret(&parsed).unwrap();
+        let dist: Vec<_> = env
+            .relation("Dist")
+            .unwrap()
+            .table()
+            .keys()
+            .cloned()
+            .collect();
+        assert_eq!(dist, vec![vec![1, 0], vec![2, 2], vec![3, 1]]);
+
+        let program = ".decl Tags(x: int, @union tags: int).\n.decl Name(x: int, @longest n: int).\n\
+                       Tags(1, [1, 2]) :- .\nTags(1, [3, 1]) :- .\n\
+                       Name(1, [1]) :- .\nName(1, [1, 2, 3]) :- .\nName(1, [2, 3]) :- .\n\
+                       ? Tags(x, t).\n? Name(x, n).\n";
+        let parsed = ProgramParser::new().parse(program).unwrap();
+        let mut env = Environment::<DNFAssumption>::new();
+        env.register_lattice("longest", |x, y| {
+            let len = |v: Value| resolve_list(v.0).map_or(0, |elems| elems.len());
+            if len(y) > len(x) { y } else { x }
+        });
+        env.interpret(&parsed).unwrap();
+        let tags: Vec<_> = env
+            .relation("Tags")
+            .unwrap()
+            .table()
+            .keys()
+            .cloned()
+            .collect();
+        assert_eq!(tags.len(), 1);
+        assert_eq!(resolve_list(tags[0][1]), Some(vec![1, 2, 3]));
+        let names: Vec<_> = env
+            .relation("Name")
+            .unwrap()
+            .table()
+            .keys()
+            .cloned()
+            .collect();
+        assert_eq!(names.len(), 1);
+        assert_eq!(resolve_list(names[0][1]), Some(vec![1, 2, 3]));
+
+        // A tuple merged under an assumption doesn't supersede a row holding without it.
+        let program = ".decl N(x: int, @min n: int).\nN(1, 2) :- .\n[A] :- .\nN(1, 1) :- A.\n";
+        let parsed = ProgramParser::new().parse(program).unwrap();
+        let mut env = Environment::<DNFAssumption>::new();
+        env.interpret(&parsed).unwrap();
+        let question = ProgramParser::new().parse("? N(x, n).").unwrap();
+        let answers: Vec<_> = env
+            .ask(question[0].body())
+            .unwrap()
+            .into_iter()
+            .map(|(bindings, value)| (bindings["n"], value.to_string()))
+            .collect();
+        assert_eq!(answers, vec![(1, "A".to_string()), (2, "true".to_string())]);
+
+        let parsed = ProgramParser::new()
+            .parse(".decl N(x: int, @shortest n: int).\n")
+            .unwrap();
+        assert_eq!(
+            Environment::<DNFAssumption>::new().interpret(&parsed),
+            Err(InterpretError::UnknownLattice("shortest".to_string()))
+        );
+    }
+
+    #[test]
+    fn declare_and_insert_facts() {
+        let mut env = Environment::<DNFAssumption>::new();
+        env.declare_relation("E", 2).unwrap();
+        env.declare_relation("Seed", 1).unwrap();
+        env.insert_facts("E", [[1, 2], [2, 3]].map(|edge| edge.map(Value).to_vec()))
+            .unwrap();
+        env.insert_facts("Seed", [vec![Value(intern("unused"))]])
+            .unwrap();
+        let program = "P(x, y) :- E(x, y).\nP(x, z) :- P(x, y), E(y, z).\n? P(x, y).\n";
+        let parsed = ProgramParser::new().parse(program).unwrap();
+        assert_eq!(env.interpret(&parsed).unwrap(), "Num rows: 3\n");
+        env.insert_facts("E", [vec![Value(3), Value(4)]]).unwrap();
+        let question = ProgramParser::new().parse("? P(1, y).").unwrap();
+        assert_eq!(env.ask(question[0].body()).unwrap().len(), 3);
+        assert_eq!(env.relation("Seed").unwrap().len(), 1);
+    }
+
+    #[test]
+    fn feed_statements() {
+        let program = "E(1, 2) :- .\nP(x, y) :- E(x, y).\n? P(x, y).\nE(2, 3) :- .\n\
+                       P(x, z) :- P(x, y), E(y, z).\n? P(x, y).\n? E(x).\n!expect P(3, x).\n";
+        let parsed = ProgramParser::new().parse(program).unwrap();
+        let mut env = Environment::<DNFAssumption>::new();
+        let logs: Vec<_> = parsed
+            .iter()
+            .map(|stmt| env.feed_statement(stmt).map(str::to_string))
+            .collect();
+        assert_eq!(logs[2], Ok("Num rows: 1\n".to_string()));
+        assert_eq!(logs[5], Ok("Num rows: 3\n".to_string()));
+        assert!(matches!(logs[6], Err(InterpretError::ArityMismatch(..))));
+        assert!(matches!(
+            logs[7],
+            Err(InterpretError::ExpectationFailed { statement: 7, .. })
+        ));
+        assert_eq!(env.relation("P").unwrap().len(), 3);
+    }
+
+    #[test]
+    fn register_external_relation() {
+        // Edges indexed by their source, which are only ever scanned with a bound source.
+        struct Edges {
+            targets: BTreeMap<Symbol, Vec<Symbol>>,
+            unbound_scans: Arc<Mutex<usize>>,
+        }
+        impl ExternalRelation for Edges {
+            fn scan<'a>(
+                &'a self,
+                pattern: &[Option<Value>],
+            ) -> Box<dyn Iterator<Item = Vec<Value>> + 'a> {
+                let Some(source) = pattern[0] else {
+                    *self.unbound_scans.lock().unwrap() += 1;
+                    return Box::new(std::iter::empty());
+                };
+                let targets = self.targets.get(&source.0).into_iter().flatten();
+                Box::new(targets.map(move |target| vec![source, Value(*target)]))
+            }
+        }
+
+        let program = "Start(1) :- .\nPath(x, y) :- Start(x), Edge(x, y).\n\
+                       Path(x, z) :- Path(x, y), Edge(y, z).\n? Path(1, y).\n?td Path(1, y).\n";
+        let parsed = ProgramParser::new().parse(program).unwrap();
+        let mut env = Environment::<DNFAssumption>::new();
+        let unbound_scans = Arc::new(Mutex::new(0));
+        let targets = BTreeMap::from([(1, vec![2, 3]), (3, vec![4]), (5, vec![6])]);
+        let edges = Edges {
+            targets,
+            unbound_scans: unbound_scans.clone(),
+        };
+        env.register_external("Edge", 2, edges);
+        assert_eq!(
+            env.interpret(&parsed).unwrap(),
+            "Num rows: 3\nNum rows: 3\n"
+        );
+        assert_eq!(*unbound_scans.lock().unwrap(), 0);
+    }
+
+    #[test]
+    fn interpret_existential_head() {
+        let program = "Person(1) :- .\nPerson(2) :- .\n\
+                       Parent(x, p) :- Person(x).\nPerson(p) :- Parent(x, p).\n\
+                       ? Parent(1, p).\n?td Parent(1, p).\n?magic Parent(1, p).\n? Person(x).\n";
+        let parsed = ProgramParser::new().parse(program).unwrap();
+        let mut env = Environment::<DNFAssumption>::new();
+        env.interpret(&parsed).unwrap();
+        let answers = env.answers();
+        // The same binding of the body always gets the same skolem term.
+        let parent = |idx: usize| -> Vec<String> {
+            answers[idx]
+                .iter()
+                .map(|(bindings, _)| Value(bindings["p"]).to_string())
+                .collect()
+        };
+        assert_eq!(parent(0), vec!["Parent#p(1)"]);
+        assert_eq!(parent(1), parent(0));
+        assert_eq!(parent(2), parent(0));
+        // Each person's chain of ancestors stops at the depth bound.
+        assert_eq!(answers[3].len(), 2 * MAX_TERM_DEPTH + 2);
+    }
+
+    #[test]
+    fn interpret_equality() {
+        let program = "E(1, pair(2, 3)) :- .\nE(4, pair(5, 4)) :- .\n\
+                       Fst(x, a) :- E(x, p), p = pair(a, b).\n\
+                       Loop(x) :- E(x, pair(a, b)), b = x.\n\
+                       Next(x, y) :- E(x, p), y = x.\n\
+                       ? Fst(x, a).\n? Loop(x).\n? Next(4, y).\n?td Fst(x, a).\n";
+        assert_eq!(
+            interpret(program),
+            "Num rows: 2\nNum rows: 1\nNum rows: 1\nNum rows: 2\n"
+        );
+    }
+
+    #[test]
+    fn trace_evaluation() {
+        let program = "E(1, 2) :- .\nP(x) :- E(x, y), [A(y)].\nP(x) :- E(x, y).\n? P(x).\n";
+        let parsed = ProgramParser::new().parse(program).unwrap();
+        let mut env = Environment::<DNFAssumption>::new();
+        let events = Arc::new(Mutex::new(vec![]));
+        let sink = events.clone();
+        env.set_trace(move |event| sink.lock().unwrap().push(event.to_string()));
+        env.interpret(&parsed).unwrap();
+        assert_eq!(
+            *events.lock().unwrap(),
+            vec![
+                "iteration 1: E(1, 2) :- . derived 1 tuples, 1 new",
+                "iteration 2: P(x) :- E(x, y), [A(y)]. derived 1 tuples, 1 new",
+                "iteration 2: P(x) :- E(x, y). derived 1 tuples, 0 new",
+                "iteration 2: P(1) changed from A(2) to true",
+            ]
+        );
+    }
+
+    #[test]
+    fn skip_subsumed_derivations() {
+        let program = "E(1, 2) :- .\nP(x) :- E(x, y).\nP(x) :- E(x, y), [A(y)].\n? P(x).\n";
+        let parsed = ProgramParser::new().parse(program).unwrap();
+        let mut env = Environment::<DNFAssumption>::new();
+        let events = Arc::new(Mutex::new(vec![]));
+        let sink = events.clone();
+        env.set_trace(move |event| sink.lock().unwrap().push(event.to_string()));
+        env.interpret(&parsed).unwrap();
+        // The second derivation of P(1) is subsumed by the first, so the row isn't merged.
+        assert_eq!(
+            *events.lock().unwrap(),
+            vec![
+                "iteration 1: E(1, 2) :- . derived 1 tuples, 1 new",
+                "iteration 2: P(x) :- E(x, y). derived 1 tuples, 1 new",
+                "iteration 2: P(x) :- E(x, y), [A(y)]. derived 1 tuples, 0 new",
+            ]
+        );
+        assert_eq!(env.answers()[0][0].1, DNFAssumption::one());
+    }
+
+    #[test]
+    fn time_questions() {
+        let program = "E(1, 2) :- .\nE(2, 3) :- .\nP(x, y) :- E(x, y).\n? P(x, y).\n? P(1, y).\n";
+        let parsed = ProgramParser::new().parse(program).unwrap();
+        let mut env = Environment::<DNFAssumption>::new();
+        env.interpret(&parsed).unwrap();
+        let timings: Vec<_> = env
+            .timings()
+            .iter()
+            .map(|timing| (timing.statement, timing.iterations))
+            .collect();
+        assert_eq!(timings, vec![(4, 3), (5, 0)]);
+    }
+
+    #[test]
+    fn limit_iterations() {
+        let program = "E(1, 2) :- .\nE(2, 3) :- .\nE(3, 4) :- .\nN(1) :- .\nN(y) :- N(x), E(x, y).\n? N(x).\n";
+        let parsed = ProgramParser::new().parse(program).unwrap();
+        let mut env = Environment::<DNFAssumption>::new();
+        env.set_max_iterations(3);
+        assert_eq!(
+            env.interpret(&parsed),
+            Err(InterpretError::IterationLimit { limit: 3 })
+        );
+        let question = ProgramParser::new().parse("? N(x).").unwrap();
+        assert!(env.ask(question[0].body()).is_err());
+        env.set_max_iterations(5);
+        assert_eq!(env.interpret(&parsed).unwrap(), "Num rows: 4\n");
+    }
+
+    #[test]
+    fn interrupt_evaluation() {
+        let program = "E(1, 2) :- .\n? E(x, y).\nP(x, y) :- E(x, y).\n? P(x, y).\n";
+        let parsed = ProgramParser::new().parse(program).unwrap();
+        let mut env = Environment::<DNFAssumption>::new();
+        let token = env.cancellation_token();
+        token.cancel();
+        assert_eq!(env.interpret(&parsed), Err(InterpretError::Cancelled));
+        token.reset();
+        assert_eq!(
+            env.interpret(&parsed).unwrap(),
+            "Num rows: 1\nNum rows: 1\n"
+        );
+
+        env.set_deadline(Instant::now());
+        assert_eq!(env.interpret(&parsed), Err(InterpretError::TimedOut));
+        assert!(env.answers().is_empty());
+    }
+
+    #[test]
+    fn sort_answers() {
+        let program = "E(3, \"b\") :- .\nE(1, \"c\") :- .\nE(1, \"a\") :- .\nE(2, 5) :- .\n\
+                       P(y, x) :- E(x, y).\n? P(y, x).\n";
+        let parsed = ProgramParser::new().parse(program).unwrap();
+        let mut env = Environment::<DNFAssumption>::new();
+        env.set_verbosity(Verbosity::Bindings);
+        env.set_sort_answers(true);
+        assert_eq!(
+            env.interpret(&parsed).unwrap(),
+            "Num rows: 4\n  x=1, y=\"a\"\n  x=1, y=\"c\"\n  x=2, y=5\n  x=3, y=\"b\"\n"
+        );
+        let question = ProgramParser::new().parse("? P(y, 1).").unwrap();
+        let answers = env.ask(question[0].body()).unwrap();
+        let ys: Vec<_> = answers.iter().map(|(bindings, _)| bindings["y"]).collect();
+        assert_eq!(ys, vec![intern("a"), intern("c")]);
+    }
+
+    #[test]
+    fn undo_partial_bindings() {
+        // Matching `pair(x, 3)` against `pair(1, 2)` binds `x` before failing, which is undone
+        // before the next row is matched.
+        let parsed = ProgramParser::new()
+            .parse("T(pair(1, 2)) :- .\nT(pair(5, 3)) :- .\n")
+            .unwrap();
+        let mut env = Environment::<DNFAssumption>::new();
+        env.interpret(&parsed).unwrap();
+        let question = ProgramParser::new()
+            .parse("? T(pair(x, 3)), T(pair(x, y)).")
+            .unwrap();
+        let answers = env.ask(question[0].body()).unwrap();
+        let expected = BTreeMap::from([("x".to_string(), 5), ("y".to_string(), 3)]);
+        assert_eq!(answers, vec![(expected, DNFAssumption::one())]);
+    }
+
+    #[test]
+    fn execute_long_bodies() {
+        // Joins run without recursion, so bodies as long as these don't grow the stack per atom.
+        let mut program = String::from("E(0, 1) :- .\nE(1, 0) :- .\n? ");
+        let atoms: Vec<_> = (0..500)
+            .map(|idx| format!("E(x{}, x{})", idx, idx + 1))
+            .collect();
+        program.push_str(&atoms.join(", "));
+        program.push_str(", x0 = 0, [A(x500)].\n");
+        let parsed = ProgramParser::new().parse(&program).unwrap();
+        let mut env = Environment::<DNFAssumption>::new();
+        env.interpret(&parsed).unwrap();
+        let answers = env.ask(parsed[2].body()).unwrap();
+        assert_eq!(answers.len(), 1);
+        assert_eq!(answers[0].0["x500"], 0);
+        assert_eq!(answers[0].1.to_string(), "A(0)");
+    }
+
+    #[test]
+    fn merge_duplicate_answers() {
+        let program =
+            "L([1, 2, 1]) :- [A].\n? L(l), member(x, l).\n!expect_count member(x, [3, 3]) = 1.\n";
+        let parsed = ProgramParser::new().parse(program).unwrap();
+        let mut env = Environment::<DNFAssumption>::new();
+        env.set_verbosity(Verbosity::Assumptions);
+        assert_eq!(
+            env.interpret(&parsed).unwrap(),
+            "Num rows: 2\n  l=[1, 2, 1], x=1  [A]\n  l=[1, 2, 1], x=2  [A]\n"
+        );
+        let question = ProgramParser::new().parse("? member(x, [4, 4]).").unwrap();
+        assert_eq!(env.ask(question[0].body()).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn assumption_budget() {
+        // Paths through a chain of assumed edges hold under every edge on them, so the values of
+        // long paths outgrow the budget, and are widened.
+        let program = "E(1, 2) :- [A(1)].\nE(2, 3) :- [A(2)].\nE(3, 4) :- [A(3)].\n\
+                       P(x, y) :- E(x, y).\nP(x, z) :- P(x, y), E(y, z).\n? P(1, 4).\n? P(2, 3).\n";
+        let parsed = ProgramParser::new().parse(program).unwrap();
+        let mut env = Environment::<DNFAssumption>::new();
+        env.set_verbosity(Verbosity::Assumptions);
+        assert_eq!(
+            env.interpret(&parsed).unwrap(),
+            "Num rows: 1\n  ()  [A(1) & A(2) & A(3)]\nNum rows: 1\n  ()  [A(2)]\n"
+        );
+        assert!(env.widened().is_empty());
+
+        env.set_assumption_budget(2);
+        assert_eq!(
+            env.interpret(&parsed).unwrap(),
+            "Num rows: 1\n  ()  [true]\n\
+             Widened rows: 1\n\
+             Num rows: 1\n  ()  [A(2)]\n\
+             Widened rows: 1\n"
+        );
+        let widened = ("P".to_string(), vec![1, 4]);
+        assert_eq!(env.widened(), &BTreeSet::from([widened]));
+    }
+
+    #[test]
+    fn stop_evaluation() {
+        let program = "N(0) :- .\nN(succ(n)) :- N(n).\n? N(x).\n";
+        let parsed = ProgramParser::new().parse(program).unwrap();
+        let mut env = Environment::<DNFAssumption>::new();
+        let token = env.cancellation_token();
+        env.set_progress(move |progress| {
+            if let Progress::Iteration { iteration: 3, .. } = progress {
+                token.stop();
+            }
+        });
+        assert_eq!(
+            env.interpret(&parsed),
+            Err(InterpretError::Stopped { iterations: 3 })
+        );
+        assert_eq!(env.relation("N").unwrap().len(), 3);
+    }
+
+    #[test]
+    fn interpret_semirings() {
+        use crate::representation::{BooleanAssumption, TopKAssumption, TropicalAssumption};
+
+        let program = r#"
+E(1, 2) :- .
+[E(2, 3)] :- .
+[E(3, 4)] :- .
+E(1, 4) :- E(2, 3).
+P(x, y) :- E(x, y).
+P(x, z) :- E(x, y), P(y, z).
+? P(1, x).
+"#;
+        let parsed = ProgramParser::new().parse(program).unwrap();
+        let mut env = Environment::<TropicalAssumption>::new();
+        env.interpret(&parsed).unwrap();
+        let costs: Vec<_> = env.answers()[0].iter().map(|(_, cost)| cost.0).collect();
+        assert_eq!(costs, vec![Some(0), Some(1), Some(1)]);
+
+        let mut env = Environment::<BooleanAssumption>::new();
+        env.interpret(&parsed).unwrap();
+        assert_eq!(env.answers()[0].len(), 3);
+
+        // `P(1, 4)` holds under `E(2, 3)` through `E(1, 4)`, the cheapest of its conjunctions.
+        let mut env = Environment::<TopKAssumption<1>>::new();
+        env.interpret(&parsed).unwrap();
+        let values: Vec<_> = env.answers()[0]
+            .iter()
+            .map(|(_, value)| value.to_string())
+            .collect();
+        assert_eq!(values, vec!["true", "E(2, 3)", "E(2, 3)"]);
+    }
+
+    #[test]
+    fn simplify_against_derived_tuples() {
+        let program = r#"
+E :- .
+[A] :- .
+[S] :- .
+P :- [E].
+R :- A, P.
+T :- S.
+:- P, [S].
+? R.
+? T.
+"#;
+        let parsed = ProgramParser::new().parse(program).unwrap();
+        let mut env = Environment::<DNFAssumption>::new();
+        env.interpret(&parsed).unwrap();
+        let leaf = |relation: &str| DNFAssumption::singleton((relation.to_string(), vec![]));
+        assert_eq!(env.answers()[0][0].1, leaf("A").times(&leaf("E")));
+        assert_eq!(env.answers()[1].len(), 1);
+
+        // E is derived with no assumptions, so assuming it is redundant. Then S alone is
+        // inconsistent.
+        env.set_simplify(true);
+        env.interpret(&parsed).unwrap();
+        assert_eq!(env.answers()[0][0].1, leaf("A"));
+        assert_eq!(env.answers()[1].len(), 0);
+    }
+
+    #[test]
+    fn arrow_discharges_assumption() {
+        let program = r#"
+[P] :- .
+Q :- P.
+G :- P -> Q.
+"#;
+        let parsed = ProgramParser::new().parse(program).unwrap();
+        let mut env = Environment::<DNFAssumption>::new();
+        env.interpret(&parsed).unwrap();
+        let mut rules = vec![];
+        for stmt in &parsed {
+            if let StatementAST::Rule(head, body) = stmt {
+                rules.push((head, body));
+            }
+        }
+        env.interpret_rules(&rules, &[], &[]).unwrap();
+        let leaf_p = ("P".to_string(), vec![]);
+        assert_eq!(env.table("Q")[&vec![]].0, DNFAssumption::singleton(leaf_p));
+        assert_eq!(env.table("G")[&vec![]].0, DNFAssumption::one());
     }
 }
@@ -0,0 +1,308 @@
+use crate::interpret::{Bindings, Diff};
+use crate::lint::Diagnostic;
+use crate::proof::Proof;
+use crate::provenance::{Provenance, Source};
+use crate::representation::{
+    BooleanAssumption, DNFAssumption, GroundTuple, LeafAssumption, Symbol, TopKAssumption,
+    TropicalAssumption,
+};
+use crate::value::{Value, resolve, resolve_term};
+
+// Serialization of question answers to JSON, for consumption by other programs. Each question's
+// answers become an array of objects mapping variable names to values, plus an `assumption` field
+// holding the answer's assumption value.
+pub trait ToJson {
+    fn to_json(&self) -> String;
+}
+
+// A leaf is serialized as an object like `{"relation": "E", "tuple": [1, 2]}`.
+impl ToJson for LeafAssumption {
+    fn to_json(&self) -> String {
+        let (relation, tuple) = self;
+        format!("{{{}}}", tuple_fields(relation, tuple))
+    }
+}
+
+// A DNF is serialized as an array of conjunctions, each an array of leaves. Zero is the empty
+// array and one is an array containing only the empty conjunction.
+impl ToJson for DNFAssumption {
+    fn to_json(&self) -> String {
+        let conjs: Vec<String> = self
+            .dnf
+            .iter()
+            .map(|conj| {
+                let leaves: Vec<String> = conj.iter().map(ToJson::to_json).collect();
+                format!("[{}]", leaves.join(", "))
+            })
+            .collect();
+        format!("[{}]", conjs.join(", "))
+    }
+}
+
+impl ToJson for BooleanAssumption {
+    fn to_json(&self) -> String {
+        self.0.to_string()
+    }
+}
+
+// Top-k values are serialized like the DNFs they keep.
+impl<const K: usize> ToJson for TopKAssumption<K> {
+    fn to_json(&self) -> String {
+        self.0.to_json()
+    }
+}
+
+// Tuples that aren't derivable have a null cost.
+impl ToJson for TropicalAssumption {
+    fn to_json(&self) -> String {
+        self.0.map_or("null".to_string(), |cost| cost.to_string())
+    }
+}
+
+// A proof is serialized as an object holding the tuple it proves and how, like
+// `{"relation": "E", "tuple": [1, 2], "from": "input"}`. Proofs by rules also hold the rule's index,
+// its bindings, and the proofs of its premises.
+impl ToJson for Proof {
+    fn to_json(&self) -> String {
+        match self {
+            Proof::Input(relation, tuple) => {
+                format!("{{{}, \"from\": \"input\"}}", tuple_fields(relation, tuple))
+            }
+            Proof::Builtin(relation, tuple) => {
+                format!(
+                    "{{{}, \"from\": \"builtin\"}}",
+                    tuple_fields(relation, tuple)
+                )
+            }
+            Proof::Rule {
+                relation,
+                tuple,
+                rule,
+                bindings,
+                premises,
+            } => {
+                let bindings: Vec<String> = bindings
+                    .iter()
+                    .map(|(var, symbol)| format!("{}: {}", string(var), value(*symbol)))
+                    .collect();
+                let premises: Vec<String> = premises.iter().map(ToJson::to_json).collect();
+                format!(
+                    "{{{}, \"from\": \"rule\", \"rule\": {}, \"bindings\": {{{}}}, \"premises\": [{}]}}",
+                    tuple_fields(relation, tuple),
+                    rule,
+                    bindings.join(", "),
+                    premises.join(", ")
+                )
+            }
+        }
+    }
+}
+
+// A diagnostic is serialized as an object like
+// `{"level": "warning", "code": "L001", "lint": "unused_relation", "message": "..."}`.
+impl ToJson for Diagnostic {
+    fn to_json(&self) -> String {
+        format!(
+            "{{\"level\": {}, \"code\": {}, \"lint\": {}, \"message\": {}}}",
+            string(self.level.name()),
+            string(self.lint.code()),
+            string(self.lint.name()),
+            string(&self.message)
+        )
+    }
+}
+
+impl ToJson for Provenance {
+    fn to_json(&self) -> String {
+        let derivations: Vec<String> = self
+            .derivations
+            .iter()
+            .map(|derivation| {
+                let source = match &derivation.source {
+                    Source::Rule(rule) => format!("\"rule\": {}", rule),
+                    Source::Builtin(name) => format!("\"builtin\": {}", string(name)),
+                };
+                let premises: Vec<String> = derivation
+                    .premises
+                    .iter()
+                    .map(|(relation, tuple)| format!("{{{}}}", tuple_fields(relation, tuple)))
+                    .collect();
+                format!(
+                    "{{{}, {}, \"premises\": [{}]}}",
+                    tuple_fields(&derivation.relation, &derivation.tuple),
+                    source,
+                    premises.join(", ")
+                )
+            })
+            .collect();
+        format!("{{\"derivations\": [{}]}}", derivations.join(", "))
+    }
+}
+
+pub fn answers_to_json<A: ToJson>(answers: &[(Bindings, A)]) -> String {
+    let objects: Vec<String> = answers
+        .iter()
+        .map(|(bindings, assumption)| {
+            let mut fields: Vec<String> = bindings
+                .iter()
+                .map(|(var, symbol)| format!("{}: {}", string(var), value(*symbol)))
+                .collect();
+            fields.push(format!("\"assumption\": {}", assumption.to_json()));
+            format!("{{{}}}", fields.join(", "))
+        })
+        .collect();
+    format!("[{}]", objects.join(", "))
+}
+
+// The difference between a relation's tuples in two models, as an object with the relation's name,
+// and arrays of the tuples added, removed, and changed, with their assumption values.
+pub fn relation_diff_to_json<A: ToJson>(relation: &str, diff: &Diff<A, GroundTuple>) -> String {
+    let tuple = |tuple: &GroundTuple| {
+        let tuple: Vec<String> = tuple.iter().map(|symbol| value(*symbol)).collect();
+        format!("[{}]", tuple.join(", "))
+    };
+    let added_removed = |tuples: &[(GroundTuple, A)]| {
+        let objects: Vec<String> = tuples
+            .iter()
+            .map(|(t, assumption)| {
+                format!(
+                    "{{\"tuple\": {}, \"assumption\": {}}}",
+                    tuple(t),
+                    assumption.to_json()
+                )
+            })
+            .collect();
+        format!("[{}]", objects.join(", "))
+    };
+    let changed: Vec<String> = diff
+        .changed
+        .iter()
+        .map(|(t, old, new)| {
+            format!(
+                "{{\"tuple\": {}, \"old\": {}, \"new\": {}}}",
+                tuple(t),
+                old.to_json(),
+                new.to_json()
+            )
+        })
+        .collect();
+    format!(
+        "{{\"relation\": {}, \"added\": {}, \"removed\": {}, \"changed\": [{}]}}",
+        string(relation),
+        added_removed(&diff.added),
+        added_removed(&diff.removed),
+        changed.join(", ")
+    )
+}
+
+fn tuple_fields(relation: &str, tuple: &GroundTuple) -> String {
+    let tuple: Vec<String> = tuple.iter().map(|symbol| value(*symbol)).collect();
+    format!(
+        "\"relation\": {}, \"tuple\": [{}]",
+        string(relation),
+        tuple.join(", ")
+    )
+}
+
+// Interned strings are serialized as JSON strings, integers as numbers, and compound terms as
+// strings of how they're written, like `"pair(1, \"a\")"`.
+fn value(symbol: Symbol) -> String {
+    if let Some(s) = resolve(symbol) {
+        string(&s)
+    } else if resolve_term(symbol).is_some() {
+        string(&Value(symbol).to_string())
+    } else {
+        symbol.to_string()
+    }
+}
+
+fn string(s: &str) -> String {
+    let mut escaped = String::from("\"");
+    for c in s.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            c if c.is_control() => escaped.push_str(&format!("\\u{:04x}", c as u32)),
+            c => escaped.push(c),
+        }
+    }
+    escaped.push('"');
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::lint::{Level, Lint};
+    use crate::representation::Assumption;
+    use crate::value::intern;
+
+    use super::*;
+
+    #[test]
+    fn dnf_to_json() {
+        let a = DNFAssumption::singleton(("A".to_string(), vec![1, 2]));
+        let b = DNFAssumption::singleton(("B".to_string(), vec![]));
+
+        assert_eq!(DNFAssumption::zero().to_json(), "[]");
+        assert_eq!(DNFAssumption::one().to_json(), "[[]]");
+        assert_eq!(
+            a.plus(&a.times(&b)).plus(&b).to_json(),
+            r#"[[{"relation": "A", "tuple": [1, 2]}], [{"relation": "B", "tuple": []}]]"#
+        );
+    }
+
+    #[test]
+    fn answers_to_json_fields() {
+        let bindings = Bindings::from([("x".to_string(), 1), ("y".to_string(), -4)]);
+        let answers = vec![
+            (bindings, DNFAssumption::one()),
+            (Bindings::new(), DNFAssumption::one()),
+        ];
+        assert_eq!(
+            answers_to_json(&answers),
+            r#"[{"x": 1, "y": -4, "assumption": [[]]}, {"assumption": [[]]}]"#
+        );
+        assert_eq!(answers_to_json::<DNFAssumption>(&[]), "[]");
+
+        let bindings = Bindings::from([("x".to_string(), intern("a"))]);
+        assert_eq!(
+            answers_to_json(&[(bindings, DNFAssumption::one())]),
+            r#"[{"x": "a", "assumption": [[]]}]"#
+        );
+    }
+
+    #[test]
+    fn relation_diff_fields() {
+        let diff = Diff {
+            added: vec![(vec![1, intern("a")], TropicalAssumption(Some(0)))],
+            removed: vec![],
+            changed: vec![(
+                vec![2],
+                TropicalAssumption(Some(1)),
+                TropicalAssumption(Some(3)),
+            )],
+        };
+        assert_eq!(
+            relation_diff_to_json("P", &diff),
+            r#"{"relation": "P", "added": [{"tuple": [1, "a"], "assumption": 0}], "removed": [], "changed": [{"tuple": [2], "old": 1, "new": 3}]}"#
+        );
+    }
+
+    #[test]
+    fn diagnostic_to_json() {
+        let diag = Diagnostic {
+            lint: Lint::UnusedRelation,
+            level: Level::Warn,
+            message: "relation P is derived but never used".to_string(),
+        };
+        assert_eq!(
+            diag.to_json(),
+            r#"{"level": "warning", "code": "L001", "lint": "unused_relation", "message": "relation P is derived but never used"}"#
+        );
+    }
+
+    #[test]
+    fn escape_strings() {
+        assert_eq!(string("a\"b\\c\n"), r#""a\"b\\c\u000a""#);
+    }
+}
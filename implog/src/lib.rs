@@ -1,7 +1,34 @@
 use lalrpop_util::lalrpop_mod;
 
+pub mod algebra;
 pub mod ast;
+pub mod binary;
+pub mod builder;
+pub mod builtin;
+pub mod codegen;
+pub mod dependency;
+pub mod facts;
+pub mod fingerprint;
+pub mod include;
+pub mod index;
 pub mod interpret;
+pub mod json;
+pub mod lint;
+pub mod magic;
+pub mod pretty;
+pub mod proof;
+pub mod provenance;
+#[cfg(feature = "python")]
+pub mod python;
 pub mod representation;
+pub mod snapshot;
+pub mod souffle;
+pub mod topdown;
+pub mod triejoin;
+pub mod value;
+pub mod view;
+#[cfg(feature = "wasm")]
+pub mod wasm;
 
 lalrpop_mod!(pub grammar);
+lalrpop_mod!(pub souffle_grammar);
@@ -0,0 +1,760 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+use std::str::FromStr;
+
+use crate::ast::{AtomAST, LiteralAST, Location, StatementAST, Type};
+use crate::builtin::CallBuiltin;
+use crate::interpret::read_literal;
+use crate::value::{Value, resolve};
+
+// Lints flag statements that are well formed, but likely mistakes. Each lint has a code and a
+// name, and its level can be configured by directives in the program header, like:
+//   .allow unused_relation.
+//   .deny unfireable_rule.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Lint {
+    // A relation is derived (or loaded) but never read by any statement.
+    UnusedRelation,
+    // A rule reads a relation that can never contain a tuple, so it can never fire.
+    UnfireableRule,
+    // A bracket assumes a tuple of a relation that isn't declared `.abducible`. Only checked when
+    // the program declares at least one abducible relation.
+    NonAbducibleBracket,
+    // A variable has the same name as a nullary relation (a propositional constant), so it doesn't
+    // refer to that relation.
+    ShadowedConstant,
+    // A relation is used with a different number of terms than it's declared with, or than it's
+    // used with elsewhere. Programs with such mismatches can't be interpreted, so this lint is
+    // denied by default.
+    ArityMismatch,
+    // A constant of a declared relation doesn't have its column's type: a string in an `int`
+    // column, or an integer in a `symbol` column.
+    TypeMismatch,
+    // A variable appears only once in a rule, so it matches anything and is likely a typo.
+    // Variables starting with `_` are meant to appear once, and aren't flagged.
+    SingletonVariable,
+    // A relation is read, but no fact, rule, input, or built-in derives any of its tuples.
+    UnderivedRelation,
+    // A rule derives a relation that no question, expectation, constraint, or output reads, even
+    // through other rules. Only checked when the program reads some relation in one of these ways.
+    UnqueriedRule,
+    // A bracket assumes a tuple of a relation that has no facts and isn't declared `.abducible`,
+    // which is likely a misspelled relation name.
+    BracketWithoutFacts,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Level {
+    Allow,
+    Warn,
+    Deny,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Diagnostic {
+    pub lint: Lint,
+    pub level: Level,
+    pub message: String,
+}
+
+const LINTS: [Lint; 10] = [
+    Lint::UnusedRelation,
+    Lint::UnfireableRule,
+    Lint::NonAbducibleBracket,
+    Lint::ShadowedConstant,
+    Lint::ArityMismatch,
+    Lint::TypeMismatch,
+    Lint::SingletonVariable,
+    Lint::UnderivedRelation,
+    Lint::UnqueriedRule,
+    Lint::BracketWithoutFacts,
+];
+
+impl Lint {
+    pub fn code(&self) -> &'static str {
+        use Lint::*;
+        match self {
+            UnusedRelation => "L001",
+            UnfireableRule => "L002",
+            NonAbducibleBracket => "L003",
+            ShadowedConstant => "L004",
+            ArityMismatch => "L005",
+            TypeMismatch => "L006",
+            SingletonVariable => "L007",
+            UnderivedRelation => "L008",
+            UnqueriedRule => "L009",
+            BracketWithoutFacts => "L010",
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        use Lint::*;
+        match self {
+            UnusedRelation => "unused_relation",
+            UnfireableRule => "unfireable_rule",
+            NonAbducibleBracket => "non_abducible_bracket",
+            ShadowedConstant => "shadowed_constant",
+            ArityMismatch => "arity_mismatch",
+            TypeMismatch => "type_mismatch",
+            SingletonVariable => "singleton_variable",
+            UnderivedRelation => "underived_relation",
+            UnqueriedRule => "unqueried_rule",
+            BracketWithoutFacts => "bracket_without_facts",
+        }
+    }
+
+    // The level of a lint that isn't configured by the program.
+    pub fn default_level(&self) -> Level {
+        match self {
+            Lint::ArityMismatch | Lint::TypeMismatch => Level::Deny,
+            _ => Level::Warn,
+        }
+    }
+}
+
+impl FromStr for Lint {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        LINTS
+            .into_iter()
+            .find(|lint| lint.name() == s || lint.code() == s)
+            .ok_or("unknown lint")
+    }
+}
+
+impl Level {
+    // How diagnostics at this level are labeled.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Level::Allow => "allow",
+            Level::Warn => "warning",
+            Level::Deny => "error",
+        }
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}[{}]({}): {}",
+            self.level.name(),
+            self.lint.code(),
+            self.lint.name(),
+            self.message
+        )
+    }
+}
+
+// Run every lint over a program. Lints configured as `allow` produce no diagnostics.
+pub fn lint(stmts: &[StatementAST]) -> Vec<Diagnostic> {
+    lint_with(stmts, &BTreeSet::new())
+}
+
+// Lint a program run with relations computed by Rust functions (see
+// `Environment::register_builtin`), which may hold tuples without being derived.
+pub fn lint_with(stmts: &[StatementAST], natives: &BTreeSet<&str>) -> Vec<Diagnostic> {
+    let mut levels: BTreeMap<Lint, Level> = LINTS
+        .iter()
+        .map(|lint| (*lint, lint.default_level()))
+        .collect();
+    for stmt in stmts {
+        if let StatementAST::LintLevel(level, lint) = stmt {
+            levels.insert(*lint, *level);
+        }
+    }
+
+    let mut found = vec![];
+    unused_relations(stmts, &mut found);
+    unfireable_rules(stmts, natives, &mut found);
+    non_abducible_brackets(stmts, &mut found);
+    shadowed_constants(stmts, &mut found);
+    found.extend(
+        arity_mismatches(stmts)
+            .into_iter()
+            .map(|(message, _)| (Lint::ArityMismatch, message)),
+    );
+    type_mismatches(stmts, &mut found);
+    singleton_variables(stmts, &mut found);
+    underived_relations(stmts, natives, &mut found);
+    unqueried_rules(stmts, &mut found);
+    brackets_without_facts(stmts, &mut found);
+
+    found
+        .into_iter()
+        .filter(|(lint, _)| levels[lint] != Level::Allow)
+        .map(|(lint, message)| Diagnostic {
+            lint,
+            level: levels[&lint],
+            message,
+        })
+        .collect()
+}
+
+fn atom_literals(atom: &AtomAST) -> Vec<&LiteralAST> {
+    match atom {
+        AtomAST::Literal(lit) | AtomAST::Brackets(lit) => vec![lit],
+        AtomAST::Arrow(lhs, rhs) => vec![lhs, rhs],
+    }
+}
+
+// The literal a head derives. The premise of an arrow in a head is assumed, not derived.
+fn conclusion(head: &AtomAST) -> &LiteralAST {
+    let (AtomAST::Literal(lit) | AtomAST::Brackets(lit) | AtomAST::Arrow(_, lit)) = head;
+    lit
+}
+
+// The relations a statement writes to and reads from.
+pub(crate) fn writes_reads(stmt: &StatementAST) -> (Vec<&str>, Vec<&str>) {
+    let mut writes = vec![];
+    let mut reads = vec![];
+    if let Some(head) = stmt.head() {
+        writes.push(conclusion(head).relation.as_str());
+    }
+    for atom in stmt.bodies().into_iter().flatten() {
+        reads.extend(
+            atom_literals(atom)
+                .into_iter()
+                .filter(|lit| CallBuiltin::of(lit).is_none())
+                .map(|lit| lit.relation.as_str()),
+        );
+    }
+    match stmt {
+        StatementAST::Builtin(builtin) => {
+            writes.push(&builtin.relation);
+            reads.push(&builtin.source);
+        }
+        StatementAST::Input(relation, _) => writes.push(relation),
+        StatementAST::Output(relation) => reads.push(relation),
+        _ => {}
+    }
+    (writes, reads)
+}
+
+fn unused_relations(stmts: &[StatementAST], found: &mut Vec<(Lint, String)>) {
+    let mut written = BTreeSet::new();
+    let mut read = BTreeSet::new();
+    for stmt in stmts {
+        let (writes, reads) = writes_reads(stmt);
+        written.extend(writes);
+        read.extend(reads);
+    }
+    for relation in written.difference(&read) {
+        found.push((
+            Lint::UnusedRelation,
+            format!("relation {} is derived but never used", relation),
+        ));
+    }
+}
+
+fn unfireable_rules(
+    stmts: &[StatementAST],
+    natives: &BTreeSet<&str>,
+    found: &mut Vec<(Lint, String)>,
+) {
+    // Compute the relations that may contain a tuple: input and native relations, relations
+    // derived by a rule whose table-reading atoms may all match, and relations computed from such
+    // relations.
+    let mut nonempty: BTreeSet<&str> = natives.clone();
+    loop {
+        let before = nonempty.len();
+        for stmt in stmts {
+            match stmt {
+                StatementAST::Rule(head, body) if fireable(body, &nonempty) => {
+                    nonempty.insert(&conclusion(head).relation);
+                }
+                StatementAST::Builtin(builtin) if nonempty.contains(builtin.source.as_str()) => {
+                    nonempty.insert(&builtin.relation);
+                }
+                StatementAST::Input(relation, _) => {
+                    nonempty.insert(relation);
+                }
+                _ => {}
+            }
+        }
+        if nonempty.len() == before {
+            break;
+        }
+    }
+
+    for stmt in stmts {
+        if let StatementAST::Rule(head, body) = stmt
+            && !fireable(body, &nonempty)
+        {
+            let relation = &conclusion(head).relation;
+            found.push((
+                Lint::UnfireableRule,
+                format!(
+                    "rule for {} reads a relation that is always empty",
+                    relation
+                ),
+            ));
+        }
+    }
+}
+
+fn fireable(body: &[AtomAST], nonempty: &BTreeSet<&str>) -> bool {
+    body.iter()
+        .filter_map(read_literal)
+        .all(|lit| nonempty.contains(lit.relation.as_str()))
+}
+
+fn non_abducible_brackets(stmts: &[StatementAST], found: &mut Vec<(Lint, String)>) {
+    let abducible: BTreeSet<&str> = stmts
+        .iter()
+        .filter_map(|stmt| match stmt {
+            StatementAST::Abducible(relation) => Some(relation.as_str()),
+            _ => None,
+        })
+        .collect();
+    if abducible.is_empty() {
+        return;
+    }
+
+    for stmt in stmts {
+        let head = stmt.head().into_iter();
+        for atom in head.chain(stmt.bodies().into_iter().flatten()) {
+            if let AtomAST::Brackets(lit) = atom
+                && !abducible.contains(lit.relation.as_str())
+            {
+                found.push((
+                    Lint::NonAbducibleBracket,
+                    format!("bracket assumes {}, which isn't abducible", lit.relation),
+                ));
+            }
+        }
+    }
+}
+
+fn shadowed_constants(stmts: &[StatementAST], found: &mut Vec<(Lint, String)>) {
+    let mut nullary = BTreeSet::new();
+    let mut vars = BTreeSet::new();
+    for stmt in stmts {
+        let head = stmt.head().into_iter();
+        for atom in head.chain(stmt.bodies().into_iter().flatten()) {
+            for lit in atom_literals(atom) {
+                if lit.terms.is_empty() {
+                    nullary.insert(lit.relation.as_str());
+                }
+            }
+            vars.extend(atom.vars());
+        }
+    }
+    for var in vars.intersection(&nullary) {
+        found.push((
+            Lint::ShadowedConstant,
+            format!("variable {} shadows the nullary relation {}", var, var),
+        ));
+    }
+}
+
+// Describe every relation used with an arity other than its declared one, or, for relations that
+// aren't declared, other than the one it's first used with. Each mismatching arity of a relation
+// is described once, where it's first used: at the literal's first term past the expected arity,
+// if it has more terms, or else at the literal, if it was parsed.
+pub(crate) fn arity_mismatches(stmts: &[StatementAST]) -> Vec<(String, Location)> {
+    let mut mismatches = vec![];
+    let mut declared: BTreeMap<&str, usize> = BTreeMap::new();
+    for (idx, stmt) in stmts.iter().enumerate() {
+        if let StatementAST::Decl(relation, columns, _) = stmt
+            && let Some(arity) = declared.insert(relation, columns.len())
+            && arity != columns.len()
+        {
+            let message = format!(
+                "relation {} is declared with both {} and {} columns",
+                relation,
+                arity,
+                columns.len()
+            );
+            let location = Location {
+                statement: Some(idx + 1),
+                span: None,
+            };
+            mismatches.push((message, location));
+        }
+    }
+
+    let mut used: BTreeMap<&str, usize> = BTreeMap::new();
+    let mut reported = BTreeSet::new();
+    for (idx, stmt) in stmts.iter().enumerate() {
+        for (relation, arity, lit) in arities_used(stmt) {
+            let expected = match declared.get(relation) {
+                Some(expected) => *expected,
+                None => *used.entry(relation).or_insert(arity),
+            };
+            if arity != expected && reported.insert((relation, arity)) {
+                let location = Location {
+                    statement: Some(idx + 1),
+                    span: lit.and_then(|lit| Location::past_arity(lit, expected).span),
+                };
+                let message = if declared.contains_key(relation) {
+                    format!(
+                        "relation {} is declared with {} columns, but used with {} terms",
+                        relation, expected, arity
+                    )
+                } else {
+                    format!(
+                        "relation {} is used with both {} and {} terms",
+                        relation, expected, arity
+                    )
+                };
+                mismatches.push((message, location));
+            }
+        }
+    }
+    mismatches
+}
+
+fn type_mismatches(stmts: &[StatementAST], found: &mut Vec<(Lint, String)>) {
+    let declared: BTreeMap<&str, &Vec<(String, Type)>> = stmts
+        .iter()
+        .filter_map(|stmt| match stmt {
+            StatementAST::Decl(relation, columns, _) => Some((relation.as_str(), columns)),
+            _ => None,
+        })
+        .collect();
+    for stmt in stmts {
+        let head = stmt.head().into_iter();
+        for lit in head
+            .chain(stmt.bodies().into_iter().flatten())
+            .flat_map(atom_literals)
+        {
+            let Some(columns) = declared.get(lit.relation.as_str()) else {
+                continue;
+            };
+            for ((name, ty), term) in columns.iter().zip(&lit.terms) {
+                let Some(symbol) = term.try_cons() else {
+                    continue;
+                };
+                let is_string = resolve(symbol).is_some();
+                if is_string != (*ty == Type::Symbol) {
+                    found.push((
+                        Lint::TypeMismatch,
+                        format!(
+                            "column {} of relation {} has type {}, but holds {}",
+                            name,
+                            lit.relation,
+                            ty.name(),
+                            Value(symbol)
+                        ),
+                    ));
+                }
+            }
+        }
+    }
+}
+
+fn singleton_variables(stmts: &[StatementAST], found: &mut Vec<(Lint, String)>) {
+    for stmt in stmts {
+        let StatementAST::Rule(head, body) = stmt else {
+            continue;
+        };
+        let mut counts: BTreeMap<&str, usize> = BTreeMap::new();
+        for var in std::iter::once(head).chain(body).flat_map(AtomAST::vars) {
+            *counts.entry(var).or_default() += 1;
+        }
+        let relation = &conclusion(head).relation;
+        for (var, count) in counts {
+            if count == 1 && !var.starts_with('_') {
+                found.push((
+                    Lint::SingletonVariable,
+                    format!(
+                        "variable {} appears only once in a rule for {}",
+                        var, relation
+                    ),
+                ));
+            }
+        }
+    }
+}
+
+// The relations whose tables a statement reads. Unlike `writes_reads`, brackets and the left-hand
+// sides of arrows are assumed rather than read.
+fn table_reads(stmt: &StatementAST) -> Vec<&str> {
+    let mut reads: Vec<&str> = stmt
+        .bodies()
+        .into_iter()
+        .flatten()
+        .filter_map(|atom| read_literal(atom).map(|lit| lit.relation.as_str()))
+        .collect();
+    match stmt {
+        StatementAST::Builtin(builtin) => reads.push(&builtin.source),
+        StatementAST::Output(relation) => reads.push(relation),
+        _ => {}
+    }
+    reads
+}
+
+fn written(stmts: &[StatementAST]) -> BTreeSet<&str> {
+    stmts.iter().flat_map(|stmt| writes_reads(stmt).0).collect()
+}
+
+fn underived_relations(
+    stmts: &[StatementAST],
+    natives: &BTreeSet<&str>,
+    found: &mut Vec<(Lint, String)>,
+) {
+    let mut written = written(stmts);
+    written.extend(natives);
+    let read: BTreeSet<&str> = stmts.iter().flat_map(table_reads).collect();
+    for relation in read.difference(&written) {
+        found.push((
+            Lint::UnderivedRelation,
+            format!("relation {} is used but never derived", relation),
+        ));
+    }
+}
+
+fn unqueried_rules(stmts: &[StatementAST], found: &mut Vec<(Lint, String)>) {
+    // Compute the relations that questions, expectations, constraints, and outputs read, directly
+    // or through the statements deriving the relations they read.
+    let mut queried: BTreeSet<&str> = stmts
+        .iter()
+        .filter(|stmt| {
+            matches!(
+                stmt,
+                StatementAST::Question(..)
+                    | StatementAST::Abduce(_)
+                    | StatementAST::Diff(..)
+                    | StatementAST::Expect(..)
+                    | StatementAST::Constraint(_)
+                    | StatementAST::Output(_)
+            )
+        })
+        .flat_map(|stmt| writes_reads(stmt).1)
+        .collect();
+    if queried.is_empty() {
+        return;
+    }
+    loop {
+        let before = queried.len();
+        for stmt in stmts {
+            let (writes, reads) = writes_reads(stmt);
+            if writes.iter().any(|relation| queried.contains(relation)) {
+                queried.extend(reads);
+            }
+        }
+        if queried.len() == before {
+            break;
+        }
+    }
+
+    // Relations that nothing reads are already flagged as unused.
+    let read: BTreeSet<&str> = stmts.iter().flat_map(|stmt| writes_reads(stmt).1).collect();
+    for stmt in stmts {
+        if let Some(head) = stmt.head()
+            && let relation = conclusion(head).relation.as_str()
+            && read.contains(relation)
+            && !queried.contains(relation)
+        {
+            found.push((
+                Lint::UnqueriedRule,
+                format!("rule for {} never reaches a question", relation),
+            ));
+        }
+    }
+}
+
+fn brackets_without_facts(stmts: &[StatementAST], found: &mut Vec<(Lint, String)>) {
+    let written = written(stmts);
+    let abducible: BTreeSet<&str> = stmts
+        .iter()
+        .filter_map(|stmt| match stmt {
+            StatementAST::Abducible(relation) => Some(relation.as_str()),
+            _ => None,
+        })
+        .collect();
+    let mut reported = BTreeSet::new();
+    for atom in stmts.iter().flat_map(|stmt| stmt.bodies()).flatten() {
+        if let AtomAST::Brackets(lit) = atom
+            && let relation = lit.relation.as_str()
+            && !written.contains(relation)
+            && !abducible.contains(relation)
+            && reported.insert(relation)
+        {
+            found.push((
+                Lint::BracketWithoutFacts,
+                format!("bracket assumes {}, which has no facts", relation),
+            ));
+        }
+    }
+}
+
+// The relations a statement uses, the arity it uses each with, and the literal using it, if any.
+fn arities_used(stmt: &StatementAST) -> Vec<(&str, usize, Option<&LiteralAST>)> {
+    let head = stmt.head().into_iter();
+    let mut used: Vec<(&str, usize, Option<&LiteralAST>)> = head
+        .chain(stmt.bodies().into_iter().flatten())
+        .flat_map(atom_literals)
+        .map(|lit| (lit.relation.as_str(), lit.terms.len(), Some(lit)))
+        .collect();
+    match stmt {
+        StatementAST::Builtin(builtin) => {
+            let (source_arity, arity) = builtin.kind.arities();
+            used.push((&builtin.source, source_arity, None));
+            used.push((&builtin.relation, arity, None));
+        }
+        StatementAST::Input(relation, arity) => used.push((relation, *arity, None)),
+        _ => {}
+    }
+    used
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::grammar::ProgramParser;
+
+    use super::*;
+
+    fn lints(program: &str) -> Vec<Lint> {
+        let parsed = ProgramParser::new().parse(program).unwrap();
+        lint(&parsed).into_iter().map(|diag| diag.lint).collect()
+    }
+
+    #[test]
+    fn lint_clean() {
+        let program = include_str!("../examples/path.dl");
+        assert_eq!(lints(program), vec![]);
+    }
+
+    #[test]
+    fn lint_unused_and_unfireable() {
+        let program = r#"
+P(x) :- Q(x).
+? P(1).
+"#;
+        assert_eq!(
+            lints(program),
+            vec![Lint::UnfireableRule, Lint::UnderivedRelation]
+        );
+
+        let program = r#"
+E(1, 2) :- .
+P(x) :- E(x, _y).
+"#;
+        assert_eq!(lints(program), vec![Lint::UnusedRelation]);
+    }
+
+    #[test]
+    fn lint_non_abducible_bracket() {
+        let program = r#"
+.abducible A.
+[A] :- .
+[B] :- .
+? A, B.
+"#;
+        assert_eq!(lints(program), vec![Lint::NonAbducibleBracket]);
+    }
+
+    #[test]
+    fn lint_shadowed_constant() {
+        let program = r#"
+A :- .
+E(1) :- .
+? E(A), A.
+"#;
+        assert_eq!(lints(program), vec![Lint::ShadowedConstant]);
+    }
+
+    #[test]
+    fn lint_arity_mismatch() {
+        let program = r#"
+.decl E(from: int, to: int).
+E(1, 2) :- .
+E(1) :- .
+P(x) :- E(x, _y).
+? P(x), P(x, x).
+"#;
+        let parsed = ProgramParser::new().parse(program).unwrap();
+        let diags = lint(&parsed);
+        let messages: Vec<_> = diags.iter().map(|diag| diag.message.as_str()).collect();
+        assert_eq!(
+            messages,
+            vec![
+                "relation E is declared with 2 columns, but used with 1 terms",
+                "relation P is used with both 1 and 2 terms"
+            ]
+        );
+        assert!(diags.iter().all(|diag| diag.level == Level::Deny));
+    }
+
+    #[test]
+    fn lint_type_mismatch() {
+        let program = r#"
+.decl Name(id: int, name: symbol).
+Name(1, "alice") :- .
+Name("bob", 2) :- .
+? Name(x, y).
+"#;
+        assert_eq!(lints(program), vec![Lint::TypeMismatch, Lint::TypeMismatch]);
+    }
+
+    #[test]
+    fn lint_singleton_variable() {
+        let program = r#"
+E(1, 2) :- .
+P(x) :- E(x, y).
+Q(x) :- E(x, _y).
+? P(x), Q(x).
+"#;
+        assert_eq!(lints(program), vec![Lint::SingletonVariable]);
+    }
+
+    #[test]
+    fn lint_unqueried_rule() {
+        let program = r#"
+E(1, 2) :- .
+P(x, y) :- E(x, y).
+Q(x, y) :- E(x, y).
+R(x, y) :- Q(x, y).
+? P(x, y).
+"#;
+        let parsed = ProgramParser::new().parse(program).unwrap();
+        let messages: Vec<_> = lint(&parsed).into_iter().map(|diag| diag.message).collect();
+        assert_eq!(
+            messages,
+            vec![
+                "relation R is derived but never used",
+                "rule for Q never reaches a question"
+            ]
+        );
+    }
+
+    #[test]
+    fn lint_bracket_without_facts() {
+        let program = r#"
+.abducible A.
+.abducible E.
+E(1, 2) :- .
+P(x) :- E(x, y), [A(y)], [B(y)], [E(y, x)].
+? P(x).
+"#;
+        let parsed = ProgramParser::new().parse(program).unwrap();
+        let messages: Vec<_> = lint(&parsed).into_iter().map(|diag| diag.message).collect();
+        assert_eq!(
+            messages,
+            vec![
+                "bracket assumes B, which isn't abducible",
+                "bracket assumes B, which has no facts"
+            ]
+        );
+    }
+
+    #[test]
+    fn lint_levels() {
+        let program = r#"
+.allow unused_relation.
+.allow underived_relation.
+.deny L002.
+P(x) :- Q(x).
+"#;
+        let parsed = ProgramParser::new().parse(program).unwrap();
+        let diags = lint(&parsed);
+        assert_eq!(diags.len(), 1);
+        assert_eq!(diags[0].lint, Lint::UnfireableRule);
+        assert_eq!(diags[0].level, Level::Deny);
+    }
+}
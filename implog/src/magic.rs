@@ -0,0 +1,273 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use crate::ast::{AtomAST, BuiltinAST, LiteralAST, TermAST, existential_vars};
+
+// Magic-set transformation. Questions normally force a full bottom-up fixpoint of every rule.
+// Instead, rules are specialized to the bindings each question (and each integrity constraint)
+// passes to the relations it reads, so only tuples relevant to the question are derived.
+//
+// Each relation defined by rules is adorned with the positions bound when it's read, e.g. reading
+// `P(1, x)` reads `P#bf`. For each adorned relation, a magic relation `magic#P#bf` holds the
+// bindings it's read with. Adorned rules are guarded by their magic relation, and magic rules pass
+// bindings from a rule's head and the atoms to the left of a literal to that literal's magic
+// relation (left-to-right sideways information passing).
+//
+// Magic relations are only filters: their tuples always have assumption value one, so guarding a
+// rule doesn't change the assumption values it derives. Adorned relations keep their original
+// names for the purpose of leaf assumptions.
+
+const MAGIC_PREFIX: &str = "magic#";
+const ADORNMENT_SEPARATOR: char = '#';
+
+pub type Rule = (AtomAST, Vec<AtomAST>);
+
+pub fn is_magic(relation: &str) -> bool {
+    relation.starts_with(MAGIC_PREFIX)
+}
+
+// The relation an adorned relation was derived from.
+pub fn original(relation: &str) -> &str {
+    relation.split(ADORNMENT_SEPARATOR).next().unwrap()
+}
+
+fn adorned(relation: &str, adornment: &str) -> String {
+    format!("{}{}{}", relation, ADORNMENT_SEPARATOR, adornment)
+}
+
+fn magic(relation: &str, adornment: &str) -> String {
+    format!("{}{}", MAGIC_PREFIX, adorned(relation, adornment))
+}
+
+fn head_literal(atom: &AtomAST) -> &LiteralAST {
+    let (AtomAST::Literal(lit) | AtomAST::Brackets(lit) | AtomAST::Arrow(_, lit)) = atom;
+    lit
+}
+
+fn with_relation(lit: &LiteralAST, relation: String) -> LiteralAST {
+    LiteralAST {
+        relation,
+        terms: lit.terms.clone(),
+        span: None,
+        term_spans: vec![],
+    }
+}
+
+// Transform rules for a set of goals (the bodies of a question and of integrity constraints).
+// Returns the transformed rules and goals, in that order.
+pub fn transform(
+    rules: &[(&AtomAST, &Vec<AtomAST>)],
+    builtins: &[BuiltinAST],
+    goals: &[&[AtomAST]],
+) -> (Vec<Rule>, Vec<Vec<AtomAST>>) {
+    let mut by_head: BTreeMap<&str, Vec<(&AtomAST, &Vec<AtomAST>)>> = BTreeMap::new();
+    for (head, body) in rules {
+        by_head
+            .entry(&head_literal(head).relation)
+            .or_default()
+            .push((head, body));
+    }
+
+    // Built-ins compute whole relations, so relations computed by built-ins, their sources, and the
+    // relations the sources depend on are evaluated without specialization.
+    let mut full: BTreeSet<&str> = BTreeSet::new();
+    let mut worklist: Vec<&str> = vec![];
+    for builtin in builtins {
+        worklist.push(&builtin.relation);
+        worklist.push(&builtin.source);
+    }
+    // So are relations with existential variables, since bindings passed to a rule's head can't
+    // bind them.
+    for (head, body) in rules {
+        if !existential_vars(head, body).is_empty() {
+            worklist.push(&head_literal(head).relation);
+        }
+    }
+    while let Some(relation) = worklist.pop() {
+        if !full.insert(relation) {
+            continue;
+        }
+        for (_, body) in by_head.get(relation).into_iter().flatten() {
+            for atom in body.iter() {
+                if let AtomAST::Literal(lit) | AtomAST::Arrow(_, lit) = atom {
+                    worklist.push(&lit.relation);
+                }
+            }
+        }
+    }
+
+    let mut transformer = Transformer {
+        adornable: by_head
+            .keys()
+            .filter(|relation| !full.contains(*relation))
+            .copied()
+            .collect(),
+        rules: vec![],
+        seen: BTreeSet::new(),
+        worklist: vec![],
+    };
+    for (head, body) in rules {
+        if !transformer
+            .adornable
+            .contains(head_literal(head).relation.as_str())
+        {
+            transformer.rules.push(((*head).clone(), (*body).clone()));
+        }
+    }
+
+    let goals = goals
+        .iter()
+        .map(|goal| transformer.adorn_body(goal, None, &BTreeSet::new()))
+        .collect();
+    while let Some((relation, adornment)) = transformer.worklist.pop() {
+        for (head, body) in &by_head[relation.as_str()] {
+            transformer.adorn_rule(head, body, &adornment);
+        }
+    }
+    (transformer.rules, goals)
+}
+
+struct Transformer<'a> {
+    adornable: BTreeSet<&'a str>,
+    rules: Vec<Rule>,
+    seen: BTreeSet<(String, String)>,
+    worklist: Vec<(String, String)>,
+}
+
+impl Transformer<'_> {
+    fn adorn_rule(&mut self, head: &AtomAST, body: &[AtomAST], adornment: &str) {
+        let lit = head_literal(head);
+        let bound: Vec<TermAST> = lit
+            .terms
+            .iter()
+            .zip(adornment.chars())
+            .filter(|(_, b)| *b == 'b')
+            .map(|(term, _)| term.clone())
+            .collect();
+        let vars = bound
+            .iter()
+            .flat_map(TermAST::vars)
+            .map(str::to_string)
+            .collect();
+        let guard = LiteralAST {
+            relation: magic(&lit.relation, adornment),
+            terms: bound,
+            span: None,
+            term_spans: vec![],
+        };
+        let body = self.adorn_body(body, Some(guard), &vars);
+        let head_lit = with_relation(lit, adorned(&lit.relation, adornment));
+        let head = match head {
+            AtomAST::Brackets(_) => AtomAST::Brackets(head_lit),
+            _ => AtomAST::Literal(head_lit),
+        };
+        self.rules.push((head, body));
+    }
+
+    // Adorn the table-reading atoms of a body, adding magic rules passing bindings to each of them.
+    // The body is guarded by `guard`, if given.
+    fn adorn_body(
+        &mut self,
+        body: &[AtomAST],
+        guard: Option<LiteralAST>,
+        bound: &BTreeSet<String>,
+    ) -> Vec<AtomAST> {
+        let mut bound = bound.clone();
+        let mut adorned_body: Vec<AtomAST> = guard.into_iter().map(AtomAST::Literal).collect();
+        // The prefix of the body binding variables, for use in magic rules. Brackets are dropped
+        // and arrows are replaced by their RHS literal, since their other variables may not be
+        // bound yet.
+        let mut prefix: Vec<AtomAST> = adorned_body.clone();
+        for atom in body {
+            let adorned_atom = match atom {
+                AtomAST::Literal(lit) => AtomAST::Literal(self.adorn_literal(lit, &bound, &prefix)),
+                AtomAST::Arrow(lhs, rhs) => {
+                    AtomAST::Arrow(lhs.clone(), self.adorn_literal(rhs, &bound, &prefix))
+                }
+                AtomAST::Brackets(_) => atom.clone(),
+            };
+            if let AtomAST::Literal(lit) | AtomAST::Arrow(_, lit) = &adorned_atom {
+                bound.extend(lit.vars().map(str::to_string));
+                prefix.push(AtomAST::Literal(lit.clone()));
+            }
+            adorned_body.push(adorned_atom);
+        }
+        adorned_body
+    }
+
+    fn adorn_literal(
+        &mut self,
+        lit: &LiteralAST,
+        bound: &BTreeSet<String>,
+        prefix: &[AtomAST],
+    ) -> LiteralAST {
+        if !self.adornable.contains(lit.relation.as_str()) {
+            return lit.clone();
+        }
+        let is_bound = |term: &TermAST| term.vars().iter().all(|var| bound.contains(*var));
+        let adornment: String = lit
+            .terms
+            .iter()
+            .map(|term| if is_bound(term) { 'b' } else { 'f' })
+            .collect();
+        let magic_head = LiteralAST {
+            relation: magic(&lit.relation, &adornment),
+            terms: lit
+                .terms
+                .iter()
+                .filter(|term| is_bound(term))
+                .cloned()
+                .collect(),
+            span: None,
+            term_spans: vec![],
+        };
+        self.rules
+            .push((AtomAST::Literal(magic_head), prefix.to_vec()));
+        if self.seen.insert((lit.relation.clone(), adornment.clone())) {
+            self.worklist
+                .push((lit.relation.clone(), adornment.clone()));
+        }
+        with_relation(lit, adorned(&lit.relation, &adornment))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::ast::StatementAST;
+    use crate::grammar::ProgramParser;
+
+    use super::*;
+
+    #[test]
+    fn transform_path() {
+        let program = r#"
+E(1, 2) :- .
+P(x, y) :- E(x, y).
+P(x, z) :- E(x, y), P(y, z).
+? P(1, z).
+"#;
+        let parsed = ProgramParser::new().parse(program).unwrap();
+        let rules: Vec<_> = parsed[0..3]
+            .iter()
+            .map(|stmt| (stmt.head().unwrap(), stmt.body()))
+            .collect();
+        let StatementAST::Question(question, _, _, _) = &parsed[3] else {
+            panic!()
+        };
+        let (rules, goals) = transform(&rules, &[], &[question]);
+
+        let AtomAST::Literal(goal) = &goals[0][0] else {
+            panic!()
+        };
+        assert_eq!(goal.relation, "P#bf");
+        let heads: BTreeSet<&str> = rules
+            .iter()
+            .map(|(head, _)| head_literal(head).relation.as_str())
+            .collect();
+        assert_eq!(
+            heads,
+            BTreeSet::from(["E#bf", "P#bf", "magic#E#bf", "magic#P#bf"])
+        );
+        assert_eq!(original("P#bf"), "P");
+        assert!(is_magic("magic#P#bf"));
+    }
+}
@@ -0,0 +1,105 @@
+use crate::ast::{Span, StatementAST};
+
+// Lines are kept within this many characters where possible, by breaking long rule bodies.
+const WIDTH: usize = 100;
+
+// Format a program canonically: one statement per line, with single spaces between tokens. Runs
+// of consecutive rules are aligned, so their `:-` and bodies start at the same columns, and bodies
+// too long for a line are broken into one atom per line, aligned under the first. Blank lines
+// separating statements in the source are kept, collapsed to one.
+pub fn pretty(source: &str, spans: &[Span], stmts: &[StatementAST]) -> String {
+    let mut out = String::new();
+    let mut idx = 0;
+    while idx < stmts.len() {
+        if idx > 0 && blank_between(source, spans[idx - 1], spans[idx]) {
+            out.push('\n');
+        }
+        // The run of rules starting here, up to the next blank line or other statement.
+        let mut end = idx + 1;
+        if let StatementAST::Rule(..) = stmts[idx] {
+            while end < stmts.len()
+                && let StatementAST::Rule(..) = stmts[end]
+                && !blank_between(source, spans[end - 1], spans[end])
+            {
+                end += 1;
+            }
+        }
+        match stmts[idx] {
+            StatementAST::Rule(..) => pretty_rules(&stmts[idx..end], &mut out),
+            _ => {
+                out.push_str(&stmts[idx].to_string());
+                out.push('\n');
+            }
+        }
+        idx = end;
+    }
+    out
+}
+
+fn blank_between(source: &str, before: Span, after: Span) -> bool {
+    source[before.end..after.start].matches('\n').count() > 1
+}
+
+fn pretty_rules(rules: &[StatementAST], out: &mut String) {
+    let heads: Vec<String> = rules
+        .iter()
+        .filter_map(|rule| rule.head().map(ToString::to_string))
+        .collect();
+    let width = heads.iter().map(|head| head.chars().count()).max();
+    for (rule, head) in rules.iter().zip(&heads) {
+        let prefix = format!("{:width$} :- ", head, width = width.unwrap_or(0));
+        let indent = prefix.chars().count();
+        let atoms: Vec<String> = rule.body().iter().map(ToString::to_string).collect();
+        let line = atoms.join(", ");
+        out.push_str(&prefix);
+        if indent + line.chars().count() + 1 > WIDTH && atoms.len() > 1 {
+            out.push_str(&atoms.join(&format!(",\n{:indent$}", "", indent = indent)));
+        } else {
+            out.push_str(&line);
+        }
+        out.push_str(".\n");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::grammar::LocatedProgramParser;
+
+    use super::*;
+
+    fn format(program: &str) -> String {
+        let (spans, stmts): (Vec<_>, Vec<_>) = LocatedProgramParser::new()
+            .parse(program)
+            .unwrap()
+            .into_iter()
+            .unzip();
+        pretty(program, &spans, &stmts)
+    }
+
+    #[test]
+    fn format_program() {
+        let program = "E(1,2):-.  E(2,3) :- .\n\n\n\
+                       Path(x,y):-E(x,y).\n\
+                       Loop(x):-E(x,x).\n\
+                       Path(x,z) :- Path(x,y),E(y,z).\n\
+                       ?Path(1,  x).\n\
+                       LongRelationNameForAHead(alpha, beta, gamma) :- First(alpha, beta), \
+                       Second(beta, gamma), Third(gamma, alpha), Fourth(alpha).\n";
+        let formatted = format(program);
+        assert_eq!(
+            formatted,
+            "E(1, 2) :- .\n\
+             E(2, 3) :- .\n\
+             \n\
+             Path(x, y) :- E(x, y).\n\
+             Loop(x)    :- E(x, x).\n\
+             Path(x, z) :- Path(x, y), E(y, z).\n\
+             ? Path(1, x).\n\
+             LongRelationNameForAHead(alpha, beta, gamma) :- First(alpha, beta),\n                                                \
+             Second(beta, gamma),\n                                                \
+             Third(gamma, alpha),\n                                                \
+             Fourth(alpha).\n"
+        );
+        assert_eq!(format(&formatted), formatted);
+    }
+}
@@ -0,0 +1,455 @@
+use std::collections::BTreeSet;
+use std::fmt;
+
+use crate::ast::{AtomAST, BuiltinAST};
+use crate::interpret::{Bindings, Environment, derive_head, ground, read_literal, unify};
+use crate::representation::{Assumption, GroundTuple};
+use crate::value::Value;
+
+// Sampling proofs of derived tuples. A tuple with many derivations may have exponentially many
+// proofs, so instead of enumerating them, proofs are sampled top-down over a computed model: each
+// step picks one way of deriving a tuple at random (a rule instance whose body holds in the model,
+// an input fact, or a built-in), then samples proofs of the tuples that rule instance reads.
+//
+// Proofs are finite, so a rule instance reading a tuple that's already being proven further up the
+// proof is never picked. If every way of deriving a tuple fails this way, another is picked higher
+// up instead.
+//
+// Proofs can also be enumerated deterministically, trying the ways of deriving each tuple in the
+// order the program lists them, to explain how a tuple was derived.
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Proof {
+    // A tuple loaded by `.input`.
+    Input(String, GroundTuple),
+    // A tuple computed by a built-in.
+    Builtin(String, GroundTuple),
+    // A tuple derived by a rule (counted from 0 among the program's rules) with some bindings of its
+    // variables, from proofs of the tuples its body reads, in order.
+    Rule {
+        relation: String,
+        tuple: GroundTuple,
+        rule: usize,
+        bindings: Bindings,
+        premises: Vec<Proof>,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Weighting {
+    // Every way of deriving a tuple is equally likely to be picked.
+    Uniform,
+    // Each leaf assumption needed by a way of deriving a tuple halves how likely it is to be picked.
+    Cost,
+}
+
+enum Step {
+    Input,
+    Builtin,
+    Rule(usize, Bindings, Vec<(String, GroundTuple)>),
+}
+
+pub struct Sampler<'a> {
+    rules: &'a [(&'a AtomAST, &'a Vec<AtomAST>)],
+    builtins: &'a [BuiltinAST],
+    weighting: Weighting,
+    rng: SplitMix64,
+    // The tuples being proven, from the root of the proof down.
+    path: Vec<(String, GroundTuple)>,
+}
+
+impl<'a> Sampler<'a> {
+    pub fn new(
+        rules: &'a [(&'a AtomAST, &'a Vec<AtomAST>)],
+        builtins: &'a [BuiltinAST],
+        weighting: Weighting,
+        seed: u64,
+    ) -> Self {
+        Self {
+            rules,
+            builtins,
+            weighting,
+            rng: SplitMix64(seed),
+            path: vec![],
+        }
+    }
+
+    // Sample a proof of a tuple in the model currently held by an environment.
+    pub fn sample<A: Assumption>(
+        &mut self,
+        env: &Environment<A>,
+        relation: &str,
+        tuple: &GroundTuple,
+    ) -> Option<Proof> {
+        let key = (relation.to_string(), tuple.clone());
+        let proven = env
+            .table(relation)
+            .get(tuple)
+            .is_some_and(|(value, _)| !value.is_zero());
+        if !proven || self.path.contains(&key) {
+            return None;
+        }
+        self.path.push(key);
+        let mut steps = self.steps(env, relation, tuple);
+        let mut proof = None;
+        while proof.is_none() && !steps.is_empty() {
+            let (step, _) = steps.swap_remove(self.pick(&steps));
+            proof = match step {
+                Step::Input => Some(Proof::Input(relation.to_string(), tuple.clone())),
+                Step::Builtin => Some(Proof::Builtin(relation.to_string(), tuple.clone())),
+                Step::Rule(rule, bindings, premises) => premises
+                    .iter()
+                    .map(|(relation, tuple)| self.sample(env, relation, tuple))
+                    .collect::<Option<Vec<_>>>()
+                    .map(|premises| Proof::Rule {
+                        relation: relation.to_string(),
+                        tuple: tuple.clone(),
+                        rule,
+                        bindings,
+                        premises,
+                    }),
+            };
+        }
+        self.path.pop();
+        proof
+    }
+
+    // Enumerate up to `limit` proofs of a tuple in the model currently held by an environment.
+    pub fn explain<A: Assumption>(
+        &mut self,
+        env: &Environment<A>,
+        relation: &str,
+        tuple: &GroundTuple,
+        limit: usize,
+    ) -> Vec<Proof> {
+        let key = (relation.to_string(), tuple.clone());
+        let proven = env
+            .table(relation)
+            .get(tuple)
+            .is_some_and(|(value, _)| !value.is_zero());
+        if !proven || self.path.contains(&key) {
+            return vec![];
+        }
+        self.path.push(key);
+        let mut proofs = vec![];
+        for (step, _) in self.steps(env, relation, tuple) {
+            if proofs.len() >= limit {
+                break;
+            }
+            match step {
+                Step::Input => proofs.push(Proof::Input(relation.to_string(), tuple.clone())),
+                Step::Builtin => proofs.push(Proof::Builtin(relation.to_string(), tuple.clone())),
+                Step::Rule(rule, bindings, premises) => {
+                    // Every combination of proofs of the premises is a proof of the tuple.
+                    let left = limit - proofs.len();
+                    let mut combinations = vec![vec![]];
+                    for (relation, tuple) in &premises {
+                        let premise_proofs = self.explain(env, relation, tuple, left);
+                        combinations = combinations
+                            .iter()
+                            .flat_map(|combination| {
+                                premise_proofs.iter().map(move |proof| {
+                                    let mut combination = combination.clone();
+                                    combination.push(proof.clone());
+                                    combination
+                                })
+                            })
+                            .take(left)
+                            .collect();
+                    }
+                    proofs.extend(combinations.into_iter().map(|premises| Proof::Rule {
+                        relation: relation.to_string(),
+                        tuple: tuple.clone(),
+                        rule,
+                        bindings: bindings.clone(),
+                        premises,
+                    }));
+                }
+            }
+        }
+        self.path.pop();
+        proofs
+    }
+
+    // The lineage of some tuples in the model currently held by an environment: the tuples
+    // themselves, and the tuples read by every way of deriving a tuple in the lineage.
+    pub fn lineage<A: Assumption>(
+        &mut self,
+        env: &Environment<A>,
+        tuples: impl IntoIterator<Item = (String, GroundTuple)>,
+    ) -> BTreeSet<(String, GroundTuple)> {
+        let mut lineage = BTreeSet::new();
+        let mut stack: Vec<_> = tuples.into_iter().collect();
+        while let Some((relation, tuple)) = stack.pop() {
+            let proven = env
+                .table(&relation)
+                .get(&tuple)
+                .is_some_and(|(value, _)| !value.is_zero());
+            if !proven || lineage.contains(&(relation.clone(), tuple.clone())) {
+                continue;
+            }
+            for (step, _) in self.steps(env, &relation, &tuple) {
+                if let Step::Rule(_, _, premises) = step {
+                    stack.extend(premises);
+                }
+            }
+            lineage.insert((relation, tuple));
+        }
+        lineage
+    }
+
+    // The ways of deriving a tuple, and their weights.
+    fn steps<A: Assumption>(
+        &self,
+        env: &Environment<A>,
+        relation: &str,
+        tuple: &GroundTuple,
+    ) -> Vec<(Step, f64)> {
+        let mut steps = vec![];
+        if env.input_facts(relation).contains(tuple) {
+            steps.push((Step::Input, 1.0));
+        }
+        if self
+            .builtins
+            .iter()
+            .any(|builtin| builtin.relation == relation)
+        {
+            steps.push((Step::Builtin, 1.0));
+        }
+        for (idx, (head, body)) in self.rules.iter().enumerate() {
+            let (AtomAST::Literal(lit) | AtomAST::Brackets(lit) | AtomAST::Arrow(_, lit)) = *head;
+            if lit.relation != relation {
+                continue;
+            }
+            let Some(bindings) = unify(lit, tuple, &Bindings::new()) else {
+                continue;
+            };
+            for (bindings, value) in env.query_from(body, None, bindings) {
+                let premises: Vec<_> = body
+                    .iter()
+                    .filter_map(read_literal)
+                    .filter(|lit| !env.is_native(&lit.relation))
+                    .filter_map(|lit| Some((lit.relation.clone(), ground(lit, &bindings)?)))
+                    .collect();
+                if premises.iter().any(|premise| self.path.contains(premise)) {
+                    continue;
+                }
+                let weight = match self.weighting {
+                    Weighting::Uniform => 1.0,
+                    Weighting::Cost => derive_head(head, &bindings, value)
+                        .map_or(0.0, |(_, _, value)| 0.5f64.powi(value.cost() as i32)),
+                };
+                steps.push((Step::Rule(idx, bindings, premises), weight));
+            }
+        }
+        steps
+    }
+
+    fn pick(&mut self, steps: &[(Step, f64)]) -> usize {
+        let total: f64 = steps.iter().map(|(_, weight)| weight).sum();
+        let mut point = self.rng.unit() * total;
+        for (idx, (_, weight)) in steps.iter().enumerate() {
+            if point < *weight {
+                return idx;
+            }
+            point -= weight;
+        }
+        steps.len() - 1
+    }
+}
+
+// Proofs are displayed as trees, one tuple per line, with premises indented under the tuple they
+// prove, like:
+//
+// P(1, 3) by rule 4 with x = 1, y = 2, z = 3
+//   P(1, 2) by rule 3 with x = 1, y = 2
+//     E(1, 2) by rule 0
+//   ...
+impl Proof {
+    fn write(&self, f: &mut fmt::Formatter<'_>, depth: usize) -> fmt::Result {
+        write!(f, "{:indent$}", "", indent = 2 * depth)?;
+        match self {
+            Proof::Input(relation, tuple) => writeln!(f, "{} from input", Tuple(relation, tuple)),
+            Proof::Builtin(relation, tuple) => {
+                writeln!(f, "{} from built-in", Tuple(relation, tuple))
+            }
+            Proof::Rule {
+                relation,
+                tuple,
+                rule,
+                bindings,
+                premises,
+            } => {
+                write!(f, "{} by rule {}", Tuple(relation, tuple), rule)?;
+                for (idx, (var, symbol)) in bindings.iter().enumerate() {
+                    let sep = if idx == 0 { " with" } else { "," };
+                    write!(f, "{} {} = {}", sep, var, Value(*symbol))?;
+                }
+                writeln!(f)?;
+                for premise in premises {
+                    premise.write(f, depth + 1)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+impl fmt::Display for Proof {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.write(f, 0)
+    }
+}
+
+struct Tuple<'a>(&'a str, &'a GroundTuple);
+
+impl fmt::Display for Tuple<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let Tuple(relation, tuple) = self;
+        if tuple.is_empty() {
+            return write!(f, "{}", relation);
+        }
+        let symbols: Vec<String> = tuple
+            .iter()
+            .map(|symbol| Value(*symbol).to_string())
+            .collect();
+        write!(f, "{}({})", relation, symbols.join(", "))
+    }
+}
+
+// A small, seedable pseudo-random number generator, so sampling is reproducible.
+struct SplitMix64(u64);
+
+impl SplitMix64 {
+    fn next(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e3779b97f4a7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+        z ^ (z >> 31)
+    }
+
+    // A number in [0, 1).
+    fn unit(&mut self) -> f64 {
+        (self.next() >> 11) as f64 / (1u64 << 53) as f64
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeSet;
+    use std::sync::{Arc, Mutex};
+
+    use crate::grammar::ProgramParser;
+    use crate::interpret::{InterpretError, Progress};
+    use crate::representation::DNFAssumption;
+
+    use super::*;
+
+    fn rules_used(proof: &Proof, rules: &mut BTreeSet<usize>) {
+        if let Proof::Rule { rule, premises, .. } = proof {
+            rules.insert(*rule);
+            for premise in premises {
+                rules_used(premise, rules);
+            }
+        }
+    }
+
+    #[test]
+    fn sample_cyclic() {
+        let program = r#"
+E(1, 2) :- .
+E(2, 1) :- .
+E(2, 3) :- .
+P(x, y) :- E(x, y).
+P(x, z) :- P(x, y), P(y, z).
+? P(1, 3).
+"#;
+        let parsed = ProgramParser::new().parse(program).unwrap();
+        let mut env = Environment::<DNFAssumption>::new();
+        env.interpret(&parsed).unwrap();
+        let proofs = env
+            .sample_proofs("P", &vec![1, 3], 20, Weighting::Uniform, 7)
+            .unwrap();
+        assert_eq!(proofs.len(), 20);
+        let mut rules = BTreeSet::new();
+        for proof in &proofs {
+            assert!(matches!(proof, Proof::Rule { rule: 4, .. }));
+            rules_used(proof, &mut rules);
+        }
+        assert_eq!(rules, BTreeSet::from([0, 2, 3, 4]));
+
+        let proofs = env
+            .sample_proofs("P", &vec![3, 1], 20, Weighting::Uniform, 7)
+            .unwrap();
+        assert!(proofs.is_empty());
+
+        // The model is kept between samples, so sampling again only checks it's a fixpoint.
+        let reports = Arc::new(Mutex::new(vec![]));
+        let sink = reports.clone();
+        env.set_progress(move |progress| sink.lock().unwrap().push(progress));
+        env.sample_proofs("P", &vec![1, 3], 1, Weighting::Uniform, 7)
+            .unwrap();
+        assert!(matches!(
+            reports.lock().unwrap()[..],
+            [_, Progress::Fixpoint { iterations: 1, .. }]
+        ));
+
+        assert_eq!(
+            env.sample_proofs("Q", &vec![1], 1, Weighting::Uniform, 7),
+            Err(InterpretError::UnknownRelation("Q".to_string()))
+        );
+        assert!(matches!(
+            env.sample_proofs("P", &vec![1], 1, Weighting::Uniform, 7),
+            Err(InterpretError::ArityMismatch(..))
+        ));
+    }
+
+    #[test]
+    fn explain_derivations() {
+        let program = r#"
+E(1, 2) :- .
+E(1, 3) :- .
+E(2, 4) :- .
+E(3, 4) :- .
+P(x, y) :- E(x, y).
+P(x, z) :- P(x, y), E(y, z).
+"#;
+        let parsed = ProgramParser::new().parse(program).unwrap();
+        let mut env = Environment::<DNFAssumption>::new();
+        env.interpret(&parsed).unwrap();
+        let proofs = env.explain("P", &vec![1, 4], false).unwrap();
+        assert_eq!(proofs.len(), 1);
+        assert_eq!(
+            proofs[0].to_string(),
+            "P(1, 4) by rule 5 with x = 1, y = 2, z = 4\n  P(1, 2) by rule 4 with x = 1, y = 2\n    E(1, 2) by rule 0\n  E(2, 4) by rule 2\n"
+        );
+        assert_eq!(env.explain("P", &vec![1, 4], true).unwrap().len(), 2);
+        assert!(env.explain("P", &vec![4, 1], true).unwrap().is_empty());
+    }
+
+    #[test]
+    fn sample_weighted_by_cost() {
+        let program = r#"
+[A] :- .
+[B] :- .
+P :- .
+P :- A, B.
+? P.
+"#;
+        let parsed = ProgramParser::new().parse(program).unwrap();
+        let mut env = Environment::<DNFAssumption>::new();
+        env.interpret(&parsed).unwrap();
+        let mut cheap = |weighting| {
+            env.sample_proofs("P", &vec![], 200, weighting, 3)
+                .unwrap()
+                .iter()
+                .filter(|proof| matches!(proof, Proof::Rule { rule: 2, .. }))
+                .count()
+        };
+        let uniform = cheap(Weighting::Uniform);
+        let cost = cheap(Weighting::Cost);
+        assert!((70..130).contains(&uniform));
+        assert!(cost > 140);
+    }
+}
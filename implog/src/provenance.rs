@@ -0,0 +1,125 @@
+use std::collections::BTreeSet;
+use std::fmt::Write;
+
+use crate::representation::GroundTuple;
+use crate::value::Value;
+
+// The derivation hypergraph of a model computed bottom-up, recorded while rules fire (see
+// `Environment::set_record_provenance`). Each derivation is a hyperedge from the tuples a rule
+// instance (or a built-in) read to the tuple it derived. Every derivation found is recorded, even
+// those deriving tuples already in the model, so the hypergraph holds every way of deriving each
+// tuple, unlike a single proof. Tuples no derivation derives, like inputs and facts added from Rust,
+// only appear as premises.
+
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Source {
+    // A rule, counted from 0 among the program's rules, like in proofs.
+    Rule(usize),
+    // A built-in, by the name of its algorithm. Built-ins read their whole source relation, so
+    // their derivations have no premises.
+    Builtin(String),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Derivation {
+    pub relation: String,
+    pub tuple: GroundTuple,
+    pub source: Source,
+    // The tuples read by the rule instance, in the order of its body. Brackets assume tuples rather
+    // than read them, so they aren't premises.
+    pub premises: Vec<(String, GroundTuple)>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Provenance {
+    pub derivations: BTreeSet<Derivation>,
+}
+
+impl Provenance {
+    // Render the hypergraph in Graphviz's DOT language. Tuples are nodes, and each derivation is a
+    // box labeled with its source, with edges from its premises and to the tuple it derived.
+    pub fn to_dot(&self) -> String {
+        let mut dot = String::from("digraph provenance {\n");
+        for (idx, derivation) in self.derivations.iter().enumerate() {
+            let label = match &derivation.source {
+                Source::Rule(rule) => format!("rule {}", rule),
+                Source::Builtin(name) => name.clone(),
+            };
+            writeln!(dot, "  d{} [shape = box, label = \"{}\"];", idx, label).unwrap();
+            for (relation, tuple) in &derivation.premises {
+                writeln!(dot, "  \"{}\" -> d{};", escape(&show(relation, tuple)), idx).unwrap();
+            }
+            let conclusion = show(&derivation.relation, &derivation.tuple);
+            writeln!(dot, "  d{} -> \"{}\";", idx, escape(&conclusion)).unwrap();
+        }
+        dot.push_str("}\n");
+        dot
+    }
+}
+
+// A tuple written like an atom, like `P(1, "a")`.
+pub fn show(relation: &str, tuple: &GroundTuple) -> String {
+    if tuple.is_empty() {
+        return relation.to_string();
+    }
+    let tuple: Vec<_> = tuple
+        .iter()
+        .map(|symbol| Value(*symbol).to_string())
+        .collect();
+    format!("{}({})", relation, tuple.join(", "))
+}
+
+fn escape(s: &str) -> String {
+    s.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::grammar::ProgramParser;
+    use crate::interpret::Environment;
+    use crate::json::ToJson;
+    use crate::representation::DNFAssumption;
+
+    use super::*;
+
+    #[test]
+    fn record_derivations() {
+        let program = "E(1, 2) :- .\nE(2, 3) :- .\nP(x, y) :- E(x, y).\nP(x, z) :- P(x, y), E(y, z).\n\
+                       Q(x) :- P(x, 3), [A(x)].\n? Q(x).\n";
+        let parsed = ProgramParser::new().parse(program).unwrap();
+        let mut env = Environment::<DNFAssumption>::new();
+        assert!(env.provenance().is_none());
+        env.set_record_provenance(true);
+        env.interpret(&parsed).unwrap();
+        let provenance = env.provenance().unwrap();
+        // Two facts, two base paths, one recursive path, and two derivations of `Q`.
+        assert_eq!(provenance.derivations.len(), 7);
+        let recursive = provenance
+            .derivations
+            .iter()
+            .find(|derivation| derivation.source == Source::Rule(3))
+            .unwrap();
+        assert_eq!(
+            (recursive.relation.as_str(), &recursive.tuple),
+            ("P", &vec![1, 3])
+        );
+        assert_eq!(
+            recursive.premises,
+            vec![("P".to_string(), vec![1, 2]), ("E".to_string(), vec![2, 3])]
+        );
+        let q = Derivation {
+            relation: "Q".to_string(),
+            tuple: vec![2],
+            source: Source::Rule(4),
+            premises: vec![("P".to_string(), vec![2, 3])],
+        };
+        assert!(provenance.derivations.contains(&q));
+
+        let dot = provenance.to_dot();
+        assert!(dot.contains("  d0 [shape = box, label = \"rule 0\"];\n  d0 -> \"E(1, 2)\";\n"));
+        assert!(dot.contains("  \"P(1, 2)\" -> d"));
+        assert!(provenance.to_json().starts_with(
+            r#"{"derivations": [{"relation": "E", "tuple": [1, 2], "rule": 0, "premises": []}, "#
+        ));
+    }
+}
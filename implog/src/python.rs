@@ -0,0 +1,157 @@
+use pyo3::exceptions::PyValueError;
+use pyo3::prelude::*;
+use pyo3::types::PyDict;
+
+use crate::ast::{StatementAST, parse_checked};
+use crate::interpret::{Bindings, Environment};
+use crate::representation::{Assumption, DNFAssumption, Symbol};
+use crate::value::{Value, intern, resolve, resolve_term};
+
+// Bindings for driving the interpreter from Python, e.g. in notebooks. Programs are interpreted
+// with DNF assumptions. Facts are given as lists or tuples of ints and strings, and answers are
+// returned as lists of dicts mapping variables to values, plus an `assumption` key holding the
+// answer's assumption formula as a string.
+
+// Programs passed from Python have no file name.
+const SOURCE: &str = "<source>";
+
+#[derive(FromPyObject)]
+enum PyValue {
+    Int(Symbol),
+    Str(String),
+}
+
+impl PyValue {
+    fn symbol(&self) -> Symbol {
+        match self {
+            PyValue::Int(symbol) => *symbol,
+            PyValue::Str(s) => intern(s),
+        }
+    }
+}
+
+#[pyclass(name = "Environment")]
+pub struct PyEnvironment {
+    env: Environment<DNFAssumption>,
+}
+
+#[pymethods]
+impl PyEnvironment {
+    #[new]
+    fn new() -> Self {
+        Self {
+            env: Environment::new(),
+        }
+    }
+
+    // Interpret a program, returning the log of answers to its questions.
+    fn interpret(&mut self, source: &str) -> PyResult<String> {
+        let (spans, stmts) = parse_checked(SOURCE, source).map_err(PyValueError::new_err)?;
+        self.env
+            .interpret(&stmts)
+            .map(str::to_string)
+            .map_err(|err| PyValueError::new_err(err.locate(SOURCE, source, &spans)))
+    }
+
+    // Add a fact, like `env.add_fact("E", (1, "a"))`. With `assume=True`, the fact holds under its
+    // own leaf assumption, as if it were in brackets.
+    #[pyo3(signature = (relation, tuple, assume = false))]
+    fn add_fact(&mut self, relation: &str, tuple: Vec<PyValue>, assume: bool) -> PyResult<()> {
+        let tuple: Vec<_> = tuple.iter().map(PyValue::symbol).collect();
+        let assumption = if assume {
+            DNFAssumption::singleton((relation.to_string(), tuple.clone()))
+        } else {
+            DNFAssumption::one()
+        };
+        self.env
+            .add_fact(relation, tuple, assumption)
+            .map_err(|err| PyValueError::new_err(err.to_string()))
+    }
+
+    // Add many facts of a relation at once, like `env.add_facts("E", [(1, 2), (2, 3)])`.
+    fn add_facts(&mut self, relation: &str, tuples: Vec<Vec<PyValue>>) -> PyResult<()> {
+        let facts = tuples.iter().map(|tuple| {
+            let tuple = tuple.iter().map(PyValue::symbol).collect();
+            (tuple, DNFAssumption::one())
+        });
+        self.env
+            .add_facts(relation, facts)
+            .map_err(|err| PyValueError::new_err(err.to_string()))
+    }
+
+    // Answer a question like `? P(x).` against the model of the last program interpreted and the
+    // facts added since.
+    fn ask<'py>(&mut self, py: Python<'py>, question: &str) -> PyResult<Vec<Bound<'py, PyDict>>> {
+        let (_, stmts) = parse_checked(SOURCE, question).map_err(PyValueError::new_err)?;
+        let [StatementAST::Question(body, _, _, _)] = stmts.as_slice() else {
+            return Err(PyValueError::new_err("expected a single question"));
+        };
+        let answers = self
+            .env
+            .ask(body)
+            .map_err(|err| PyValueError::new_err(err.to_string()))?;
+        answers
+            .iter()
+            .map(|(bindings, value)| answer_dict(py, bindings, value))
+            .collect()
+    }
+}
+
+fn answer_dict<'py>(
+    py: Python<'py>,
+    bindings: &Bindings,
+    value: &DNFAssumption,
+) -> PyResult<Bound<'py, PyDict>> {
+    let dict = PyDict::new(py);
+    for (var, symbol) in bindings {
+        // Compound terms are given as strings of how they're written.
+        match resolve(*symbol) {
+            Some(s) => dict.set_item(var, s)?,
+            None if resolve_term(*symbol).is_some() => {
+                dict.set_item(var, Value(*symbol).to_string())?
+            }
+            None => dict.set_item(var, symbol)?,
+        }
+    }
+    dict.set_item("assumption", value.to_string())?;
+    Ok(dict)
+}
+
+#[pymodule]
+fn implog(module: &Bound<'_, PyModule>) -> PyResult<()> {
+    module.add_class::<PyEnvironment>()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn add_facts_and_ask() {
+        Python::initialize();
+        Python::attach(|py| {
+            let mut env = PyEnvironment::new();
+            env.interpret("P(x, y) :- E(x, y), E(y, x).\n? P(x, y).\n")
+                .unwrap();
+            env.add_fact(
+                "E",
+                vec![PyValue::Int(1), PyValue::Str("a".to_string())],
+                false,
+            )
+            .unwrap();
+            env.add_fact(
+                "E",
+                vec![PyValue::Str("a".to_string()), PyValue::Int(1)],
+                true,
+            )
+            .unwrap();
+            let answers = env.ask(py, "? P(1, y).").unwrap();
+            assert_eq!(answers.len(), 1);
+            let y = answers[0].get_item("y").unwrap().unwrap();
+            assert_eq!(y.extract::<String>().unwrap(), "a");
+            let assumption = answers[0].get_item("assumption").unwrap().unwrap();
+            assert_eq!(assumption.extract::<String>().unwrap(), "E(\"a\", 1)");
+            assert!(env.ask(py, "? P(x, y) :- .").is_err());
+        });
+    }
+}
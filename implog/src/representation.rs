@@ -1,12 +1,18 @@
+use std::cmp::Ordering;
 use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+use std::ops::Deref;
+use std::sync::{Arc, LazyLock, RwLock};
+
+use crate::value::Value;
 
 // Arbitrary type used for ground symbols. For now, should implement Copy.
 pub type Symbol = i64;
 pub type GroundTuple = Vec<Symbol>;
 
 // Store assumption values per ground tuple. There are two assumption values, an "old" value and a
-// "new" value. The old value is the accumulated assumption value for this tuple from prior
-// iterations and the new value is the assumption value computed during the current iteration.
+// "new" value. The old value is the accumulated assumption value for this tuple from all prior
+// iterations and the new value is the delta added to it during the most recent iteration.
 pub type Table<A> = BTreeMap<GroundTuple, (A, A)>;
 
 // A "leaf" assumption is the label corresponding to some ground atom in some table. They are created
@@ -21,7 +27,22 @@ pub type LeafAssumption = (String, GroundTuple);
 // - Discharge a leaf assumption from an assumption value.
 // - Calculate a delta value between two assumptions - given assumption values a and b, delta(a, b)
 //   computes some value c such that a + b = a + c.
-pub trait Assumption {
+// - Check whether one assumption value subsumes another - given assumption values a and b,
+//   a.subsumes(b) holds exactly when a + b = a, that is when delta(a, b) is zero. Then a holds
+//   whenever b does, so b entails a.
+// - Prune the parts of an assumption value that entail an inconsistent assumption value (one under
+//   which some integrity constraint is violated).
+// - Calculate the cost of an assumption value: the fewest leaf assumptions it can hold under.
+// - List the leaf assumptions an assumption value mentions.
+// - Measure the size of an assumption value: how many leaf assumptions its formula holds, counting
+//   repeats. Semirings that forget leaf assumptions have values of size 0.
+// - Widen an assumption value to one of at most some size that it entails, so a value holds
+//   whenever it did before, and maybe more often.
+// - Rewrite the leaf assumptions an assumption value mentions, like those of values loaded with
+//   symbols from another process (see `value::Remap`).
+// Assumption values are displayed as formulas, for printing answers, and are shared between the
+// threads of parallel joins.
+pub trait Assumption: Clone + fmt::Display + Send + Sync {
     fn is_zero(&self) -> bool;
     fn zero() -> Self;
     fn one() -> Self;
@@ -30,27 +51,199 @@ pub trait Assumption {
     fn times(&self, other: &Self) -> Self;
     fn discharge(&self, label: LeafAssumption) -> Self;
     fn delta(&self, other: &Self) -> Self;
+    // Semirings can check subsumption without building the delta, which redundant derivations
+    // would otherwise do just to throw it away.
+    fn subsumes(&self, other: &Self) -> bool {
+        self.delta(other).is_zero()
+    }
+    fn prune(&self, inconsistent: &Self) -> Self;
+    fn cost(&self) -> usize;
+    // The cost of the cheapest assumptions a value holds under, where each leaf assumption costs
+    // its weight. Semirings that forget leaf assumptions can't weigh them, so they fall back to
+    // `cost`.
+    fn weighted_cost(&self, _weight: &dyn Fn(&LeafAssumption) -> usize) -> usize {
+        self.cost()
+    }
+    // The probability that a value holds, where leaf assumptions hold independently with their
+    // probabilities. Semirings that forget leaf assumptions treat them as certain.
+    fn probability(&self, _probability: &dyn Fn(&LeafAssumption) -> f64) -> f64 {
+        if self.is_zero() { 0.0 } else { 1.0 }
+    }
+    fn leaves(&self) -> BTreeSet<LeafAssumption>;
+    // The conjunctions of leaf assumptions a value is the sum of. Semirings that forget leaf
+    // assumptions treat non-zero values as holding with no assumptions.
+    fn conjuncts(&self) -> BTreeSet<BTreeSet<LeafAssumption>> {
+        if self.is_zero() {
+            BTreeSet::new()
+        } else {
+            BTreeSet::from([BTreeSet::new()])
+        }
+    }
+    fn size(&self) -> usize;
+    // Semirings that forget leaf assumptions never outgrow a size, so their values stay as they are.
+    fn widen(&self, _size: usize) -> Self {
+        self.clone()
+    }
+    // Semirings that forget leaf assumptions have none to rewrite.
+    fn map_leaves(&self, _map: &dyn Fn(&LeafAssumption) -> LeafAssumption) -> Self {
+        self.clone()
+    }
 }
 
 // NOTE: DNF is not normal w.r.t. simplification modulo the theory of the user-given rules. It is
 // normal w.r.t. the structural properties (ACI).
 #[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct DNFAssumption {
-    pub dnf: BTreeSet<BTreeSet<LeafAssumption>>,
+    pub dnf: BTreeSet<Conjunct>,
+}
+
+// A conjunction of leaf assumptions. Conjunctions are hash-consed: equal conjunctions share one
+// set, so DNFs repeating a conjunction don't each hold a copy of it, and conjunctions are compared
+// by pointer. Like strings (see `value::intern`), conjunctions are interned for every environment,
+// on every thread, and are never freed.
+#[derive(Clone)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(from = "BTreeSet<LeafAssumption>", into = "BTreeSet<LeafAssumption>")
+)]
+pub struct Conjunct(Arc<BTreeSet<LeafAssumption>>);
+
+static CONJUNCTS: RwLock<BTreeSet<Arc<BTreeSet<LeafAssumption>>>> = RwLock::new(BTreeSet::new());
+
+// The empty conjunction, which every value of one holds, is looked up once.
+static EMPTY: LazyLock<Conjunct> = LazyLock::new(|| Conjunct::new(BTreeSet::new()));
+
+impl Conjunct {
+    pub fn new(leaves: BTreeSet<LeafAssumption>) -> Self {
+        if let Some(conj) = CONJUNCTS.read().unwrap().get(&leaves) {
+            return Self(conj.clone());
+        }
+        // Another thread may have interned the conjunction between releasing the read lock and
+        // taking the write lock.
+        let mut conjuncts = CONJUNCTS.write().unwrap();
+        if let Some(conj) = conjuncts.get(&leaves) {
+            return Self(conj.clone());
+        }
+        let conj = Arc::new(leaves);
+        conjuncts.insert(conj.clone());
+        Self(conj)
+    }
+}
+
+impl Deref for Conjunct {
+    type Target = BTreeSet<LeafAssumption>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl PartialEq for Conjunct {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+impl Eq for Conjunct {}
+
+// Conjunctions are ordered by their leaves, not their pointers, so DNFs list them in the same order
+// whatever order they were interned in.
+impl Ord for Conjunct {
+    fn cmp(&self, other: &Self) -> Ordering {
+        if self == other {
+            Ordering::Equal
+        } else {
+            self.0.cmp(&other.0)
+        }
+    }
+}
+
+impl PartialOrd for Conjunct {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl fmt::Debug for Conjunct {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+impl From<BTreeSet<LeafAssumption>> for Conjunct {
+    fn from(leaves: BTreeSet<LeafAssumption>) -> Self {
+        Self::new(leaves)
+    }
+}
+
+impl From<Conjunct> for BTreeSet<LeafAssumption> {
+    fn from(conj: Conjunct) -> Self {
+        (*conj.0).clone()
+    }
 }
 
 impl DNFAssumption {
+    // Remove the conjunctions subsumed by smaller ones. Only those are cloned, rather than the
+    // whole DNF being rebuilt, since most sums and products subsume nothing.
     fn weak_simplify(&mut self) {
-        let mut to_remove = BTreeSet::new();
-        for conj1 in &self.dnf {
-            for conj2 in &self.dnf {
-                if conj1.is_superset(conj2) && conj1.len() != conj2.len() {
-                    to_remove.insert(conj1.clone());
-                }
-            }
+        if self.dnf.len() < 2 {
+            return;
         }
-        self.dnf = self.dnf.difference(&to_remove).cloned().collect();
+        let subsumed: Vec<_> = self
+            .dnf
+            .iter()
+            .filter(|conj1| {
+                self.dnf
+                    .iter()
+                    .any(|conj2| conj1.is_superset(conj2) && conj1.len() != conj2.len())
+            })
+            .cloned()
+            .collect();
+        for conj in &subsumed {
+            self.dnf.remove(conj);
+        }
+    }
+
+    fn is_one(&self) -> bool {
+        self.dnf.len() == 1 && self.dnf.first().unwrap().is_empty()
+    }
+}
+
+fn shannon_expansion(
+    dnf: &BTreeSet<BTreeSet<LeafAssumption>>,
+    probability: &dyn Fn(&LeafAssumption) -> f64,
+    memo: &mut BTreeMap<BTreeSet<BTreeSet<LeafAssumption>>, f64>,
+) -> f64 {
+    let Some(leaf) = dnf.iter().flatten().next() else {
+        return if dnf.is_empty() { 0.0 } else { 1.0 };
+    };
+    if dnf.contains(&BTreeSet::new()) {
+        return 1.0;
+    }
+    if let Some(p) = memo.get(dnf) {
+        return *p;
     }
+    let holds = dnf
+        .iter()
+        .map(|conj| {
+            conj.iter()
+                .filter(|other| *other != leaf)
+                .cloned()
+                .collect()
+        })
+        .collect();
+    let fails = dnf
+        .iter()
+        .filter(|conj| !conj.contains(leaf))
+        .cloned()
+        .collect();
+    let p = probability(leaf);
+    let total = p * shannon_expansion(&holds, probability, memo)
+        + (1.0 - p) * shannon_expansion(&fails, probability, memo);
+    memo.insert(dnf.clone(), total);
+    total
 }
 
 impl Assumption for DNFAssumption {
@@ -66,17 +259,22 @@ impl Assumption for DNFAssumption {
 
     fn one() -> Self {
         Self {
-            dnf: BTreeSet::from([BTreeSet::new()]),
+            dnf: BTreeSet::from([EMPTY.clone()]),
         }
     }
 
     fn singleton(leaf: LeafAssumption) -> Self {
         Self {
-            dnf: BTreeSet::from([BTreeSet::from([leaf])]),
+            dnf: BTreeSet::from([Conjunct::new(BTreeSet::from([leaf]))]),
         }
     }
 
     fn plus(&self, other: &Self) -> Self {
+        if other.is_zero() {
+            return self.clone();
+        } else if self.is_zero() {
+            return other.clone();
+        }
         let mut new = DNFAssumption {
             dnf: self.dnf.union(&other.dnf).cloned().collect(),
         };
@@ -84,12 +282,26 @@ impl Assumption for DNFAssumption {
         new
     }
 
+    // Bodies multiply the values they read into one, and most values are zero or one, so those
+    // products are found without building a DNF.
     fn times(&self, other: &Self) -> Self {
+        if self.is_zero() || other.is_one() {
+            return self.clone();
+        } else if other.is_zero() || self.is_one() {
+            return other.clone();
+        }
         let mut new = Self::zero();
         for self_conj in &self.dnf {
             for other_conj in &other.dnf {
-                new.dnf
-                    .insert(self_conj.union(other_conj).cloned().collect());
+                // Conjunctions holding the other are their own product, which needn't be interned.
+                let conj = if self_conj.is_superset(other_conj) {
+                    self_conj.clone()
+                } else if other_conj.is_superset(self_conj) {
+                    other_conj.clone()
+                } else {
+                    Conjunct::new(self_conj.union(other_conj).cloned().collect())
+                };
+                new.dnf.insert(conj);
             }
         }
         new.weak_simplify();
@@ -99,9 +311,13 @@ impl Assumption for DNFAssumption {
     fn discharge(&self, label: LeafAssumption) -> Self {
         let mut new = Self::zero();
         for self_conj in &self.dnf {
-            let mut new_conj = self_conj.clone();
-            new_conj.remove(&label);
-            new.dnf.insert(new_conj);
+            if self_conj.contains(&label) {
+                let mut new_conj = BTreeSet::clone(self_conj);
+                new_conj.remove(&label);
+                new.dnf.insert(Conjunct::new(new_conj));
+            } else {
+                new.dnf.insert(self_conj.clone());
+            }
         }
         new.weak_simplify();
         new
@@ -110,12 +326,401 @@ impl Assumption for DNFAssumption {
     fn delta(&self, other: &Self) -> Self {
         let mut new = Self::zero();
         for other_conj in &other.dnf {
-            if self.dnf.iter().all(|self_conj| !other_conj.is_superset(self_conj)) {
+            if self
+                .dnf
+                .iter()
+                .all(|self_conj| !other_conj.is_superset(self_conj))
+            {
                 new.dnf.insert(other_conj.clone());
             }
         }
         new
     }
+
+    fn subsumes(&self, other: &Self) -> bool {
+        other.dnf.iter().all(|other_conj| {
+            self.dnf
+                .iter()
+                .any(|self_conj| other_conj.is_superset(self_conj))
+        })
+    }
+
+    fn prune(&self, inconsistent: &Self) -> Self {
+        if inconsistent.is_zero() {
+            return self.clone();
+        }
+        Self {
+            dnf: self
+                .dnf
+                .iter()
+                .filter(|conj| {
+                    !inconsistent
+                        .dnf
+                        .iter()
+                        .any(|nogood| conj.is_superset(nogood))
+                })
+                .cloned()
+                .collect(),
+        }
+    }
+
+    fn cost(&self) -> usize {
+        self.dnf
+            .iter()
+            .map(|conj| conj.len())
+            .min()
+            .unwrap_or(usize::MAX)
+    }
+
+    fn weighted_cost(&self, weight: &dyn Fn(&LeafAssumption) -> usize) -> usize {
+        self.dnf
+            .iter()
+            .map(|conj| conj.iter().map(weight).fold(0, usize::saturating_add))
+            .min()
+            .unwrap_or(usize::MAX)
+    }
+
+    // Conjunctions of a DNF may overlap, so their probabilities can't just be summed. Instead, the
+    // DNF is split on a leaf into the disjoint cases where the leaf holds and where it doesn't
+    // (Shannon expansion), until no leaves are left.
+    fn probability(&self, probability: &dyn Fn(&LeafAssumption) -> f64) -> f64 {
+        shannon_expansion(&self.conjuncts(), probability, &mut BTreeMap::new())
+    }
+
+    fn leaves(&self) -> BTreeSet<LeafAssumption> {
+        self.dnf
+            .iter()
+            .flat_map(|conj| conj.iter())
+            .cloned()
+            .collect()
+    }
+
+    fn conjuncts(&self) -> BTreeSet<BTreeSet<LeafAssumption>> {
+        self.dnf.iter().map(|conj| BTreeSet::clone(conj)).collect()
+    }
+
+    fn size(&self) -> usize {
+        self.dnf.iter().map(|conj| conj.len()).sum()
+    }
+
+    // Every conjunction holds the leaves they all share, so a value entails the conjunction of
+    // those. When even that is too big, the value widens to one.
+    fn widen(&self, size: usize) -> Self {
+        if self.size() <= size {
+            return self.clone();
+        }
+        let mut conjs = self.dnf.iter();
+        let Some(first) = conjs.next() else {
+            return self.clone();
+        };
+        let shared = conjs.fold(BTreeSet::clone(first), |shared, conj| {
+            shared.intersection(conj).cloned().collect()
+        });
+        if shared.len() > size {
+            return Self::one();
+        }
+        Self {
+            dnf: BTreeSet::from([Conjunct::new(shared)]),
+        }
+    }
+
+    fn map_leaves(&self, map: &dyn Fn(&LeafAssumption) -> LeafAssumption) -> Self {
+        let dnf = self
+            .dnf
+            .iter()
+            .map(|conj| Conjunct::new(conj.iter().map(map).collect()))
+            .collect();
+        Self { dnf }
+    }
+}
+
+// A DNF is displayed like `A(1, 2) & B | C`. Zero is `false`, and one is `true`.
+impl fmt::Display for DNFAssumption {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.dnf.is_empty() {
+            return write!(f, "false");
+        }
+        for (idx, conj) in self.dnf.iter().enumerate() {
+            if idx > 0 {
+                write!(f, " | ")?;
+            }
+            if conj.is_empty() {
+                write!(f, "true")?;
+            }
+            for (idx, (relation, tuple)) in conj.iter().enumerate() {
+                if idx > 0 {
+                    write!(f, " & ")?;
+                }
+                write!(f, "{}", relation)?;
+                if !tuple.is_empty() {
+                    let symbols: Vec<_> = tuple
+                        .iter()
+                        .map(|symbol| Value(*symbol).to_string())
+                        .collect();
+                    write!(f, "({})", symbols.join(", "))?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+// The semirings below forget which leaf assumptions a value holds under: a leaf assumption is
+// treated as holding, and discharging it does nothing. Since they can't tell which assumptions are
+// inconsistent, an integrity constraint violated under any assumptions prunes every value.
+
+// Whether a tuple is derivable at all.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct BooleanAssumption(pub bool);
+
+impl Assumption for BooleanAssumption {
+    fn is_zero(&self) -> bool {
+        !self.0
+    }
+
+    fn zero() -> Self {
+        Self(false)
+    }
+
+    fn one() -> Self {
+        Self(true)
+    }
+
+    fn singleton(_leaf: LeafAssumption) -> Self {
+        Self(true)
+    }
+
+    fn plus(&self, other: &Self) -> Self {
+        Self(self.0 || other.0)
+    }
+
+    fn times(&self, other: &Self) -> Self {
+        Self(self.0 && other.0)
+    }
+
+    fn discharge(&self, _label: LeafAssumption) -> Self {
+        *self
+    }
+
+    fn delta(&self, other: &Self) -> Self {
+        Self(other.0 && !self.0)
+    }
+
+    fn subsumes(&self, other: &Self) -> bool {
+        self.0 || !other.0
+    }
+
+    fn prune(&self, inconsistent: &Self) -> Self {
+        Self(self.0 && !inconsistent.0)
+    }
+
+    fn cost(&self) -> usize {
+        if self.0 { 0 } else { usize::MAX }
+    }
+
+    fn leaves(&self) -> BTreeSet<LeafAssumption> {
+        BTreeSet::new()
+    }
+
+    fn size(&self) -> usize {
+        0
+    }
+}
+
+impl fmt::Display for BooleanAssumption {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+// The fewest leaf assumptions a tuple can be derived with (the tropical semiring, where each leaf
+// assumption costs 1). None is the zero value, for tuples that aren't derivable.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct TropicalAssumption(pub Option<usize>);
+
+impl Assumption for TropicalAssumption {
+    fn is_zero(&self) -> bool {
+        self.0.is_none()
+    }
+
+    fn zero() -> Self {
+        Self(None)
+    }
+
+    fn one() -> Self {
+        Self(Some(0))
+    }
+
+    fn singleton(_leaf: LeafAssumption) -> Self {
+        Self(Some(1))
+    }
+
+    fn plus(&self, other: &Self) -> Self {
+        match (self.0, other.0) {
+            (Some(a), Some(b)) => Self(Some(a.min(b))),
+            (a, b) => Self(a.or(b)),
+        }
+    }
+
+    fn times(&self, other: &Self) -> Self {
+        Self(self.0.zip(other.0).map(|(a, b)| a.saturating_add(b)))
+    }
+
+    fn discharge(&self, _label: LeafAssumption) -> Self {
+        *self
+    }
+
+    fn delta(&self, other: &Self) -> Self {
+        match (self.0, other.0) {
+            (Some(a), Some(b)) if b >= a => Self(None),
+            _ => *other,
+        }
+    }
+
+    fn subsumes(&self, other: &Self) -> bool {
+        match (self.0, other.0) {
+            (Some(a), Some(b)) => a <= b,
+            (_, b) => b.is_none(),
+        }
+    }
+
+    fn prune(&self, inconsistent: &Self) -> Self {
+        if inconsistent.is_zero() {
+            *self
+        } else {
+            Self(None)
+        }
+    }
+
+    fn cost(&self) -> usize {
+        self.0.unwrap_or(usize::MAX)
+    }
+
+    fn leaves(&self) -> BTreeSet<LeafAssumption> {
+        BTreeSet::new()
+    }
+
+    fn size(&self) -> usize {
+        0
+    }
+}
+
+// Costs are displayed as numbers, and tuples that aren't derivable have an infinite cost.
+impl fmt::Display for TropicalAssumption {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.0 {
+            Some(cost) => write!(f, "{}", cost),
+            None => write!(f, "inf"),
+        }
+    }
+}
+
+// The k cheapest conjunctions of a DNF, where conjunctions of fewer leaf assumptions are cheaper
+// (ties are broken by the leaves, so values don't depend on the order tuples are derived in). Each
+// tuple keeps a few representative justifications, rather than every way it can hold, so values
+// stay small however many derivations a tuple has. Values hold under fewer assumptions than their
+// DNFs do, so questions may miss answers whose cheap conjunctions are all pruned as inconsistent.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct TopKAssumption<const K: usize>(pub DNFAssumption);
+
+impl<const K: usize> TopKAssumption<K> {
+    fn top(mut value: DNFAssumption) -> Self {
+        if value.dnf.len() > K {
+            let mut conjs: Vec<_> = value.dnf.into_iter().collect();
+            conjs.sort_by(|a, b| a.len().cmp(&b.len()).then_with(|| a.cmp(b)));
+            conjs.truncate(K);
+            value.dnf = conjs.into_iter().collect();
+        }
+        Self(value)
+    }
+}
+
+impl<const K: usize> Assumption for TopKAssumption<K> {
+    fn is_zero(&self) -> bool {
+        self.0.is_zero()
+    }
+
+    fn zero() -> Self {
+        Self(DNFAssumption::zero())
+    }
+
+    fn one() -> Self {
+        Self(DNFAssumption::one())
+    }
+
+    fn singleton(leaf: LeafAssumption) -> Self {
+        Self(DNFAssumption::singleton(leaf))
+    }
+
+    fn plus(&self, other: &Self) -> Self {
+        Self::top(self.0.plus(&other.0))
+    }
+
+    fn times(&self, other: &Self) -> Self {
+        Self::top(self.0.times(&other.0))
+    }
+
+    fn discharge(&self, label: LeafAssumption) -> Self {
+        Self::top(self.0.discharge(label))
+    }
+
+    // Conjunctions too expensive to be kept by the sum aren't new.
+    fn delta(&self, other: &Self) -> Self {
+        if self.subsumes(other) {
+            Self::zero()
+        } else {
+            Self::top(self.0.delta(&other.0))
+        }
+    }
+
+    fn subsumes(&self, other: &Self) -> bool {
+        self.0.subsumes(&other.0) || self.plus(other) == *self
+    }
+
+    fn prune(&self, inconsistent: &Self) -> Self {
+        Self(self.0.prune(&inconsistent.0))
+    }
+
+    fn cost(&self) -> usize {
+        self.0.cost()
+    }
+
+    fn weighted_cost(&self, weight: &dyn Fn(&LeafAssumption) -> usize) -> usize {
+        self.0.weighted_cost(weight)
+    }
+
+    fn probability(&self, probability: &dyn Fn(&LeafAssumption) -> f64) -> f64 {
+        self.0.probability(probability)
+    }
+
+    fn leaves(&self) -> BTreeSet<LeafAssumption> {
+        self.0.leaves()
+    }
+
+    fn conjuncts(&self) -> BTreeSet<BTreeSet<LeafAssumption>> {
+        self.0.conjuncts()
+    }
+
+    fn size(&self) -> usize {
+        self.0.size()
+    }
+
+    fn widen(&self, size: usize) -> Self {
+        Self(self.0.widen(size))
+    }
+
+    fn map_leaves(&self, map: &dyn Fn(&LeafAssumption) -> LeafAssumption) -> Self {
+        Self(self.0.map_leaves(map))
+    }
+}
+
+impl<const K: usize> fmt::Display for TopKAssumption<K> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.fmt(f)
+    }
 }
 
 #[cfg(test)]
@@ -141,6 +746,72 @@ mod tests {
         assert_eq!(a, a_prime);
     }
 
+    #[test]
+    fn hash_cons_conjuncts() {
+        let leaf_a = ("A".to_string(), vec![1]);
+        let leaf_b = ("B".to_string(), vec![]);
+        let ab = BTreeSet::from([leaf_a.clone(), leaf_b.clone()]);
+        assert!(Arc::ptr_eq(
+            &Conjunct::new(ab.clone()).0,
+            &Conjunct::new(ab.clone()).0
+        ));
+
+        // Products built in either order share their conjunction.
+        let a = DNFAssumption::singleton(leaf_a.clone());
+        let b = DNFAssumption::singleton(leaf_b.clone());
+        let (left, right) = (a.times(&b), b.times(&a));
+        assert!(Arc::ptr_eq(
+            &left.dnf.first().unwrap().0,
+            &right.dnf.first().unwrap().0
+        ));
+        assert_eq!(*left.dnf.first().unwrap().deref(), ab);
+
+        // Conjunctions are ordered by their leaves, whatever order they were interned in.
+        let b_or_a = b.plus(&a);
+        let conjs: Vec<_> = b_or_a
+            .dnf
+            .iter()
+            .map(|conj| BTreeSet::clone(conj))
+            .collect();
+        assert_eq!(
+            conjs,
+            vec![BTreeSet::from([leaf_a]), BTreeSet::from([leaf_b])]
+        );
+    }
+
+    #[test]
+    fn dnf_widen() {
+        let leaf = |name: &str| (name.to_string(), vec![]);
+        let [a, b, c] = ["A", "B", "C"].map(|name| DNFAssumption::singleton(leaf(name)));
+        let value = a.times(&b).plus(&a.times(&c));
+        assert_eq!(value.widen(4), value);
+        assert_eq!(value.widen(3), a);
+        assert!(a.subsumes(&value));
+        assert_eq!(value.widen(0), DNFAssumption::one());
+        assert_eq!(b.plus(&c).widen(1), DNFAssumption::one());
+        assert_eq!(DNFAssumption::zero().widen(0), DNFAssumption::zero());
+    }
+
+    #[test]
+    fn dnf_probability() {
+        let a = DNFAssumption::singleton(("A".to_string(), vec![]));
+        let b = DNFAssumption::singleton(("B".to_string(), vec![]));
+        let c = DNFAssumption::singleton(("C".to_string(), vec![]));
+        let probability = |leaf: &LeafAssumption| match leaf.0.as_str() {
+            "A" => 0.5,
+            "B" => 0.2,
+            _ => 0.1,
+        };
+
+        // A or (B and C), where the conjunctions can hold together.
+        let value = a.plus(&b.times(&c));
+        assert!((value.probability(&probability) - (0.5 + 0.5 * 0.02)).abs() < 1e-9);
+        // A or (A and B) is just A.
+        assert_eq!(a.plus(&a.times(&b)).probability(&probability), 0.5);
+        assert_eq!(DNFAssumption::zero().probability(&probability), 0.0);
+        assert_eq!(DNFAssumption::one().probability(&probability), 1.0);
+    }
+
     #[test]
     fn dnf_plus_times() {
         let leaf_a = ("A".to_string(), vec![]);
@@ -200,4 +871,95 @@ mod tests {
         assert_eq!(a.delta(&ab), zero);
         assert_eq!(ab.delta(&ab), zero);
     }
+
+    #[test]
+    fn dnf_subsumes() {
+        let leaf_a = ("A".to_string(), vec![]);
+        let leaf_b = ("B".to_string(), vec![]);
+        let leaf_c = ("C".to_string(), vec![]);
+
+        let zero = DNFAssumption::zero();
+        let one = DNFAssumption::one();
+        let a = DNFAssumption::singleton(leaf_a);
+        let b = DNFAssumption::singleton(leaf_b);
+        let c = DNFAssumption::singleton(leaf_c);
+        let ab = a.times(&b);
+
+        assert!(a.subsumes(&ab));
+        assert!(!ab.subsumes(&a));
+        assert!(a.plus(&c).subsumes(&ab.plus(&c)));
+        assert!(!a.subsumes(&ab.plus(&c)));
+        assert!(one.subsumes(&a));
+        assert!(a.subsumes(&zero));
+        assert!(!zero.subsumes(&one));
+        for (x, y) in [(&a, &ab), (&ab, &a), (&a, &c), (&one, &zero), (&zero, &ab)] {
+            assert_eq!(x.subsumes(y), x.delta(y).is_zero());
+        }
+    }
+
+    #[test]
+    fn dnf_prune() {
+        let leaf_a = ("A".to_string(), vec![]);
+        let leaf_b = ("B".to_string(), vec![]);
+        let leaf_c = ("C".to_string(), vec![]);
+
+        let zero = DNFAssumption::zero();
+        let one = DNFAssumption::one();
+        let a = DNFAssumption::singleton(leaf_a);
+        let b = DNFAssumption::singleton(leaf_b);
+        let c = DNFAssumption::singleton(leaf_c);
+        let ab = a.times(&b);
+
+        assert_eq!(ab.plus(&c).prune(&ab), c);
+        assert_eq!(ab.prune(&a), zero);
+        assert_eq!(a.prune(&ab), a);
+        assert_eq!(a.prune(&zero), a);
+        assert_eq!(a.plus(&c).prune(&one), zero);
+    }
+
+    #[test]
+    fn boolean_tropical() {
+        let leaf = ("A".to_string(), vec![]);
+        let yes = BooleanAssumption::one();
+        assert_eq!(yes.times(&BooleanAssumption::singleton(leaf.clone())), yes);
+        assert_eq!(BooleanAssumption::zero().delta(&yes), yes);
+        assert!(yes.delta(&yes).is_zero());
+        assert!(yes.subsumes(&yes));
+        assert!(!BooleanAssumption::zero().subsumes(&yes));
+
+        let one = TropicalAssumption::one();
+        let two = TropicalAssumption::singleton(leaf.clone()).times(&TropicalAssumption(Some(1)));
+        assert_eq!(two, TropicalAssumption(Some(2)));
+        assert_eq!(two.plus(&one), one);
+        assert_eq!(two.plus(&TropicalAssumption::zero()), two);
+        assert_eq!(two.delta(&one), one);
+        assert!(one.delta(&two).is_zero());
+        assert!(one.subsumes(&two));
+        assert!(!two.subsumes(&one));
+        assert!(two.subsumes(&TropicalAssumption::zero()));
+        assert_eq!(two.discharge(leaf), two);
+    }
+
+    #[test]
+    fn top_k() {
+        let leaf = |name: &str| (name.to_string(), vec![]);
+        let [a, b, c, d] =
+            ["A", "B", "C", "D"].map(|name| TopKAssumption::<2>::singleton(leaf(name)));
+
+        // B & C is dropped as the most expensive, whatever order the conjunctions are added in.
+        let value = b.times(&c).plus(&d).plus(&a);
+        assert_eq!(value, a.plus(&d).plus(&b.times(&c)));
+        assert_eq!(
+            value.0,
+            DNFAssumption::singleton(leaf("A")).plus(&DNFAssumption::singleton(leaf("D")))
+        );
+        assert_eq!(value.to_string(), "A | D");
+
+        // Dropped conjunctions aren't new, but cheaper ones are.
+        assert!(value.subsumes(&b.times(&c)));
+        assert!(value.delta(&b.times(&c)).is_zero());
+        assert!(!value.subsumes(&TopKAssumption::one()));
+        assert_eq!(value.plus(&TopKAssumption::one()), TopKAssumption::one());
+        assert_eq!(a.times(&b).discharge(leaf("A")), b);
+    }
 }
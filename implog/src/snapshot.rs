@@ -0,0 +1,127 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::sync::{Arc, RwLock};
+
+use crate::representation::{Assumption, GroundTuple, Table};
+
+// Read-only snapshots of an environment's model, for answering lookups from other threads while
+// the environment evaluates a new batch of facts. Each published model is an immutable snapshot
+// with a version, counted from 1 (version 0 is the empty model, before anything is published).
+// Readers only hold the lock long enough to clone a pointer to the current snapshot, so lookups
+// never wait on evaluation, and a snapshot held by a reader stays consistent as newer ones are
+// published. Each relation's table is held behind its own pointer, so publishing only copies the
+// tables that changed since the last snapshot, and shares the others with it.
+
+#[derive(Debug)]
+pub struct Snapshot<A: Assumption> {
+    version: u64,
+    // The full value of every tuple with a non-zero value.
+    tables: BTreeMap<String, Arc<BTreeMap<GroundTuple, A>>>,
+}
+
+impl<A: Assumption> Snapshot<A> {
+    pub fn version(&self) -> u64 {
+        self.version
+    }
+
+    // The assumption value under which a tuple holds, if it holds under some assumptions.
+    pub fn holds(&self, relation: &str, tuple: &GroundTuple) -> Option<&A> {
+        self.tables.get(relation)?.get(tuple)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct QueryHandle<A: Assumption> {
+    current: Arc<RwLock<Arc<Snapshot<A>>>>,
+}
+
+impl<A: Assumption> QueryHandle<A> {
+    pub(crate) fn new() -> Self {
+        let empty = Snapshot {
+            version: 0,
+            tables: BTreeMap::new(),
+        };
+        Self {
+            current: Arc::new(RwLock::new(Arc::new(empty))),
+        }
+    }
+
+    // Publish a model to every handle, replacing the current snapshot. Only the tables of the
+    // relations changed since the current snapshot was published are copied.
+    pub(crate) fn publish(&self, tables: &BTreeMap<String, Table<A>>, changed: &BTreeSet<String>) {
+        let last = self.snapshot();
+        let tables = tables
+            .iter()
+            .map(|(relation, table)| {
+                if !changed.contains(relation)
+                    && let Some(shared) = last.tables.get(relation)
+                {
+                    return (relation.clone(), shared.clone());
+                }
+                let table = table
+                    .iter()
+                    .filter(|(_, (value, _))| !value.is_zero())
+                    .map(|(tuple, (value, _))| (tuple.clone(), value.clone()))
+                    .collect();
+                (relation.clone(), Arc::new(table))
+            })
+            .collect();
+        let mut current = self.current.write().unwrap();
+        let version = current.version + 1;
+        *current = Arc::new(Snapshot { version, tables });
+    }
+
+    // The last snapshot published. Lookups against one snapshot are consistent with each other.
+    pub fn snapshot(&self) -> Arc<Snapshot<A>> {
+        self.current.read().unwrap().clone()
+    }
+
+    pub fn version(&self) -> u64 {
+        self.snapshot().version()
+    }
+
+    // Look up a tuple in the last snapshot published.
+    pub fn holds(&self, relation: &str, tuple: &GroundTuple) -> Option<A> {
+        self.snapshot().holds(relation, tuple).cloned()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+
+    use crate::grammar::ProgramParser;
+    use crate::interpret::Environment;
+    use crate::representation::DNFAssumption;
+
+    use super::*;
+
+    #[test]
+    fn read_snapshots_from_other_threads() {
+        let program = "E(1, 2) :- .\nP(x, y) :- E(x, y).\nP(x, z) :- P(x, y), E(y, z).\n";
+        let parsed = ProgramParser::new().parse(program).unwrap();
+        let mut env = Environment::<DNFAssumption>::new();
+        env.interpret(&parsed).unwrap();
+        let handle = env.query_handle();
+        assert_eq!(handle.version(), 1);
+        assert_eq!(handle.holds("P", &vec![1, 2]), Some(DNFAssumption::one()));
+
+        // Asking again changes no table, so the new snapshot shares every table with the last.
+        let question = ProgramParser::new().parse("? P(x, y).").unwrap();
+        env.ask(question[0].body()).unwrap();
+        let first = handle.snapshot();
+        env.ask(question[0].body()).unwrap();
+        let before = handle.snapshot();
+        assert_eq!(before.version(), 3);
+        assert!(Arc::ptr_eq(&first.tables["P"], &before.tables["P"]));
+        env.add_fact("E", vec![2, 3], DNFAssumption::one()).unwrap();
+        env.ask(question[0].body()).unwrap();
+
+        let reader = handle.clone();
+        let holds = thread::spawn(move || reader.holds("P", &vec![1, 3]))
+            .join()
+            .unwrap();
+        assert_eq!(holds, Some(DNFAssumption::one()));
+        assert_eq!(handle.version(), 4);
+        assert_eq!(before.holds("P", &vec![1, 3]), None);
+    }
+}
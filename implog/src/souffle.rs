@@ -0,0 +1,248 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use crate::ast::{AtomAST, LiteralAST, StatementAST, TermAST};
+use crate::representation::GroundTuple;
+
+// A useful subset of Soufflé's syntax: `.decl` declarations with typed columns, `.input` and
+// `.output` directives, dot-terminated facts, and rules whose bodies are conjunctions of positive
+// literals. Programs in this syntax are lowered to the usual statements.
+#[derive(Debug, Clone)]
+pub enum SouffleStatement {
+    Decl(String, Vec<(String, String)>),
+    Input(String),
+    Output(String),
+    Clause(LiteralAST, Vec<LiteralAST>),
+}
+
+// Numeric columns, and `symbol` columns holding strings, are supported.
+const TYPES: [&str; 3] = ["number", "unsigned", "symbol"];
+
+// Facts written inline for input relations, which hold alongside the facts loaded from their fact
+// files.
+pub type InputFacts = Vec<(String, GroundTuple)>;
+
+// Lower a program to statements. In Soufflé, input relations can also have facts written in the
+// program, but input relations can't be derived by statements, so those facts are returned apart,
+// to be added to an environment as facts before interpreting the statements.
+pub fn lower(stmts: &[SouffleStatement]) -> Result<(Vec<StatementAST>, InputFacts), String> {
+    let mut arities: BTreeMap<&str, usize> = BTreeMap::new();
+    for stmt in stmts {
+        if let SouffleStatement::Decl(relation, columns) = stmt {
+            if let Some((_, ty)) = columns.iter().find(|(_, ty)| !TYPES.contains(&ty.as_str())) {
+                return Err(format!(
+                    "unsupported type {} in declaration of {}",
+                    ty, relation
+                ));
+            }
+            if arities.insert(relation, columns.len()).is_some() {
+                return Err(format!("relation {} is declared more than once", relation));
+            }
+        }
+    }
+
+    let arity = |relation: &str| {
+        arities
+            .get(relation)
+            .copied()
+            .ok_or_else(|| format!("relation {} is not declared", relation))
+    };
+    let check_lit = |lit: &LiteralAST| {
+        let arity = arity(&lit.relation)?;
+        if arity != lit.terms.len() {
+            return Err(format!(
+                "relation {} has arity {}, but is used with {} terms",
+                lit.relation,
+                arity,
+                lit.terms.len()
+            ));
+        }
+        Ok(())
+    };
+
+    let inputs: BTreeSet<&str> = stmts
+        .iter()
+        .filter_map(|stmt| match stmt {
+            SouffleStatement::Input(relation) => Some(relation.as_str()),
+            _ => None,
+        })
+        .collect();
+
+    let mut lowered = vec![];
+    let mut facts = vec![];
+    for stmt in stmts {
+        match stmt {
+            SouffleStatement::Decl(_, _) => {}
+            SouffleStatement::Input(relation) => {
+                lowered.push(StatementAST::Input(relation.clone(), arity(relation)?));
+            }
+            SouffleStatement::Output(relation) => {
+                arity(relation)?;
+                lowered.push(StatementAST::Output(relation.clone()));
+            }
+            SouffleStatement::Clause(head, body) => {
+                check_lit(head)?;
+                for lit in body {
+                    check_lit(lit)?;
+                }
+                if inputs.contains(head.relation.as_str()) {
+                    let tuple = head.terms.iter().map(|term| match term {
+                        TermAST::Constant(symbol) if body.is_empty() => Some(*symbol),
+                        _ => None,
+                    });
+                    let Some(tuple) = tuple.collect() else {
+                        return Err(format!(
+                            "input relation {} can only have ground facts",
+                            head.relation
+                        ));
+                    };
+                    facts.push((head.relation.clone(), tuple));
+                    continue;
+                }
+                let mut fresh = Fresh::new(head, body);
+                let head = AtomAST::Literal(fresh.rename_wildcards(head));
+                let body = body
+                    .iter()
+                    .map(|lit| AtomAST::Literal(fresh.rename_wildcards(lit)))
+                    .collect();
+                lowered.push(StatementAST::Rule(head, body));
+            }
+        }
+    }
+    Ok((lowered, facts))
+}
+
+// Each `_` in a clause is a distinct variable, so it's renamed to a variable not used elsewhere in
+// the clause.
+struct Fresh {
+    used: BTreeSet<String>,
+    next: usize,
+}
+
+impl Fresh {
+    fn new(head: &LiteralAST, body: &[LiteralAST]) -> Self {
+        let used = head
+            .vars()
+            .chain(body.iter().flat_map(LiteralAST::vars))
+            .map(str::to_string)
+            .collect();
+        Self { used, next: 0 }
+    }
+
+    fn rename_wildcards(&mut self, lit: &LiteralAST) -> LiteralAST {
+        let terms = lit
+            .terms
+            .iter()
+            .map(|term| match term {
+                TermAST::Variable(var) if var == "_" => TermAST::Variable(self.fresh()),
+                _ => term.clone(),
+            })
+            .collect();
+        LiteralAST {
+            relation: lit.relation.clone(),
+            terms,
+            span: lit.span,
+            term_spans: lit.term_spans.clone(),
+        }
+    }
+
+    fn fresh(&mut self) -> String {
+        loop {
+            let var = format!("_{}", self.next);
+            self.next += 1;
+            if !self.used.contains(&var) {
+                return var;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::ast::check;
+    use crate::interpret::Environment;
+    use crate::representation::{Assumption, DNFAssumption};
+    use crate::souffle_grammar::ProgramParser;
+
+    use super::*;
+
+    fn parse_and_lower(program: &str) -> Result<Vec<StatementAST>, String> {
+        lower(&ProgramParser::new().parse(program).unwrap()).map(|(lowered, _)| lowered)
+    }
+
+    #[test]
+    fn lower_path() {
+        let program = r#"
+// Transitive closure.
+.decl Edge(x: number, y: number)
+.decl Path(x: number, y: number)
+.input Edge
+.output Path
+
+/* Facts and rules. */
+Edge(1, -2).
+Path(x, y) :- Edge(x, y).
+Path(x, z) :- Edge(x, y), Path(y, z).
+"#;
+        let parsed = ProgramParser::new().parse(program).unwrap();
+        let (lowered, facts) = lower(&parsed).unwrap();
+        assert_eq!(lowered.len(), 4);
+        assert_eq!(facts, vec![("Edge".to_string(), vec![1, -2])]);
+        assert!(matches!(&lowered[0], StatementAST::Input(relation, 2) if relation == "Edge"));
+        assert!(matches!(&lowered[1], StatementAST::Output(relation) if relation == "Path"));
+        assert!(lowered.iter().all(check));
+    }
+
+    #[test]
+    fn lower_wildcards() {
+        let program = r#"
+.decl E(x: number, y: number)
+.decl N(x: number)
+N(x) :- E(x, _), E(_, x).
+"#;
+        let lowered = parse_and_lower(program).unwrap();
+        let vars: BTreeSet<&str> = lowered[0].body().iter().flat_map(AtomAST::vars).collect();
+        assert_eq!(vars, BTreeSet::from(["x", "_0", "_1"]));
+    }
+
+    #[test]
+    fn lower_errors() {
+        assert!(parse_and_lower(".decl E(x: float)").is_err());
+        assert!(parse_and_lower(".decl E(x: number)\nE(1, 2).").is_err());
+        assert!(parse_and_lower("E(1).").is_err());
+        assert!(parse_and_lower(".input E").is_err());
+        assert!(parse_and_lower(".decl E(x: number)\n.input E\nE(x) :- E(x).").is_err());
+        assert!(
+            ProgramParser::new()
+                .parse(".decl E(x: number)\nE(99999999999999999999).")
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn interpret_souffle() {
+        let dir = std::env::temp_dir().join(format!("implog-souffle-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("Edge.facts"), "1\t2\n2\t3\n").unwrap();
+        let program = r#"
+.decl Edge(x: number, y: number)
+.decl Path(x: number, y: number)
+.input Edge
+.output Path
+Edge(3, 4).
+Path(x, y) :- Edge(x, y).
+Path(x, z) :- Edge(x, y), Path(y, z).
+"#;
+        let (lowered, facts) = lower(&ProgramParser::new().parse(program).unwrap()).unwrap();
+        let mut env = Environment::<DNFAssumption>::new();
+        env.set_facts_dir(&dir);
+        env.set_output_dir(&dir);
+        for (relation, tuple) in facts {
+            env.add_fact(&relation, tuple, DNFAssumption::one())
+                .unwrap();
+        }
+        env.interpret(&lowered).unwrap();
+        let dumped = std::fs::read_to_string(dir.join("Path.csv")).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(dumped, "1\t2\n1\t3\n1\t4\n2\t3\n2\t4\n3\t4\n");
+    }
+}
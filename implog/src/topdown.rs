@@ -0,0 +1,279 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use crate::ast::{AtomAST, BuiltinAST, LiteralAST, existential_vars};
+use crate::builtin::{CallBuiltin, NativeBuiltin};
+use crate::interpret::{
+    Bindings, body_assumption, derive_head, pattern, read_literal, unify, unify_term,
+};
+use crate::representation::{Assumption, GroundTuple, Symbol, Table};
+
+// Top-down evaluation with tabling. Questions are answered by resolving their atoms left to right
+// against the rules defining each relation, so only the tuples reachable from the question's calls
+// are derived. Each call (a relation, and the symbols its positions are bound to) has a memo table
+// holding the answers found for it so far. A call that's already being solved reads its memo table
+// instead of recursing, and evaluation is repeated until no memo table changes, so recursive
+// relations terminate and reach the same assumption values as bottom-up evaluation.
+//
+// Memo tables are ordinary tables, but only the full values are used.
+
+type Call = (String, Vec<Option<Symbol>>);
+
+pub struct TopDown<'a, A: Assumption> {
+    rules: BTreeMap<&'a str, Vec<(&'a AtomAST, &'a Vec<AtomAST>)>>,
+    builtins: BTreeMap<&'a str, Vec<&'a BuiltinAST>>,
+    inputs: &'a BTreeMap<String, Vec<GroundTuple>>,
+    natives: &'a BTreeMap<String, NativeBuiltin>,
+    memo: BTreeMap<Call, Table<A>>,
+    inconsistent: A,
+    // Calls solved in the current pass over the question.
+    solved: BTreeSet<Call>,
+    changed: bool,
+}
+
+impl<'a, A: Assumption> TopDown<'a, A> {
+    pub fn new(
+        rules: &[(&'a AtomAST, &'a Vec<AtomAST>)],
+        builtins: &'a [BuiltinAST],
+        inputs: &'a BTreeMap<String, Vec<GroundTuple>>,
+        natives: &'a BTreeMap<String, NativeBuiltin>,
+    ) -> Self {
+        let mut by_head: BTreeMap<&str, Vec<_>> = BTreeMap::new();
+        for (head, body) in rules {
+            let (AtomAST::Literal(lit) | AtomAST::Brackets(lit) | AtomAST::Arrow(_, lit)) = *head;
+            by_head
+                .entry(&lit.relation)
+                .or_default()
+                .push((*head, *body));
+        }
+        let mut by_relation: BTreeMap<&str, Vec<_>> = BTreeMap::new();
+        for builtin in builtins {
+            by_relation
+                .entry(&builtin.relation)
+                .or_default()
+                .push(builtin);
+        }
+        Self {
+            rules: by_head,
+            builtins: by_relation,
+            inputs,
+            natives,
+            memo: BTreeMap::new(),
+            inconsistent: A::zero(),
+            solved: BTreeSet::new(),
+            changed: false,
+        }
+    }
+
+    // Answer a question. Integrity constraints are solved alongside the question, so conjuncts
+    // they rule out are pruned.
+    pub fn answer(
+        &mut self,
+        question: &[AtomAST],
+        constraints: &[&Vec<AtomAST>],
+    ) -> Vec<(Bindings, A)> {
+        loop {
+            self.changed = false;
+            self.solved.clear();
+
+            let mut inconsistent = self.inconsistent.clone();
+            for body in constraints {
+                for (_, value) in self.query(body, Bindings::new()) {
+                    inconsistent = inconsistent.plus(&value);
+                }
+            }
+            if !self.inconsistent.delta(&inconsistent).is_zero() {
+                self.inconsistent = inconsistent;
+                for table in self.memo.values_mut() {
+                    for (value, _) in table.values_mut() {
+                        *value = value.prune(&self.inconsistent);
+                    }
+                }
+                self.changed = true;
+            }
+
+            let answers = self.query(question, Bindings::new());
+            if !self.changed {
+                return answers;
+            }
+        }
+    }
+
+    fn query(&mut self, query: &[AtomAST], bindings: Bindings) -> Vec<(Bindings, A)> {
+        let mut answers = vec![];
+        let mut values = vec![];
+        self.query_helper(query, 0, bindings, &mut values, &mut answers);
+        answers
+    }
+
+    fn query_helper(
+        &mut self,
+        query: &[AtomAST],
+        idx: usize,
+        bindings: Bindings,
+        values: &mut Vec<A>,
+        answers: &mut Vec<(Bindings, A)>,
+    ) {
+        if idx == query.len() {
+            let assumption = body_assumption(query, &bindings, values, &self.inconsistent);
+            if !assumption.is_zero() {
+                answers.push((bindings, assumption));
+            }
+            return;
+        }
+
+        if let AtomAST::Literal(lit) = &query[idx]
+            && let Some(builtin) = CallBuiltin::of(lit)
+        {
+            for bindings in builtin.solve(&lit.terms, &bindings) {
+                values.push(A::one());
+                self.query_helper(query, idx + 1, bindings, values, answers);
+                values.pop();
+            }
+            return;
+        }
+        let Some(lit) = read_literal(&query[idx]) else {
+            // Brackets don't read tables, they're handled once all variables are bound.
+            return self.query_helper(query, idx + 1, bindings, values, answers);
+        };
+        let tuples: Vec<_> = self
+            .solve(&call(lit, &bindings))
+            .iter()
+            .filter(|(_, (value, _))| !value.is_zero())
+            .map(|(tuple, (value, _))| (tuple.clone(), value.clone()))
+            .collect();
+        for (tuple, value) in tuples {
+            let Some(bindings) = unify(lit, &tuple, &bindings) else {
+                continue;
+            };
+            values.push(value);
+            self.query_helper(query, idx + 1, bindings, values, answers);
+            values.pop();
+        }
+    }
+
+    // Solve a call from the input facts, rules, and built-ins defining its relation, at most once
+    // per pass. Returns the answers found for the call so far.
+    fn solve(&mut self, call: &Call) -> &Table<A> {
+        if !self.solved.insert(call.clone()) {
+            return &self.memo[call];
+        }
+        self.memo.entry(call.clone()).or_default();
+
+        let relation = call.0.as_str();
+        let mut found = vec![];
+        for tuple in self.inputs.get(relation).into_iter().flatten() {
+            if matches(&call.1, tuple) {
+                found.push((tuple.clone(), A::one()));
+            }
+        }
+        // Built-in relations are called with the call's pattern.
+        if let Some(native) = self.natives.get(relation) {
+            for tuple in native.call(relation, &call.1) {
+                found.push((tuple, A::one()));
+            }
+        }
+        for (head, body) in self.rules.get(relation).cloned().into_iter().flatten() {
+            let (AtomAST::Literal(lit) | AtomAST::Brackets(lit) | AtomAST::Arrow(_, lit)) = head;
+            let Some(mut bindings) = bind(lit, &call.1) else {
+                continue;
+            };
+            // Existential variables are bound to skolem terms by the head, not by the call, so
+            // their tuples are only kept if they match it.
+            for var in existential_vars(head, body) {
+                bindings.remove(var);
+            }
+            for (bindings, value) in self.query(body, bindings) {
+                if let Some((_, tuple, value)) = derive_head(head, &bindings, value)
+                    && matches(&call.1, &tuple)
+                {
+                    found.push((tuple, value));
+                }
+            }
+        }
+        for builtin in self.builtins.get(relation).cloned().into_iter().flatten() {
+            // Built-ins compute whole relations, so their source is solved with no bindings.
+            let (source_arity, _) = builtin.kind.arities();
+            let source = self.solve(&(builtin.source.clone(), vec![None; source_arity]));
+            for (tuple, value) in builtin.kind.evaluate(source) {
+                if matches(&call.1, &tuple) {
+                    found.push((tuple, value));
+                }
+            }
+        }
+
+        let table = self.memo.get_mut(call).unwrap();
+        for (tuple, value) in found {
+            let (old, _) = table.entry(tuple).or_insert_with(|| (A::zero(), A::zero()));
+            let delta = old.delta(&value);
+            if !delta.is_zero() {
+                *old = old.plus(&delta);
+                self.changed = true;
+            }
+        }
+        &self.memo[call]
+    }
+}
+
+// The call made by a literal under some bindings.
+fn call(lit: &LiteralAST, bindings: &Bindings) -> Call {
+    (lit.relation.clone(), pattern(lit, bindings))
+}
+
+fn matches(pattern: &[Option<Symbol>], tuple: &GroundTuple) -> bool {
+    pattern
+        .iter()
+        .zip(tuple)
+        .all(|(bound, symbol)| bound.is_none_or(|bound| bound == *symbol))
+}
+
+// Bind the variables of a rule's head to the symbols bound by a call, if the head can answer it.
+fn bind(head: &LiteralAST, pattern: &[Option<Symbol>]) -> Option<Bindings> {
+    let mut bindings = Bindings::new();
+    for (term, bound) in head.terms.iter().zip(pattern) {
+        let Some(symbol) = bound else {
+            continue;
+        };
+        if !unify_term(term, *symbol, &mut bindings) {
+            return None;
+        }
+    }
+    Some(bindings)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::ast::StatementAST;
+    use crate::grammar::ProgramParser;
+    use crate::representation::DNFAssumption;
+
+    use super::*;
+
+    #[test]
+    fn top_down_only_solves_reachable_calls() {
+        let program = r#"
+E(1, 2) :- .
+E(2, 3) :- .
+E(5, 6) :- .
+P(x, y) :- E(x, y).
+P(x, z) :- E(x, y), P(y, z).
+? P(1, z).
+"#;
+        let parsed = ProgramParser::new().parse(program).unwrap();
+        let rules: Vec<_> = parsed[0..5]
+            .iter()
+            .map(|stmt| (stmt.head().unwrap(), stmt.body()))
+            .collect();
+        let StatementAST::Question(question, _, _, _) = &parsed[5] else {
+            panic!()
+        };
+        let (inputs, natives) = (BTreeMap::new(), BTreeMap::new());
+        let mut top_down = TopDown::<DNFAssumption>::new(&rules, &[], &inputs, &natives);
+        assert_eq!(top_down.answer(question, &[]).len(), 2);
+        assert!(
+            top_down
+                .memo
+                .keys()
+                .all(|(_, pattern)| pattern[0] != Some(5))
+        );
+    }
+}
@@ -0,0 +1,305 @@
+use std::collections::BTreeSet;
+
+use crate::ast::{LiteralAST, TermAST};
+use crate::interpret::Bindings;
+use crate::representation::{GroundTuple, Symbol};
+
+// Leapfrog triejoin, a worst-case optimal join. Nested loops join literals one at a time, so a
+// cyclic body like the triangle `E(x, y), E(y, z), E(z, x)` may enumerate every path of two edges
+// before the third literal rules most of them out. Leapfrog triejoin binds one variable at a time
+// instead: the values of a variable are the intersection of the values every literal using it
+// allows, given the variables bound before it. Intersecting never enumerates more bindings than
+// the join could output, up to a constant factor per literal.
+//
+// Each literal's rows are sorted by their constants, then their variables' values in the global
+// order variables are bound, so the rows agreeing on the constants and the variables bound so far
+// are contiguous (a node of the literal's trie), and the values of the next variable among them are
+// sorted. Intersections seek through these nodes in sorted indexes of the tables, which are kept
+// across joins and updated as rows are added (see `TrieIndex`).
+
+// A table's tuples, with their columns reordered and sorted. Indexes only hold tuples, so the
+// values of the rows joined are read from the table once an answer is found.
+pub(crate) struct TrieIndex {
+    // The positions of the tuples in the order they're sorted on.
+    columns: Vec<usize>,
+    rows: BTreeSet<Vec<Symbol>>,
+}
+
+impl TrieIndex {
+    pub(crate) fn new<'a>(
+        columns: Vec<usize>,
+        tuples: impl Iterator<Item = &'a GroundTuple>,
+    ) -> Self {
+        let mut index = Self {
+            columns,
+            rows: BTreeSet::new(),
+        };
+        for tuple in tuples {
+            index.insert(tuple);
+        }
+        index
+    }
+
+    pub(crate) fn columns(&self) -> &[usize] {
+        &self.columns
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.rows.len()
+    }
+
+    pub(crate) fn insert(&mut self, tuple: &GroundTuple) {
+        let row = self.columns.iter().map(|pos| tuple[*pos]).collect();
+        self.rows.insert(row);
+    }
+
+    pub(crate) fn remove(&mut self, tuple: &GroundTuple) {
+        let row: Vec<_> = self.columns.iter().map(|pos| tuple[*pos]).collect();
+        self.rows.remove(&row);
+    }
+}
+
+// The order of the columns a literal's index is sorted on: its constants, the first occurrence of
+// each of its variables, in the global order, then its other columns, which repeat variables.
+pub(crate) fn trie_columns(lit: &LiteralAST, order: &[String]) -> Vec<usize> {
+    let mut constants = vec![];
+    let mut vars: Vec<(usize, usize)> = vec![];
+    let mut repeated = vec![];
+    for (pos, term) in lit.terms.iter().enumerate() {
+        match term {
+            TermAST::Constant(_) => constants.push(pos),
+            TermAST::Variable(var) => {
+                let var = order.iter().position(|bound| bound == var).unwrap();
+                if vars.iter().all(|(seen, _)| *seen != var) {
+                    vars.push((var, pos));
+                } else {
+                    repeated.push(pos);
+                }
+            }
+            _ => panic!("Only flat literals can be joined by leapfrog triejoin."),
+        }
+    }
+    vars.sort();
+    let vars = vars.into_iter().map(|(_, pos)| pos);
+    constants.into_iter().chain(vars).chain(repeated).collect()
+}
+
+// A literal's trie: the rows of an index (sorted as by `trie_columns`) starting with the literal's
+// constants. Rows not matching the literal's repeated variables aren't left out, so the answers
+// found are checked against the tables.
+pub(crate) struct Trie<'a> {
+    // The indexes of the literal's variables in the global order, ascending.
+    vars: Vec<usize>,
+    constants: Vec<Symbol>,
+    rows: &'a BTreeSet<Vec<Symbol>>,
+}
+
+impl<'a> Trie<'a> {
+    pub(crate) fn new(lit: &LiteralAST, order: &[String], index: &'a TrieIndex) -> Self {
+        let mut vars = vec![];
+        let mut constants = vec![];
+        for term in &lit.terms {
+            match term {
+                TermAST::Constant(cons) => constants.push(*cons),
+                TermAST::Variable(var) => {
+                    let var = order.iter().position(|bound| bound == var).unwrap();
+                    if !vars.contains(&var) {
+                        vars.push(var);
+                    }
+                }
+                _ => panic!("Only flat literals can be joined by leapfrog triejoin."),
+            }
+        }
+        vars.sort();
+        Self {
+            vars,
+            constants,
+            rows: &index.rows,
+        }
+    }
+
+    // The node of the rows agreeing with the constants, and the values of the variables before a
+    // column of the trie.
+    fn prefix(&self, key: &[Symbol], col: usize) -> Vec<Symbol> {
+        let bound = self.vars[..col].iter().map(|var| key[*var]);
+        self.constants.iter().copied().chain(bound).collect()
+    }
+
+    // The first value at least some candidate following a node.
+    fn seek(&self, prefix: &[Symbol], candidate: Symbol) -> Option<Symbol> {
+        let mut lower = prefix.to_vec();
+        lower.push(candidate);
+        let row = self.rows.range(lower..).next()?;
+        row.starts_with(prefix).then(|| row[prefix.len()])
+    }
+
+    fn is_empty(&self) -> bool {
+        let row = self.rows.range(self.constants.clone()..).next();
+        row.is_none_or(|row| !row.starts_with(&self.constants))
+    }
+}
+
+// Whether a literal can be joined by leapfrog triejoin: its terms are variables and constants.
+pub(crate) fn is_flat(lit: &LiteralAST) -> bool {
+    lit.terms
+        .iter()
+        .all(|term| matches!(term, TermAST::Variable(_) | TermAST::Constant(_)))
+}
+
+// Whether the join of some literals is cyclic, by GYO reduction of the hypergraph whose edges are
+// the literals' sets of variables: variables in only one edge are removed, as are edges contained
+// in other edges, until nothing changes. The join is acyclic if at most one edge is left. Nested
+// loops join acyclic bodies well enough, given a good order, so only cyclic bodies are worth
+// joining by leapfrog triejoin.
+pub(crate) fn is_cyclic(lits: &[&LiteralAST]) -> bool {
+    let mut edges: Vec<BTreeSet<&str>> = lits.iter().map(|lit| lit.vars().collect()).collect();
+    loop {
+        let before = edges.clone();
+        let all: Vec<&str> = edges.iter().flatten().copied().collect();
+        for edge in &mut edges {
+            edge.retain(|var| all.iter().filter(|other| *other == var).count() > 1);
+        }
+        let mut idx = 0;
+        while idx < edges.len() {
+            let contained =
+                (0..edges.len()).any(|other| other != idx && edges[idx].is_subset(&edges[other]));
+            if contained {
+                edges.remove(idx);
+            } else {
+                idx += 1;
+            }
+        }
+        if edges.len() <= 1 {
+            return false;
+        }
+        if edges == before {
+            return true;
+        }
+    }
+}
+
+// Join tries, calling a function with the bindings of each answer. Answers are found in the order
+// of their bindings' values.
+pub(crate) fn triejoin(order: &[String], tries: &[Trie], emit: &mut dyn FnMut(Bindings)) {
+    // Ground literals have no variables to intersect on, so they only need a matching row.
+    if tries.iter().any(Trie::is_empty) {
+        return;
+    }
+    let mut key = vec![];
+    search(order, tries, &mut key, emit);
+}
+
+fn search(order: &[String], tries: &[Trie], key: &mut Vec<Symbol>, emit: &mut dyn FnMut(Bindings)) {
+    let depth = key.len();
+    if depth == order.len() {
+        let bindings = order.iter().cloned().zip(key.iter().copied()).collect();
+        return emit(bindings);
+    }
+
+    // The tries using the variable at this depth, and the nodes of the variables bound before it.
+    let using: Vec<(&Trie, Vec<Symbol>)> = tries
+        .iter()
+        .filter_map(|trie| {
+            let col = trie.vars.iter().position(|var| *var == depth)?;
+            Some((trie, trie.prefix(key, col)))
+        })
+        .collect();
+    let mut candidate = Symbol::MIN;
+    loop {
+        // Seek each trie to its first value at least the candidate, in turn, raising the candidate
+        // to the values found, until every trie agrees on it.
+        let mut agreed = 0;
+        let mut turn = 0;
+        while agreed < using.len() {
+            let (trie, prefix) = &using[turn];
+            let Some(value) = trie.seek(prefix, candidate) else {
+                return;
+            };
+            if value == candidate {
+                agreed += 1;
+            } else {
+                candidate = value;
+                agreed = 1;
+            }
+            turn = (turn + 1) % using.len();
+        }
+
+        key.push(candidate);
+        search(order, tries, key, emit);
+        key.pop();
+
+        match candidate.checked_add(1) {
+            Some(next) => candidate = next,
+            None => return,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::ast::{AtomAST, StatementAST};
+    use crate::grammar::ProgramParser;
+
+    use super::*;
+
+    fn body(question: &str) -> Vec<LiteralAST> {
+        let parsed = ProgramParser::new().parse(question).unwrap();
+        let StatementAST::Question(body, ..) = &parsed[0] else {
+            panic!()
+        };
+        body.iter()
+            .map(|atom| match atom {
+                AtomAST::Literal(lit) => lit.clone(),
+                _ => panic!(),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn detect_cycles() {
+        let cyclic = |question| {
+            let body = body(question);
+            is_cyclic(&body.iter().collect::<Vec<_>>())
+        };
+        assert!(cyclic("? E(x, y), E(y, z), E(z, x)."));
+        assert!(cyclic("? E(a, b), E(b, c), E(c, d), E(d, a)."));
+        assert!(!cyclic("? E(x, y), E(y, z), E(z, w)."));
+        assert!(!cyclic("? E(x, y), E(y, z), F(x, y, z)."));
+        assert!(!cyclic("? E(x, y)."));
+    }
+
+    #[test]
+    fn join_triangles() {
+        let edges: Vec<GroundTuple> = [(1, 2), (2, 3), (3, 1), (2, 4), (4, 1), (3, 3), (1, 4)]
+            .into_iter()
+            .map(|(x, y)| vec![x, y])
+            .collect();
+        let body = body("? E(x, y), E(y, z), E(z, x).");
+        let order = vec!["x".to_string(), "y".to_string(), "z".to_string()];
+        let indexes: Vec<_> = body
+            .iter()
+            .map(|lit| TrieIndex::new(trie_columns(lit, &order), edges.iter()))
+            .collect();
+        let tries: Vec<_> = body
+            .iter()
+            .zip(&indexes)
+            .map(|(lit, index)| Trie::new(lit, &order, index))
+            .collect();
+        let mut triangles = vec![];
+        triejoin(&order, &tries, &mut |bindings| {
+            triangles.push(order.iter().map(|var| bindings[var]).collect::<Vec<_>>());
+        });
+        assert_eq!(
+            triangles,
+            vec![
+                vec![1, 2, 3],
+                vec![1, 2, 4],
+                vec![2, 3, 1],
+                vec![2, 4, 1],
+                vec![3, 1, 2],
+                vec![3, 3, 3],
+                vec![4, 1, 2],
+            ]
+        );
+    }
+}
@@ -0,0 +1,369 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+use std::sync::RwLock;
+
+use crate::representation::{Assumption, GroundTuple, LeafAssumption, Symbol};
+
+// Strings are interned to symbols, so tuples stay vectors of integers. Interned strings get
+// symbols counting up from `i64::MIN`, far below any integer a program is likely to use, so a
+// symbol can be printed as the string it stands for without knowing the type of its column.
+const FIRST_STRING: Symbol = i64::MIN;
+
+struct Interner {
+    symbols: BTreeMap<String, Symbol>,
+    strings: Vec<String>,
+}
+
+// The interner is shared by every environment, on every thread. Symbols are looked up far more
+// often than strings are interned, so lookups only take the read lock.
+static INTERNER: RwLock<Interner> = RwLock::new(Interner {
+    symbols: BTreeMap::new(),
+    strings: Vec::new(),
+});
+
+pub fn intern(s: &str) -> Symbol {
+    if let Some(symbol) = INTERNER.read().unwrap().symbols.get(s) {
+        return *symbol;
+    }
+    // Another thread may have interned the string between releasing the read lock and taking the
+    // write lock.
+    let mut interner = INTERNER.write().unwrap();
+    if let Some(symbol) = interner.symbols.get(s) {
+        return *symbol;
+    }
+    // Symbols are 64 bits wide, so this only fails after 2^62 strings, but a string getting the
+    // symbol of a compound term would silently make the two equal.
+    let symbol = FIRST_STRING + interner.strings.len() as Symbol;
+    assert!(symbol < FIRST_TERM, "Too many strings were interned.");
+    interner.strings.push(s.to_string());
+    interner.symbols.insert(s.to_string(), symbol);
+    symbol
+}
+
+// How many strings have been interned.
+pub fn interned() -> usize {
+    INTERNER.read().unwrap().strings.len()
+}
+
+// The string a symbol stands for, if it's an interned string rather than an integer.
+pub fn resolve(symbol: Symbol) -> Option<String> {
+    let interner = INTERNER.read().unwrap();
+    let idx = usize::try_from(symbol.checked_sub(FIRST_STRING)?).ok()?;
+    interner.strings.get(idx).cloned()
+}
+
+// Compound terms like `pair(1, "a")` are interned to symbols too, counting up from `i64::MIN / 2`,
+// far above the symbols of interned strings. A term's depth is one more than the deepest of its
+// arguments, where integers and strings have depth 0. Terms deeper than `MAX_TERM_DEPTH` aren't
+// interned, so recursive rules building ever deeper terms (like `N(succ(n)) :- N(n).`) still reach
+// a fixpoint.
+const FIRST_TERM: Symbol = i64::MIN / 2;
+pub const MAX_TERM_DEPTH: usize = 64;
+
+// Lists are compound terms: `[]` is the empty list, and `[x | xs]` applies the functor `[|]` to the
+// head and tail of a non-empty list. These functors can't be written as identifiers, so lists are
+// only ever written as `[1, 2 | xs]`. A list's depth is one more than its length, so lists hold at
+// most `MAX_TERM_DEPTH - 1` elements.
+pub const NIL: &str = "[]";
+pub const CONS: &str = "[|]";
+
+struct Terms {
+    symbols: BTreeMap<(String, Vec<Symbol>), Symbol>,
+    terms: Vec<(String, Vec<Symbol>, usize)>,
+}
+
+static TERMS: RwLock<Terms> = RwLock::new(Terms {
+    symbols: BTreeMap::new(),
+    terms: Vec::new(),
+});
+
+// Intern the compound term applying a functor to arguments, or return `None` if it would be deeper
+// than `MAX_TERM_DEPTH`.
+pub fn intern_term(functor: &str, args: &[Symbol]) -> Option<Symbol> {
+    let key = (functor.to_string(), args.to_vec());
+    if let Some(symbol) = TERMS.read().unwrap().symbols.get(&key) {
+        return Some(*symbol);
+    }
+    let mut terms = TERMS.write().unwrap();
+    if let Some(symbol) = terms.symbols.get(&key) {
+        return Some(*symbol);
+    }
+    let depth = 1 + args.iter().map(|arg| terms.depth(*arg)).max().unwrap_or(0);
+    if depth > MAX_TERM_DEPTH {
+        return None;
+    }
+    // Likewise, compound terms never get the symbols of non-negative integers.
+    let symbol = FIRST_TERM + terms.terms.len() as Symbol;
+    assert!(symbol < 0, "Too many compound terms were interned.");
+    terms
+        .terms
+        .push((functor.to_string(), args.to_vec(), depth));
+    terms.symbols.insert(key, symbol);
+    Some(symbol)
+}
+
+// The functor and arguments of a symbol, if it's an interned compound term.
+pub fn resolve_term(symbol: Symbol) -> Option<(String, Vec<Symbol>)> {
+    let terms = TERMS.read().unwrap();
+    let (functor, args, _) = terms.term(symbol)?;
+    Some((functor.clone(), args.clone()))
+}
+
+// The list of some elements in front of a tail, or `None` if it would be too deep.
+pub fn intern_list(elems: &[Symbol], tail: Symbol) -> Option<Symbol> {
+    elems
+        .iter()
+        .rev()
+        .try_fold(tail, |tail, elem| intern_term(CONS, &[*elem, tail]))
+}
+
+pub fn nil() -> Symbol {
+    intern_term(NIL, &[]).unwrap()
+}
+
+// The elements of a symbol, if it's a list.
+pub fn resolve_list(symbol: Symbol) -> Option<Vec<Symbol>> {
+    let (elems, tail) = list_parts(symbol);
+    (tail == nil()).then_some(elems)
+}
+
+// The elements at the front of a symbol, following the tails of `[|]` terms, and the tail after
+// them.
+fn list_parts(mut symbol: Symbol) -> (Vec<Symbol>, Symbol) {
+    let mut elems = vec![];
+    while let Some((functor, args)) = resolve_term(symbol)
+        && functor == CONS
+    {
+        elems.push(args[0]);
+        symbol = args[1];
+    }
+    (elems, symbol)
+}
+
+// The strings and compound terms some symbols stand for, saved along with the symbols (in
+// snapshots, checkpoints, and SQLite databases) so they can be interned again when loaded. Symbols
+// depend on the order strings and terms were interned in, which differs between processes, so
+// loaded symbols are remapped to the symbols their strings and terms have in this one.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SymbolTable {
+    pub strings: Vec<(Symbol, String)>,
+    // Terms are listed in the order they were interned, so the arguments of a term come before it.
+    pub terms: Vec<(Symbol, String, Vec<Symbol>)>,
+}
+
+impl SymbolTable {
+    // The strings and terms some symbols stand for, including those nested in the terms.
+    pub fn of(symbols: impl IntoIterator<Item = Symbol>) -> Self {
+        let mut pending: Vec<Symbol> = symbols
+            .into_iter()
+            .filter(|symbol| *symbol < 0)
+            .collect::<BTreeSet<_>>()
+            .into_iter()
+            .collect();
+        let interner = INTERNER.read().unwrap();
+        let terms = TERMS.read().unwrap();
+        let mut found_strings = BTreeMap::new();
+        let mut found_terms = BTreeMap::new();
+        while let Some(symbol) = pending.pop() {
+            let idx = symbol.checked_sub(FIRST_STRING);
+            let idx = idx.and_then(|idx| usize::try_from(idx).ok());
+            if let Some(s) = idx.and_then(|idx| interner.strings.get(idx)) {
+                found_strings.insert(symbol, s.clone());
+            } else if let Some((functor, args, _)) = terms.term(symbol)
+                && !found_terms.contains_key(&symbol)
+            {
+                pending.extend(args);
+                found_terms.insert(symbol, (functor.clone(), args.clone()));
+            }
+        }
+        Self {
+            strings: found_strings.into_iter().collect(),
+            terms: found_terms
+                .into_iter()
+                .map(|(symbol, (functor, args))| (symbol, functor, args))
+                .collect(),
+        }
+    }
+
+    // Intern the strings and terms, mapping the symbols they were saved with to the symbols they
+    // have now. Fails if a term can't be interned, which only happens to corrupt tables.
+    pub fn intern(&self) -> Result<Remap, String> {
+        let mut remap = Remap::default();
+        for (symbol, s) in &self.strings {
+            remap.0.insert(*symbol, intern(s));
+        }
+        for (symbol, functor, args) in &self.terms {
+            let args: Vec<_> = args.iter().map(|arg| remap.symbol(*arg)).collect();
+            let Some(term) = intern_term(functor, &args) else {
+                return Err(format!("term {}(...) is too deep", functor));
+            };
+            remap.0.insert(*symbol, term);
+        }
+        Ok(remap)
+    }
+}
+
+// A mapping from the symbols of loaded data to the symbols of this process (see `SymbolTable`).
+// Integers, and symbols missing from the table, map to themselves.
+#[derive(Debug, Clone, Default)]
+pub struct Remap(BTreeMap<Symbol, Symbol>);
+
+impl Remap {
+    pub fn symbol(&self, symbol: Symbol) -> Symbol {
+        self.0.get(&symbol).copied().unwrap_or(symbol)
+    }
+
+    pub fn tuple(&self, tuple: &[Symbol]) -> GroundTuple {
+        tuple.iter().map(|symbol| self.symbol(*symbol)).collect()
+    }
+
+    pub fn leaf(&self, (relation, tuple): &LeafAssumption) -> LeafAssumption {
+        (relation.clone(), self.tuple(tuple))
+    }
+
+    pub fn value<A: Assumption>(&self, value: &A) -> A {
+        value.map_leaves(&|leaf| self.leaf(leaf))
+    }
+}
+
+impl Terms {
+    fn term(&self, symbol: Symbol) -> Option<&(String, Vec<Symbol>, usize)> {
+        let idx = usize::try_from(symbol.checked_sub(FIRST_TERM)?).ok()?;
+        self.terms.get(idx)
+    }
+
+    fn depth(&self, symbol: Symbol) -> usize {
+        self.term(symbol).map_or(0, |(_, _, depth)| *depth)
+    }
+}
+
+// Displays a symbol as the integer it is, as the quoted string it stands for, or as the compound
+// term it stands for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Value(pub Symbol);
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(s) = resolve(self.0) {
+            return write!(f, "\"{}\"", s);
+        }
+        let Some((functor, args)) = resolve_term(self.0) else {
+            return write!(f, "{}", self.0);
+        };
+        if functor == CONS || functor == NIL {
+            let (elems, tail) = list_parts(self.0);
+            write!(f, "[")?;
+            for (idx, elem) in elems.iter().enumerate() {
+                if idx > 0 {
+                    write!(f, ", ")?;
+                }
+                write!(f, "{}", Value(*elem))?;
+            }
+            if tail != nil() {
+                write!(f, " | {}", Value(tail))?;
+            }
+            return write!(f, "]");
+        }
+        write!(f, "{}(", functor)?;
+        for (idx, arg) in args.iter().enumerate() {
+            if idx > 0 {
+                write!(f, ", ")?;
+            }
+            write!(f, "{}", Value(*arg))?;
+        }
+        write!(f, ")")
+    }
+}
+
+// Parse a symbol written as an integer, or as a double-quoted string (without escapes).
+pub fn parse_value(s: &str) -> Option<Symbol> {
+    match s.strip_prefix('"').and_then(|s| s.strip_suffix('"')) {
+        Some(s) if !s.contains('"') => Some(intern(s)),
+        Some(_) => None,
+        None => s.parse().ok(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn intern_and_display() {
+        let alice = intern("alice");
+        assert_eq!(intern("alice"), alice);
+        assert_ne!(intern("bob"), alice);
+        assert_eq!(resolve(alice).as_deref(), Some("alice"));
+        assert_eq!(resolve(-3), None);
+        assert_eq!(Value(alice).to_string(), "\"alice\"");
+        assert_eq!(Value(-3).to_string(), "-3");
+        assert_eq!(parse_value("\"alice\""), Some(alice));
+        assert_eq!(parse_value("12"), Some(12));
+        assert_eq!(parse_value("\"a\"b\""), None);
+    }
+
+    #[test]
+    fn intern_concurrently() {
+        let names: Vec<_> = (0..64).map(|n| format!("concurrent{}", n)).collect();
+        let symbols: Vec<Vec<Symbol>> = std::thread::scope(|scope| {
+            let threads: Vec<_> = (0..4)
+                .map(|_| scope.spawn(|| names.iter().map(|name| intern(name)).collect()))
+                .collect();
+            threads
+                .into_iter()
+                .map(|thread| thread.join().unwrap())
+                .collect()
+        });
+        assert!(symbols.iter().all(|thread| *thread == symbols[0]));
+        for (name, symbol) in names.iter().zip(&symbols[0]) {
+            assert_eq!(resolve(*symbol).as_ref(), Some(name));
+        }
+    }
+
+    #[test]
+    fn intern_terms() {
+        let pair = intern_term("pair", &[1, intern("a")]).unwrap();
+        assert_eq!(intern_term("pair", &[1, intern("a")]), Some(pair));
+        assert_eq!(Value(pair).to_string(), "pair(1, \"a\")");
+        assert_eq!(resolve(pair), None);
+        assert_eq!(resolve_term(1), None);
+
+        let mut term = 0;
+        for _ in 0..MAX_TERM_DEPTH {
+            term = intern_term("succ", &[term]).unwrap();
+        }
+        assert_eq!(intern_term("succ", &[term]), None);
+    }
+
+    #[test]
+    fn remap_symbols() {
+        let pair = intern_term("pair", &[intern("saved"), 1]).unwrap();
+        let table = SymbolTable::of([pair, 2]);
+        assert_eq!(table.strings, vec![(intern("saved"), "saved".to_string())]);
+        assert_eq!(table.terms.len(), 1);
+
+        // Symbols saved by another process, where the string and term were interned later.
+        let (string, term) = (FIRST_STRING + (1 << 40), FIRST_TERM + (1 << 40));
+        let saved = SymbolTable {
+            strings: vec![(string, "saved".to_string())],
+            terms: vec![(term, "pair".to_string(), vec![string, 1])],
+        };
+        let remap = saved.intern().unwrap();
+        assert_eq!(
+            remap.tuple(&[term, string, 3]),
+            vec![pair, intern("saved"), 3]
+        );
+    }
+
+    #[test]
+    fn intern_lists() {
+        let list = intern_list(&[1, 2], nil()).unwrap();
+        assert_eq!(Value(list).to_string(), "[1, 2]");
+        assert_eq!(resolve_list(list), Some(vec![1, 2]));
+        assert_eq!(Value(nil()).to_string(), "[]");
+        let partial = intern_list(&[1], intern("a")).unwrap();
+        assert_eq!(Value(partial).to_string(), "[1 | \"a\"]");
+        assert_eq!(resolve_list(partial), None);
+    }
+}
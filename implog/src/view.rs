@@ -0,0 +1,158 @@
+use crate::interpret::Diff;
+use crate::representation::{Assumption, GroundTuple, Table};
+use crate::value::Value;
+
+// A read-only view of a relation's table, so embedders can consume derived tuples without parsing
+// printed answers. Only tuples with a non-zero full value are in the relation, and they're visited
+// in the order of the table. Values are wrapped in `Value`, which displays them as the integers,
+// strings, or compound terms they stand for, and assumption values display as formulas.
+pub struct RelationView<'a, A: Assumption> {
+    table: &'a Table<A>,
+    // The column names of the relation, if it's declared.
+    columns: Option<&'a [String]>,
+}
+
+impl<'a, A: Assumption> RelationView<'a, A> {
+    pub(crate) fn new(table: &'a Table<A>, columns: Option<&'a [String]>) -> Self {
+        Self { table, columns }
+    }
+
+    pub fn columns(&self) -> Option<&'a [String]> {
+        self.columns
+    }
+
+    // The number of tuples in the relation.
+    pub fn len(&self) -> usize {
+        self.iter().count()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.iter().next().is_none()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (Vec<Value>, &'a A)> + 'a {
+        rows(self.table.iter())
+    }
+
+    // The assumption value under which a tuple holds, if it's in the relation.
+    pub fn get(&self, tuple: &[Value]) -> Option<&'a A> {
+        let tuple: GroundTuple = tuple.iter().map(|value| value.0).collect();
+        self.table
+            .get(&tuple)
+            .map(|(value, _)| value)
+            .filter(|value| !value.is_zero())
+    }
+
+    // The tuples whose first columns hold a key, in order.
+    pub fn lookup(&self, key: &[Value]) -> impl Iterator<Item = (Vec<Value>, &'a A)> + 'a {
+        let key: GroundTuple = key.iter().map(|value| value.0).collect();
+        let start = key.clone();
+        rows(
+            self.table
+                .range(start..)
+                .take_while(move |(tuple, _)| tuple.starts_with(&key)),
+        )
+    }
+
+    // The tuples added to, removed from, or holding under a different assumption value in a newer
+    // view of the relation, like one computed by a changed program.
+    pub fn diff(&self, newer: &RelationView<'_, A>) -> Diff<A, GroundTuple> {
+        Diff::new(tuples(self.table), tuples(newer.table))
+    }
+
+    // The table itself, including its zero rows and the deltas of the last fixpoint iteration.
+    pub fn table(&self) -> &'a Table<A> {
+        self.table
+    }
+}
+
+fn tuples<A: Assumption>(table: &Table<A>) -> impl Iterator<Item = (GroundTuple, A)> + '_ {
+    table
+        .iter()
+        .filter(|(_, (value, _))| !value.is_zero())
+        .map(|(tuple, (value, _))| (tuple.clone(), value.clone()))
+}
+
+fn rows<'a, A: Assumption + 'a>(
+    rows: impl Iterator<Item = (&'a GroundTuple, &'a (A, A))> + 'a,
+) -> impl Iterator<Item = (Vec<Value>, &'a A)> + 'a {
+    rows.filter(|(_, (value, _))| !value.is_zero())
+        .map(|(tuple, (value, _))| (tuple.iter().copied().map(Value).collect(), value))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::grammar::ProgramParser;
+    use crate::interpret::Environment;
+    use crate::representation::DNFAssumption;
+    use crate::value::intern;
+
+    use super::*;
+
+    #[test]
+    fn read_derived_relation() {
+        let program = ".decl P(from: int, to: symbol).\nE(1, \"a\") :- .\nE(2, \"b\") :- .\n\
+                       [A] :- .\nP(x, y) :- E(x, y), [A].\nP(1, \"c\") :- .\n? P(x, y).\n";
+        let parsed = ProgramParser::new().parse(program).unwrap();
+        let mut env = Environment::<DNFAssumption>::new();
+        env.interpret(&parsed).unwrap();
+        let view = env.relation("P").unwrap();
+        assert_eq!(view.len(), 3);
+        assert_eq!(
+            view.columns(),
+            Some(&["from".to_string(), "to".to_string()][..])
+        );
+        let rows: Vec<_> = view
+            .iter()
+            .map(|(tuple, value)| {
+                let tuple: Vec<_> = tuple.iter().map(ToString::to_string).collect();
+                (tuple.join(", "), value.to_string())
+            })
+            .collect();
+        assert_eq!(
+            rows,
+            vec![
+                ("1, \"a\"".to_string(), "A".to_string()),
+                ("1, \"c\"".to_string(), "true".to_string()),
+                ("2, \"b\"".to_string(), "A".to_string()),
+            ]
+        );
+        assert_eq!(
+            view.get(&[Value(1), Value(intern("c"))]),
+            Some(&DNFAssumption::one())
+        );
+        assert_eq!(view.get(&[Value(2), Value(intern("c"))]), None);
+        assert_eq!(view.lookup(&[Value(1)]).count(), 2);
+        assert_eq!(view.lookup(&[Value(3)]).count(), 0);
+        assert!(env.relation("Q").is_none());
+    }
+
+    #[test]
+    fn diff_relation() {
+        let old = "[A] :- .\nE(1, 2) :- .\nE(2, 3) :- .\nE(3, 4) :- A.\n? E(x, y).\n";
+        let new = "[B] :- .\nE(1, 2) :- .\nE(3, 4) :- B.\nE(4, 5) :- .\n? E(x, y).\n";
+        let mut old_env = Environment::<DNFAssumption>::new();
+        old_env
+            .interpret(&ProgramParser::new().parse(old).unwrap())
+            .unwrap();
+        let mut new_env = Environment::<DNFAssumption>::new();
+        new_env
+            .interpret(&ProgramParser::new().parse(new).unwrap())
+            .unwrap();
+        let (old, new) = (
+            old_env.relation("E").unwrap(),
+            new_env.relation("E").unwrap(),
+        );
+        let diff = old.diff(&new);
+        assert_eq!(diff.added, vec![(vec![4, 5], DNFAssumption::one())]);
+        assert_eq!(diff.removed, vec![(vec![2, 3], DNFAssumption::one())]);
+        assert_eq!(diff.changed.len(), 1);
+        let (tuple, old_value, new_value) = &diff.changed[0];
+        assert_eq!(tuple, &vec![3, 4]);
+        assert_eq!(
+            (old_value.to_string(), new_value.to_string()),
+            ("A".to_string(), "B".to_string())
+        );
+        assert!(old.diff(&old).is_empty());
+    }
+}
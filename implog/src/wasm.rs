@@ -0,0 +1,102 @@
+use wasm_bindgen::prelude::*;
+
+use crate::ast::{StatementAST, parse_checked};
+use crate::interpret::Environment;
+use crate::json::answers_to_json;
+use crate::representation::DNFAssumption;
+
+// Bindings for running programs from JavaScript, e.g. in a browser playground. Programs are
+// interpreted with DNF assumptions, answers are returned as JSON strings (see `json`), and errors
+// are thrown as located messages.
+
+// Programs passed from JavaScript have no file name.
+const SOURCE: &str = "<source>";
+
+// Check that a program parses and every statement is well formed.
+#[wasm_bindgen]
+pub fn parse(source: &str) -> Result<(), String> {
+    parse_checked(SOURCE, source).map(|_| ())
+}
+
+// Interpret a program, returning a JSON array holding the answers to each of its questions.
+#[wasm_bindgen]
+pub fn run(source: &str) -> Result<String, String> {
+    let mut session = Session::new();
+    session.run(source)
+}
+
+// An environment kept across calls, so questions can be asked of the last program run.
+#[wasm_bindgen]
+pub struct Session {
+    env: Environment<DNFAssumption>,
+}
+
+#[wasm_bindgen]
+impl Session {
+    #[wasm_bindgen(constructor)]
+    pub fn new() -> Self {
+        Self {
+            env: Environment::new(),
+        }
+    }
+
+    pub fn run(&mut self, source: &str) -> Result<String, String> {
+        let (spans, stmts) = parse_checked(SOURCE, source)?;
+        self.env
+            .interpret(&stmts)
+            .map_err(|err| err.locate(SOURCE, source, &spans))?;
+        let answers: Vec<_> = self
+            .env
+            .answers()
+            .iter()
+            .map(|answers| answers_to_json(answers))
+            .collect();
+        Ok(format!("[{}]", answers.join(", ")))
+    }
+
+    // Answer a question like `? P(x).` against the model of the last program run, returning a JSON
+    // array of its answers.
+    pub fn ask(&mut self, question: &str) -> Result<String, String> {
+        let (_, stmts) = parse_checked(SOURCE, question)?;
+        let [StatementAST::Question(body, _, _, _)] = stmts.as_slice() else {
+            return Err(format!("{}: expected a single question", SOURCE));
+        };
+        let answers = self
+            .env
+            .ask(body)
+            .map_err(|err| format!("{}: {}", SOURCE, err))?;
+        Ok(answers_to_json(&answers))
+    }
+}
+
+impl Default for Session {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn run_and_ask() {
+        assert_eq!(
+            parse("P(x) :- [A(y)]."),
+            Err(
+                "<source>:1:1: a variable of a bracket or arrow isn't bound by the body\n  | P(x) :- [A(y)]."
+                    .to_string()
+            )
+        );
+        let mut session = Session::new();
+        assert_eq!(
+            session.run("E(1, 2) :- .\n? E(x, 2).\n").unwrap(),
+            r#"[[{"x": 1, "assumption": [[]]}]]"#
+        );
+        assert_eq!(
+            session.ask("? E(1, y).").unwrap(),
+            r#"[{"y": 2, "assumption": [[]]}]"#
+        );
+        assert!(session.ask("E(1, 2) :- .").is_err());
+    }
+}